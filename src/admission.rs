@@ -0,0 +1,345 @@
+//! Policies that decide whether an arriving item joins the queue at all,
+//! and what happens to it if the buffer is full.
+//!
+//! Arriving items that find the buffer full are dropped by default, and
+//! counted. Other overflow policies drop the oldest buffered item instead,
+//! or block arrivals until there's room. With a retrial orbit, arrivals that
+//! would be dropped try again later instead. A token bucket can also
+//! throttle arrivals that come too fast, whether or not there's room.
+//!
+//! Arriving items can balk, i.e., decide not to join, depending on how many
+//! items are buffered, and buffered items renege if they haven't entered
+//! service within a sampled patience time. Items can take a callback
+//! instead, when they find the buffer full or run out of patience: they
+//! wait in a virtual queue, in order of arrival, and are recalled to the
+//! front of the buffer once every item that's still buffered arrived after
+//! them.
+
+use crate::dist::Distribution;
+use crate::rng::Rng;
+use crate::Time;
+
+/// Balking, where an arriving item that sees `n` items in the buffer decides
+/// not to join:
+/// - `Threshold`: Items join only if fewer than the given number are
+///   buffered.
+/// - `Probability`: Items join with probability `p(n)`.
+#[derive(Debug, Clone, Copy)]
+pub enum Balking {
+    Threshold(u32),
+    Probability(fn(u32) -> f64),
+}
+
+impl Balking {
+    /// The probability that an item that sees `n` buffered items joins.
+    pub fn join_probability(&self, n: u32) -> f64 {
+        match self {
+            Balking::Threshold(threshold) => {
+                if n < *threshold {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Balking::Probability(p) => p(n).clamp(0.0, 1.0),
+        }
+    }
+
+    /// Decide whether an item that sees `n` buffered items balks.
+    pub fn balks(&self, n: u32, rng: &mut Rng) -> bool {
+        match self.join_probability(n) {
+            p if p >= 1.0 => false,
+            p if p <= 0.0 => true,
+            p => rng.uniform() >= p,
+        }
+    }
+}
+
+/// A token bucket that limits the rate at which arrivals join. The bucket
+/// holds up to `burst` tokens and refills at `rate` tokens per unit of time,
+/// and each arrival that joins takes a token. Arrivals that find the bucket
+/// empty are throttled. The bucket starts out full.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TokenBucket {
+    pub rate: f64,
+    pub burst: f64,
+    tokens: f64,
+    updated: Time,
+}
+
+impl TokenBucket {
+    /// Create a full bucket.
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            updated: Time(0),
+        }
+    }
+
+    /// Refill the bucket up to the given time, and take a token if there is
+    /// one, returning whether there was.
+    pub fn take(&mut self, now: Time) -> bool {
+        let elapsed = now.0.saturating_sub(self.updated.0) as f64;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = self.updated.max(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// What happens to an arriving item that finds the buffer full:
+/// - `DropNewest`: The arriving item is dropped (tail-drop).
+/// - `DropOldest`: The oldest buffered item is dropped to make room for the
+///   arriving one (head-drop).
+/// - `Block`: The arriving item waits outside the queue until there's room,
+///   and if it came from an arrival source, the source's next arrival waits
+///   with it.
+/// - `Callback`: The arriving item takes a callback, and waits in the virtual
+///   queue until it's recalled (see `QueueState`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    #[default]
+    DropNewest,
+    DropOldest,
+    Block,
+    Callback,
+}
+
+/// A retrial orbit, where an arrival that would be dropped because the
+/// buffer is full tries again after a sampled delay, e.g., a caller who gets
+/// a busy signal and redials. An item gives up after `max_attempts` attempts
+/// in all, counting the first.
+#[derive(Debug)]
+pub struct Retrial {
+    pub delay: Box<dyn Distribution>,
+    pub max_attempts: u32,
+}
+
+impl Retrial {
+    /// Create an orbit with the given retry delay and maximum number of
+    /// attempts.
+    pub fn new(delay: impl Distribution + 'static, max_attempts: u32) -> Self {
+        Self {
+            delay: Box::new(delay),
+            max_attempts,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, EventType, QueueState, Time};
+
+    #[test]
+    fn test_balking() {
+        // Items that see 2 or more buffered items balk, and that's logged
+        // separately from items dropped for lack of capacity.
+        let state = QueueState::new(10, 1, 100).with_balking(Balking::Threshold(2));
+        let sim = &mut Simulation::new(state);
+        for t in 0..5 {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run_until(Time(10));
+        let balked = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::Balked)
+            .map(|e| e.time.0)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![3, 4], balked);
+        assert_eq!(2, sim.state.balked);
+
+        // Joining gets less likely as the buffer grows.
+        let smooth = Balking::Probability(|n| 1.0 / (n + 1) as f64);
+        assert_eq!(1.0, smooth.join_probability(0));
+        assert_eq!(0.25, smooth.join_probability(3));
+        let rng = &mut Rng::new(0);
+        let joined = (0..10_000).filter(|_| !smooth.balks(1, rng)).count();
+        assert!((joined as f64 / 10_000.0 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_overflow() {
+        // With room for one buffered item and services of 10, the arrival at
+        // 2 finds the buffer full.
+        let run = |overflow| {
+            let state = QueueState::new(1, 1, 10).with_overflow(overflow);
+            let sim = &mut Simulation::new(state);
+            for t in 0..3 {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            sim.log
+                .contents
+                .iter()
+                .filter(|e| {
+                    matches!(
+                        e.event_type,
+                        EventType::Evicted | EventType::Blocked | EventType::Unblocked
+                    )
+                })
+                .map(|e| (e.time.0, e.event_type))
+                .collect::<Vec<_>>()
+        };
+
+        // The oldest buffered item makes room for it.
+        assert_eq!(vec![(2, EventType::Evicted)], run(Overflow::DropOldest));
+
+        // It waits until the buffered item enters service at 10.
+        assert_eq!(
+            vec![(2, EventType::Blocked), (10, EventType::Unblocked)],
+            run(Overflow::Block)
+        );
+    }
+
+    #[test]
+    fn test_retrial() {
+        // With room for one buffered item and services of 10, the arrival at
+        // 2 finds the buffer full and retries every 5 units. It fails at 7,
+        // and gets in at 12, after the buffered item entered service at 10,
+        // unless it gives up after two attempts.
+        use crate::dist::Deterministic;
+        let run = |max_attempts| {
+            let state = QueueState::new(1, 1, 10)
+                .with_retrial(Retrial::new(Deterministic(5.0), max_attempts));
+            let sim = &mut Simulation::new(state);
+            for t in 0..3 {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            let retries = sim
+                .log
+                .contents
+                .iter()
+                .filter(|e| {
+                    matches!(
+                        e.event_type,
+                        EventType::RetryScheduled
+                            | EventType::RetrySucceeded
+                            | EventType::RetryAbandoned
+                    )
+                })
+                .map(|e| (e.time.0, e.event_type))
+                .collect::<Vec<_>>();
+            (retries, sim.state.dropped)
+        };
+
+        assert_eq!(
+            (
+                vec![
+                    (2, EventType::RetryScheduled),
+                    (7, EventType::RetryScheduled),
+                    (12, EventType::RetrySucceeded)
+                ],
+                0
+            ),
+            run(3)
+        );
+        assert_eq!(
+            (
+                vec![
+                    (2, EventType::RetryScheduled),
+                    (7, EventType::RetryAbandoned)
+                ],
+                1
+            ),
+            run(2)
+        );
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        // A bucket with room for 2 tokens that refills at 1 token per 5 units
+        // lets in the arrivals at 0 and 1, throttles those at 2 and 3, and
+        // has a token again by 5. Throttled arrivals aren't dropped ones.
+        let state = QueueState::new(10, 1, 100).with_token_bucket(TokenBucket::new(0.2, 2.0));
+        let sim = &mut Simulation::new(state);
+        for t in [0, 1, 2, 3, 5] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run_until(Time(10));
+        let throttled = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::Throttled)
+            .map(|e| e.time.0)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![2, 3], throttled);
+        assert_eq!((2, 0), (sim.state.throttled, sim.state.dropped));
+    }
+
+    #[test]
+    fn test_callbacks() {
+        // Items arrive at 0, 1, and 2 for services of 10. With room for one
+        // buffered item, the third takes a callback and is recalled at 10,
+        // when the second enters service. With a patience of 5, the second
+        // and third take callbacks at 6 and 7, and are both recalled at 10.
+        use crate::dist::Deterministic;
+        let run = |state: QueueState| {
+            let sim = &mut Simulation::new(state);
+            for t in [0, 1, 2] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            assert_eq!(Time(30), sim.state.time);
+            sim.log
+                .contents
+                .iter()
+                .filter(|e| {
+                    matches!(
+                        e.event_type,
+                        EventType::CallbackQueued | EventType::Recalled
+                    )
+                })
+                .map(|e| (e.time.0, e.event_type, e.item.unwrap().0))
+                .collect::<Vec<_>>()
+        };
+
+        let full = QueueState::new(1, 1, 10).with_overflow(Overflow::Callback);
+        assert_eq!(
+            vec![
+                (2, EventType::CallbackQueued, 2),
+                (10, EventType::Recalled, 2)
+            ],
+            run(full)
+        );
+
+        let impatient = QueueState::new(10, 1, 10)
+            .with_patience(Deterministic(5.0))
+            .with_callbacks();
+        assert_eq!(
+            vec![
+                (6, EventType::CallbackQueued, 1),
+                (7, EventType::CallbackQueued, 2),
+                (10, EventType::Recalled, 1),
+                (10, EventType::Recalled, 2)
+            ],
+            run(impatient)
+        );
+    }
+}
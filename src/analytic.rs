@@ -0,0 +1,222 @@
+//! Closed-form results for Markovian queues, to check simulations against.
+//!
+//! With Poisson arrivals at rate λ and exponential services at rate μ on c
+//! servers, the offered load is a = λ / μ, and the Erlang formulas give the
+//! long-run behavior exactly. A simulation of the same configuration should
+//! land close to them given a long enough run, which makes them a quick
+//! sanity check on both the model and the simulator.
+
+use crate::stats::SummaryStats;
+
+/// The Erlang B formula: the probability that an arrival finds all `c`
+/// servers busy in a system without a buffer, at offered load `a`.
+pub fn erlang_b(c: u32, a: f64) -> f64 {
+    // The recursion B(k) = a B(k - 1) / (k + a B(k - 1)) is stable where the
+    // direct formula overflows.
+    (1..=c).fold(1.0, |b, k| a * b / (k as f64 + a * b))
+}
+
+/// The Erlang C formula: the probability that an arrival has to wait in a
+/// system with `c` servers and an unlimited buffer, at offered load `a`,
+/// which has to be less than `c`.
+pub fn erlang_c(c: u32, a: f64) -> f64 {
+    let b = erlang_b(c, a);
+    c as f64 * b / (c as f64 - a * (1.0 - b))
+}
+
+/// An analytical metric next to its simulated estimate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    pub metric: &'static str,
+    pub analytic: f64,
+    pub simulated: f64,
+}
+
+impl Comparison {
+    /// The difference relative to the analytical value, or the absolute
+    /// difference if that's 0.
+    pub fn relative_error(&self) -> f64 {
+        let error = (self.simulated - self.analytic).abs();
+        match self.analytic {
+            0.0 => error,
+            analytic => error / analytic.abs(),
+        }
+    }
+}
+
+/// The M/M/c queue, with an unlimited buffer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MMc {
+    pub arrival_rate: f64,
+    pub service_rate: f64,
+    pub servers: u32,
+}
+
+impl MMc {
+    /// Describe an M/M/c queue, or return `None` if it isn't stable, i.e.,
+    /// if the servers can't keep up with arrivals.
+    pub fn new(arrival_rate: f64, service_rate: f64, servers: u32) -> Option<Self> {
+        let queue = Self {
+            arrival_rate,
+            service_rate,
+            servers,
+        };
+        (queue.utilization() < 1.0).then_some(queue)
+    }
+
+    /// The offered load, a.
+    pub fn offered_load(&self) -> f64 {
+        self.arrival_rate / self.service_rate
+    }
+
+    /// The server utilization, ρ = a / c.
+    pub fn utilization(&self) -> f64 {
+        self.offered_load() / self.servers as f64
+    }
+
+    /// The probability that an arrival has to wait.
+    pub fn wait_probability(&self) -> f64 {
+        erlang_c(self.servers, self.offered_load())
+    }
+
+    /// The mean number waiting, Lq.
+    pub fn mean_queue_length(&self) -> f64 {
+        let rho = self.utilization();
+        self.wait_probability() * rho / (1.0 - rho)
+    }
+
+    /// The mean wait, Wq.
+    pub fn mean_wait(&self) -> f64 {
+        self.mean_queue_length() / self.arrival_rate
+    }
+
+    /// The mean sojourn time, W.
+    pub fn mean_sojourn(&self) -> f64 {
+        self.mean_wait() + 1.0 / self.service_rate
+    }
+
+    /// The mean number in the system, L.
+    pub fn mean_number_in_system(&self) -> f64 {
+        self.arrival_rate * self.mean_sojourn()
+    }
+
+    /// Compare the closed-form results with the summary of a simulation of
+    /// the same queue. It's up to the caller to make sure the configurations
+    /// match, with a buffer large enough that nothing is dropped.
+    pub fn compare(&self, summary: &SummaryStats) -> Vec<Comparison> {
+        let mut comparisons = vec![
+            Comparison {
+                metric: "utilization",
+                analytic: self.utilization(),
+                simulated: summary.utilization.unwrap_or(f64::NAN),
+            },
+            Comparison {
+                metric: "mean_queue_length",
+                analytic: self.mean_queue_length(),
+                simulated: summary.mean_queue_length,
+            },
+            Comparison {
+                metric: "mean_number_in_system",
+                analytic: self.mean_number_in_system(),
+                simulated: summary.mean_number_in_system,
+            },
+        ];
+        if let (Some(wait), Some(sojourn)) = (summary.wait, summary.sojourn) {
+            comparisons.push(Comparison {
+                metric: "mean_wait",
+                analytic: self.mean_wait(),
+                simulated: wait.mean,
+            });
+            comparisons.push(Comparison {
+                metric: "mean_sojourn",
+                analytic: self.mean_sojourn(),
+                simulated: sojourn.mean,
+            });
+        }
+        comparisons
+    }
+}
+
+/// The Erlang loss system, M/M/c/c, where arrivals that find every server
+/// busy are lost.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErlangLoss {
+    pub arrival_rate: f64,
+    pub service_rate: f64,
+    pub servers: u32,
+}
+
+impl ErlangLoss {
+    /// Describe an Erlang loss system.
+    pub fn new(arrival_rate: f64, service_rate: f64, servers: u32) -> Self {
+        Self {
+            arrival_rate,
+            service_rate,
+            servers,
+        }
+    }
+
+    /// The probability that an arrival is lost.
+    pub fn blocking_probability(&self) -> f64 {
+        erlang_b(self.servers, self.arrival_rate / self.service_rate)
+    }
+
+    /// The server utilization, i.e., the carried load over the number of
+    /// servers.
+    pub fn utilization(&self) -> f64 {
+        let a = self.arrival_rate / self.service_rate;
+        a * (1.0 - self.blocking_probability()) / self.servers as f64
+    }
+
+    /// Compare the closed-form results with the summary of a simulation of
+    /// the same system, i.e., one with a buffer that's always passed straight
+    /// through to the servers.
+    pub fn compare(&self, summary: &SummaryStats) -> Vec<Comparison> {
+        vec![
+            Comparison {
+                metric: "blocking_probability",
+                analytic: self.blocking_probability(),
+                simulated: summary.flow.loss_probability(),
+            },
+            Comparison {
+                metric: "utilization",
+                analytic: self.utilization(),
+                simulated: summary.utilization.unwrap_or(f64::NAN),
+            },
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrival::PoissonArrivals;
+    use crate::dist::Exponential;
+    use crate::simulation::Simulation;
+    use crate::{QueueState, Time};
+
+    #[test]
+    fn test_erlang_formulas() {
+        // Two servers at an offered load of 1.5.
+        assert!((erlang_b(2, 1.5) - 1.125 / 3.625).abs() < 1e-12);
+        assert!((erlang_c(2, 1.5) - 0.642857).abs() < 1e-6);
+        let queue = MMc::new(0.015, 0.01, 2).unwrap();
+        assert!((queue.mean_queue_length() - 1.928571).abs() < 1e-6);
+        assert!((queue.mean_wait() - 128.5714).abs() < 1e-4);
+        assert_eq!(None, MMc::new(0.02, 0.01, 2));
+    }
+
+    #[test]
+    fn test_mmc_comparison() {
+        // A long run of an M/M/2 queue lands within a few percent of the
+        // closed-form results.
+        let state = QueueState::new(1000, 2, 0).with_service_time(Exponential(0.01), 1);
+        let sim = &mut Simulation::new(state);
+        sim.add_source(PoissonArrivals::new(0.015, 3));
+        sim.run_until(Time(5_000_000));
+        let queue = MMc::new(0.015, 0.01, 2).unwrap();
+        for comparison in queue.compare(&sim.summary()) {
+            assert!(comparison.relative_error() < 0.05, "{:?}", comparison);
+        }
+    }
+}
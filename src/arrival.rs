@@ -0,0 +1,974 @@
+//! Arrival sources generate the times at which items arrive at the queue.
+//! Every arrival model implements the `ArrivalProcess` trait, which is the
+//! single point where the engine asks for the next arrival.
+//!
+//! Arrivals from a source are scheduled lazily: a `Simulation` only ever holds
+//! the next pending arrival from each source, and schedules the one after it
+//! when it fires. This keeps the message queue small no matter how long the
+//! simulation runs.
+
+use std::fmt::Debug;
+
+use crate::dist::Distribution;
+use crate::item::Deadline;
+use crate::rng::Rng;
+use crate::{Event, EventType, QueueState, Time};
+
+/// Identifies an arrival source registered with a `Simulation`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SourceId(pub u32);
+
+/// Attributes of an individual arriving item.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Attributes {
+    /// The arrival stream the item belongs to, if any.
+    pub source: Option<SourceId>,
+    /// The item's priority, where higher values are served first. Without
+    /// one, the item gets the priority of its source (see
+    /// `QueueState::with_priority`), or 0.
+    pub priority: Option<u32>,
+    /// The item's known service requirement, which is served instead of a
+    /// sampled service time.
+    pub work: Option<f64>,
+    /// An estimate of the item's service requirement, which is only used for
+    /// scheduling.
+    pub estimate: Option<f64>,
+    /// The item's deadline. Without one, the item gets the queue's default
+    /// deadline, if any (see `QueueState::with_deadline`).
+    pub deadline: Option<Deadline>,
+    /// An identifier the item keeps as it moves from node to node in a
+    /// network, if any.
+    pub tag: Option<u64>,
+}
+
+/// A model of when items arrive.
+pub trait ArrivalProcess: Debug {
+    /// The time from `now` until the next arrival, or `None` if there are no
+    /// more arrivals. The current queue state is available for models whose
+    /// arrivals depend on it.
+    fn next_interarrival(&mut self, now: Time, state: &QueueState) -> Option<Time>;
+
+    /// Take any events the process generated internally while computing
+    /// interarrival times, e.g., a change of regime. These can be timestamped
+    /// later than the current time, but no later than the next arrival.
+    fn take_events(&mut self) -> Vec<Event> {
+        vec![]
+    }
+
+    /// Called when an item leaves the queue. Models in which departures
+    /// cause new arrivals return the time from `now` until that arrival, which
+    /// is scheduled in addition to the arrival returned by
+    /// `next_interarrival`.
+    ///
+    /// Departures are attributed to the source of the departing item, and a
+    /// departing item without a source is reported to every source.
+    fn on_departure(&mut self, _now: Time, _state: &QueueState) -> Option<Time> {
+        None
+    }
+
+    /// Called when arrivals after `horizon` won't be used, so that models
+    /// that search for the next arrival, e.g., by thinning, can give up at
+    /// the horizon instead of searching on.
+    fn set_horizon(&mut self, _horizon: Time) {}
+
+    /// Shut the process off after it has generated `n` arrivals.
+    fn stop_after(self, n: u32) -> StopArrivals<Self>
+    where
+        Self: Sized,
+    {
+        StopArrivals {
+            inner: self,
+            max_arrivals: Some(n),
+            horizon: None,
+            arrivals: 0,
+        }
+    }
+
+    /// Shut the process off once arrivals would come after `horizon`.
+    fn stop_at(mut self, horizon: Time) -> StopArrivals<Self>
+    where
+        Self: Sized,
+    {
+        self.set_horizon(horizon);
+        StopArrivals {
+            inner: self,
+            max_arrivals: None,
+            horizon: Some(horizon),
+            arrivals: 0,
+        }
+    }
+}
+
+/// An arrival process that shuts off after a fixed number of arrivals or once
+/// the clock passes a horizon, whichever comes first. Once arrivals stop, the
+/// queue drains naturally, which is what finite-horizon experiments need.
+///
+/// This is created with `ArrivalProcess::stop_after` and
+/// `ArrivalProcess::stop_at`, and both limits can be combined, e.g.,
+/// `PoissonArrivals::new(1.0, 0).stop_after(100).stop_at(Time(50))`.
+#[derive(Debug, Clone)]
+pub struct StopArrivals<A> {
+    inner: A,
+    max_arrivals: Option<u32>,
+    horizon: Option<Time>,
+    arrivals: u32,
+}
+
+impl<A> StopArrivals<A> {
+    /// The number of arrivals generated so far.
+    pub fn arrivals(&self) -> u32 {
+        self.arrivals
+    }
+
+    /// Count an arrival at `now + delay`, or return `None` if it's past a
+    /// limit.
+    fn admit(&mut self, now: Time, delay: Time) -> Option<Time> {
+        let over_count = self.max_arrivals.is_some_and(|n| self.arrivals >= n);
+        let over_horizon = self.horizon.is_some_and(|h| Time(now.0 + delay.0) > h);
+        if over_count || over_horizon {
+            None
+        } else {
+            self.arrivals += 1;
+            Some(delay)
+        }
+    }
+}
+
+impl<A: ArrivalProcess> ArrivalProcess for StopArrivals<A> {
+    fn next_interarrival(&mut self, now: Time, state: &QueueState) -> Option<Time> {
+        if self.max_arrivals.is_some_and(|n| self.arrivals >= n) {
+            return None;
+        }
+        let delay = self.inner.next_interarrival(now, state)?;
+        self.admit(now, delay)
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        self.inner.take_events()
+    }
+
+    fn on_departure(&mut self, now: Time, state: &QueueState) -> Option<Time> {
+        let delay = self.inner.on_departure(now, state)?;
+        self.admit(now, delay)
+    }
+
+    fn set_horizon(&mut self, horizon: Time) {
+        let horizon = self.horizon.map_or(horizon, |h| h.min(horizon));
+        self.inner.set_horizon(horizon);
+    }
+}
+
+/// Arrivals at a fixed interval.
+#[derive(Debug, Clone)]
+pub struct DeterministicArrivals {
+    interval: Time,
+}
+
+impl DeterministicArrivals {
+    /// Create an arrival source with a fixed interarrival time.
+    pub fn new(interval: Time) -> Self {
+        Self { interval }
+    }
+}
+
+impl ArrivalProcess for DeterministicArrivals {
+    fn next_interarrival(&mut self, _now: Time, _state: &QueueState) -> Option<Time> {
+        Some(self.interval)
+    }
+}
+
+/// Arrivals replayed from a recorded trace of arrival times.
+#[derive(Debug, Clone)]
+pub struct TraceArrivals {
+    times: Vec<Time>,
+    next: usize,
+}
+
+impl TraceArrivals {
+    /// Create an arrival source from absolute arrival times, which are sorted
+    /// first. Times before the simulation clock when an arrival is requested
+    /// are skipped.
+    pub fn new(mut times: Vec<Time>) -> Self {
+        times.sort();
+        Self { times, next: 0 }
+    }
+}
+
+impl ArrivalProcess for TraceArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        while let Some(&time) = self.times.get(self.next) {
+            self.next += 1;
+            if time >= now {
+                return Some(Time(time.0 - now.0));
+            }
+        }
+        None
+    }
+}
+
+/// Arrivals with independent interarrival times drawn from any distribution,
+/// i.e., a renewal process. For example, an `Empirical` distribution replays
+/// the interarrival times observed in a real system.
+#[derive(Debug)]
+pub struct RenewalArrivals {
+    interarrival: Box<dyn Distribution>,
+    rng: Rng,
+    clock: f64,
+}
+
+impl RenewalArrivals {
+    /// Create a renewal arrival source with the given interarrival time
+    /// distribution.
+    pub fn new(interarrival: impl Distribution + 'static, seed: u64) -> Self {
+        Self {
+            interarrival: Box::new(interarrival),
+            rng: Rng::new(seed),
+            clock: 0.0,
+        }
+    }
+}
+
+impl ArrivalProcess for RenewalArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        if self.clock < now.0 as f64 - 0.5 {
+            self.clock = now.0 as f64;
+        }
+        self.clock += self.interarrival.sample(&mut self.rng).max(0.0);
+        Some(Time(Time::from_f64(self.clock).0.saturating_sub(now.0)))
+    }
+}
+
+/// Poisson arrivals, i.e., exponentially distributed interarrival times with
+/// rate `rate` (the mean interarrival time is `1 / rate`).
+#[derive(Debug, Clone)]
+pub struct PoissonArrivals {
+    rate: f64,
+    rng: Rng,
+    /// The exact, real-valued time of the most recent arrival. Rounding to a
+    /// whole `Time` happens only when reporting, so rounding errors don't
+    /// accumulate and bias the arrival rate.
+    clock: f64,
+}
+
+impl PoissonArrivals {
+    /// Create a Poisson arrival source with the given rate and RNG seed.
+    pub fn new(rate: f64, seed: u64) -> Self {
+        Self {
+            rate,
+            rng: Rng::new(seed),
+            clock: 0.0,
+        }
+    }
+}
+
+impl ArrivalProcess for PoissonArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        // If the source is started late, catch the clock up first.
+        if self.clock < now.0 as f64 - 0.5 {
+            self.clock = now.0 as f64;
+        }
+        self.clock += self.rng.exponential(self.rate);
+        Some(Time(Time::from_f64(self.clock).0.saturating_sub(now.0)))
+    }
+}
+
+/// A time-varying arrival rate.
+#[derive(Debug, Clone)]
+pub enum Rate {
+    /// An arbitrary rate function.
+    Function(fn(Time) -> f64),
+    /// A piecewise-constant rate given as `(start, rate)` pairs sorted by
+    /// start time. Each rate applies until the next start time, and the last
+    /// one applies forever. The rate before the first start time is zero.
+    Piecewise(Vec<(Time, f64)>),
+    /// A base rate scaled by a repeating profile of multipliers, each of
+    /// which applies for `slot` time units. For example, 24 hourly multipliers
+    /// with a `slot` of one hour give a daily cycle, and 168 give a weekly
+    /// one.
+    Periodic {
+        base: f64,
+        slot: Time,
+        multipliers: Vec<f64>,
+    },
+}
+
+impl Rate {
+    /// The rate at the given time.
+    pub fn at(&self, time: Time) -> f64 {
+        match self {
+            Rate::Function(f) => f(time),
+            Rate::Piecewise(table) => table
+                .iter()
+                .take_while(|(start, _)| *start <= time)
+                .last()
+                .map_or(0.0, |&(_, rate)| rate),
+            Rate::Periodic {
+                base,
+                slot,
+                multipliers,
+            } => {
+                let i = (time.0 / slot.0.max(1)) as usize % multipliers.len();
+                base * multipliers[i]
+            }
+        }
+    }
+}
+
+/// Non-homogeneous Poisson arrivals, i.e., Poisson arrivals whose rate varies
+/// with time.
+///
+/// Arrivals are generated by _thinning_: candidate arrivals are drawn from a
+/// homogeneous Poisson process at the maximum rate, and each candidate at time
+/// `t` is kept with probability `rate(t) / max_rate`. Thinning gives up,
+/// and the source shuts off, once candidates pass the horizon (see
+/// `ArrivalProcess::stop_at`), or the end of the clock, so a rate that drops
+/// to zero for good doesn't keep it searching forever.
+#[derive(Debug, Clone)]
+pub struct NonHomogeneousPoissonArrivals {
+    rate: Rate,
+    max_rate: f64,
+    rng: Rng,
+    clock: f64,
+    horizon: Time,
+}
+
+impl NonHomogeneousPoissonArrivals {
+    /// Create an arrival source from a rate function. `max_rate` must bound
+    /// the rate function from above, or arrivals will be too sparse where the
+    /// bound is exceeded.
+    pub fn new(rate: fn(Time) -> f64, max_rate: f64, seed: u64) -> Self {
+        Self::with_rate(Rate::Function(rate), max_rate, seed)
+    }
+
+    /// Create an arrival source from a piecewise-constant rate table of
+    /// `(start, rate)` pairs.
+    pub fn piecewise(mut table: Vec<(Time, f64)>, seed: u64) -> Self {
+        table.sort_by_key(|&(start, _)| start);
+        let max_rate = table.iter().map(|&(_, rate)| rate).fold(0.0, f64::max);
+        Self::with_rate(Rate::Piecewise(table), max_rate, seed)
+    }
+
+    /// Create an arrival source whose rate is `base` times a repeating
+    /// profile of multipliers, each lasting `slot` time units.
+    pub fn periodic(base: f64, slot: Time, multipliers: Vec<f64>, seed: u64) -> Self {
+        assert!(
+            !multipliers.is_empty(),
+            "a periodic profile needs multipliers"
+        );
+        let max_rate = base * multipliers.iter().copied().fold(0.0, f64::max);
+        let rate = Rate::Periodic {
+            base,
+            slot,
+            multipliers,
+        };
+        Self::with_rate(rate, max_rate, seed)
+    }
+
+    fn with_rate(rate: Rate, max_rate: f64, seed: u64) -> Self {
+        Self {
+            rate,
+            max_rate,
+            rng: Rng::new(seed),
+            clock: 0.0,
+            horizon: Time(u32::MAX),
+        }
+    }
+
+    /// Check whether the rate is zero from the given time on, in which case
+    /// thinning would never accept another candidate.
+    fn is_exhausted(&self, time: f64) -> bool {
+        match &self.rate {
+            Rate::Function(_) | Rate::Periodic { .. } => self.max_rate <= 0.0,
+            // Each rate applies until the next start time, so only the pieces
+            // that end after `time` still matter.
+            Rate::Piecewise(table) => table
+                .iter()
+                .zip(
+                    table
+                        .iter()
+                        .skip(1)
+                        .map(|(start, _)| start.0 as f64)
+                        .chain([f64::INFINITY]),
+                )
+                .filter(|&(_, end)| end > time)
+                .all(|(&(_, rate), _)| rate <= 0.0),
+        }
+    }
+}
+
+impl ArrivalProcess for NonHomogeneousPoissonArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        if self.clock < now.0 as f64 - 0.5 {
+            self.clock = now.0 as f64;
+        }
+        loop {
+            if self.is_exhausted(self.clock) {
+                return None;
+            }
+            self.clock += self.rng.exponential(self.max_rate);
+            // Candidates past the horizon round to later times.
+            if self.clock >= self.horizon.0 as f64 + 0.5 {
+                return None;
+            }
+            let rate = self.rate.at(Time::from_f64(self.clock));
+            if self.rng.uniform() * self.max_rate < rate {
+                return Some(Time(Time::from_f64(self.clock).0.saturating_sub(now.0)));
+            }
+        }
+    }
+
+    fn set_horizon(&mut self, horizon: Time) {
+        self.horizon = self.horizon.min(horizon);
+    }
+}
+
+/// Markov-modulated Poisson arrivals.
+///
+/// An underlying continuous-time Markov chain switches between _regimes_, and
+/// arrivals in each regime are Poisson with that regime's rate. This is a
+/// common model of bursty traffic. Every regime switch is reported as an
+/// `EventType::RegimeChanged` event.
+#[derive(Debug, Clone)]
+pub struct MarkovModulatedArrivals {
+    rates: Vec<f64>,
+    transitions: Vec<Vec<f64>>,
+    regime: usize,
+    rng: Rng,
+    clock: f64,
+    events: Vec<Event>,
+}
+
+impl MarkovModulatedArrivals {
+    /// Create an arrival source that starts in regime 0.
+    ///
+    /// `rates[i]` is the arrival rate in regime `i`, and `transitions[i][j]`
+    /// is the rate of switching from regime `i` to regime `j`. The diagonal of
+    /// `transitions` is ignored.
+    pub fn new(rates: Vec<f64>, transitions: Vec<Vec<f64>>, seed: u64) -> Self {
+        assert!(
+            transitions.len() == rates.len() && transitions.iter().all(|r| r.len() == rates.len()),
+            "transitions must be a square matrix with one row per regime"
+        );
+        Self {
+            rates,
+            transitions,
+            regime: 0,
+            rng: Rng::new(seed),
+            clock: 0.0,
+            events: vec![],
+        }
+    }
+
+    /// The current regime.
+    pub fn regime(&self) -> usize {
+        self.regime
+    }
+
+    /// The total rate of leaving the current regime.
+    fn leave_rate(&self) -> f64 {
+        self.transitions[self.regime]
+            .iter()
+            .enumerate()
+            .filter(|&(j, _)| j != self.regime)
+            .map(|(_, rate)| rate)
+            .sum()
+    }
+}
+
+impl ArrivalProcess for MarkovModulatedArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        if self.clock < now.0 as f64 - 0.5 {
+            self.clock = now.0 as f64;
+        }
+        loop {
+            // Race the next arrival against the next regime switch.
+            let arrival_rate = self.rates[self.regime];
+            let leave_rate = self.leave_rate();
+            let total = arrival_rate + leave_rate;
+            if total <= 0.0 {
+                // An absorbing regime without arrivals.
+                return None;
+            }
+            self.clock += self.rng.exponential(total);
+            if self.rng.uniform() * total < arrival_rate {
+                return Some(Time(Time::from_f64(self.clock).0.saturating_sub(now.0)));
+            }
+
+            // Pick the next regime in proportion to the switching rates.
+            // Falling through to the last candidate guards against rounding.
+            let row = &self.transitions[self.regime];
+            let mut target = self.rng.uniform() * leave_rate;
+            let mut next = self.regime;
+            for (j, &rate) in row.iter().enumerate() {
+                if j == self.regime || rate <= 0.0 {
+                    continue;
+                }
+                next = j;
+                target -= rate;
+                if target < 0.0 {
+                    break;
+                }
+            }
+            self.regime = next;
+            self.events.push(Event {
+                time: Time::from_f64(self.clock),
+                event_type: EventType::RegimeChanged(next as u32),
+                source: None,
+                server: None,
+                item: None,
+                metadata: None,
+            });
+        }
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Bursty arrivals that alternate between ON periods, with Poisson arrivals at
+/// rate `rate`, and OFF periods without arrivals.
+///
+/// The source starts in an ON period. The start of every period is reported
+/// as an `EventType::SourceOn` or `EventType::SourceOff` event.
+#[derive(Debug)]
+pub struct OnOffArrivals {
+    rate: f64,
+    on_duration: Box<dyn Distribution>,
+    off_duration: Box<dyn Distribution>,
+    rng: Rng,
+    clock: f64,
+    on: bool,
+    /// When the current period ends, or `None` before the first period.
+    period_end: Option<f64>,
+    events: Vec<Event>,
+}
+
+impl OnOffArrivals {
+    /// Create an on-off source with the given arrival rate during ON periods
+    /// and the given period duration distributions.
+    pub fn new(
+        rate: f64,
+        on_duration: impl Distribution + 'static,
+        off_duration: impl Distribution + 'static,
+        seed: u64,
+    ) -> Self {
+        Self {
+            rate,
+            on_duration: Box::new(on_duration),
+            off_duration: Box::new(off_duration),
+            rng: Rng::new(seed),
+            clock: 0.0,
+            on: true,
+            period_end: None,
+            events: vec![],
+        }
+    }
+
+    /// Start a new ON or OFF period at the current clock.
+    fn start_period(&mut self, on: bool) -> f64 {
+        self.on = on;
+        let (duration, event_type) = if on {
+            (self.on_duration.sample(&mut self.rng), EventType::SourceOn)
+        } else {
+            (
+                self.off_duration.sample(&mut self.rng),
+                EventType::SourceOff,
+            )
+        };
+        self.events.push(Event {
+            time: Time::from_f64(self.clock),
+            event_type,
+            source: None,
+            server: None,
+            item: None,
+            metadata: None,
+        });
+        self.clock + duration
+    }
+}
+
+impl ArrivalProcess for OnOffArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        if self.clock < now.0 as f64 - 0.5 {
+            self.clock = now.0 as f64;
+        }
+        let mut period_end = match self.period_end {
+            Some(end) => end,
+            None => self.start_period(true),
+        };
+        loop {
+            if self.on {
+                // Arrivals are memoryless, so a candidate that falls past the
+                // end of the period can simply be discarded.
+                let candidate = self.clock + self.rng.exponential(self.rate);
+                if candidate < period_end {
+                    self.clock = candidate;
+                    self.period_end = Some(period_end);
+                    return Some(Time(Time::from_f64(self.clock).0.saturating_sub(now.0)));
+                }
+            }
+            self.clock = period_end;
+            period_end = self.start_period(!self.on);
+        }
+    }
+
+    fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+/// Self-exciting (Hawkes) arrivals, for clustered demand.
+///
+/// The arrival intensity at time `t` is
+///
+/// ```text
+/// baseline + sum(excitation * exp(-decay * (t - t_i)))
+/// ```
+///
+/// where the sum is over all earlier arrival times `t_i`, so every arrival
+/// temporarily raises the chance of more arrivals. The process is stable when
+/// `excitation / decay < 1`, in which case the long-run arrival rate is
+/// `baseline / (1 - excitation / decay)`.
+///
+/// Arrivals are generated with Ogata's thinning method. The intensity only
+/// decays between arrivals, so the intensity at the current time bounds it
+/// until the next arrival.
+#[derive(Debug, Clone)]
+pub struct HawkesArrivals {
+    baseline: f64,
+    excitation: f64,
+    decay: f64,
+    rng: Rng,
+    clock: f64,
+    /// The excited part of the intensity at `clock`.
+    excited: f64,
+}
+
+impl HawkesArrivals {
+    /// Create a Hawkes arrival source with the given baseline rate,
+    /// excitation magnitude, and exponential decay rate.
+    pub fn new(baseline: f64, excitation: f64, decay: f64, seed: u64) -> Self {
+        Self {
+            baseline,
+            excitation,
+            decay,
+            rng: Rng::new(seed),
+            clock: 0.0,
+            excited: 0.0,
+        }
+    }
+
+    /// The arrival intensity at the source's clock.
+    pub fn intensity(&self) -> f64 {
+        self.baseline + self.excited
+    }
+}
+
+impl ArrivalProcess for HawkesArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        if self.clock < now.0 as f64 - 0.5 {
+            self.excited *= (-self.decay * (now.0 as f64 - self.clock)).exp();
+            self.clock = now.0 as f64;
+        }
+        loop {
+            let bound = self.intensity();
+            if bound <= 0.0 {
+                return None;
+            }
+            let wait = self.rng.exponential(bound);
+            self.clock += wait;
+            self.excited *= (-self.decay * wait).exp();
+            if self.rng.uniform() * bound < self.intensity() {
+                self.excited += self.excitation;
+                return Some(Time(Time::from_f64(self.clock).0.saturating_sub(now.0)));
+            }
+        }
+    }
+}
+
+/// Arrivals from a closed, finite population of customers, as in the
+/// machine-repair model.
+///
+/// Each customer starts out _thinking_ (or, for machines, working) for a
+/// sampled think time, then arrives at the queue. After leaving the queue, it
+/// thinks again before its next arrival. At most `population` customers are
+/// ever in the queue at once.
+///
+/// Note: The buffer should be able to hold the whole population, because
+/// customers that are discarded never come back.
+#[derive(Debug)]
+pub struct FinitePopulationArrivals {
+    population: u32,
+    think_time: Box<dyn Distribution>,
+    rng: Rng,
+    /// Arrival times of customers that haven't arrived for the first time,
+    /// latest first. `None` until the first arrival is requested.
+    first_arrivals: Option<Vec<Time>>,
+}
+
+impl FinitePopulationArrivals {
+    /// Create a finite population of customers with the given think time
+    /// distribution.
+    pub fn new(population: u32, think_time: impl Distribution + 'static, seed: u64) -> Self {
+        Self {
+            population,
+            think_time: Box::new(think_time),
+            rng: Rng::new(seed),
+            first_arrivals: None,
+        }
+    }
+
+    /// Sample a think time.
+    fn think(&mut self) -> Time {
+        Time::from_f64(self.think_time.sample(&mut self.rng))
+    }
+}
+
+impl ArrivalProcess for FinitePopulationArrivals {
+    fn next_interarrival(&mut self, now: Time, _state: &QueueState) -> Option<Time> {
+        // Only the first arrival of each customer comes through here, and
+        // these are handed out in time order. All later arrivals come from
+        // `on_departure`.
+        if self.first_arrivals.is_none() {
+            let mut times = (0..self.population)
+                .map(|_| Time(now.0 + self.think().0))
+                .collect::<Vec<_>>();
+            times.sort_by_key(|&t| std::cmp::Reverse(t));
+            self.first_arrivals = Some(times);
+        }
+        let time = self.first_arrivals.as_mut()?.pop()?;
+        Some(Time(time.0.saturating_sub(now.0)))
+    }
+
+    fn on_departure(&mut self, _now: Time, _state: &QueueState) -> Option<Time> {
+        Some(self.think())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poisson_rate() {
+        // Over a long horizon, the number of arrivals per unit time should be
+        // close to the rate, despite rounding to whole time units.
+        let state = &QueueState::new(1, 1, 1);
+        let arrivals = &mut PoissonArrivals::new(0.3, 11);
+        let mut now = Time(0);
+        let mut n = 0;
+        while now < Time(100_000) {
+            now = Time(now.0 + arrivals.next_interarrival(now, state).unwrap().0);
+            n += 1;
+        }
+        let rate = n as f64 / now.0 as f64;
+        assert!((rate - 0.3).abs() < 0.01, "rate = {}", rate);
+    }
+
+    #[test]
+    fn test_trace_arrivals() {
+        let state = &QueueState::new(1, 1, 1);
+        let arrivals = &mut TraceArrivals::new(vec![Time(7), Time(2), Time(4)]);
+        assert_eq!(Some(Time(2)), arrivals.next_interarrival(Time(0), state));
+        assert_eq!(Some(Time(2)), arrivals.next_interarrival(Time(2), state));
+        assert_eq!(Some(Time(3)), arrivals.next_interarrival(Time(4), state));
+        assert_eq!(None, arrivals.next_interarrival(Time(7), state));
+    }
+
+    #[test]
+    fn test_piecewise_rate_thinning() {
+        // A busy first half followed by a quiet second half, after which the
+        // source shuts off.
+        let state = &QueueState::new(1, 1, 1);
+        let table = vec![(Time(0), 1.0), (Time(5000), 0.1), (Time(10_000), 0.0)];
+        let arrivals = &mut NonHomogeneousPoissonArrivals::piecewise(table, 5);
+        let mut now = Time(0);
+        let (mut busy, mut quiet) = (0, 0);
+        while let Some(dt) = arrivals.next_interarrival(now, state) {
+            now = Time(now.0 + dt.0);
+            if now < Time(5000) {
+                busy += 1;
+            } else {
+                quiet += 1;
+            }
+        }
+        assert!(now <= Time(10_000));
+        assert!((busy as f64 / 5000.0 - 1.0).abs() < 0.05, "busy = {}", busy);
+        assert!(
+            (quiet as f64 / 5000.0 - 0.1).abs() < 0.02,
+            "quiet = {}",
+            quiet
+        );
+    }
+
+    #[test]
+    fn test_thinning_stops_at_the_horizon() {
+        // The rate drops to 0 for good at 100, which the bound of 1 can't
+        // tell, so thinning only stops at the horizon.
+        let state = &QueueState::new(1, 1, 1);
+        let rate = |t: Time| if t < Time(100) { 1.0 } else { 0.0 };
+        let arrivals = &mut NonHomogeneousPoissonArrivals::new(rate, 1.0, 3).stop_at(Time(200));
+        let mut now = Time(0);
+        while let Some(dt) = arrivals.next_interarrival(now, state) {
+            now = Time(now.0 + dt.0);
+        }
+        assert!(now < Time(100));
+        assert!(arrivals.arrivals() > 50);
+        assert_eq!(None, arrivals.next_interarrival(now, state));
+    }
+
+    #[test]
+    fn test_mmpp_regime_changes_are_logged() {
+        // Regime 1 has no arrivals, so every arrival has to happen in regime
+        // 0, and the log has to show the switches in time order.
+        let sim = &mut crate::simulation::Simulation::new(QueueState::new(1000, 1000, 1));
+        let arrivals =
+            MarkovModulatedArrivals::new(vec![2.0, 0.0], vec![vec![0.0, 0.1], vec![0.1, 0.0]], 9);
+        sim.add_source(arrivals);
+        sim.run_until(Time(1000));
+
+        let log = &sim.log.contents;
+        assert!(log.windows(2).all(|w| w[0].time <= w[1].time));
+        let mut regime = 0;
+        for e in log {
+            match e.event_type {
+                EventType::RegimeChanged(r) => regime = r,
+                EventType::BufferIncremented => assert_eq!(0, regime),
+                _ => {}
+            }
+        }
+        let switches = log
+            .iter()
+            .filter(|e| matches!(e.event_type, EventType::RegimeChanged(_)))
+            .count();
+        assert!(switches > 50, "switches = {}", switches);
+    }
+
+    #[test]
+    fn test_finite_population_never_exceeds_population() {
+        // Three machines that break down and are repaired by one repairer.
+        let sim = &mut crate::simulation::Simulation::new(QueueState::new(10, 1, 4));
+        let think_time = crate::dist::Exponential(0.1);
+        sim.add_source(FinitePopulationArrivals::new(3, think_time, 4));
+
+        let mut max_in_system = 0;
+        while let Some(state) = sim.step_until(Time(10_000)) {
+            max_in_system = max_in_system.max(state.buffer_count + state.server_count);
+        }
+        assert_eq!(3, max_in_system);
+
+        // Customers keep coming back, well beyond the population size.
+        let arrivals = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::BufferIncremented)
+            .count();
+        assert!(arrivals > 1000, "arrivals = {}", arrivals);
+    }
+
+    #[test]
+    fn test_on_off_arrivals_only_while_on() {
+        let sim = &mut crate::simulation::Simulation::new(QueueState::new(1000, 1000, 1));
+        let on = crate::dist::Deterministic(20.0);
+        let off = crate::dist::Deterministic(80.0);
+        sim.add_source(OnOffArrivals::new(1.0, on, off, 6));
+        sim.run_until(Time(990));
+
+        let mut on = false;
+        let mut periods = 0;
+        for e in &sim.log.contents {
+            match e.event_type {
+                EventType::SourceOn => {
+                    on = true;
+                    periods += 1;
+                    assert_eq!(0, e.time.0 % 100);
+                }
+                EventType::SourceOff => on = false,
+                EventType::BufferIncremented => assert!(on),
+                _ => {}
+            }
+        }
+        assert_eq!(10, periods);
+    }
+
+    #[test]
+    fn test_hawkes_long_run_rate() {
+        // With excitation / decay = 1/2, the rate is twice the baseline.
+        let state = &QueueState::new(1, 1, 1);
+        let arrivals = &mut HawkesArrivals::new(0.5, 0.05, 0.1, 8);
+        let mut now = Time(0);
+        let mut n = 0;
+        while now < Time(200_000) {
+            now = Time(now.0 + arrivals.next_interarrival(now, state).unwrap().0);
+            n += 1;
+        }
+        let rate = n as f64 / now.0 as f64;
+        assert!((rate - 1.0).abs() < 0.05, "rate = {}", rate);
+    }
+
+    #[test]
+    fn test_stop_arrivals() {
+        // Both limits let `run` finish with an empty queue.
+        let sim = &mut crate::simulation::Simulation::new(QueueState::new(100, 1, 2));
+        sim.add_source(PoissonArrivals::new(1.0, 3).stop_after(10));
+        sim.run();
+        let arrivals = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::BufferIncremented)
+            .collect::<Vec<_>>();
+        assert_eq!(10, arrivals.len());
+        assert_eq!(0, sim.state.buffer_count + sim.state.server_count);
+
+        let sim = &mut crate::simulation::Simulation::new(QueueState::new(100, 1, 2));
+        sim.add_source(
+            PoissonArrivals::new(1.0, 3)
+                .stop_after(1000)
+                .stop_at(Time(20)),
+        );
+        sim.run();
+        let last_arrival = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::BufferIncremented)
+            .map(|e| e.time)
+            .max();
+        assert!(last_arrival <= Some(Time(20)));
+        assert!(sim.state.time > Time(20));
+        assert_eq!(0, sim.state.buffer_count + sim.state.server_count);
+    }
+
+    #[test]
+    fn test_renewal_arrivals_from_observed_data() {
+        let state = &QueueState::new(1, 1, 1);
+        let observed = crate::dist::Empirical::new(&[2.0, 4.0]);
+        let arrivals = &mut RenewalArrivals::new(observed, 1);
+        let mut now = Time(0);
+        for _ in 0..100 {
+            let dt = arrivals.next_interarrival(now, state).unwrap();
+            assert!(dt == Time(2) || dt == Time(4));
+            now = Time(now.0 + dt.0);
+        }
+    }
+
+    #[test]
+    fn test_periodic_profile() {
+        // A two-slot day: busy for the first 10 time units, idle for the next.
+        let state = &QueueState::new(1, 1, 1);
+        let arrivals =
+            &mut NonHomogeneousPoissonArrivals::periodic(2.0, Time(10), vec![1.0, 0.0], 4);
+        let mut now = Time(0);
+        let mut n = 0;
+        while now < Time(10_000) {
+            now = Time(now.0 + arrivals.next_interarrival(now, state).unwrap().0);
+            // Rounding can push an arrival just past the end of a busy slot.
+            assert!(now.0 % 20 <= 10, "arrival at {:?}", now);
+            n += 1;
+        }
+        assert!((n as f64 / 10_000.0 - 1.0).abs() < 0.05, "n = {}", n);
+    }
+}
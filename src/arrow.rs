@@ -0,0 +1,639 @@
+//! Arrow record batches of event logs.
+//!
+//! With the `arrow` feature, `EventLog::to_arrow` lays out a log as Arrow
+//! record batches, with the columns of `EventColumns`, and `write_stream`
+//! writes them in the Arrow IPC stream format, which Python, Polars, and
+//! DataFusion read directly, e.g., with `pyarrow.ipc.open_stream` or
+//! `polars.read_ipc_stream`, without parsing CSV.
+//!
+//! The crate doesn't depend on the `arrow` crate, so the batches here are
+//! plain buffers in Arrow's memory layout, and the IPC metadata is encoded
+//! by a small FlatBuffers builder of its own. Only what event logs need is
+//! supported: unsigned integers, doubles, and strings, without compression.
+
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::columns::EventColumns;
+use crate::EventLog;
+
+/// The most rows in a record batch.
+pub const BATCH_ROWS: usize = 64 * 1024;
+
+/// The types of Arrow arrays in event logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    UInt32,
+    UInt64,
+    Float64,
+    Utf8,
+}
+
+/// A column of a record batch: its name, type, and whether it has nulls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub data_type: DataType,
+    pub nullable: bool,
+}
+
+/// An Arrow array, as its buffers in Arrow's memory layout.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Array {
+    pub len: usize,
+    pub null_count: usize,
+    /// A bit per value, least significant first, set if the value isn't
+    /// null, or nothing if no value is.
+    pub validity: Vec<u8>,
+    /// For strings, where each value starts in `values`, and where the last
+    /// one ends.
+    pub offsets: Vec<i32>,
+    /// The values, little-endian, with zeros in place of nulls.
+    pub values: Vec<u8>,
+}
+
+impl Array {
+    /// An array of values that may be null.
+    fn new<T: Copy>(values: &[Option<T>], width: usize, bytes: impl Fn(T) -> Vec<u8>) -> Self {
+        let mut array = Self {
+            len: values.len(),
+            ..Self::default()
+        };
+        let mut validity = vec![0u8; values.len().div_ceil(8)];
+        for (i, value) in values.iter().enumerate() {
+            match value {
+                Some(value) => {
+                    validity[i / 8] |= 1 << (i % 8);
+                    array.values.extend(bytes(*value));
+                }
+                None => {
+                    array.null_count += 1;
+                    array.values.extend(std::iter::repeat_n(0, width));
+                }
+            }
+        }
+        if array.null_count > 0 {
+            array.validity = validity;
+        }
+        array
+    }
+
+    /// An array of strings.
+    fn strings(values: &[String]) -> Self {
+        let mut array = Self {
+            len: values.len(),
+            offsets: vec![0],
+            ..Self::default()
+        };
+        for value in values {
+            array.values.extend(value.as_bytes());
+            array.offsets.push(array.values.len() as i32);
+        }
+        array
+    }
+
+    /// The buffers of the array in the order of the IPC format: validity,
+    /// then offsets, if any, then values.
+    fn buffers(&self) -> Vec<Vec<u8>> {
+        let mut buffers = vec![self.validity.clone()];
+        if !self.offsets.is_empty() {
+            buffers.push(self.offsets.iter().flat_map(|o| o.to_le_bytes()).collect());
+        }
+        buffers.push(self.values.clone());
+        buffers
+    }
+}
+
+/// Rows of a table in Arrow's columnar layout.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RecordBatch {
+    pub fields: Vec<Field>,
+    pub columns: Vec<Array>,
+}
+
+impl RecordBatch {
+    /// The number of rows.
+    pub fn num_rows(&self) -> usize {
+        self.columns.first().map_or(0, |column| column.len)
+    }
+}
+
+/// The fields of the event columns.
+fn event_fields() -> Vec<Field> {
+    use DataType::*;
+    [
+        ("time", UInt32, false),
+        ("event_type", Utf8, false),
+        ("data", UInt32, true),
+        ("source", UInt32, true),
+        ("server", UInt32, true),
+        ("item", UInt64, true),
+        ("priority", UInt32, true),
+        ("work", Float64, true),
+        ("pass", UInt32, true),
+        ("tag", UInt64, true),
+    ]
+    .into_iter()
+    .map(|(name, data_type, nullable)| Field {
+        name,
+        data_type,
+        nullable,
+    })
+    .collect()
+}
+
+/// A range of rows of the event columns as a record batch.
+fn event_batch(columns: &EventColumns, rows: Range<usize>) -> RecordBatch {
+    let u32s = |values: &[Option<u32>]| Array::new(values, 4, |v: u32| v.to_le_bytes().to_vec());
+    let u64s = |values: &[Option<u64>]| Array::new(values, 8, |v: u64| v.to_le_bytes().to_vec());
+    let time = columns.time[rows.clone()].iter().map(|&t| Some(t));
+    let work = &columns.work[rows.clone()];
+    RecordBatch {
+        fields: event_fields(),
+        columns: vec![
+            u32s(&time.collect::<Vec<_>>()),
+            Array::strings(&columns.event_type[rows.clone()]),
+            u32s(&columns.data[rows.clone()]),
+            u32s(&columns.source[rows.clone()]),
+            u32s(&columns.server[rows.clone()]),
+            u64s(&columns.item[rows.clone()]),
+            u32s(&columns.priority[rows.clone()]),
+            Array::new(work, 8, |v: f64| v.to_le_bytes().to_vec()),
+            u32s(&columns.pass[rows.clone()]),
+            u64s(&columns.tag[rows]),
+        ],
+    }
+}
+
+/// Builds a FlatBuffer back to front, the way FlatBuffers does, so that
+/// everything an object refers to comes after it. Objects are referred to by
+/// their distance from the end of the buffer.
+#[derive(Debug, Default)]
+struct Builder {
+    /// The buffer so far, which is the tail of the finished buffer.
+    bytes: Vec<u8>,
+    /// The distances of the fields of the table being built, by field ID.
+    fields: Vec<(u16, usize)>,
+    /// The distance at which the table being built started.
+    table_start: usize,
+}
+
+impl Builder {
+    /// Pad so that `size` bytes written next end on a multiple of `align`.
+    fn align(&mut self, align: usize, size: usize) {
+        let padding = (align - (self.bytes.len() + size) % align) % align;
+        self.prepend(&vec![0; padding]);
+    }
+
+    fn prepend(&mut self, bytes: &[u8]) {
+        self.bytes.splice(0..0, bytes.iter().copied());
+    }
+
+    /// Write a little-endian scalar, aligned to its size.
+    fn scalar(&mut self, bytes: &[u8]) -> usize {
+        self.align(bytes.len(), bytes.len());
+        self.prepend(bytes);
+        self.bytes.len()
+    }
+
+    /// Write an offset to an object.
+    fn offset(&mut self, target: usize) -> usize {
+        self.align(4, 4);
+        let offset = (self.bytes.len() + 4 - target) as u32;
+        self.prepend(&offset.to_le_bytes());
+        self.bytes.len()
+    }
+
+    fn string(&mut self, s: &str) -> usize {
+        self.align(4, s.len() + 1);
+        self.prepend(&[0]);
+        self.prepend(s.as_bytes());
+        self.prepend(&(s.len() as u32).to_le_bytes());
+        self.bytes.len()
+    }
+
+    /// A vector of objects.
+    fn offsets(&mut self, targets: &[usize]) -> usize {
+        self.align(4, 4 * targets.len());
+        for &target in targets.iter().rev() {
+            self.offset(target);
+        }
+        self.prepend(&(targets.len() as u32).to_le_bytes());
+        self.bytes.len()
+    }
+
+    /// A vector of structs of 8-byte fields, given as their bytes.
+    fn structs(&mut self, count: usize, bytes: &[u8]) -> usize {
+        self.align(4, bytes.len());
+        self.align(8, bytes.len());
+        self.prepend(bytes);
+        self.prepend(&(count as u32).to_le_bytes());
+        self.bytes.len()
+    }
+
+    fn start_table(&mut self) {
+        self.fields.clear();
+        self.table_start = self.bytes.len();
+    }
+
+    /// Add a scalar field to the table being built.
+    fn field(&mut self, id: u16, bytes: &[u8]) {
+        let at = self.scalar(bytes);
+        self.fields.push((id, at));
+    }
+
+    /// Add a field referring to an object to the table being built.
+    fn field_offset(&mut self, id: u16, target: usize) {
+        let at = self.offset(target);
+        self.fields.push((id, at));
+    }
+
+    /// Finish the table being built, writing its vtable just before it.
+    fn end_table(&mut self) -> usize {
+        self.align(4, 4);
+        self.prepend(&[0; 4]);
+        let table = self.bytes.len();
+        let slots = self.fields.iter().map(|&(id, _)| id + 1).max().unwrap_or(0);
+        let mut vtable = vec![0u16; slots as usize];
+        for &(id, at) in &self.fields {
+            vtable[id as usize] = (table - at) as u16;
+        }
+        for &slot in vtable.iter().rev() {
+            self.prepend(&slot.to_le_bytes());
+        }
+        self.prepend(&((table - self.table_start) as u16).to_le_bytes());
+        self.prepend(&(4 + 2 * slots).to_le_bytes());
+        // The table starts with the distance back to its vtable.
+        let vtable_distance = (self.bytes.len() - table) as i32;
+        let at = self.bytes.len() - table;
+        self.bytes[at..at + 4].copy_from_slice(&vtable_distance.to_le_bytes());
+        table
+    }
+
+    /// Finish the buffer with the given root table.
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        self.align(8, 4);
+        self.offset(root);
+        self.bytes
+    }
+}
+
+/// The IDs of message headers and types in the Arrow schema.
+const SCHEMA: u8 = 1;
+const RECORD_BATCH: u8 = 3;
+const INT: u8 = 2;
+const FLOATING_POINT: u8 = 3;
+const UTF8: u8 = 5;
+/// Version 5 of the IPC metadata.
+const V5: i16 = 4;
+
+/// A message with the given header and body length.
+fn message(mut builder: Builder, header_type: u8, header: usize, body: usize) -> Vec<u8> {
+    builder.start_table();
+    builder.field(3, &(body as i64).to_le_bytes());
+    builder.field_offset(2, header);
+    builder.field(0, &V5.to_le_bytes());
+    builder.field(1, &[header_type]);
+    let message = builder.end_table();
+    builder.finish(message)
+}
+
+/// The message of a schema.
+fn schema_message(fields: &[Field]) -> Vec<u8> {
+    let mut builder = Builder::default();
+    let mut offsets = vec![];
+    for field in fields {
+        let name = builder.string(field.name);
+        let children = builder.offsets(&[]);
+        builder.start_table();
+        let type_type = match field.data_type {
+            DataType::UInt32 | DataType::UInt64 => {
+                let bits: i32 = match field.data_type {
+                    DataType::UInt32 => 32,
+                    _ => 64,
+                };
+                builder.field(0, &bits.to_le_bytes());
+                builder.field(1, &[0]);
+                INT
+            }
+            DataType::Float64 => {
+                // Double precision.
+                builder.field(0, &2i16.to_le_bytes());
+                FLOATING_POINT
+            }
+            DataType::Utf8 => UTF8,
+        };
+        let data_type = builder.end_table();
+        builder.start_table();
+        builder.field_offset(0, name);
+        builder.field_offset(3, data_type);
+        builder.field_offset(5, children);
+        builder.field(1, &[field.nullable as u8]);
+        builder.field(2, &[type_type]);
+        offsets.push(builder.end_table());
+    }
+    let fields = builder.offsets(&offsets);
+    builder.start_table();
+    builder.field_offset(1, fields);
+    // Little-endian.
+    builder.field(0, &0i16.to_le_bytes());
+    let schema = builder.end_table();
+    message(builder, SCHEMA, schema, 0)
+}
+
+/// The message and body of a record batch.
+fn batch_message(batch: &RecordBatch) -> (Vec<u8>, Vec<u8>) {
+    let mut body = vec![];
+    let mut buffers = vec![];
+    let mut nodes = vec![];
+    for column in &batch.columns {
+        nodes.extend((column.len as i64).to_le_bytes());
+        nodes.extend((column.null_count as i64).to_le_bytes());
+        for buffer in column.buffers() {
+            buffers.extend((body.len() as i64).to_le_bytes());
+            buffers.extend((buffer.len() as i64).to_le_bytes());
+            body.extend(&buffer);
+            body.resize(body.len().next_multiple_of(8), 0);
+        }
+    }
+    let mut builder = Builder::default();
+    let buffer_count = buffers.len() / 16;
+    let buffers = builder.structs(buffer_count, &buffers);
+    let nodes = builder.structs(batch.columns.len(), &nodes);
+    builder.start_table();
+    builder.field(0, &(batch.num_rows() as i64).to_le_bytes());
+    builder.field_offset(1, nodes);
+    builder.field_offset(2, buffers);
+    let header = builder.end_table();
+    (message(builder, RECORD_BATCH, header, body.len()), body)
+}
+
+/// Write a message framed as in the IPC format: a continuation marker, the
+/// length of the metadata padded to 8 bytes, the metadata, and the body.
+fn write_message(writer: &mut impl Write, metadata: &[u8], body: &[u8]) -> io::Result<()> {
+    let padded = metadata.len().next_multiple_of(8);
+    writer.write_all(&u32::MAX.to_le_bytes())?;
+    writer.write_all(&(padded as i32).to_le_bytes())?;
+    writer.write_all(metadata)?;
+    writer.write_all(&vec![0; padded - metadata.len()])?;
+    writer.write_all(body)
+}
+
+/// Write record batches with the given fields in the Arrow IPC stream
+/// format: the schema, then each batch, then the end-of-stream marker.
+pub fn write_stream(
+    writer: &mut impl Write,
+    fields: &[Field],
+    batches: &[RecordBatch],
+) -> io::Result<()> {
+    write_message(writer, &schema_message(fields), &[])?;
+    for batch in batches {
+        let (metadata, body) = batch_message(batch);
+        write_message(writer, &metadata, &body)?;
+    }
+    writer.write_all(&u32::MAX.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())
+}
+
+impl EventLog {
+    /// The events of the log as Arrow record batches of up to `BATCH_ROWS`
+    /// rows each, with the columns of `EventColumns`.
+    pub fn to_arrow(&self) -> Vec<RecordBatch> {
+        let columns = EventColumns::from_log(self);
+        (0..columns.len())
+            .step_by(BATCH_ROWS)
+            .map(|start| event_batch(&columns, start..columns.len().min(start + BATCH_ROWS)))
+            .collect()
+    }
+
+    /// Write the events of the log as an Arrow IPC stream.
+    pub fn write_arrow(&self, writer: &mut impl Write) -> io::Result<()> {
+        write_stream(writer, &event_fields(), &self.to_arrow())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    /// Little-endian integers at a position in a buffer.
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes(bytes[at..at + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn i64_at(bytes: &[u8], at: usize) -> i64 {
+        i64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    /// A table of a FlatBuffer, read through its vtable, to check what the
+    /// builder wrote independently of it.
+    #[derive(Debug, Clone, Copy)]
+    struct Table<'a> {
+        bytes: &'a [u8],
+        at: usize,
+    }
+
+    impl<'a> Table<'a> {
+        /// The root table of a buffer.
+        fn root(bytes: &'a [u8]) -> Self {
+            let at = u32_at(bytes, 0) as usize;
+            Self { bytes, at }
+        }
+
+        /// Where a field is, if it's present.
+        fn slot(&self, id: usize) -> Option<usize> {
+            let distance = i32::from_le_bytes(self.bytes[self.at..self.at + 4].try_into().unwrap());
+            let vtable = (self.at as i64 - distance as i64) as usize;
+            let entry = 4 + 2 * id;
+            if entry >= u16_at(self.bytes, vtable) as usize {
+                return None;
+            }
+            let offset = u16_at(self.bytes, vtable + entry) as usize;
+            (offset > 0).then_some(self.at + offset)
+        }
+
+        /// A scalar field, or 0 if it's absent, which is the default.
+        fn scalar(&self, id: usize, size: usize) -> i64 {
+            self.slot(id).map_or(0, |at| {
+                let mut bytes = [0; 8];
+                bytes[..size].copy_from_slice(&self.bytes[at..at + size]);
+                i64::from_le_bytes(bytes)
+            })
+        }
+
+        /// The target of an offset field.
+        fn target(&self, id: usize) -> usize {
+            let at = self.slot(id).expect("missing field");
+            at + u32_at(self.bytes, at) as usize
+        }
+
+        fn table(&self, id: usize) -> Table<'a> {
+            Table {
+                bytes: self.bytes,
+                at: self.target(id),
+            }
+        }
+
+        fn string(&self, id: usize) -> &'a str {
+            let at = self.target(id);
+            let len = u32_at(self.bytes, at) as usize;
+            std::str::from_utf8(&self.bytes[at + 4..at + 4 + len]).unwrap()
+        }
+
+        /// The start and length of a vector field.
+        fn vector(&self, id: usize) -> (usize, usize) {
+            let at = self.target(id);
+            (at + 4, u32_at(self.bytes, at) as usize)
+        }
+
+        fn tables(&self, id: usize) -> Vec<Table<'a>> {
+            let (start, len) = self.vector(id);
+            (0..len)
+                .map(|i| {
+                    let at = start + 4 * i;
+                    Table {
+                        bytes: self.bytes,
+                        at: at + u32_at(self.bytes, at) as usize,
+                    }
+                })
+                .collect()
+        }
+
+        /// A vector of structs of two 8-byte integers.
+        fn pairs(&self, id: usize) -> Vec<(i64, i64)> {
+            let (start, len) = self.vector(id);
+            assert_eq!(0, start % 8);
+            (0..len)
+                .map(|i| {
+                    let at = start + 16 * i;
+                    (i64_at(self.bytes, at), i64_at(self.bytes, at + 8))
+                })
+                .collect()
+        }
+    }
+
+    /// Split the next message off a stream, returning its metadata and body.
+    fn next_message<'a>(
+        stream: &mut &'a [u8],
+        body: impl Fn(&[u8]) -> usize,
+    ) -> (&'a [u8], &'a [u8]) {
+        assert_eq!(u32::MAX, u32_at(stream, 0));
+        let len = u32_at(stream, 4) as usize;
+        assert_eq!(0, len % 8);
+        let metadata = &stream[8..8 + len];
+        let body_len = body(metadata);
+        let body = &stream[8 + len..8 + len + body_len];
+        *stream = &stream[8 + len + body_len..];
+        (metadata, body)
+    }
+
+    #[test]
+    fn test_arrow() {
+        // Three items arriving at 0 to a queue with room for one make a log
+        // with a drop, which has no item.
+        let sim = &mut Simulation::new(QueueState::new(1, 1, 5));
+        for _ in 0..3 {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        }
+        sim.run();
+        let batches = sim.log.to_arrow();
+        assert_eq!(1, batches.len());
+        let batch = &batches[0];
+        assert_eq!(sim.log.contents.len(), batch.num_rows());
+        assert_eq!("event_type", batch.fields[1].name);
+        assert_eq!(DataType::Utf8, batch.fields[1].data_type);
+
+        // The times have no nulls, so no validity bitmap, and the items
+        // have a null for the drop.
+        let [time, event_type, .., item] = [0, 1, 5].map(|i| &batch.columns[i]);
+        assert_eq!((0, true), (time.null_count, time.validity.is_empty()));
+        let dropped = sim
+            .log
+            .contents
+            .iter()
+            .position(|e| e.item.is_none())
+            .unwrap();
+        assert_eq!(1, item.null_count);
+        assert_eq!(0, item.validity[dropped / 8] & (1 << (dropped % 8)));
+        assert_eq!(8 * batch.num_rows(), item.values.len());
+        let first = &event_type.values[..event_type.offsets[1] as usize];
+        assert_eq!(b"BufferIncremented", first);
+
+        // The stream is the schema, the batch, and the end-of-stream marker.
+        let mut bytes = vec![];
+        sim.log.write_arrow(&mut bytes).unwrap();
+        let stream = &mut bytes.as_slice();
+        let body_len = |metadata: &[u8]| Table::root(metadata).scalar(3, 8) as usize;
+
+        // The schema has a field per column, with its name, nullability, and
+        // type: unsigned integers, doubles, or strings, without children.
+        let (metadata, body) = next_message(stream, body_len);
+        let message = Table::root(metadata);
+        assert_eq!(V5 as i64, message.scalar(0, 2));
+        assert_eq!(SCHEMA as i64, message.scalar(1, 1));
+        assert!(body.is_empty());
+        let schema = message.table(2);
+        assert_eq!(0, schema.scalar(0, 2));
+        let fields = schema.tables(1);
+        assert_eq!(batch.fields.len(), fields.len());
+        for (field, expected) in fields.iter().zip(&batch.fields) {
+            assert_eq!(expected.name, field.string(0));
+            assert_eq!(expected.nullable as i64, field.scalar(1, 1));
+            assert_eq!(0, field.vector(5).1);
+            let data_type = field.table(3);
+            let (type_type, details) = match expected.data_type {
+                DataType::UInt32 => (INT, vec![32, 0]),
+                DataType::UInt64 => (INT, vec![64, 0]),
+                DataType::Float64 => (FLOATING_POINT, vec![2]),
+                DataType::Utf8 => (UTF8, vec![]),
+            };
+            assert_eq!(type_type as i64, field.scalar(2, 1));
+            let sizes = [4, 1];
+            for (id, &value) in details.iter().enumerate() {
+                let size = if type_type == FLOATING_POINT {
+                    2
+                } else {
+                    sizes[id]
+                };
+                assert_eq!(value, data_type.scalar(id, size));
+            }
+        }
+
+        // The batch has the rows, a node per column with its length and null
+        // count, and the column buffers, 8-byte aligned, in the body.
+        let (metadata, body) = next_message(stream, body_len);
+        let message = Table::root(metadata);
+        assert_eq!(RECORD_BATCH as i64, message.scalar(1, 1));
+        let header = message.table(2);
+        assert_eq!(batch.num_rows() as i64, header.scalar(0, 8));
+        let nodes = batch
+            .columns
+            .iter()
+            .map(|c| (c.len as i64, c.null_count as i64))
+            .collect::<Vec<_>>();
+        assert_eq!(nodes, header.pairs(1));
+        let buffers = header.pairs(2);
+        let expected = batch
+            .columns
+            .iter()
+            .flat_map(Array::buffers)
+            .collect::<Vec<_>>();
+        assert_eq!(expected.len(), buffers.len());
+        for (&(offset, len), expected) in buffers.iter().zip(&expected) {
+            let (offset, len) = (offset as usize, len as usize);
+            assert_eq!(0, offset % 8);
+            assert_eq!(expected.as_slice(), &body[offset..offset + len]);
+        }
+
+        assert_eq!([0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0], **stream);
+    }
+}
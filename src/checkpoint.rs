@@ -0,0 +1,171 @@
+//! Interim summaries during a run.
+//!
+//! A long run can take a while, and its estimates may settle well before
+//! it's over. `Checkpoints` hands a snapshot of the summary statistics to an
+//! observer at regular intervals, either of simulated time or of events, and
+//! the observer can stop the run there, e.g., once the estimates it's
+//! watching have stabilized.
+
+use std::fmt;
+use std::ops::ControlFlow;
+
+use crate::stats::SummaryStats;
+use crate::Time;
+
+/// How often to take a checkpoint:
+/// - `Time`: Every so many units of simulated time.
+/// - `Events`: Every so many logged events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Every {
+    Time(u32),
+    Events(u64),
+}
+
+/// Called with the summary at each checkpoint, returning whether to go on.
+pub type Observer = Box<dyn FnMut(&SummaryStats) -> ControlFlow<()>>;
+
+/// Decides when checkpoints are due and passes them to an observer.
+///
+/// A checkpoint at a time reflects every event up to and including it, so
+/// it's only taken once the next message is due after it, and the snapshot
+/// ends at the last event before then. Checkpoints with no events between
+/// them are taken once.
+pub struct Checkpoints {
+    every: Every,
+    observer: Observer,
+    /// The next checkpoint, as a time or a number of events.
+    next: u64,
+    events: u64,
+    stopped: bool,
+}
+
+impl Checkpoints {
+    /// Create checkpoints at the given interval for an observer.
+    pub fn new(every: Every, observer: Observer) -> Self {
+        let next = match every {
+            Every::Time(0) | Every::Events(0) => panic!("the checkpoint interval must be positive"),
+            Every::Time(n) => n as u64,
+            Every::Events(n) => n,
+        };
+        Self {
+            every,
+            observer,
+            next,
+            events: 0,
+            stopped: false,
+        }
+    }
+
+    /// Count events as they're logged.
+    pub fn count(&mut self, events: usize) -> &mut Self {
+        self.events += events as u64;
+        self
+    }
+
+    /// Whether a checkpoint is due before a message at the given time.
+    pub fn is_due(&self, before: Time) -> bool {
+        match self.every {
+            Every::Time(_) => self.next < before.0 as u64,
+            Every::Events(_) => self.events >= self.next,
+        }
+    }
+
+    /// Hand a summary to the observer and move on to the next checkpoint
+    /// after the given time, returning whether the run should go on.
+    pub fn observe(&mut self, summary: &SummaryStats, before: Time) -> bool {
+        self.stopped = (self.observer)(summary).is_break();
+        match self.every {
+            Every::Time(interval) => {
+                let interval = interval as u64;
+                let before = before.0 as u64;
+                self.next += (before - self.next).div_ceil(interval) * interval;
+            }
+            Every::Events(n) => self.next = (self.events / n + 1) * n,
+        }
+        !self.stopped
+    }
+
+    /// Whether the observer stopped the run.
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+}
+
+impl fmt::Debug for Checkpoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpoints")
+            .field("every", &self.every)
+            .field("next", &self.next)
+            .field("events", &self.events)
+            .field("stopped", &self.stopped)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState};
+
+    fn simulation() -> Simulation {
+        // Items arrive every 4 units from 0 to 36 for services of 3.
+        let mut sim = Simulation::new(QueueState::new(10, 1, 3));
+        for t in (0..40).step_by(4) {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim
+    }
+
+    #[test]
+    fn test_time_checkpoints() {
+        // Checkpoints at 10, 20, and 30 see the run up to the last event
+        // before each, and the observer stops the run at the third.
+        let ends = Rc::new(RefCell::new(vec![]));
+        let seen = Rc::clone(&ends);
+        let mut sim = simulation();
+        sim.set_checkpoints(
+            Every::Time(10),
+            Box::new(move |summary| {
+                seen.borrow_mut().push(summary.end);
+                if summary.end.0 < 25 {
+                    ControlFlow::Continue(())
+                } else {
+                    ControlFlow::Break(())
+                }
+            }),
+        );
+        let summary = sim.run();
+        assert_eq!(vec![Time(8), Time(20), Time(28)], *ends.borrow());
+        assert!(sim.stopped());
+        assert_eq!(Time(28), summary.end);
+        assert!(sim.step().is_none());
+    }
+
+    #[test]
+    fn test_event_checkpoints() {
+        // Each item adds four events over its stay, so checkpoints every 10
+        // events come every two or three arrivals, and a run that's never
+        // stopped goes to the end.
+        let counts = Rc::new(RefCell::new(vec![]));
+        let seen = Rc::clone(&counts);
+        let mut sim = simulation();
+        sim.set_checkpoints(
+            Every::Events(10),
+            Box::new(move |summary| {
+                seen.borrow_mut().push(summary.flow.arrivals);
+                ControlFlow::Continue(())
+            }),
+        );
+        let summary = sim.run();
+        assert_eq!(vec![3, 5, 8, 10], *counts.borrow());
+        assert!(!sim.stopped());
+        assert_eq!(10, summary.flow.arrivals);
+    }
+}
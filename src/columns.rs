@@ -0,0 +1,186 @@
+//! Columnar tables of events and items.
+//!
+//! Analytics tools, and columnar formats like Parquet and Arrow, work with
+//! tables stored column by column. `EventColumns` and `ItemColumns` lay out
+//! the event log and the per-item records of a run that way, with a fixed
+//! schema, so they can be handed to a columnar writer as they are, written
+//! out as CSV, or, with the `parquet` feature, written as Parquet files.
+//!
+//! Events have the columns:
+//! - `time` (u32): When the event happened.
+//! - `event_type` (string): The name of the event type, e.g., `FedBack`.
+//! - `data` (u32, nullable): The data of the event type, e.g., the pass of
+//!   `FedBack`, if it has any.
+//! - `source`, `server` (u32, nullable), `item` (u64, nullable): What the
+//!   event is tagged with.
+//! - `priority`, `pass` (u32, nullable), `work` (f64, nullable), `tag` (u64,
+//!   nullable): The metadata of the item the event is tagged with.
+//!
+//! Items that finished service have the columns `item` (u64), `departure`
+//! (u32), and `wait`, `service`, and `sojourn` (u32).
+
+use std::io::{self, Write};
+
+use crate::stats::ItemTimes;
+use crate::EventLog;
+
+/// The events of a log, column by column.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EventColumns {
+    pub time: Vec<u32>,
+    pub event_type: Vec<String>,
+    pub data: Vec<Option<u32>>,
+    pub source: Vec<Option<u32>>,
+    pub server: Vec<Option<u32>>,
+    pub item: Vec<Option<u64>>,
+    pub priority: Vec<Option<u32>>,
+    pub work: Vec<Option<f64>>,
+    pub pass: Vec<Option<u32>>,
+    pub tag: Vec<Option<u64>>,
+}
+
+impl EventColumns {
+    /// Lay out the events of a log.
+    pub fn from_log(log: &EventLog) -> Self {
+        let mut columns = Self::default();
+        for e in &log.contents {
+            let (name, data) = e.event_type.parts();
+            columns.time.push(e.time.0);
+            columns.event_type.push(name.to_string());
+            columns.data.push(data);
+            columns.source.push(e.source.map(|id| id.0));
+            columns.server.push(e.server.map(|id| id.0));
+            columns.item.push(e.item.map(|id| id.0));
+            columns.priority.push(e.metadata.map(|m| m.priority));
+            columns.work.push(e.metadata.and_then(|m| m.work));
+            columns.pass.push(e.metadata.map(|m| m.pass));
+            columns.tag.push(e.metadata.and_then(|m| m.tag));
+        }
+        columns
+    }
+
+    /// The number of rows.
+    pub fn len(&self) -> usize {
+        self.time.len()
+    }
+
+    /// Whether there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.time.is_empty()
+    }
+
+    /// Write the rows as CSV, with a header row and nulls left empty.
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(
+            writer,
+            "time,event_type,data,source,server,item,priority,work,pass,tag"
+        )?;
+        for i in 0..self.len() {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{}",
+                self.time[i],
+                self.event_type[i],
+                nullable(self.data[i]),
+                nullable(self.source[i]),
+                nullable(self.server[i]),
+                nullable(self.item[i]),
+                nullable(self.priority[i]),
+                nullable(self.work[i]),
+                nullable(self.pass[i]),
+                nullable(self.tag[i]),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The items that finished service in a run, column by column.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ItemColumns {
+    pub item: Vec<u64>,
+    pub departure: Vec<u32>,
+    pub wait: Vec<u32>,
+    pub service: Vec<u32>,
+    pub sojourn: Vec<u32>,
+}
+
+impl ItemColumns {
+    /// Lay out the items of a log.
+    pub fn from_log(log: &EventLog) -> Self {
+        let times = ItemTimes::from_log(log);
+        Self {
+            item: times.items.iter().map(|id| id.0).collect(),
+            departure: times.departures.iter().map(|t| t.0).collect(),
+            service: times.services(),
+            wait: times.waits,
+            sojourn: times.sojourns,
+        }
+    }
+
+    /// The number of rows.
+    pub fn len(&self) -> usize {
+        self.item.len()
+    }
+
+    /// Whether there are no rows.
+    pub fn is_empty(&self) -> bool {
+        self.item.is_empty()
+    }
+
+    /// Write the rows as CSV, with a header row.
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "item,departure,wait,service,sojourn")?;
+        for i in 0..self.len() {
+            writeln!(
+                writer,
+                "{},{},{},{},{}",
+                self.item[i], self.departure[i], self.wait[i], self.service[i], self.sojourn[i]
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A nullable value as a CSV field.
+fn nullable(value: Option<impl ToString>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    #[test]
+    fn test_columns() {
+        // Items arriving at 0 and 1 for services of 5 wait 0 and 4.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let events = EventColumns::from_log(&sim.log);
+        assert_eq!(sim.log.contents.len(), events.len());
+        assert_eq!("BufferIncremented", events.event_type[0]);
+        assert_eq!((Some(0), None), (events.item[0], events.server[0]));
+
+        let items = ItemColumns::from_log(&sim.log);
+        assert_eq!(vec![0, 4], items.wait);
+        assert_eq!(vec![5, 5], items.service);
+        let csv = &mut vec![];
+        items.write_csv(csv).unwrap();
+        assert_eq!(
+            "item,departure,wait,service,sojourn\n0,5,0,5,5\n1,10,4,5,9\n",
+            String::from_utf8(csv.clone()).unwrap()
+        );
+        let csv = &mut vec![];
+        events.write_csv(csv).unwrap();
+        let csv = String::from_utf8(csv.clone()).unwrap();
+        assert_eq!(Some("0,BufferIncremented,,,,0,0,,1,"), csv.lines().nth(1));
+    }
+}
@@ -0,0 +1,362 @@
+//! Networks defined in JSON config files.
+//!
+//! A config lists the nodes of a network, the arrival sources that feed
+//! them, and the routes between them, e.g.:
+//!
+//! ```json
+//! {
+//!     "seed": 1,
+//!     "nodes": [
+//!         {"name": "cpu", "buffer": 50, "servers": 2, "service": {"exponential": 0.5}},
+//!         {"name": "disk", "service": 3}
+//!     ],
+//!     "sources": [{"node": "cpu", "poisson": 0.4}],
+//!     "routes": [{"from": "cpu", "to": "disk", "probability": 0.3}]
+//! }
+//! ```
+//!
+//! Nodes have a unique name, a buffer capacity (`"buffer"`, unlimited by
+//! default), a number of servers (`"servers"`, 1 by default, or
+//! `"infinite"`), and a service time, which is either a fixed duration or a
+//! distribution: `{"deterministic": d}`, `{"exponential": rate}`,
+//! `{"uniform": [low, high]}`, `{"lognormal": [mu, sigma]}`, `{"gamma":
+//! [shape, scale]}`, `{"erlang": [k, rate]}`, or `{"weibull": [shape,
+//! scale]}`. Sources are Poisson (`"poisson": rate`) or renewal processes
+//! (`"interarrival": distribution`). Routes have a probability of 1 by
+//! default, and items leave the network with whatever probability is left
+//! over at a node. Optionally, `"blocking": true` turns on blocking after
+//! service, and `"population": {"node": name, "size": n}` makes the network
+//! closed.
+//!
+//! Node `i`, and the `i`th source, use RNGs seeded with `seed + i`.
+
+use std::collections::HashMap;
+
+use crate::arrival::{PoissonArrivals, RenewalArrivals};
+use crate::dist::{
+    Deterministic, Distribution, Erlang, Exponential, Gamma, LogNormal, Uniform, Weibull,
+};
+use crate::json::{Json, SyntaxError};
+use crate::network::{Network, NodeId};
+use crate::routing::Probabilistic;
+use crate::QueueState;
+
+/// Errors raised when a config can't be turned into a network. Problems with
+/// a particular node, source, or route name it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The text isn't valid JSON, as found at the given byte offset.
+    Syntax(usize),
+    /// The top level of the config is invalid.
+    Network(String),
+    /// A node's settings are invalid.
+    Node { node: String, problem: String },
+    /// The settings of a source feeding a node are invalid.
+    Source { node: String, problem: String },
+    /// A route between two nodes is invalid.
+    Route {
+        from: String,
+        to: String,
+        problem: String,
+    },
+}
+
+impl From<SyntaxError> for ConfigError {
+    fn from(SyntaxError(at): SyntaxError) -> Self {
+        ConfigError::Syntax(at)
+    }
+}
+
+/// Parse a distribution given as an object with a single entry, e.g.,
+/// `{"exponential": 0.5}`.
+fn distribution(json: &Json) -> Result<Box<dyn Distribution>, String> {
+    let Json::Object(entries) = json else {
+        return Err("expected a distribution".to_string());
+    };
+    let [(name, params)] = entries.as_slice() else {
+        return Err("expected a single distribution".to_string());
+    };
+    let one = || {
+        params
+            .as_f64()
+            .ok_or(format!("expected a number for {name}"))
+    };
+    let two = || match params
+        .as_array()
+        .map(|a| a.iter().map(Json::as_f64).collect::<Vec<_>>())
+    {
+        Some(a) if a.len() == 2 && a.iter().all(Option::is_some) => {
+            Ok((a[0].unwrap(), a[1].unwrap()))
+        }
+        _ => Err(format!("expected two numbers for {name}")),
+    };
+    Ok(match name.as_str() {
+        "deterministic" => Box::new(Deterministic(one()?)),
+        "exponential" => Box::new(Exponential(one()?)),
+        "uniform" => {
+            let (low, high) = two()?;
+            Box::new(Uniform { low, high })
+        }
+        "lognormal" => {
+            let (mu, sigma) = two()?;
+            Box::new(LogNormal { mu, sigma })
+        }
+        "gamma" => {
+            let (shape, scale) = two()?;
+            Box::new(Gamma { shape, scale })
+        }
+        "erlang" => {
+            let (k, rate) = two()?;
+            let k = Json::Number(k)
+                .as_u32()
+                .filter(|&k| k > 0)
+                .ok_or("expected a positive whole number of phases for erlang")?;
+            Box::new(Erlang { k, rate })
+        }
+        "weibull" => {
+            let (shape, scale) = two()?;
+            Box::new(Weibull { shape, scale })
+        }
+        _ => return Err(format!("unknown distribution {name:?}")),
+    })
+}
+
+/// Build the queue of a node from its settings.
+fn queue_state(json: &Json, seed: u64) -> Result<QueueState, String> {
+    let buffer = match json.get("buffer") {
+        None => u32::MAX,
+        Some(buffer) => buffer.as_u32().ok_or("invalid buffer capacity")?,
+    };
+    let (servers, infinite) = match json.get("servers") {
+        None => (1, false),
+        Some(Json::String(s)) if s == "infinite" => (1, true),
+        Some(servers) => (servers.as_u32().ok_or("invalid number of servers")?, false),
+    };
+    let state = match json.get("service").ok_or("missing service time")? {
+        Json::Number(duration) if *duration >= 0.0 => {
+            QueueState::new(buffer, servers, duration.round() as u32)
+        }
+        Json::Number(_) => return Err("negative service time".to_string()),
+        service => {
+            let dist = distribution(service)?;
+            QueueState::new(buffer, servers, 0).with_service_time(dist, seed)
+        }
+    };
+    Ok(if infinite {
+        state.with_infinite_servers()
+    } else {
+        state
+    })
+}
+
+/// Build a network from a JSON config.
+pub fn network(text: &str) -> Result<Network, ConfigError> {
+    let json = Json::parse(text)?;
+    let invalid = |problem: &str| ConfigError::Network(problem.to_string());
+    let seed = match json.get("seed") {
+        None => 0,
+        Some(seed) => seed.as_u64().ok_or(invalid("invalid seed"))?,
+    };
+    let mut network = Network::new();
+    if json.get("blocking") == Some(&Json::Bool(true)) {
+        network = network.with_blocking();
+    }
+
+    let nodes = json
+        .get("nodes")
+        .and_then(Json::as_array)
+        .ok_or(invalid("missing list of nodes"))?;
+    for (i, node) in nodes.iter().enumerate() {
+        let name = node
+            .get("name")
+            .and_then(Json::as_str)
+            .map(str::to_string)
+            .ok_or(ConfigError::Node {
+                node: format!("nodes[{i}]"),
+                problem: "missing name".to_string(),
+            })?;
+        let error = |problem: String| ConfigError::Node {
+            node: name.clone(),
+            problem,
+        };
+        if network.node_id(&name).is_some() {
+            return Err(error("duplicate name".to_string()));
+        }
+        let state = queue_state(node, seed + i as u64).map_err(&error)?;
+        network.add_node(name.clone(), state);
+    }
+    let lookup = |network: &Network, json: &Json, key: &str| {
+        let name = json.get(key).and_then(Json::as_str).unwrap_or_default();
+        network.node_id(name).ok_or(name.to_string())
+    };
+
+    let sources = json
+        .get("sources")
+        .and_then(Json::as_array)
+        .unwrap_or_default();
+    for (i, source) in sources.iter().enumerate() {
+        let node = lookup(&network, source, "node").map_err(|node| ConfigError::Source {
+            node,
+            problem: "unknown node".to_string(),
+        })?;
+        let error = |problem: &str| ConfigError::Source {
+            node: network.node(node).name.clone(),
+            problem: problem.to_string(),
+        };
+        let seed = seed + i as u64;
+        if let Some(rate) = source.get("poisson") {
+            let rate = rate
+                .as_f64()
+                .filter(|r| *r > 0.0)
+                .ok_or(error("invalid rate"))?;
+            network.add_source(node, PoissonArrivals::new(rate, seed));
+        } else if let Some(interarrival) = source.get("interarrival") {
+            let dist = distribution(interarrival).map_err(|problem| error(&problem))?;
+            network.add_source(node, RenewalArrivals::new(dist, seed));
+        } else {
+            return Err(error("missing arrival process"));
+        }
+    }
+
+    let mut routes: HashMap<NodeId, Vec<(NodeId, f64)>> = HashMap::new();
+    let edges = json
+        .get("routes")
+        .and_then(Json::as_array)
+        .unwrap_or_default();
+    for route in edges {
+        let name = |key| {
+            route
+                .get(key)
+                .and_then(Json::as_str)
+                .unwrap_or_default()
+                .to_string()
+        };
+        let error = |problem: &str| ConfigError::Route {
+            from: name("from"),
+            to: name("to"),
+            problem: problem.to_string(),
+        };
+        let from = lookup(&network, route, "from").map_err(|_| error("unknown node"))?;
+        let to = lookup(&network, route, "to").map_err(|_| error("unknown node"))?;
+        let probability = match route.get("probability") {
+            None => 1.0,
+            Some(p) => p
+                .as_f64()
+                .filter(|p| (0.0..=1.0).contains(p))
+                .ok_or(error("invalid probability"))?,
+        };
+        let out = routes.entry(from).or_default();
+        if out.iter().map(|(_, p)| p).sum::<f64>() + probability > 1.0 + 1e-9 {
+            return Err(error("probabilities out of the node add up to more than 1"));
+        }
+        out.push((to, probability));
+    }
+    for (from, out) in routes {
+        network.set_router(from, Probabilistic::new(out, seed + from.0 as u64));
+    }
+
+    if let Some(population) = json.get("population") {
+        let node = lookup(&network, population, "node").map_err(|node| ConfigError::Node {
+            node,
+            problem: "unknown reference node for the population".to_string(),
+        })?;
+        let size = population
+            .get("size")
+            .and_then(Json::as_u32)
+            .ok_or(invalid("invalid population size"))?;
+        network = network.with_population(node, size);
+    }
+    Ok(network)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventMessage, EventMessageType, EventType, Time};
+
+    #[test]
+    fn test_network_from_config() {
+        // Items arriving at 0 and 1 at the first node move on to the second
+        // at 5 and 10, as in a tandem network.
+        let config = r#"{
+            "nodes": [
+                {"name": "first", "buffer": 10, "service": 5},
+                {"name": "second", "servers": 2, "service": {"deterministic": 7}}
+            ],
+            "routes": [{"from": "first", "to": "second"}]
+        }"#;
+        let network = &mut Network::from_json(config).unwrap();
+        let (first, second) = (
+            network.node_id("first").unwrap(),
+            network.node_id("second").unwrap(),
+        );
+        for t in [0, 1] {
+            network.schedule(
+                first,
+                EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                },
+            );
+        }
+        network.run();
+        let exits = network
+            .log
+            .iter()
+            .filter(|e| e.node == second && e.event.event_type == EventType::ServerDecremented)
+            .map(|e| e.event.time.0)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![12, 17], exits);
+    }
+
+    #[test]
+    fn test_config_errors() {
+        // Errors point at the offending node or route.
+        let error = |config| Network::from_json(config).unwrap_err();
+        assert_eq!(ConfigError::Syntax(11), error(r#"{"nodes": [}"#));
+        assert_eq!(
+            ConfigError::Node {
+                node: "a".to_string(),
+                problem: "missing service time".to_string()
+            },
+            error(r#"{"nodes": [{"name": "a"}]}"#)
+        );
+        assert_eq!(
+            ConfigError::Route {
+                from: "a".to_string(),
+                to: "b".to_string(),
+                problem: "unknown node".to_string()
+            },
+            error(
+                r#"{"nodes": [{"name": "a", "service": 1}], "routes": [{"from": "a", "to": "b"}]}"#
+            )
+        );
+        assert!(matches!(
+            error(
+                r#"{"nodes": [{"name": "a", "service": 1}, {"name": "b", "service": 1}],
+                "routes": [{"from": "a", "to": "b", "probability": 0.6},
+                           {"from": "a", "to": "a", "probability": 0.6}]}"#
+            ),
+            ConfigError::Route { to, .. } if to == "a"
+        ));
+
+        // Counts have to be whole numbers that fit.
+        let node_error = |node| {
+            let config = format!(r#"{{"nodes": [{{"name": "a", {node}}}]}}"#);
+            match Network::from_json(&config).unwrap_err() {
+                ConfigError::Node { problem, .. } => problem,
+                e => panic!("expected a node error, got {e:?}"),
+            }
+        };
+        assert_eq!(
+            "invalid buffer capacity",
+            node_error(r#""buffer": 4294967296, "service": 1"#)
+        );
+        assert_eq!(
+            "invalid number of servers",
+            node_error(r#""servers": 1.5, "service": 1"#)
+        );
+        assert!(node_error(r#""service": {"erlang": [2.5, 1]}"#).contains("erlang"));
+        assert!(node_error(r#""service": {"erlang": [0, 1]}"#).contains("erlang"));
+    }
+}
@@ -0,0 +1,85 @@
+//! The economics of a run: what waiting, capacity, and lost items cost.
+//!
+//! Adding servers cuts waiting and losses but costs money of its own, so
+//! comparing the total cost of runs with different staffing levels shows
+//! where the trade-off balances out.
+
+use crate::stats::SummaryStats;
+
+/// The cost rates of a queue:
+/// - `waiting`: Per item per unit of time spent in the buffer.
+/// - `capacity`: Per server on shift per unit of time, whether busy or not,
+///   or, with an unbounded server pool, per busy server.
+/// - `loss`: Per item lost, i.e., dropped, balked, throttled, or reneged.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+    pub waiting: f64,
+    pub capacity: f64,
+    pub loss: f64,
+}
+
+/// The costs of a run, by component.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Costs {
+    pub waiting: f64,
+    pub capacity: f64,
+    pub loss: f64,
+}
+
+impl Costs {
+    /// The sum of the components.
+    pub fn total(&self) -> f64 {
+        self.waiting + self.capacity + self.loss
+    }
+}
+
+impl CostModel {
+    /// Create a cost model with the given rates.
+    pub fn new(waiting: f64, capacity: f64, loss: f64) -> Self {
+        Self {
+            waiting,
+            capacity,
+            loss,
+        }
+    }
+
+    /// Work out the costs of a run from its summary.
+    pub fn evaluate(&self, summary: &SummaryStats) -> Costs {
+        let duration = summary.end.0 as f64;
+        let flow = &summary.flow;
+        let lost = flow.dropped + flow.balked + flow.throttled + flow.reneged;
+        Costs {
+            waiting: self.waiting * summary.mean_queue_length * duration,
+            capacity: self.capacity
+                * summary.mean_capacity.unwrap_or(summary.mean_busy_servers)
+                * duration,
+            loss: self.loss * lost as f64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    #[test]
+    fn test_costs() {
+        // With a buffer of 1 and services of 10, of the items arriving at 0,
+        // 1, 2, and 12, the one at 2 is dropped, and the ones at 1 and 12
+        // wait 9 and 8. The run ends at 30.
+        let sim = &mut Simulation::new(QueueState::new(1, 1, 10));
+        for t in [0, 1, 2, 12] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        let costs = CostModel::new(2.0, 1.0, 50.0).evaluate(&sim.run());
+        assert!((costs.waiting - 34.0).abs() < 1e-9);
+        assert!((costs.capacity - 30.0).abs() < 1e-9);
+        assert_eq!(50.0, costs.loss);
+        assert!((costs.total() - 114.0).abs() < 1e-9);
+    }
+}
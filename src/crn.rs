@@ -0,0 +1,125 @@
+//! Common random numbers for comparing scenarios.
+//!
+//! To estimate the difference between two configurations, e.g., 2 versus 3
+//! servers, it pays to drive both with the same random draws: the same items
+//! arrive at the same times and need the same service, so the difference in
+//! outcomes is down to the configuration rather than luck. `Streams` hands
+//! out the seeds for each purpose in a replication, so each scenario only
+//! has to use them in the same way.
+//!
+//! The antithetic streams of a replication mirror its draws instead, which
+//! pushes the outcomes of the pair in opposite directions; see
+//! `Replications::run_antithetic`.
+
+use crate::replication::Replications;
+use crate::rng::Rng;
+use crate::stats::{Interval, SummaryStats};
+
+/// What a stream of random numbers is for:
+/// - `Arrivals`: The arrival source with the given index.
+/// - `Service`: Service times.
+/// - `Other`: Every other random draw, e.g., patience or routing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stream {
+    Arrivals(u32),
+    Service,
+    Other,
+}
+
+/// The seeds of the streams of one replication, which may be antithetic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Streams {
+    pub replication: u64,
+    pub antithetic: bool,
+}
+
+impl Streams {
+    /// The streams of the replication with the given index.
+    pub fn new(replication: u64) -> Self {
+        Self {
+            replication,
+            antithetic: false,
+        }
+    }
+
+    /// The antithetic counterparts of these streams.
+    pub fn antithetic(self) -> Self {
+        Self {
+            antithetic: !self.antithetic,
+            ..self
+        }
+    }
+
+    /// The seed for a stream.
+    pub fn seed(&self, stream: Stream) -> u64 {
+        let index = match stream {
+            Stream::Service => 0,
+            Stream::Other => 1,
+            Stream::Arrivals(i) => 2 + i as u64,
+        };
+        // Clear the top bit, which would select an antithetic stream.
+        let seed = Rng::stream(self.replication, index).next_u64() >> 1;
+        if self.antithetic {
+            Rng::antithetic_seed(seed)
+        } else {
+            seed
+        }
+    }
+}
+
+/// Run two scenarios over `n` replications, each pair on the same streams,
+/// and return the confidence interval for the mean difference in a metric,
+/// the second scenario's value minus the first's.
+pub fn compare(
+    n: u64,
+    mut first: impl FnMut(Streams) -> SummaryStats,
+    mut second: impl FnMut(Streams) -> SummaryStats,
+    metric: impl Fn(&SummaryStats) -> Option<f64>,
+    level: f64,
+) -> Option<Interval> {
+    let a = Replications::run(n, |i| first(Streams::new(i)));
+    let b = Replications::run(n, |i| second(Streams::new(i)));
+    let differences = a
+        .runs
+        .iter()
+        .zip(&b.runs)
+        .filter_map(|(a, b)| Some(metric(b)? - metric(a)?))
+        .collect::<Vec<_>>();
+    Interval::new(&differences, level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrival::PoissonArrivals;
+    use crate::dist::Exponential;
+    use crate::simulation::Simulation;
+    use crate::{QueueState, Time};
+
+    #[test]
+    fn test_common_random_numbers() {
+        // Speeding up two servers by 10% cuts the mean wait. Driving both
+        // speeds with the same streams pins the difference down much more
+        // tightly than independent streams do.
+        let scenario = |rate, offset| {
+            move |streams: Streams| {
+                let streams = Streams::new(streams.replication + offset);
+                let state = QueueState::new(1000, 2, 0)
+                    .with_service_time(Exponential(rate), streams.seed(Stream::Other))
+                    .with_service_stream(streams.seed(Stream::Service));
+                let sim = &mut Simulation::new(state);
+                sim.add_source(PoissonArrivals::new(
+                    0.015,
+                    streams.seed(Stream::Arrivals(0)),
+                ));
+                sim.run_until(Time(50_000));
+                sim.summary()
+            }
+        };
+        let wait = |s: &SummaryStats| s.wait.map(|w| w.mean);
+        let common = compare(10, scenario(0.01, 0), scenario(0.011, 0), wait, 0.95).unwrap();
+        let independent = compare(10, scenario(0.01, 0), scenario(0.011, 100), wait, 0.95).unwrap();
+        assert!(common.upper() < 0.0);
+        assert!(common.half_width < independent.half_width / 2.0);
+    }
+}
@@ -0,0 +1,152 @@
+//! Service disciplines, i.e., the rule for which buffered item is served
+//! next.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::item::{Item, ItemId};
+use crate::rng::Rng;
+
+/// Which buffered item is served next:
+/// - `Fifo`: The item that arrived first.
+/// - `Lifo`: The item that arrived last.
+/// - `Siro`: An item chosen uniformly at random.
+/// - `Priority`: The item with the highest priority, and the one that arrived
+///   first among those.
+/// - `Sjf`: The item with the smallest service requirement, and the one that
+///   arrived first among those. The requirement is the known one, or else an
+///   estimate, and items without either go after all the others.
+/// - `Srpt`: Like `Sjf`, but an arriving item that finds every server busy
+///   preempts the service with the most remaining work if its own
+///   requirement is smaller, so the item with the shortest remaining
+///   processing time is always in service.
+///
+/// Items put back at the front of the buffer after an interrupted service
+/// count as having arrived first, and their requirement is the work they had
+/// left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Discipline {
+    #[default]
+    Fifo,
+    Lifo,
+    Siro,
+    Priority,
+    Sjf,
+    Srpt,
+}
+
+impl Discipline {
+    /// The position in the buffer of the item to serve next. The buffer
+    /// shouldn't be empty.
+    pub fn select(
+        &self,
+        buffer: &VecDeque<ItemId>,
+        items: &HashMap<ItemId, Item>,
+        rng: &mut Rng,
+    ) -> usize {
+        // `max_by` returns the last maximum and `min_by` the first minimum,
+        // so searches for a maximum go from the back.
+        let buffered = || buffer.iter().map(|id| &items[id]).enumerate();
+        match self {
+            Discipline::Fifo => Some(0),
+            Discipline::Lifo => buffer.len().checked_sub(1),
+            Discipline::Siro => Some((rng.uniform() * buffer.len() as f64) as usize),
+            Discipline::Priority => buffered()
+                .rev()
+                .max_by_key(|(_, item)| item.priority)
+                .map(|(i, _)| i),
+            Discipline::Sjf | Discipline::Srpt => buffered()
+                .min_by(|(_, a), (_, b)| {
+                    let work = |item: &Item| item.requirement().unwrap_or(f64::INFINITY);
+                    work(a).total_cmp(&work(b))
+                })
+                .map(|(i, _)| i),
+        }
+        .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrival::Attributes;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, EventType, QueueState, Time};
+
+    /// Serve items with the given requirements, arriving one per time unit
+    /// while the first is in service, and return the IDs in order of service.
+    fn service_order(discipline: Discipline, work: &[Option<f64>]) -> Vec<u64> {
+        let state = QueueState::new(10, 1, 10).with_discipline(discipline);
+        let sim = &mut Simulation::new(state);
+        for (t, &work) in work.iter().enumerate() {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::ArriveWith(Attributes {
+                    work,
+                    ..Attributes::default()
+                }),
+                time: Time(t as u32),
+            });
+        }
+        sim.run();
+        sim.log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::BufferDecremented)
+            .map(|e| e.item.unwrap().0)
+            .collect()
+    }
+
+    #[test]
+    fn test_disciplines() {
+        let unknown = [None; 5];
+        assert_eq!(
+            vec![0, 1, 2, 3, 4],
+            service_order(Discipline::Fifo, &unknown)
+        );
+        assert_eq!(
+            vec![0, 4, 3, 2, 1],
+            service_order(Discipline::Lifo, &unknown)
+        );
+
+        let mut siro = service_order(Discipline::Siro, &unknown);
+        assert_eq!(0, siro[0]);
+        siro.sort();
+        assert_eq!(vec![0, 1, 2, 3, 4], siro);
+
+        // Known requirements go shortest first, and unknown ones go last.
+        let work = [Some(5.0), None, Some(8.0), Some(2.0), Some(8.0)];
+        assert_eq!(vec![0, 3, 2, 4, 1], service_order(Discipline::Sjf, &work));
+    }
+
+    #[test]
+    fn test_srpt() {
+        // A 10-unit job starts at 0. A 3-unit job arrives at 2 and preempts
+        // it, since 8 units are left. A job estimated at 20 units arrives at
+        // 3 and waits, and is served after the first job resumes and ends.
+        let state = QueueState::new(10, 1, 4).with_discipline(Discipline::Srpt);
+        let sim = &mut Simulation::new(state);
+        for (t, work, estimate) in [
+            (0, Some(10.0), None),
+            (2, Some(3.0), None),
+            (3, None, Some(20.0)),
+        ] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::ArriveWith(Attributes {
+                    work,
+                    estimate,
+                    ..Attributes::default()
+                }),
+                time: Time(t),
+            });
+        }
+        sim.run();
+
+        let exits = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerDecremented && e.item.is_some())
+            .map(|e| (e.time.0, e.item.unwrap().0))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(5, 1), (13, 0), (17, 2)], exits);
+    }
+}
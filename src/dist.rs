@@ -0,0 +1,270 @@
+//! Probability distributions for durations such as think times and service
+//! times.
+//!
+//! Distributions are sampled with an external `Rng`, so that whoever owns the
+//! RNG decides how random streams are shared.
+
+use std::fmt::Debug;
+
+use crate::rng::Rng;
+
+/// A distribution of non-negative, real-valued durations.
+pub trait Distribution: Debug {
+    /// Draw a sample.
+    fn sample(&self, rng: &mut Rng) -> f64;
+}
+
+impl<D: Distribution + ?Sized> Distribution for Box<D> {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        (**self).sample(rng)
+    }
+}
+
+/// Always the same value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Deterministic(pub f64);
+
+impl Distribution for Deterministic {
+    fn sample(&self, _rng: &mut Rng) -> f64 {
+        self.0
+    }
+}
+
+/// The exponential distribution with the given rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exponential(pub f64);
+
+impl Distribution for Exponential {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        rng.exponential(self.0)
+    }
+}
+
+/// The continuous uniform distribution on `[low, high)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Uniform {
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Distribution for Uniform {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        self.low + (self.high - self.low) * rng.uniform()
+    }
+}
+
+/// The log-normal distribution, i.e., the distribution of `exp(X)` where `X`
+/// is normal with mean `mu` and standard deviation `sigma`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LogNormal {
+    pub mu: f64,
+    pub sigma: f64,
+}
+
+impl Distribution for LogNormal {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        (self.mu + self.sigma * rng.normal()).exp()
+    }
+}
+
+/// The gamma distribution with the given shape and scale, so that the mean is
+/// `shape * scale`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gamma {
+    pub shape: f64,
+    pub scale: f64,
+}
+
+impl Distribution for Gamma {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        // Marsaglia and Tsang's method, which needs `shape >= 1`. Smaller
+        // shapes are handled by boosting the shape by one and scaling the
+        // result by `u^(1 / shape)`.
+        let (shape, boost) = if self.shape < 1.0 {
+            (self.shape + 1.0, rng.uniform().powf(1.0 / self.shape))
+        } else {
+            (self.shape, 1.0)
+        };
+        let d = shape - 1.0 / 3.0;
+        let c = 1.0 / (9.0 * d).sqrt();
+        loop {
+            let x = rng.normal();
+            let v = (1.0 + c * x).powi(3);
+            if v <= 0.0 {
+                continue;
+            }
+            let u = 1.0 - rng.uniform();
+            if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+                return d * v * self.scale * boost;
+            }
+        }
+    }
+}
+
+/// The Erlang distribution, i.e., the sum of `k` independent exponentials
+/// with the given rate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Erlang {
+    pub k: u32,
+    pub rate: f64,
+}
+
+impl Distribution for Erlang {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        (0..self.k).map(|_| rng.exponential(self.rate)).sum()
+    }
+}
+
+/// The Weibull distribution with the given shape and scale.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Weibull {
+    pub shape: f64,
+    pub scale: f64,
+}
+
+impl Distribution for Weibull {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        self.scale * (-(1.0 - rng.uniform()).ln()).powf(1.0 / self.shape)
+    }
+}
+
+/// The empirical distribution of observed samples.
+///
+/// By default, sampling resamples the observations with replacement. With
+/// interpolation, the empirical CDF is interpolated linearly between
+/// consecutive order statistics, which gives a continuous distribution over
+/// the observed range instead of only the observed values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Empirical {
+    sorted: Vec<f64>,
+    interpolate: bool,
+}
+
+impl Empirical {
+    /// Create an empirical distribution from observed samples. Samples that
+    /// aren't finite are ignored, and at least one sample has to remain.
+    pub fn new(samples: &[f64]) -> Self {
+        let mut sorted = samples
+            .iter()
+            .copied()
+            .filter(|x| x.is_finite())
+            .collect::<Vec<_>>();
+        assert!(
+            !sorted.is_empty(),
+            "an empirical distribution needs samples"
+        );
+        sorted.sort_by(f64::total_cmp);
+        Self {
+            sorted,
+            interpolate: false,
+        }
+    }
+
+    /// Interpolate the empirical CDF linearly when sampling.
+    pub fn interpolated(mut self) -> Self {
+        self.interpolate = true;
+        self
+    }
+}
+
+impl Distribution for Empirical {
+    fn sample(&self, rng: &mut Rng) -> f64 {
+        let u = rng.uniform();
+        if self.interpolate {
+            let position = u * (self.sorted.len() - 1) as f64;
+            let i = position.floor() as usize;
+            let j = (i + 1).min(self.sorted.len() - 1);
+            let fraction = position - i as f64;
+            self.sorted[i] + fraction * (self.sorted[j] - self.sorted[i])
+        } else {
+            self.sorted[(u * self.sorted.len() as f64) as usize]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_ranges() {
+        let rng = &mut Rng::new(2);
+        assert_eq!(3.0, Deterministic(3.0).sample(rng));
+        let uniform = Uniform {
+            low: 2.0,
+            high: 4.0,
+        };
+        assert!((0..1000)
+            .map(|_| uniform.sample(rng))
+            .all(|x| (2.0..4.0).contains(&x)));
+        assert!((0..1000)
+            .map(|_| Exponential(1.0).sample(rng))
+            .all(|x| x >= 0.0));
+    }
+
+    #[test]
+    fn test_empirical() {
+        let rng = &mut Rng::new(3);
+        let observed = [4.0, 1.0, f64::NAN, 2.0];
+        let resampled = Empirical::new(&observed);
+        let xs = (0..1000).map(|_| resampled.sample(rng)).collect::<Vec<_>>();
+        assert!(xs.iter().all(|x| [1.0, 2.0, 4.0].contains(x)));
+        assert!([1.0, 2.0, 4.0].iter().all(|x| xs.contains(x)));
+
+        // Interpolated samples cover the observed range, not just the values.
+        let interpolated = Empirical::new(&observed).interpolated();
+        let xs = (0..1000)
+            .map(|_| interpolated.sample(rng))
+            .collect::<Vec<_>>();
+        assert!(xs.iter().all(|x| (1.0..=4.0).contains(x)));
+        assert!(xs.iter().any(|x| ![1.0, 2.0, 4.0].contains(x)));
+    }
+
+    #[test]
+    fn test_sample_means() {
+        // Check each distribution's sample mean against its exact mean.
+        let rng = &mut Rng::new(4);
+        let cases: Vec<(Box<dyn Distribution>, f64)> = vec![
+            (
+                Box::new(LogNormal {
+                    mu: 0.0,
+                    sigma: 0.5,
+                }),
+                0.125f64.exp(),
+            ),
+            (
+                Box::new(Gamma {
+                    shape: 2.5,
+                    scale: 2.0,
+                }),
+                5.0,
+            ),
+            (
+                Box::new(Gamma {
+                    shape: 0.5,
+                    scale: 2.0,
+                }),
+                1.0,
+            ),
+            (Box::new(Erlang { k: 3, rate: 0.5 }), 6.0),
+            (
+                Box::new(Weibull {
+                    shape: 1.0,
+                    scale: 3.0,
+                }),
+                3.0,
+            ),
+        ];
+        let n = 100_000;
+        for (dist, mean) in cases {
+            let sample_mean = (0..n).map(|_| dist.sample(rng)).sum::<f64>() / n as f64;
+            assert!(
+                (sample_mean - mean).abs() < 0.02 * mean,
+                "{:?}: {} vs {}",
+                dist,
+                sample_mean,
+                mean
+            );
+        }
+    }
+}
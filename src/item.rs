@@ -0,0 +1,410 @@
+//! Individual items moving through the queue.
+//!
+//! Every item admitted to the buffer gets a unique ID, and the queue keeps
+//! track of each item until it leaves, so that per-item waiting times and the
+//! order of service can be worked out from the event log.
+//!
+//! Items are classed by the arrival source they came from, and can have a
+//! priority, either their own or their source's. Items can also have
+//! deadlines, by which they have to start or finish service, and missed
+//! deadlines are logged and counted.
+
+use crate::arrival::SourceId;
+use crate::Time;
+
+/// Identifies an item. IDs are assigned in order of admission, starting
+/// from 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ItemId(pub u64);
+
+/// When an item is due:
+/// - `At`: At the given time.
+/// - `Within`: The given amount of time after the item arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deadline {
+    At(Time),
+    Within(u32),
+}
+
+impl Deadline {
+    /// The due time of an item that arrived at the given time.
+    pub fn due(&self, arrived: Time) -> Time {
+        match self {
+            Deadline::At(time) => *time,
+            Deadline::Within(delay) => Time(arrived.0 + delay),
+        }
+    }
+}
+
+/// What an item has to do by its deadline to meet the service level
+/// agreement (SLA):
+/// - `Start`: Enter service.
+/// - `Complete`: Finish service and leave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Sla {
+    #[default]
+    Start,
+    Complete,
+}
+
+/// An item in the system, either waiting in the buffer or in service.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Item {
+    pub id: ItemId,
+    /// The arrival source the item came from, i.e., its class.
+    pub source: Option<SourceId>,
+    /// The item's priority, where higher values are served first under the
+    /// priority discipline.
+    pub priority: u32,
+    /// The item's known service requirement, if any.
+    pub work: Option<f64>,
+    /// An estimate of the item's service requirement, if any, which is used
+    /// for scheduling but not for the service itself.
+    pub estimate: Option<f64>,
+    /// When the item joined the buffer, or rejoined it for another pass.
+    pub arrived: Time,
+    /// The work the item has left if its service was interrupted and is to
+    /// be resumed.
+    pub remaining_work: Option<f64>,
+    /// When the item is due, if it has a deadline.
+    pub due: Option<Time>,
+    /// The number of the item's current pass through service, starting from
+    /// 1, which goes up each time the item is fed back to the buffer.
+    pub pass: u32,
+    /// The item this one is a subtask of, if it was forked from another.
+    pub parent: Option<ItemId>,
+    /// The identifier the item keeps across the nodes of a network, if any.
+    pub tag: Option<u64>,
+}
+
+impl Item {
+    /// Create an item that arrived at the given time.
+    pub fn new(id: ItemId, source: Option<SourceId>, priority: u32, arrived: Time) -> Self {
+        Self {
+            id,
+            source,
+            priority,
+            work: None,
+            estimate: None,
+            arrived,
+            remaining_work: None,
+            due: None,
+            pass: 1,
+            parent: None,
+            tag: None,
+        }
+    }
+
+    /// The service requirement that scheduling goes by: the remaining work of
+    /// an interrupted service, or else the known requirement, or else the
+    /// estimate.
+    pub fn requirement(&self) -> Option<f64> {
+        self.remaining_work.or(self.work).or(self.estimate)
+    }
+
+    /// The details of the item that events involving it carry.
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            priority: self.priority,
+            work: self.requirement(),
+            pass: self.pass,
+            tag: self.tag,
+        }
+    }
+}
+
+/// Details of an item, as of an event involving it, that are carried on the
+/// event so that analysis of an event log doesn't have to look them up
+/// elsewhere. The item's class and server are on the event itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metadata {
+    /// The item's priority.
+    pub priority: u32,
+    /// The item's remaining service requirement, or its estimate, if known.
+    pub work: Option<f64>,
+    /// The item's pass through service, starting from 1.
+    pub pass: u32,
+    /// The identifier the item keeps across the nodes of a network, if any.
+    pub tag: Option<u64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, EventType, QueueState};
+
+    #[test]
+    fn test_fifo_order_and_waits() {
+        // Items are served in order of arrival, and each one's wait is the
+        // time between joining and leaving the buffer.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run_until(Time(2));
+        assert_eq!(
+            vec![ItemId(1), ItemId(2)],
+            sim.state.buffered().collect::<Vec<_>>()
+        );
+        assert_eq!(Time(1), sim.state.item(ItemId(1)).arrived);
+        sim.run();
+
+        let log = &sim.log.contents;
+        let time_of = |event_type, id| {
+            log.iter()
+                .find(|e| e.event_type == event_type && e.item == Some(id))
+                .map(|e| e.time.0)
+                .unwrap()
+        };
+        let waits = (0..3)
+            .map(|i| {
+                time_of(EventType::BufferDecremented, ItemId(i))
+                    - time_of(EventType::BufferIncremented, ItemId(i))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![0, 4, 8], waits);
+        assert_eq!(15, time_of(EventType::ServerDecremented, ItemId(2)));
+        assert!(sim.state.items.is_empty());
+    }
+
+    #[test]
+    fn test_priority_buffer() {
+        // While the first item is in service, items with priorities 0, 2, 1,
+        // and 2 (from the "urgent" source) queue up. They're served highest
+        // priority first, and in order of arrival within a priority.
+        use crate::arrival::{Attributes, SourceId};
+        use crate::discipline::Discipline;
+        let urgent = SourceId(0);
+        let state = QueueState::new(10, 1, 10)
+            .with_discipline(Discipline::Priority)
+            .with_priority(urgent, 2);
+        let sim = &mut Simulation::new(state);
+        for (t, source, priority) in [
+            (0, None, None),
+            (1, None, Some(0)),
+            (2, None, Some(2)),
+            (3, None, Some(1)),
+            (4, Some(urgent), None),
+        ] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::ArriveWith(Attributes {
+                    source,
+                    priority,
+                    ..Attributes::default()
+                }),
+                time: Time(t),
+            });
+        }
+        sim.run();
+
+        let served = sim
+            .log
+            .contents
+            .iter()
+            .filter_map(|e| match e.event_type {
+                EventType::PriorityServed(priority) => Some((e.item.unwrap().0, priority)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(0, 0), (2, 2), (4, 2), (3, 1), (1, 0)], served);
+    }
+
+    #[test]
+    fn test_preemptive_priority() {
+        // A low-priority item starts a 10-unit service at 0 and is preempted
+        // by a high-priority item at 4, which is served right away. The
+        // low-priority item then resumes with 6 units left, or restarts.
+        use crate::arrival::Attributes;
+        use crate::service::Preemption;
+        let exits = |preemption| {
+            let state = QueueState::new(10, 1, 10)
+                .with_preemptive_priority()
+                .with_preemption(preemption);
+            let sim = &mut Simulation::new(state);
+            for (t, priority) in [(0, 0), (4, 1)] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::ArriveWith(Attributes {
+                        priority: Some(priority),
+                        ..Attributes::default()
+                    }),
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            assert!(sim
+                .log
+                .contents
+                .iter()
+                .any(|e| e.event_type == EventType::ServiceInterrupted
+                    && e.item == Some(ItemId(0))
+                    && e.time == Time(4)));
+            sim.log
+                .contents
+                .iter()
+                .filter(|e| e.event_type == EventType::ServerDecremented && e.item.is_some())
+                .map(|e| (e.time.0, e.item.unwrap().0))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![(14, 1), (20, 0)], exits(Preemption::Resume));
+        assert_eq!(vec![(14, 1), (24, 0)], exits(Preemption::Restart));
+    }
+
+    #[test]
+    fn test_reneging() {
+        // With a patience of 15 and services of 10, the third item gives up
+        // at 17 before the server gets to it at 20, while the second item
+        // enters service at 10 and its abandonment is cancelled.
+        use crate::dist::Deterministic;
+        let state = QueueState::new(10, 1, 10).with_patience(Deterministic(15.0));
+        let sim = &mut Simulation::new(state);
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run_until(Time(10));
+        assert_eq!(
+            1,
+            sim.emq
+                .messages
+                .iter()
+                .filter(|em| matches!(em.event_message_type, EventMessageType::Renege(_)))
+                .count()
+        );
+        sim.run();
+
+        let reneged = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::Reneged)
+            .map(|e| (e.time.0, e.item.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(17, ItemId(2))], reneged);
+        assert_eq!(0, sim.state.buffer_count);
+        assert!((sim.state.abandonment_rate() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_deadlines() {
+        // Items arrive at 0, 1, and 2 for services of 10, and are due within
+        // 15. The third item only starts at 20, and the second and third only
+        // finish at 20 and 30.
+        let missed = |sla| {
+            let state = QueueState::new(10, 1, 10)
+                .with_deadline(Deadline::Within(15))
+                .with_sla(sla);
+            let sim = &mut Simulation::new(state);
+            for t in [0, 1, 2] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            assert_eq!(Time(30), sim.state.time);
+            let missed = sim
+                .log
+                .contents
+                .iter()
+                .filter(|e| e.event_type == EventType::DeadlineMissed)
+                .map(|e| (e.time.0, e.item.unwrap().0))
+                .collect::<Vec<_>>();
+            (missed, sim.state.sla_attainment())
+        };
+        assert_eq!((vec![(17, 2)], 2.0 / 3.0), missed(Sla::Start));
+        assert_eq!((vec![(16, 1), (17, 2)], 1.0 / 3.0), missed(Sla::Complete));
+    }
+
+    #[test]
+    fn test_reneging_misses_deadlines() {
+        // With a patience of 5, the second and third items give up at 6 and
+        // 7, well before they're due at 16 and 17, and still miss their
+        // deadlines, while the first is served in time.
+        use crate::dist::Deterministic;
+        for sla in [Sla::Start, Sla::Complete] {
+            let state = QueueState::new(10, 1, 10)
+                .with_patience(Deterministic(5.0))
+                .with_deadline(Deadline::Within(15))
+                .with_sla(sla);
+            let sim = &mut Simulation::new(state);
+            for t in [0, 1, 2] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            let missed = sim
+                .log
+                .contents
+                .iter()
+                .filter(|e| e.event_type == EventType::DeadlineMissed)
+                .map(|e| (e.time.0, e.item.unwrap().0))
+                .collect::<Vec<_>>();
+            assert_eq!(vec![(16, 1), (17, 2)], missed);
+            assert!((sim.state.sla_attainment() - 1.0 / 3.0).abs() < 1e-9);
+            assert!(sim.state.due.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_fork_join() {
+        // An item forks into 3 subtasks on 2 servers, so two are served in
+        // parallel and the third after them, and the item leaves when the
+        // third finishes.
+        let state = QueueState::new(10, 2, 10).with_fork(3);
+        let sim = &mut Simulation::new(state);
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(0),
+        });
+        sim.run();
+        let joined = sim
+            .log
+            .contents
+            .iter()
+            .filter_map(|e| match e.event_type {
+                EventType::Joined(latency) => Some((e.time.0, e.item.unwrap(), latency)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(20, ItemId(0), 20)], joined);
+        assert!(sim.state.items.is_empty());
+        assert_eq!(1, sim.state.arrivals());
+    }
+
+    #[test]
+    fn test_metadata() {
+        // Every event of an item carries its metadata, including the event
+        // of its departure, after it has left the system.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        let attributes = crate::arrival::Attributes {
+            priority: Some(3),
+            work: Some(4.0),
+            tag: Some(9),
+            ..Default::default()
+        };
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::ArriveWith(attributes),
+            time: Time(0),
+        });
+        sim.run();
+        let metadata = Metadata {
+            priority: 3,
+            work: Some(4.0),
+            pass: 1,
+            tag: Some(9),
+        };
+        assert!(sim.log.contents.len() >= 4);
+        for e in &sim.log.contents {
+            assert_eq!(Some(metadata), e.metadata, "{e:?}");
+        }
+        assert!(sim.state.departed.is_empty());
+    }
+}
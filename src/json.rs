@@ -0,0 +1,1162 @@
+//! Saving and reloading runs as JSON.
+//!
+//! Events, event messages, logs, and summary statistics convert to and from
+//! `Json` values, which parse from and print as JSON text, so runs can be
+//! saved, reloaded, and exchanged with other tools. Structs
+//! become objects with a key per field, IDs and times become numbers,
+//! `None` becomes `null`, and enums are tagged by variant name, either as a
+//! string for variants without data, e.g., `"Balked"`, or as an object with
+//! the data under the name, e.g., `{"FedBack": 2}`.
+//!
+//! Numbers are JSON numbers, i.e., doubles, so whole numbers above 2^53,
+//! e.g., large item tags, don't survive the round trip exactly.
+//!
+//! With the `serde` feature, queue states, and the settings they're built
+//! with, such as the service discipline and overflow policy, convert too.
+
+use std::fmt;
+
+use crate::arrival::{Attributes, SourceId};
+use crate::item::{Deadline, ItemId, Metadata};
+use crate::process::ProcessId;
+use crate::server::ServerId;
+use crate::stats::{Flow, ServiceLevel, SummaryStats, TimeStats};
+#[cfg(feature = "serde")]
+use crate::{
+    admission::Overflow,
+    discipline::Discipline,
+    item::Sla,
+    server::Batch,
+    service::{Feedback, Preemption},
+    QueueState,
+};
+use crate::{Event, EventLog, EventMessage, EventMessageType, EventType, Time};
+
+/// A parsed JSON value. Objects keep their keys in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /// Parse a JSON document.
+    pub fn parse(text: &str) -> Result<Json, SyntaxError> {
+        let mut parser = Parser {
+            bytes: text.as_bytes(),
+            position: 0,
+        };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position < parser.bytes.len() {
+            return Err(SyntaxError(parser.position));
+        }
+        Ok(value)
+    }
+
+    /// The value of a key, if this is an object that has it.
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// The value as a number, if it is one.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    /// The value as a non-negative whole number, if it is one.
+    pub fn as_u64(&self) -> Option<u64> {
+        self.as_f64()
+            .filter(|x| *x >= 0.0 && x.fract() == 0.0)
+            .map(|x| x as u64)
+    }
+
+    /// The value as a whole number that fits in a `u32`, if it is one.
+    pub fn as_u32(&self) -> Option<u32> {
+        self.as_u64().and_then(|n| u32::try_from(n).ok())
+    }
+
+    /// The value as a string, if it is one.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The elements of an array, if this is one.
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(values) => Some(values),
+            _ => None,
+        }
+    }
+}
+
+/// Prints compact JSON text, with numbers that aren't finite as `null`.
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let string = |f: &mut fmt::Formatter<'_>, s: &str| {
+            write!(f, "\"")?;
+            for c in s.chars() {
+                match c {
+                    '"' => write!(f, "\\\"")?,
+                    '\\' => write!(f, "\\\\")?,
+                    '\n' => write!(f, "\\n")?,
+                    '\t' => write!(f, "\\t")?,
+                    '\r' => write!(f, "\\r")?,
+                    c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+                    c => write!(f, "{c}")?,
+                }
+            }
+            write!(f, "\"")
+        };
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(x) if x.is_finite() => write!(f, "{x}"),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(s) => string(f, s),
+            Json::Array(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// A recursive descent JSON parser.
+struct Parser<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|b| b.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+    }
+
+    fn error<T>(&self) -> Result<T, SyntaxError> {
+        Err(SyntaxError(self.position))
+    }
+
+    /// Consume the given byte, after any whitespace.
+    fn expect(&mut self, byte: u8) -> Result<(), SyntaxError> {
+        self.skip_whitespace();
+        if self.bytes.get(self.position) != Some(&byte) {
+            return self.error();
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Consume the given byte if it's next, after any whitespace.
+    fn accept(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        let found = self.bytes.get(self.position) == Some(&byte);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn value(&mut self) -> Result<Json, SyntaxError> {
+        self.skip_whitespace();
+        match self.bytes.get(self.position) {
+            Some(b'{') => self.object(),
+            Some(b'[') => self.array(),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'-' | b'0'..=b'9') => self.number(),
+            _ => self.literal(),
+        }
+    }
+
+    fn literal(&mut self) -> Result<Json, SyntaxError> {
+        for (word, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if self.bytes[self.position..].starts_with(word.as_bytes()) {
+                self.position += word.len();
+                return Ok(value);
+            }
+        }
+        self.error()
+    }
+
+    fn number(&mut self) -> Result<Json, SyntaxError> {
+        let start = self.position;
+        while self
+            .bytes
+            .get(self.position)
+            .is_some_and(|b| matches!(b, b'-' | b'+' | b'.' | b'e' | b'E' | b'0'..=b'9'))
+        {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.bytes[start..self.position])
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .map(Json::Number)
+            .ok_or(SyntaxError(start))
+    }
+
+    fn string(&mut self) -> Result<String, SyntaxError> {
+        self.expect(b'"')?;
+        let mut s = String::new();
+        loop {
+            let start = self.position;
+            while self
+                .bytes
+                .get(self.position)
+                .is_some_and(|&b| b != b'"' && b != b'\\')
+            {
+                self.position += 1;
+            }
+            s.push_str(std::str::from_utf8(&self.bytes[start..self.position]).or(self.error())?);
+            match self.bytes.get(self.position) {
+                Some(b'"') => {
+                    self.position += 1;
+                    return Ok(s);
+                }
+                Some(b'\\') => {
+                    let escaped = match self.bytes.get(self.position + 1) {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{8}',
+                        Some(b'f') => '\u{c}',
+                        Some(b'n') => '\n',
+                        Some(b't') => '\t',
+                        Some(b'r') => '\r',
+                        Some(b'u') => {
+                            s.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return self.error(),
+                    };
+                    s.push(escaped);
+                    self.position += 2;
+                }
+                _ => return self.error(),
+            }
+        }
+    }
+
+    /// Consume a `\uXXXX` escape, or a pair of them for a character outside
+    /// the Basic Multilingual Plane, written as UTF-16 surrogates.
+    fn unicode_escape(&mut self) -> Result<char, SyntaxError> {
+        let start = self.position;
+        let high = self.code_unit()?;
+        let code = match high {
+            0xd800..=0xdbff => {
+                let low = self.code_unit()?;
+                if !(0xdc00..=0xdfff).contains(&low) {
+                    return Err(SyntaxError(start));
+                }
+                0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+            }
+            _ => high,
+        };
+        char::from_u32(code).ok_or(SyntaxError(start))
+    }
+
+    /// Consume a single `\uXXXX` escape, returning its UTF-16 code unit.
+    fn code_unit(&mut self) -> Result<u32, SyntaxError> {
+        let digits = self
+            .bytes
+            .get(self.position..self.position + 6)
+            .filter(|escape| {
+                escape.starts_with(b"\\u") && escape[2..].iter().all(u8::is_ascii_hexdigit)
+            })
+            .and_then(|escape| {
+                u32::from_str_radix(std::str::from_utf8(&escape[2..]).ok()?, 16).ok()
+            })
+            .ok_or(SyntaxError(self.position))?;
+        self.position += 6;
+        Ok(digits)
+    }
+
+    fn array(&mut self) -> Result<Json, SyntaxError> {
+        self.expect(b'[')?;
+        let mut values = vec![];
+        if self.accept(b']') {
+            return Ok(Json::Array(values));
+        }
+        loop {
+            values.push(self.value()?);
+            if self.accept(b']') {
+                return Ok(Json::Array(values));
+            }
+            self.expect(b',')?;
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, SyntaxError> {
+        self.expect(b'{')?;
+        let mut entries = vec![];
+        if self.accept(b'}') {
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.string()?;
+            self.expect(b':')?;
+            entries.push((key, self.value()?));
+            if self.accept(b'}') {
+                return Ok(Json::Object(entries));
+            }
+            self.expect(b',')?;
+        }
+    }
+}
+
+/// JSON text that isn't valid, as found at the given byte offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyntaxError(pub usize);
+
+/// Conversion to a JSON value.
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+/// Conversion from a JSON value, failing with a description of the problem.
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self, String>;
+}
+
+/// Parse JSON text into a value.
+pub fn parse<T: FromJson>(text: &str) -> Result<T, String> {
+    let json = Json::parse(text).map_err(|SyntaxError(at)| format!("invalid JSON at byte {at}"))?;
+    T::from_json(&json)
+}
+
+/// The value of a field of an object, where a missing field reads as
+/// `null`.
+fn field<T: FromJson>(json: &Json, key: &str) -> Result<T, String> {
+    let value = match json {
+        Json::Object(_) => json.get(key).unwrap_or(&Json::Null),
+        _ => return Err(format!("expected an object with {key}")),
+    };
+    T::from_json(value).map_err(|problem| format!("{key}: {problem}"))
+}
+
+/// An object with the given fields.
+fn object(fields: Vec<(&str, Json)>) -> Json {
+    Json::Object(
+        fields
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect(),
+    )
+}
+
+/// An enum variant, tagged by name, with its data if it has any.
+fn variant(name: &str, data: Option<Json>) -> Json {
+    match data {
+        None => Json::String(name.to_string()),
+        Some(data) => object(vec![(name, data)]),
+    }
+}
+
+/// The name of an enum variant and its data, if it has any.
+fn tag(json: &Json) -> Result<(&str, Option<&Json>), String> {
+    match json {
+        Json::String(name) => Ok((name, None)),
+        Json::Object(entries) if entries.len() == 1 => Ok((&entries[0].0, Some(&entries[0].1))),
+        _ => Err("expected a variant name".to_string()),
+    }
+}
+
+/// The data of an enum variant that needs it.
+fn data<T: FromJson>(name: &str, data: Option<&Json>) -> Result<T, String> {
+    T::from_json(data.ok_or(format!("{name} needs data"))?)
+}
+
+impl ToJson for f64 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self)
+    }
+}
+
+impl FromJson for f64 {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        json.as_f64().ok_or("expected a number".to_string())
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        match json {
+            Json::Bool(b) => Ok(*b),
+            _ => Err("expected a boolean".to_string()),
+        }
+    }
+}
+
+impl ToJson for u64 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self as f64)
+    }
+}
+
+impl FromJson for u64 {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        json.as_u64().ok_or("expected a whole number".to_string())
+    }
+}
+
+impl ToJson for u32 {
+    fn to_json(&self) -> Json {
+        Json::Number(*self as f64)
+    }
+}
+
+impl FromJson for u32 {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        u64::from_json(json)?
+            .try_into()
+            .map_err(|_| "number out of range".to_string())
+    }
+}
+
+impl ToJson for usize {
+    fn to_json(&self) -> Json {
+        Json::Number(*self as f64)
+    }
+}
+
+impl FromJson for usize {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(u64::from_json(json)? as usize)
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        self.as_ref().map_or(Json::Null, T::to_json)
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        match json {
+            Json::Null => Ok(None),
+            _ => T::from_json(json).map(Some),
+        }
+    }
+}
+
+impl<A: ToJson, B: ToJson> ToJson for (A, B) {
+    fn to_json(&self) -> Json {
+        Json::Array(vec![self.0.to_json(), self.1.to_json()])
+    }
+}
+
+impl<A: FromJson, B: FromJson> FromJson for (A, B) {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        match json.as_array() {
+            Some([a, b]) => Ok((A::from_json(a)?, B::from_json(b)?)),
+            _ => Err("expected a pair".to_string()),
+        }
+    }
+}
+
+/// IDs and times are their numbers.
+macro_rules! newtype_json {
+    ($($t:ident($inner:ty)),*) => {
+        $(
+            impl ToJson for $t {
+                fn to_json(&self) -> Json {
+                    self.0.to_json()
+                }
+            }
+
+            impl FromJson for $t {
+                fn from_json(json: &Json) -> Result<Self, String> {
+                    <$inner>::from_json(json).map($t)
+                }
+            }
+        )*
+    };
+}
+
+newtype_json!(
+    Time(u32),
+    SourceId(u32),
+    ServerId(u32),
+    ItemId(u64),
+    ProcessId(u32)
+);
+
+impl ToJson for EventType {
+    fn to_json(&self) -> Json {
+        let (name, data) = self.parts();
+        variant(name, data.map(|n| n.to_json()))
+    }
+}
+
+impl FromJson for EventType {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        use EventType::*;
+        let (name, json) = tag(json)?;
+        let n = || data::<u32>(name, json);
+        Ok(match name {
+            "BufferIncremented" => BufferIncremented,
+            "BufferDecremented" => BufferDecremented,
+            "ServerIncremented" => ServerIncremented,
+            "ServerDecremented" => ServerDecremented,
+            "RegimeChanged" => RegimeChanged(n()?),
+            "SourceOn" => SourceOn,
+            "SourceOff" => SourceOff,
+            "CapacityChanged" => CapacityChanged(n()?),
+            "VacationStarted" => VacationStarted,
+            "VacationEnded" => VacationEnded,
+            "Jockeyed" => Jockeyed(n()?),
+            "SwitchOverStarted" => SwitchOverStarted(n()?),
+            "SwitchOverEnded" => SwitchOverEnded(n()?),
+            "PhaseCompleted" => PhaseCompleted(n()?),
+            "ServiceInterrupted" => ServiceInterrupted,
+            "PriorityServed" => PriorityServed(n()?),
+            "Reneged" => Reneged,
+            "Balked" => Balked,
+            "Dropped" => Dropped,
+            "Throttled" => Throttled,
+            "CallbackQueued" => CallbackQueued,
+            "Recalled" => Recalled,
+            "Evicted" => Evicted,
+            "Blocked" => Blocked,
+            "Unblocked" => Unblocked,
+            "FedBack" => FedBack(n()?),
+            "DeadlineMissed" => DeadlineMissed,
+            "Joined" => Joined(n()?),
+            "ResourceAcquired" => ResourceAcquired(n()?),
+            "ResourceReleased" => ResourceReleased(n()?),
+            "ServerActivated" => ServerActivated,
+            "ServerDeactivated" => ServerDeactivated,
+            "ServerBlocked" => ServerBlocked,
+            "ServerUnblocked" => ServerUnblocked,
+            "RetryScheduled" => RetryScheduled,
+            "RetrySucceeded" => RetrySucceeded,
+            "RetryAbandoned" => RetryAbandoned,
+            _ => return Err(format!("unknown event type {name}")),
+        })
+    }
+}
+
+impl ToJson for Event {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("time", self.time.to_json()),
+            ("event_type", self.event_type.to_json()),
+            ("source", self.source.to_json()),
+            ("server", self.server.to_json()),
+            ("item", self.item.to_json()),
+            ("metadata", self.metadata.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Event {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            time: field(json, "time")?,
+            event_type: field(json, "event_type")?,
+            source: field(json, "source")?,
+            server: field(json, "server")?,
+            item: field(json, "item")?,
+            metadata: field(json, "metadata")?,
+        })
+    }
+}
+
+impl ToJson for Metadata {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("priority", self.priority.to_json()),
+            ("work", self.work.to_json()),
+            ("pass", self.pass.to_json()),
+            ("tag", self.tag.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Metadata {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            priority: field(json, "priority")?,
+            work: field(json, "work")?,
+            pass: field(json, "pass")?,
+            tag: field(json, "tag")?,
+        })
+    }
+}
+
+impl ToJson for EventLog {
+    fn to_json(&self) -> Json {
+        Json::Array(self.contents.iter().map(Event::to_json).collect())
+    }
+}
+
+impl FromJson for EventLog {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        let mut log = EventLog::new();
+        for event in json.as_array().ok_or("expected a list of events")? {
+            log.push(Event::from_json(event)?);
+        }
+        Ok(log)
+    }
+}
+
+impl ToJson for Deadline {
+    fn to_json(&self) -> Json {
+        match self {
+            Deadline::At(time) => variant("At", Some(time.to_json())),
+            Deadline::Within(d) => variant("Within", Some(d.to_json())),
+        }
+    }
+}
+
+impl FromJson for Deadline {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        match tag(json)? {
+            ("At", time) => Ok(Deadline::At(data("At", time)?)),
+            ("Within", d) => Ok(Deadline::Within(data("Within", d)?)),
+            (name, _) => Err(format!("unknown deadline {name}")),
+        }
+    }
+}
+
+impl ToJson for Attributes {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("source", self.source.to_json()),
+            ("priority", self.priority.to_json()),
+            ("work", self.work.to_json()),
+            ("estimate", self.estimate.to_json()),
+            ("deadline", self.deadline.to_json()),
+            ("tag", self.tag.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Attributes {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            source: field(json, "source")?,
+            priority: field(json, "priority")?,
+            work: field(json, "work")?,
+            estimate: field(json, "estimate")?,
+            deadline: field(json, "deadline")?,
+            tag: field(json, "tag")?,
+        })
+    }
+}
+
+impl ToJson for EventMessageType {
+    fn to_json(&self) -> Json {
+        use EventMessageType::*;
+        let (name, data) = match self {
+            Arrive => ("Arrive", None),
+            ArriveFrom(id) => ("ArriveFrom", Some(id.to_json())),
+            ArriveWith(attributes) => ("ArriveWith", Some(attributes.to_json())),
+            Retry(attributes, attempt) => ("Retry", Some((*attributes, *attempt).to_json())),
+            CallToServe => ("CallToServe", None),
+            Exit(server) => ("Exit", Some(server.to_json())),
+            CompletePhase(server, phase) => ("CompletePhase", Some((*server, *phase).to_json())),
+            Interrupt(server) => ("Interrupt", Some(server.to_json())),
+            SetCapacity(capacity) => ("SetCapacity", Some(capacity.to_json())),
+            EndVacation(server) => ("EndVacation", Some(server.to_json())),
+            EndSwitchOver(server) => ("EndSwitchOver", Some(server.to_json())),
+            Renege(id) => ("Renege", Some(id.to_json())),
+            Recall(id) => ("Recall", Some(id.to_json())),
+            CheckDeadline(id) => ("CheckDeadline", Some(id.to_json())),
+            Resume(id) => ("Resume", Some(id.to_json())),
+        };
+        variant(name, data)
+    }
+}
+
+impl FromJson for EventMessageType {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        use EventMessageType::*;
+        let (name, json) = tag(json)?;
+        Ok(match name {
+            "Arrive" => Arrive,
+            "ArriveFrom" => ArriveFrom(data(name, json)?),
+            "ArriveWith" => ArriveWith(data(name, json)?),
+            "Retry" => {
+                let (attributes, attempt) = data(name, json)?;
+                Retry(attributes, attempt)
+            }
+            "CallToServe" => CallToServe,
+            "Exit" => Exit(data(name, json)?),
+            "CompletePhase" => {
+                let (server, phase) = data(name, json)?;
+                CompletePhase(server, phase)
+            }
+            "Interrupt" => Interrupt(data(name, json)?),
+            "SetCapacity" => SetCapacity(data(name, json)?),
+            "EndVacation" => EndVacation(data(name, json)?),
+            "EndSwitchOver" => EndSwitchOver(data(name, json)?),
+            "Renege" => Renege(data(name, json)?),
+            "Recall" => Recall(data(name, json)?),
+            "CheckDeadline" => CheckDeadline(data(name, json)?),
+            "Resume" => Resume(data(name, json)?),
+            _ => return Err(format!("unknown event message type {name}")),
+        })
+    }
+}
+
+impl ToJson for EventMessage {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("event_message_type", self.event_message_type.to_json()),
+            ("time", self.time.to_json()),
+        ])
+    }
+}
+
+impl FromJson for EventMessage {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            event_message_type: field(json, "event_message_type")?,
+            time: field(json, "time")?,
+        })
+    }
+}
+
+impl ToJson for TimeStats {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("count", self.count.to_json()),
+            ("mean", self.mean.to_json()),
+            ("variance", self.variance.to_json()),
+            ("min", self.min.to_json()),
+            ("max", self.max.to_json()),
+            ("p50", self.p50.to_json()),
+            ("p90", self.p90.to_json()),
+            ("p99", self.p99.to_json()),
+        ])
+    }
+}
+
+impl FromJson for TimeStats {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            count: field(json, "count")?,
+            mean: field(json, "mean")?,
+            variance: field(json, "variance")?,
+            min: field(json, "min")?,
+            max: field(json, "max")?,
+            p50: field(json, "p50")?,
+            p90: field(json, "p90")?,
+            p99: field(json, "p99")?,
+        })
+    }
+}
+
+impl ToJson for Flow {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("start", self.start.to_json()),
+            ("end", self.end.to_json()),
+            ("arrivals", self.arrivals.to_json()),
+            ("departures", self.departures.to_json()),
+            ("dropped", self.dropped.to_json()),
+            ("balked", self.balked.to_json()),
+            ("throttled", self.throttled.to_json()),
+            ("reneged", self.reneged.to_json()),
+        ])
+    }
+}
+
+impl FromJson for Flow {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            start: field(json, "start")?,
+            end: field(json, "end")?,
+            arrivals: field(json, "arrivals")?,
+            departures: field(json, "departures")?,
+            dropped: field(json, "dropped")?,
+            balked: field(json, "balked")?,
+            throttled: field(json, "throttled")?,
+            reneged: field(json, "reneged")?,
+        })
+    }
+}
+
+impl ToJson for ServiceLevel {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("target", self.target.to_json()),
+            ("attained", self.attained.to_json()),
+        ])
+    }
+}
+
+impl FromJson for ServiceLevel {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            target: field(json, "target")?,
+            attained: field(json, "attained")?,
+        })
+    }
+}
+
+impl ToJson for SummaryStats {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("end", self.end.to_json()),
+            ("mean_busy_servers", self.mean_busy_servers.to_json()),
+            ("mean_capacity", self.mean_capacity.to_json()),
+            ("utilization", self.utilization.to_json()),
+            ("mean_queue_length", self.mean_queue_length.to_json()),
+            (
+                "mean_number_in_system",
+                self.mean_number_in_system.to_json(),
+            ),
+            ("peak_queue_length", self.peak_queue_length.to_json()),
+            (
+                "peak_number_in_system",
+                self.peak_number_in_system.to_json(),
+            ),
+            ("wait", self.wait.to_json()),
+            ("service", self.service.to_json()),
+            ("sojourn", self.sojourn.to_json()),
+            ("interdeparture", self.interdeparture.to_json()),
+            ("flow", self.flow.to_json()),
+            ("service_level", self.service_level.to_json()),
+        ])
+    }
+}
+
+impl FromJson for SummaryStats {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Self {
+            end: field(json, "end")?,
+            mean_busy_servers: field(json, "mean_busy_servers")?,
+            mean_capacity: field(json, "mean_capacity")?,
+            utilization: field(json, "utilization")?,
+            mean_queue_length: field(json, "mean_queue_length")?,
+            mean_number_in_system: field(json, "mean_number_in_system")?,
+            peak_queue_length: field(json, "peak_queue_length")?,
+            peak_number_in_system: field(json, "peak_number_in_system")?,
+            wait: field(json, "wait")?,
+            service: field(json, "service")?,
+            sojourn: field(json, "sojourn")?,
+            interdeparture: field(json, "interdeparture")?,
+            flow: field(json, "flow")?,
+            service_level: field(json, "service_level")?,
+        })
+    }
+}
+
+/// Enums without data, tagged by variant name.
+#[cfg(feature = "serde")]
+macro_rules! unit_enum_json {
+    ($($t:ident { $($v:ident),* }),*) => {
+        $(
+            impl ToJson for $t {
+                fn to_json(&self) -> Json {
+                    match self {
+                        $($t::$v => variant(stringify!($v), None),)*
+                    }
+                }
+            }
+
+            impl FromJson for $t {
+                fn from_json(json: &Json) -> Result<Self, String> {
+                    match tag(json)? {
+                        $((stringify!($v), None) => Ok($t::$v),)*
+                        (name, _) => Err(format!("unknown {} {name}", stringify!($t))),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "serde")]
+unit_enum_json!(
+    Discipline {
+        Fifo,
+        Lifo,
+        Siro,
+        Priority,
+        Sjf,
+        Srpt
+    },
+    Overflow {
+        DropNewest,
+        DropOldest,
+        Block,
+        Callback
+    },
+    Preemption { Resume, Restart },
+    Sla { Start, Complete }
+);
+
+#[cfg(feature = "serde")]
+impl ToJson for Batch {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("min", self.min.to_json()),
+            ("max", self.max.to_json()),
+        ])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl FromJson for Batch {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        let (min, max) = (field(json, "min")?, field(json, "max")?);
+        if !(1..=max).contains(&min) {
+            return Err(format!("invalid batch sizes {min} to {max}"));
+        }
+        Ok(Batch { min, max })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl ToJson for Feedback {
+    fn to_json(&self) -> Json {
+        object(vec![
+            ("probability", self.probability.to_json()),
+            ("max_passes", self.max_passes.to_json()),
+        ])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl FromJson for Feedback {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        Ok(Feedback {
+            probability: field(json, "probability")?,
+            max_passes: field(json, "max_passes")?,
+        })
+    }
+}
+
+/// A queue state saves as its time, capacities, settings, and counts. The
+/// items in it, and its models of random times, don't save, so reloading
+/// it gives an empty queue with the same settings and loss counts at the
+/// same time.
+#[cfg(feature = "serde")]
+impl ToJson for QueueState {
+    fn to_json(&self) -> Json {
+        let infinite = self.has_infinite_servers();
+        object(vec![
+            ("time", self.time.to_json()),
+            ("buffer_capacity", self.buffer_capacity.to_json()),
+            ("server_capacity", self.server_capacity.to_json()),
+            ("infinite_servers", infinite.to_json()),
+            ("server_duration", self.server_duration.to_json()),
+            ("buffer_count", self.buffer_count.to_json()),
+            ("server_count", self.server_count.to_json()),
+            ("discipline", self.discipline.to_json()),
+            ("preemptive", self.preemptive.to_json()),
+            ("preemption", self.preemption.to_json()),
+            ("overflow", self.overflow.to_json()),
+            ("deadline", self.deadline.to_json()),
+            ("sla", self.sla.to_json()),
+            ("fork", self.fork.to_json()),
+            ("batch", self.batch.to_json()),
+            ("feedback", self.feedback.to_json()),
+            ("dropped", self.dropped.to_json()),
+            ("evicted", self.evicted.to_json()),
+            ("blocked", self.blocked.to_json()),
+            ("balked", self.balked.to_json()),
+            ("throttled", self.throttled.to_json()),
+            ("reneged", self.reneged.to_json()),
+        ])
+    }
+}
+
+#[cfg(feature = "serde")]
+impl FromJson for QueueState {
+    fn from_json(json: &Json) -> Result<Self, String> {
+        let infinite = field(json, "infinite_servers")?;
+        let servers = if infinite {
+            0
+        } else {
+            field(json, "server_capacity")?
+        };
+        let mut state = QueueState::new(
+            field(json, "buffer_capacity")?,
+            servers,
+            field(json, "server_duration")?,
+        )
+        .with_discipline(field(json, "discipline")?)
+        .with_preemption(field(json, "preemption")?)
+        .with_overflow(field(json, "overflow")?)
+        .with_sla(field(json, "sla")?)
+        .with_batch(field(json, "batch")?);
+        if infinite {
+            state = state.with_infinite_servers();
+        }
+        state.preemptive = field(json, "preemptive")?;
+        state.deadline = field(json, "deadline")?;
+        state.fork = match field(json, "fork")? {
+            Some(0) => return Err("fork: an item must fork into at least one subtask".to_string()),
+            fork => fork,
+        };
+        state.feedback = field(json, "feedback")?;
+        state.time = field(json, "time")?;
+        state.dropped = field(json, "dropped")?;
+        state.evicted = field(json, "evicted")?;
+        state.blocked = field(json, "blocked")?;
+        state.balked = field(json, "balked")?;
+        state.throttled = field(json, "throttled")?;
+        state.reneged = field(json, "reneged")?;
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::QueueState;
+
+    #[test]
+    fn test_round_trip() {
+        // A log and summary of a short run, and every kind of message, come
+        // back the same after printing and parsing.
+        let sim = &mut Simulation::new(QueueState::new(1, 1, 5));
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        let summary = sim.run();
+        let text = summary.to_json().to_string();
+        assert_eq!(summary, parse::<SummaryStats>(&text).unwrap());
+        let log = parse::<EventLog>(&sim.log.to_json().to_string()).unwrap();
+        assert_eq!(sim.log.contents, log.contents);
+
+        let attributes = Attributes {
+            source: Some(SourceId(1)),
+            priority: Some(2),
+            work: Some(0.5),
+            deadline: Some(Deadline::Within(10)),
+            ..Attributes::default()
+        };
+        let messages = [
+            EventMessageType::Arrive,
+            EventMessageType::ArriveWith(attributes),
+            EventMessageType::Retry(attributes, 3),
+            EventMessageType::CompletePhase(ServerId(1), 2),
+            EventMessageType::Renege(ItemId(7)),
+        ]
+        .map(|event_message_type| EventMessage {
+            event_message_type,
+            time: Time(4),
+        });
+        for message in messages {
+            let text = message.to_json().to_string();
+            assert_eq!(message, parse::<EventMessage>(&text).unwrap());
+        }
+        assert_eq!(
+            r#"{"event_message_type":{"Exit":0},"time":9}"#,
+            EventMessage {
+                event_message_type: EventMessageType::Exit(ServerId(0)),
+                time: Time(9),
+            }
+            .to_json()
+            .to_string()
+        );
+        assert!(parse::<Event>(r#"{"time": 1, "event_type": "Nope"}"#).is_err());
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        // Escapes for control characters and code units, including a
+        // surrogate pair, decode, and unpaired surrogates don't.
+        let text = r#""a\b\f\u00e9\u20ac\ud83d\ude00""#;
+        assert_eq!(
+            Ok(Json::String("a\u{8}\u{c}é€😀".to_string())),
+            Json::parse(text)
+        );
+        assert_eq!(Err(SyntaxError(7)), Json::parse(r#""\ud83d""#));
+        assert_eq!(Err(SyntaxError(1)), Json::parse(r#""\ude00""#));
+        assert_eq!(Err(SyntaxError(1)), Json::parse(r#""\u12g4""#));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_queue_state_round_trip() {
+        // Four items arrive at once to a queue with room for one that serves
+        // batches of at least two, so three are evicted. The queue saves its
+        // settings and counts, and reloads empty with the same ones.
+        let state = QueueState::new(1, 2, 5)
+            .with_discipline(Discipline::Lifo)
+            .with_overflow(Overflow::DropOldest)
+            .with_deadline(Deadline::Within(8))
+            .with_sla(Sla::Complete)
+            .with_batch(Batch::new(3).with_min(2))
+            .with_feedback(Feedback::new(0.5));
+        let sim = &mut Simulation::new(state);
+        for _ in 0..4 {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        }
+        sim.run_until(Time(1));
+        let text = sim.state.to_json().to_string();
+        assert!(text.contains(r#""buffer_count":1,"#));
+        assert!(text.contains(r#""evicted":3,"#));
+        let state = parse::<QueueState>(&text).unwrap();
+        assert_eq!(0, state.buffer_count);
+        assert_eq!(
+            text.replace(r#""buffer_count":1"#, r#""buffer_count":0"#),
+            state.to_json().to_string()
+        );
+
+        let state = QueueState::new(1, 0, 5).with_infinite_servers();
+        let state = parse::<QueueState>(&state.to_json().to_string()).unwrap();
+        assert!(state.has_infinite_servers());
+        assert!(parse::<QueueState>(&text.replace(r#""min":2"#, r#""min":4"#)).is_err());
+    }
+}
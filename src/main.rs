@@ -1,17 +1,128 @@
 #![allow(dead_code)]
 
 use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use admission::{Balking, Overflow, Retrial, TokenBucket};
+use arrival::{Attributes, PoissonArrivals, SourceId};
+use discipline::Discipline;
+use dist::{Deterministic, Distribution};
+use item::{Deadline, Item, ItemId, Metadata, Sla};
+use process::ProcessId;
+use resource::ResourcePool;
+use rng::Rng;
+use server::{Batch, NPolicy, Polling, ServerId, ServerPool, ServerSelection, Vacation};
+use service::{Feedback, Preemption, ProcessorSharing, ServiceTime};
+use simulation::Simulation;
+
+mod admission;
+mod analytic;
+mod arrival;
+#[cfg(feature = "arrow")]
+mod arrow;
+mod checkpoint;
+mod columns;
+mod config;
+mod cost;
+mod crn;
+mod discipline;
+mod dist;
+mod gzip;
+mod item;
+mod json;
+mod metric;
+mod network;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "plot")]
+mod plot;
+mod process;
+mod profile;
+#[cfg(feature = "prometheus")]
+mod prometheus;
+mod replay;
+mod replication;
+mod report;
+mod resource;
+mod rng;
+mod routing;
+mod sampler;
+mod server;
+mod service;
+mod simulation;
+mod sink;
+mod sql;
+mod stats;
+mod timeline;
+mod trace;
+mod window;
 
 /// The system state, which includes the time, buffer and server counts, and
 /// static server capacity and duration.
+///
+/// Every item in the system is kept, by ID, until it leaves. The buffer holds
+/// the IDs of waiting items, and `buffer_count` is kept equal to its length.
+/// The service time model and the RNG it samples from are also kept here, so
+/// that `handle_message` can sample a duration when an item enters service.
+/// The policies that decide what happens to items along the way, e.g., which
+/// item is served next or what happens when the buffer is full, are set with
+/// the `with_*` builders and described in their own modules.
 #[derive(Debug)]
 struct QueueState {
     time: Time,
     buffer_count: u32,
+    buffer: VecDeque<ItemId>,
+    items: HashMap<ItemId, Item>,
+    departed: Vec<Item>,
+    next_item: u64,
     buffer_capacity: u32,
     server_count: u32,
     server_capacity: u32,
     server_duration: u32,
+    server_pool: ServerPool,
+    in_service: HashMap<ServerId, Vec<ItemId>>,
+    resources: Vec<ResourcePool>,
+    priorities: HashMap<SourceId, u32>,
+    discipline: Discipline,
+    preemptive: bool,
+    preempting: HashSet<ServerId>,
+    overflow: Overflow,
+    dropped: u32,
+    evicted: u32,
+    blocked: u32,
+    blocked_arrivals: VecDeque<(Attributes, Time, bool)>,
+    resumed_sources: Vec<SourceId>,
+    retrial: Option<Retrial>,
+    virtual_queue: VecDeque<ItemId>,
+    callbacks: bool,
+    called_back: u32,
+    balking: Option<Balking>,
+    balked: u32,
+    token_bucket: Option<TokenBucket>,
+    throttled: u32,
+    patience: Option<Box<dyn Distribution>>,
+    reneged: u32,
+    deadline: Option<Deadline>,
+    sla: Sla,
+    due_items: u32,
+    missed_deadlines: u32,
+    due: HashMap<ItemId, Option<SourceId>>,
+    met: Vec<ItemId>,
+    fork: Option<u32>,
+    forks: HashMap<ItemId, u32>,
+    subtasks: u64,
+    batch: Batch,
+    service_time: Box<dyn ServiceTime>,
+    vacation: Option<Vacation>,
+    setup_time: Option<Box<dyn Distribution>>,
+    n_policy: Option<NPolicy>,
+    polling: Option<Polling>,
+    preemption: Preemption,
+    feedback: Option<Feedback>,
+    processor_sharing: Option<ProcessorSharing>,
+    rng: Rng,
+    /// A dedicated RNG for service times, if any.
+    service_rng: Option<Rng>,
 }
 
 /// A "newtype" wrapper around a primitive type that represents simulation time.
@@ -20,9 +131,17 @@ struct QueueState {
 /// with [impunity](https://users.rust-lang.org/t/cannot-sort-floats/35897).
 /// If time were represented by a float (e.g., `f32`), we'd have to jump through
 /// some extra hoops because of possible NaNs.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Ord, PartialOrd, Default)]
 struct Time(u32);
 
+impl Time {
+    /// Round a real-valued time to the nearest whole `Time`, clamping
+    /// negative values to zero.
+    fn from_f64(time: f64) -> Self {
+        Time(time.round().clamp(0.0, u32::MAX as f64) as u32)
+    }
+}
+
 /// Methods to construct and update the system state.
 impl QueueState {
     /// Create an empty queue.
@@ -30,13 +149,524 @@ impl QueueState {
         Self {
             time: Time(0),
             buffer_count: 0,
+            buffer: VecDeque::new(),
+            items: HashMap::new(),
+            departed: vec![],
+            next_item: 0,
             buffer_capacity,
             server_count: 0,
             server_capacity,
             server_duration,
+            server_pool: ServerPool::new(&vec![1.0; server_capacity as usize]),
+            in_service: HashMap::new(),
+            resources: vec![],
+            priorities: HashMap::new(),
+            discipline: Discipline::default(),
+            preemptive: false,
+            preempting: HashSet::new(),
+            overflow: Overflow::default(),
+            dropped: 0,
+            evicted: 0,
+            blocked: 0,
+            blocked_arrivals: VecDeque::new(),
+            resumed_sources: vec![],
+            retrial: None,
+            virtual_queue: VecDeque::new(),
+            callbacks: false,
+            called_back: 0,
+            balking: None,
+            balked: 0,
+            token_bucket: None,
+            throttled: 0,
+            patience: None,
+            reneged: 0,
+            deadline: None,
+            sla: Sla::default(),
+            due_items: 0,
+            missed_deadlines: 0,
+            due: HashMap::new(),
+            met: vec![],
+            fork: None,
+            forks: HashMap::new(),
+            subtasks: 0,
+            batch: Batch::default(),
+            service_time: Box::new(Deterministic(server_duration as f64)),
+            vacation: None,
+            setup_time: None,
+            n_policy: None,
+            polling: None,
+            preemption: Preemption::default(),
+            feedback: None,
+            processor_sharing: None,
+            rng: Rng::new(0),
+            service_rng: None,
+        }
+    }
+
+    /// Replace the service time model, sampling from an RNG with the given
+    /// seed.
+    fn with_service_time(mut self, service_time: impl ServiceTime + 'static, seed: u64) -> Self {
+        self.service_time = Box::new(service_time);
+        self.rng = Rng::new(seed);
+        self
+    }
+
+    /// Sample service times from a dedicated stream with the given seed,
+    /// apart from the RNG for every other random draw. The sequence of
+    /// service times then doesn't shift with draws for, e.g., patience or
+    /// balking, which keeps scenarios in step under common random numbers.
+    fn with_service_stream(mut self, seed: u64) -> Self {
+        self.service_rng = Some(Rng::new(seed));
+        self
+    }
+
+    /// Replace the server pool with servers of the given speeds. The server
+    /// capacity becomes the number of servers.
+    fn with_server_speeds(mut self, speeds: &[f64]) -> Self {
+        let selection = self.server_pool.selection;
+        self.server_pool = ServerPool::new(speeds);
+        self.server_pool.selection = selection;
+        self.server_capacity = speeds.len() as u32;
+        self
+    }
+
+    /// Give the queue an unbounded number of servers, so that no item ever
+    /// waits, e.g., to model a pure delay.
+    fn with_infinite_servers(mut self) -> Self {
+        self.server_pool.unbounded = true;
+        self.server_capacity = u32::MAX;
+        self
+    }
+
+    /// Check if the queue has an unbounded number of servers.
+    fn has_infinite_servers(&self) -> bool {
+        self.server_pool.unbounded
+    }
+
+    /// Set the policy for choosing among idle servers.
+    fn with_server_selection(mut self, selection: ServerSelection) -> Self {
+        self.server_pool.selection = selection;
+        self
+    }
+
+    /// Make every service need units from a pool of a secondary resource as
+    /// well as a server. Pools are identified by the order they're added in.
+    fn with_resource_pool(mut self, pool: ResourcePool) -> Self {
+        self.resources.push(pool);
+        self
+    }
+
+    /// Give items from a source the given priority, unless they arrive with
+    /// their own.
+    fn with_priority(mut self, source: SourceId, priority: u32) -> Self {
+        self.priorities.insert(source, priority);
+        self
+    }
+
+    /// Set the rule for which buffered item is served next.
+    fn with_discipline(mut self, discipline: Discipline) -> Self {
+        self.discipline = discipline;
+        self
+    }
+
+    /// Serve the buffered item with the highest priority first, and let
+    /// arriving items preempt the service of lower-priority items. Whether
+    /// preempted items resume or restart is set with `with_preemption`.
+    fn with_preemptive_priority(mut self) -> Self {
+        self.discipline = Discipline::Priority;
+        self.preemptive = true;
+        self
+    }
+
+    /// Set what happens to arrivals that find the buffer full.
+    fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+
+    /// Send arrivals that would be dropped into a retrial orbit.
+    fn with_retrial(mut self, retrial: Retrial) -> Self {
+        self.retrial = Some(retrial);
+        self
+    }
+
+    /// Sample the delay before the next attempt of an item in orbit that has
+    /// made `attempts` attempts, unless there's no orbit or the item gives up.
+    fn sample_retry_delay(&mut self, attempts: u32) -> Option<Time> {
+        let retrial = self
+            .retrial
+            .as_ref()
+            .filter(|r| attempts < r.max_attempts)?;
+        Some(Time::from_f64(retrial.delay.sample(&mut self.rng)))
+    }
+
+    /// Let arriving items balk depending on how many items are buffered.
+    fn with_balking(mut self, balking: Balking) -> Self {
+        self.balking = Some(balking);
+        self
+    }
+
+    /// Decide whether an arriving item balks, counting it if it does.
+    fn balks(&mut self) -> bool {
+        let balks = self
+            .balking
+            .is_some_and(|balking| balking.balks(self.buffer_count, &mut self.rng));
+        if balks {
+            self.balked += 1;
+        }
+        balks
+    }
+
+    /// Limit the rate at which arrivals join with a token bucket.
+    fn with_token_bucket(mut self, token_bucket: TokenBucket) -> Self {
+        self.token_bucket = Some(token_bucket);
+        self
+    }
+
+    /// Decide whether an arrival at the given time is throttled, counting it
+    /// if it is.
+    fn throttles(&mut self, time: Time) -> bool {
+        let throttles = self
+            .token_bucket
+            .as_mut()
+            .is_some_and(|bucket| !bucket.take(time));
+        if throttles {
+            self.throttled += 1;
+        }
+        throttles
+    }
+
+    /// Make each buffered item abandon the queue if it hasn't entered service
+    /// within a patience time sampled when it arrives.
+    fn with_patience(mut self, patience: impl Distribution + 'static) -> Self {
+        self.patience = Some(Box::new(patience));
+        self
+    }
+
+    /// Give items the given deadline, unless they arrive with their own.
+    fn with_deadline(mut self, deadline: Deadline) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set what items have to do by their deadlines.
+    fn with_sla(mut self, sla: Sla) -> Self {
+        self.sla = sla;
+        self
+    }
+
+    /// Check whether an item missed its deadline, counting it if it did, and
+    /// return its source if so. Only an item that entered service, or left
+    /// after it, as the SLA requires, met its deadline, so one that reneged,
+    /// was evicted, or is waiting for a callback missed it.
+    fn misses_deadline(&mut self, id: ItemId) -> Option<Option<SourceId>> {
+        let source = self.due.remove(&id)?;
+        self.missed_deadlines += 1;
+        Some(source)
+    }
+
+    /// Record that an item did what the SLA requires of it, if it's due.
+    fn meet_deadline(&mut self, id: ItemId, sla: Sla) {
+        if self.sla == sla && self.due.remove(&id).is_some() {
+            self.met.push(id);
+        }
+    }
+
+    /// Fork each admitted item into `k` subtasks, which are buffered and
+    /// served separately, possibly in parallel. The item leaves once all of
+    /// its subtasks have finished.
+    fn with_fork(mut self, k: u32) -> Self {
+        assert!(k > 0, "an item must fork into at least one subtask");
+        self.fork = Some(k);
+        self
+    }
+
+    /// Add a new item that forks into `k` subtasks to the system, and add
+    /// the subtasks to the back of the buffer, returning their IDs. The
+    /// item itself isn't buffered, and the subtasks share its attributes
+    /// except for its deadline.
+    fn push_forked(&mut self, attributes: Attributes, time: Time, k: u32) -> Vec<ItemId> {
+        let parent = self.new_item(attributes, time);
+        self.forks.insert(parent, k);
+        let subtask = Attributes {
+            deadline: None,
+            ..attributes
+        };
+        (0..k)
+            .map(|_| {
+                let id = self.push_buffer(subtask, time);
+                self.items.get_mut(&id).unwrap().parent = Some(parent);
+                self.subtasks += 1;
+                id
+            })
+            .collect()
+    }
+
+    /// Record that the given subtasks finished, and remove and return the
+    /// items whose last subtasks they were.
+    fn join(&mut self, finished: &[ItemId]) -> Vec<Item> {
+        let mut joined = vec![];
+        let parents = finished.iter().filter_map(|id| self.items[id].parent);
+        for parent in parents.collect::<Vec<_>>() {
+            let remaining = self.forks.get_mut(&parent).expect("unknown fork");
+            *remaining -= 1;
+            if *remaining == 0 {
+                self.forks.remove(&parent);
+                self.meet_deadline(parent, Sla::Complete);
+                joined.extend(self.remove_item(parent));
+            }
+        }
+        joined
+    }
+
+    /// Serve items in batches.
+    fn with_batch(mut self, batch: Batch) -> Self {
+        self.batch = batch;
+        self
+    }
+
+    /// Set whether interrupted services are resumed or restarted.
+    fn with_preemption(mut self, preemption: Preemption) -> Self {
+        self.preemption = preemption;
+        self
+    }
+
+    /// Send items that finish service back to the buffer for another pass
+    /// with some probability.
+    fn with_feedback(mut self, feedback: Feedback) -> Self {
+        self.feedback = Some(feedback);
+        self
+    }
+
+    /// Share a single processor among the items in service, with the number
+    /// of servers limiting how many items are in service at once.
+    fn with_processor_sharing(mut self) -> Self {
+        self.processor_sharing = Some(ProcessorSharing::new());
+        self
+    }
+
+    /// Send servers on vacation when they finish a service and find the
+    /// buffer empty.
+    fn with_vacation(mut self, vacation: Vacation) -> Self {
+        self.vacation = Some(vacation);
+        self
+    }
+
+    /// Make servers spend a sampled setup time before serving after an idle
+    /// period, including a vacation.
+    fn with_setup_time(mut self, setup_time: impl Distribution + 'static) -> Self {
+        self.setup_time = Some(Box::new(setup_time));
+        self
+    }
+
+    /// Switch servers on only once enough items are buffered. Every server
+    /// starts out switched off.
+    fn with_n_policy(mut self, n_policy: NPolicy) -> Self {
+        for server in &mut self.server_pool.servers {
+            server.active = false;
+        }
+        self.n_policy = Some(n_policy);
+        self
+    }
+
+    /// Make the queue a polling system. The queue should have a single
+    /// server.
+    fn with_polling(mut self, polling: Polling) -> Self {
+        self.polling = Some(polling);
+        self
+    }
+
+    /// The number of items waiting in the buffer a polling server is at.
+    fn polled(&self) -> u32 {
+        let Some(polling) = &self.polling else {
+            return self.buffer_count;
+        };
+        self.buffer
+            .iter()
+            .filter(|id| polling.buffer_of(&self.items[id]) == polling.current())
+            .count() as u32
+    }
+
+    /// Start serving the buffer an idle polling server is at, or send the
+    /// server on to the next buffer if its visit is over and there's work
+    /// elsewhere, returning the next buffer and when the server gets there.
+    fn poll(&mut self, now: Time) -> Option<(u32, Time)> {
+        let waiting = self.polled();
+        let polling = self.polling.as_mut()?;
+        if polling.is_switching() || self.server_count > 0 {
+            return None;
+        }
+        if !polling.is_open() && waiting > 0 {
+            polling.open(waiting);
+        }
+        if self.buffer_count == 0 || waiting > 0 && polling.may_serve() {
+            return None;
+        }
+        let next = polling.start_switch_over();
+        let length = Time::from_f64(polling.switch_over.sample(&mut self.rng));
+        Some((next, Time(now.0 + length.0)))
+    }
+
+    /// Switch on the idle servers whose thresholds the buffer has reached
+    /// under an N-policy, and return them.
+    fn activate_servers(&mut self) -> Vec<ServerId> {
+        let Some(n_policy) = &self.n_policy else {
+            return vec![];
+        };
+        let mut activated = vec![];
+        for (i, server) in self.server_pool.servers.iter_mut().enumerate() {
+            let id = ServerId(i as u32);
+            if !server.active && !server.busy && self.buffer_count >= n_policy.threshold(id) {
+                server.active = true;
+                activated.push(id);
+            }
+        }
+        activated
+    }
+
+    /// Sample the setup time of a server that's starting a service now, which
+    /// is zero unless the server has been idle.
+    fn sample_setup_time(&mut self, server: ServerId) -> Time {
+        let needs_setup = std::mem::replace(
+            &mut self.server_pool.servers[server.0 as usize].needs_setup,
+            false,
+        );
+        match &self.setup_time {
+            Some(setup_time) if needs_setup => Time::from_f64(setup_time.sample(&mut self.rng)),
+            _ => Time(0),
         }
     }
 
+    /// Send a server on vacation if there's a vacation policy and the buffer
+    /// is empty, returning when the vacation ends. Under a single vacation
+    /// policy, a server `returning` from vacation stays.
+    fn start_vacation(&mut self, server: ServerId, returning: bool) -> Option<Time> {
+        let length = match &self.vacation {
+            Some(Vacation::Multiple(length)) if self.buffer_count == 0 => length,
+            Some(Vacation::Single(length)) if self.buffer_count == 0 && !returning => length,
+            _ => return None,
+        };
+        let length = Time::from_f64(length.sample(&mut self.rng));
+        self.server_pool.servers[server.0 as usize].on_vacation = true;
+        Some(Time(self.time.0 + length.0))
+    }
+
+    /// Bring a server back from vacation.
+    fn end_vacation(&mut self, server: ServerId) -> &mut Self {
+        self.server_pool.servers[server.0 as usize].on_vacation = false;
+        self
+    }
+
+    /// Sample the durations of the phases of a service that's starting now
+    /// for an item from the given source on the given server. The sampled
+    /// service time is the amount of work, which faster servers get through
+    /// sooner.
+    fn sample_service_phases(&mut self, source: Option<SourceId>, server: ServerId) -> Vec<Time> {
+        let speed = self.server_pool.get(server).speed;
+        let rng = self.service_rng.as_mut().unwrap_or(&mut self.rng);
+        self.service_time
+            .sample_phases(source, rng)
+            .iter()
+            .map(|work| Time::from_f64(work.0 as f64 / speed))
+            .collect()
+    }
+
+    /// The time the given server takes to get through an amount of work.
+    fn work_time(&self, work: f64, server: ServerId) -> Time {
+        Time::from_f64(work / self.server_pool.get(server).speed)
+    }
+
+    /// Change the number of servers on shift. Busy servers that go off shift
+    /// finish their current item first, so the number of busy servers can
+    /// briefly exceed the capacity.
+    fn set_server_capacity(&mut self, server_capacity: u32) -> &mut Self {
+        self.server_pool.set_capacity(server_capacity);
+        self.server_capacity = server_capacity;
+        self
+    }
+
+    /// Put an idle server to work on the given items and return it.
+    ///
+    /// Note: This should only be called when `can_serve` is `true`.
+    fn start_service(&mut self, items: &[ItemId]) -> ServerId {
+        self.inc_server();
+        let server = self
+            .server_pool
+            .acquire(&mut self.rng)
+            .expect("no idle server");
+        self.in_service.insert(server, items.to_vec());
+        self.resources.iter_mut().for_each(ResourcePool::acquire);
+        for &id in items {
+            // A forked item starts once the last of its subtasks does.
+            match self.items[&id].parent {
+                Some(parent) => {
+                    let items = &self.items;
+                    let waiting = self
+                        .buffer
+                        .iter()
+                        .chain(&self.virtual_queue)
+                        .any(|other| items[other].parent == Some(parent));
+                    if !waiting {
+                        self.meet_deadline(parent, Sla::Start);
+                    }
+                }
+                None => self.meet_deadline(id, Sla::Start),
+            }
+        }
+        server
+    }
+
+    /// The items a server is serving.
+    fn serving(&self, server: ServerId) -> &[ItemId] {
+        self.in_service.get(&server).map_or(&[], |items| items)
+    }
+
+    /// Free a server and its resource units at the end of a service, and let
+    /// the items it served leave. A server that finds the buffer empty goes
+    /// idle, and needs setup before its next service, and may switch off
+    /// under an N-policy.
+    fn end_service(&mut self, server: ServerId) -> &mut Self {
+        self.server_pool.release(server);
+        self.resources.iter_mut().for_each(ResourcePool::release);
+        for id in self.in_service.remove(&server).unwrap_or_default() {
+            self.meet_deadline(id, Sla::Complete);
+            self.remove_item(id);
+        }
+        if let Some(sharing) = self.processor_sharing.as_mut() {
+            sharing.finish(server, self.time);
+        }
+        if self.buffer_count == 0 {
+            let stops = self.n_policy.as_ref().is_some_and(|n| n.stops_when_empty);
+            let server = &mut self.server_pool.servers[server.0 as usize];
+            server.needs_setup = true;
+            server.active &= !stops;
+        }
+        self.dec_server()
+    }
+
+    /// Put the items finishing service on a server that need another pass
+    /// back at the end of the buffer, and return them. Fed-back items rejoin
+    /// even if the buffer is full, and keep their known requirement.
+    fn feed_back(&mut self, server: ServerId) -> Vec<ItemId> {
+        let Some(feedback) = self.feedback else {
+            return vec![];
+        };
+        let serving = self.in_service.remove(&server).unwrap_or_default();
+        let (returning, leaving): (Vec<_>, Vec<_>) = serving
+            .into_iter()
+            .partition(|id| feedback.returns(self.items[id].pass, &mut self.rng));
+        self.in_service.insert(server, leaving);
+        for &id in &returning {
+            let item = self.items.get_mut(&id).unwrap();
+            item.pass += 1;
+            item.arrived = self.time;
+            item.remaining_work = None;
+            self.buffer.push_back(id);
+            self.inc_buffer();
+        }
+        returning
+    }
+
     /// Set the time.
     fn set_time(&mut self, time: Time) -> &mut Self {
         self.time = time;
@@ -55,6 +685,315 @@ impl QueueState {
         self
     }
 
+    /// Add a new item with the given attributes, which arrived at the given
+    /// time, to the back of the buffer, and return its ID.
+    fn push_buffer(&mut self, attributes: Attributes, time: Time) -> ItemId {
+        let id = self.new_item(attributes, time);
+        self.buffer.push_back(id);
+        self.inc_buffer();
+        id
+    }
+
+    /// Add a new item with the given attributes, which arrived at the given
+    /// time, to the system, and return its ID.
+    fn new_item(&mut self, attributes: Attributes, time: Time) -> ItemId {
+        let due = attributes.deadline.or(self.deadline).map(|d| d.due(time));
+        let id = ItemId(self.next_item);
+        self.next_item += 1;
+        if due.is_some() {
+            self.due_items += 1;
+            self.due.insert(id, attributes.source);
+        }
+        let priority = attributes.priority.unwrap_or_else(|| {
+            attributes
+                .source
+                .and_then(|source| self.priorities.get(&source).copied())
+                .unwrap_or(0)
+        });
+        let item = Item {
+            work: attributes.work,
+            estimate: attributes.estimate,
+            due,
+            tag: attributes.tag,
+            ..Item::new(id, attributes.source, priority, time)
+        };
+        self.items.insert(id, item);
+        id
+    }
+
+    /// Remove the item that's next according to the service discipline from
+    /// the buffer.
+    fn pop_buffer(&mut self) -> ItemId {
+        self.dec_buffer();
+        let index = match self.polling.as_mut() {
+            // A polling server takes the first item in the buffer it's at.
+            Some(polling) => {
+                polling.serve();
+                let items = &self.items;
+                self.buffer
+                    .iter()
+                    .position(|id| polling.buffer_of(&items[id]) == polling.current())
+                    .expect("empty polled buffer")
+            }
+            None => self
+                .discipline
+                .select(&self.buffer, &self.items, &mut self.rng),
+        };
+        self.buffer.remove(index).expect("empty buffer")
+    }
+
+    /// Remove the next batch of items from the buffer.
+    fn pop_batch(&mut self) -> Vec<ItemId> {
+        let size = self.batch.max.min(self.buffer_count);
+        (0..size).map(|_| self.pop_buffer()).collect()
+    }
+
+    /// Sample how long an arriving item is willing to wait, if items renege.
+    fn sample_patience(&mut self) -> Option<Time> {
+        let patience = self.patience.as_ref()?;
+        Some(Time::from_f64(patience.sample(&mut self.rng)))
+    }
+
+    /// Remove an item from the system, keeping a copy until the end of the
+    /// step so the step's events can still be annotated with its metadata.
+    fn remove_item(&mut self, id: ItemId) -> Option<Item> {
+        let item = self.items.remove(&id)?;
+        self.departed.push(item);
+        Some(item)
+    }
+
+    /// The metadata of an item that's in the system or left it this step.
+    fn metadata(&self, id: ItemId) -> Option<Metadata> {
+        let departed = || self.departed.iter().rev().find(|item| item.id == id);
+        self.items.get(&id).or_else(departed).map(Item::metadata)
+    }
+
+    /// Remove an item from the buffer, returning whether it was there.
+    fn remove_buffered(&mut self, id: ItemId) -> bool {
+        match self.buffer.iter().position(|&buffered| buffered == id) {
+            Some(index) => {
+                self.buffer.remove(index);
+                self.dec_buffer();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the item at the tail of the buffer from the system, e.g.,
+    /// because it jumped to another queue, and return it.
+    fn take_last(&mut self) -> Option<Item> {
+        let id = *self.buffer.back()?;
+        self.remove_buffered(id);
+        self.remove_item(id)
+    }
+
+    /// Remove an item that ran out of patience from the buffer and from the
+    /// system, and return it, unless it already entered service.
+    fn renege(&mut self, id: ItemId) -> Option<Item> {
+        if !self.remove_buffered(id) {
+            return None;
+        }
+        self.reneged += 1;
+        self.remove_item(id)
+    }
+
+    /// Offer items that run out of patience a callback instead of letting
+    /// them renege.
+    fn with_callbacks(mut self) -> Self {
+        self.callbacks = true;
+        self
+    }
+
+    /// Put an item in the virtual queue, in its place by order of arrival.
+    fn call_back(&mut self, id: ItemId) -> &mut Self {
+        let key = |item: &Item| (item.arrived, item.id);
+        let place = key(&self.items[&id]);
+        let index = self
+            .virtual_queue
+            .iter()
+            .position(|other| key(&self.items[other]) > place)
+            .unwrap_or(self.virtual_queue.len());
+        self.virtual_queue.insert(index, id);
+        self.called_back += 1;
+        self
+    }
+
+    /// The item at the head of the virtual queue, if it arrived before every
+    /// buffered item, so that it's due to be recalled.
+    fn next_recall(&self) -> Option<ItemId> {
+        let key = |id: &ItemId| (self.items[id].arrived, *id);
+        let head = self.virtual_queue.front()?;
+        self.buffer
+            .iter()
+            .all(|buffered| key(head) < key(buffered))
+            .then_some(*head)
+    }
+
+    /// Move an item from the head of the virtual queue to the front of the
+    /// buffer, returning whether it was there. Recalled items rejoin even if
+    /// the buffer is full.
+    fn recall(&mut self, id: ItemId) -> bool {
+        if self.virtual_queue.front() != Some(&id) {
+            return false;
+        }
+        self.virtual_queue.pop_front();
+        self.buffer.push_front(id);
+        self.inc_buffer();
+        true
+    }
+
+    /// Remove the oldest buffered item from the buffer and from the system to
+    /// make room, and return it.
+    fn evict_oldest(&mut self) -> Item {
+        let index = (0..self.buffer.len())
+            .min_by_key(|&i| {
+                let item = &self.items[&self.buffer[i]];
+                (item.arrived, item.id)
+            })
+            .expect("empty buffer");
+        let id = self.buffer.remove(index).expect("empty buffer");
+        self.dec_buffer();
+        self.evicted += 1;
+        self.remove_item(id).expect("unknown item")
+    }
+
+    /// Hold an arrival that found the buffer full until there's room. An
+    /// arrival `from_source` also holds up its source's next arrival.
+    fn block(&mut self, attributes: Attributes, time: Time, from_source: bool) -> &mut Self {
+        self.blocked += 1;
+        self.blocked_arrivals
+            .push_back((attributes, time, from_source));
+        self
+    }
+
+    /// Take the sources whose blocked arrivals have been admitted since the
+    /// last call, so that their next arrivals can be scheduled.
+    fn take_resumed_sources(&mut self) -> Vec<SourceId> {
+        std::mem::take(&mut self.resumed_sources)
+    }
+
+    /// The number of arrivals so far, whether they were admitted, dropped,
+    /// balked, or throttled. Blocked arrivals and arrivals in orbit count once
+    /// they're admitted or give up.
+    fn arrivals(&self) -> u64 {
+        self.admitted() + self.dropped as u64 + self.balked as u64 + self.throttled as u64
+    }
+
+    /// The fraction of arrivals that were dropped because the buffer was
+    /// full, including those that gave up retrying.
+    fn loss_probability(&self) -> f64 {
+        match self.arrivals() {
+            0 => 0.0,
+            arrivals => self.dropped as f64 / arrivals as f64,
+        }
+    }
+
+    /// The fraction of admitted items that reneged.
+    fn abandonment_rate(&self) -> f64 {
+        match self.admitted() {
+            0 => 0.0,
+            admitted => self.reneged as f64 / admitted as f64,
+        }
+    }
+
+    /// The number of items admitted so far, not counting subtasks.
+    fn admitted(&self) -> u64 {
+        self.next_item - self.subtasks
+    }
+
+    /// The fraction of items with a deadline that didn't miss it, counting
+    /// those that are still due.
+    fn sla_attainment(&self) -> f64 {
+        if self.due_items == 0 {
+            1.0
+        } else {
+            (self.due_items - self.missed_deadlines) as f64 / self.due_items as f64
+        }
+    }
+
+    /// The IDs of the buffered items, from front to back.
+    fn buffered(&self) -> impl Iterator<Item = ItemId> + '_ {
+        self.buffer.iter().copied()
+    }
+
+    /// Get an item that's in the system.
+    fn item(&self, id: ItemId) -> &Item {
+        &self.items[&id]
+    }
+
+    /// The number of heap bytes allocated for tracking items.
+    fn heap_bytes(&self) -> usize {
+        self.items.capacity() * std::mem::size_of::<(ItemId, Item)>()
+            + (self.buffer.capacity() + self.virtual_queue.capacity())
+                * std::mem::size_of::<ItemId>()
+            + self.due.capacity() * std::mem::size_of::<(ItemId, Option<SourceId>)>()
+    }
+
+    /// Interrupt the service on a server that was due to finish at `exit`,
+    /// putting its items back at the front of the buffer in their original
+    /// order, and return them. Under the resume policy, the items keep the
+    /// work they had left; under the restart policy, they start over. Items
+    /// put back can overflow the buffer capacity.
+    fn interrupt_service(&mut self, server: ServerId, exit: Time) -> Vec<ItemId> {
+        self.preempting.remove(&server);
+        let remaining_work = match (self.preemption, self.processor_sharing.as_mut()) {
+            (Preemption::Resume, Some(sharing)) => sharing.finish(server, self.time),
+            (Preemption::Resume, None) => {
+                Some(exit.0.saturating_sub(self.time.0) as f64 * self.server_pool.get(server).speed)
+            }
+            (Preemption::Restart, _) => None,
+        };
+        let items = self.in_service.remove(&server).unwrap_or_default();
+        for &id in items.iter().rev() {
+            if let Some(item) = self.items.get_mut(&id) {
+                item.remaining_work = remaining_work;
+            }
+            self.buffer.push_front(id);
+            self.inc_buffer();
+        }
+        self.end_service(server);
+        items
+    }
+
+    /// Choose a server to interrupt for an item buffered at `now` that can't
+    /// be served right away. Under preemptive priorities, this is the server
+    /// with the lowest-priority item, provided it's lower than the new item's.
+    /// Under SRPT, it's the server with the most remaining work, provided it's
+    /// more than the new item's requirement. The server is set aside, so that
+    /// it isn't chosen again before it's interrupted.
+    fn preemption_victim(&mut self, item: ItemId, now: Time) -> Option<ServerId> {
+        let new = *self.item(item);
+        let srpt = match self.discipline {
+            Discipline::Priority if self.preemptive => false,
+            Discipline::Srpt => true,
+            _ => return None,
+        };
+        let requirement = new.requirement().unwrap_or(f64::INFINITY);
+        // Candidates are ranked by a key, lowest first.
+        let mut busy = vec![];
+        for (&server, items) in &self.in_service {
+            if self.preempting.contains(&server) {
+                continue;
+            }
+            let key = if srpt {
+                let server = self.server_pool.get(server);
+                let remaining = server.busy_until.0.saturating_sub(now.0) as f64 * server.speed;
+                (remaining > requirement).then_some(-remaining)
+            } else {
+                let priority = self.items[&items[0]].priority;
+                (priority < new.priority).then_some(priority as f64)
+            };
+            if let Some(key) = key {
+                busy.push((key, server));
+            }
+        }
+        busy.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        let (_, server) = busy.first().copied()?;
+        self.preempting.insert(server);
+        Some(server)
+    }
+
     /// Increment the server count.
     fn inc_server(&mut self) -> &mut Self {
         self.server_count += 1;
@@ -76,10 +1015,18 @@ impl QueueState {
 
     /// Check if the queue can serve the next item.
     ///
-    /// This returns `true` if the buffer is occupied and the server is
-    /// under capacity.
+    /// This returns `true` if the buffer holds at least a minimum batch, the
+    /// server is under capacity, and a server is available, i.e., not away on
+    /// vacation. With infinite servers, a server is always available.
     fn can_serve(&self) -> bool {
-        self.buffer_count > 0 && self.server_count < self.server_capacity
+        self.buffer_count >= self.batch.min.max(1)
+            && (self.has_infinite_servers()
+                || self.server_count < self.server_capacity && self.server_pool.has_available())
+            && self.resources.iter().all(ResourcePool::has_available)
+            && self
+                .polling
+                .as_ref()
+                .is_none_or(|polling| polling.may_serve() && self.polled() > 0)
     }
 }
 
@@ -92,15 +1039,46 @@ struct EventMessage {
     time: Time,
 }
 
-/// The _event message type_ is one of three possible values:
+/// The _event message type_ is one of fifteen possible values:
 /// - `Arrive`: Signals the arrival of an item at the queue.
+/// - `ArriveFrom`: Signals the arrival of an item generated by an arrival
+///   source, which is handled like `Arrive` (see the `arrival` module).
+/// - `ArriveWith`: Signals the arrival of an item with the given attributes,
+///   e.g., one injected into a running simulation.
+/// - `Retry`: Signals another attempt by an item in the retrial orbit with
+///   the given attributes, and the number of the attempt.
 /// - `CallToServe`: Calls the next buffered item to be served.
-/// - `Exit`: Signals the exit of an item from the queue.
+/// - `Exit`: Signals the exit of an item from the queue, freeing the given
+///   server.
+/// - `CompletePhase`: Signals the end of the given phase of a multi-phase
+///   service on the given server (see the `service` module).
+/// - `Interrupt`: Interrupts the service on the given server, e.g., because
+///   of a breakdown, which cancels its exit (see `Simulation`).
+/// - `SetCapacity`: Changes the number of servers on shift.
+/// - `EndVacation`: Brings the given server back from vacation.
+/// - `EndSwitchOver`: Brings a polling server to the next buffer.
+/// - `Renege`: Signals that the given item ran out of patience, so it leaves
+///   the buffer if it's still waiting.
+/// - `Recall`: Recalls the given item from the virtual queue to the buffer.
+/// - `CheckDeadline`: Checks whether the given item missed its deadline.
+/// - `Resume`: Resumes a process (see the `process` module).
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum EventMessageType {
     Arrive,
+    ArriveFrom(SourceId),
+    ArriveWith(Attributes),
+    Retry(Attributes, u32),
     CallToServe,
-    Exit,
+    Exit(ServerId),
+    CompletePhase(ServerId, u32),
+    Interrupt(ServerId),
+    SetCapacity(u32),
+    EndVacation(ServerId),
+    EndSwitchOver(ServerId),
+    Renege(ItemId),
+    Recall(ItemId),
+    CheckDeadline(ItemId),
+    Resume(ProcessId),
 }
 
 /// A priority queue that holds event messages in order of event time.
@@ -124,6 +1102,14 @@ impl EventMessageQueue {
         }
     }
 
+    /// Remove the messages that match a predicate, returning them.
+    fn cancel(&mut self, predicate: impl Fn(&EventMessage) -> bool) -> Vec<EventMessage> {
+        let (cancelled, kept) = self.messages.drain(..).partition(predicate);
+        self.messages = kept;
+        self.size = self.messages.len() as u32;
+        cancelled
+    }
+
     /// Push a new item onto the message queue.
     fn push(&mut self, message: EventMessage) -> &mut Self {
         self.messages.push(message);
@@ -141,6 +1127,21 @@ impl EventMessageQueue {
             None
         }
     }
+
+    /// The time of the message at the head of the queue.
+    fn peek_time(&self) -> Option<Time> {
+        self.messages.last().map(|e| e.time)
+    }
+
+    /// The message at the head of the queue.
+    fn peek(&self) -> Option<&EventMessage> {
+        self.messages.last()
+    }
+
+    /// The number of heap bytes allocated for messages.
+    fn heap_bytes(&self) -> usize {
+        self.messages.capacity() * std::mem::size_of::<EventMessage>()
+    }
 }
 
 /// An _event_ is data that represents a declarative statement about something
@@ -149,10 +1150,20 @@ impl EventMessageQueue {
 /// There can be a one-to-one corresponds between an event message and an
 /// event, but, in general, multiple events can follow the successful
 /// hanlding of a single event message.
+///
+/// Events caused by an item from a registered arrival source are tagged with
+/// that source, so that events can be broken down by arrival stream. Events
+/// involving a particular server or item are tagged with that server or item.
+/// Server events for a batch are tagged with the first item in the batch.
+/// Events tagged with an item also carry the item's metadata as of the event.
 #[derive(Debug, Clone, Copy, PartialEq)]
 struct Event {
     time: Time,
     event_type: EventType,
+    source: Option<SourceId>,
+    server: Option<ServerId>,
+    item: Option<ItemId>,
+    metadata: Option<Metadata>,
 }
 
 /// The _event types_ defines here reflect the operations on the `State`.
@@ -166,6 +1177,133 @@ enum EventType {
     BufferDecremented,
     ServerIncremented,
     ServerDecremented,
+    /// An arrival source switched to the regime with the given index.
+    RegimeChanged(u32),
+    /// An on-off arrival source started an ON period.
+    SourceOn,
+    /// An on-off arrival source started an OFF period.
+    SourceOff,
+    /// The number of servers on shift changed to the given value.
+    CapacityChanged(u32),
+    /// A server went on vacation.
+    VacationStarted,
+    /// A server came back from vacation.
+    VacationEnded,
+    /// The item at the tail of the buffer jumped to the parallel queue at the
+    /// node with the given ID in a network, which decrements the buffer
+    /// count.
+    Jockeyed(u32),
+    /// A polling server left to switch over to the buffer with the given
+    /// index.
+    SwitchOverStarted(u32),
+    /// A polling server got to the buffer with the given index.
+    SwitchOverEnded(u32),
+    /// A server finished the phase with the given index of a multi-phase
+    /// service.
+    PhaseCompleted(u32),
+    /// The service of an item was interrupted, and the item went back to the
+    /// buffer.
+    ServiceInterrupted,
+    /// An item with the given priority was taken from the buffer under the
+    /// priority discipline.
+    PriorityServed(u32),
+    /// An item ran out of patience and left the buffer without being served,
+    /// which decrements the buffer count.
+    Reneged,
+    /// An arriving item decided not to join the queue.
+    Balked,
+    /// An arriving item was dropped because the buffer was full.
+    Dropped,
+    /// An arriving item was turned away by admission control.
+    Throttled,
+    /// An item that found the buffer full, or ran out of patience, left for
+    /// the virtual queue to wait for a callback.
+    CallbackQueued,
+    /// An item was recalled from the virtual queue to the front of the
+    /// buffer, which increments the buffer count.
+    Recalled,
+    /// The oldest buffered item was dropped to make room for an arrival.
+    Evicted,
+    /// An arriving item was held back because the buffer was full.
+    Blocked,
+    /// A blocked arrival was admitted to the buffer.
+    Unblocked,
+    /// An item finished service and went back to the buffer for the pass
+    /// with the given number, which increments the buffer count.
+    FedBack(u32),
+    /// An item was still waiting, or still in the system, when it was due.
+    DeadlineMissed,
+    /// The last subtask of a forked item finished, so the item left after
+    /// the given end-to-end time in the system.
+    Joined(u32),
+    /// A server took units from the resource pool with the given index for
+    /// a service.
+    ResourceAcquired(u32),
+    /// A server gave back units to the resource pool with the given index.
+    ResourceReleased(u32),
+    /// A server switched on under an N-policy.
+    ServerActivated,
+    /// A server switched off under an N-policy.
+    ServerDeactivated,
+    /// A server finished a service, but the next queue in the network had
+    /// no room for the item, so the server holds on to it.
+    ServerBlocked,
+    /// Room opened up for the item a blocked server was holding on to, so
+    /// it can leave.
+    ServerUnblocked,
+    /// An arrival that found the buffer full joined the retrial orbit, or an
+    /// item in orbit failed again, and will try again later.
+    RetryScheduled,
+    /// An item in orbit was admitted to the buffer.
+    RetrySucceeded,
+    /// An item in orbit gave up after its last attempt.
+    RetryAbandoned,
+}
+
+impl EventType {
+    /// The name of the event type and its data, if it has any.
+    fn parts(&self) -> (&'static str, Option<u32>) {
+        use EventType::*;
+        match self {
+            BufferIncremented => ("BufferIncremented", None),
+            BufferDecremented => ("BufferDecremented", None),
+            ServerIncremented => ("ServerIncremented", None),
+            ServerDecremented => ("ServerDecremented", None),
+            RegimeChanged(n) => ("RegimeChanged", Some(*n)),
+            SourceOn => ("SourceOn", None),
+            SourceOff => ("SourceOff", None),
+            CapacityChanged(n) => ("CapacityChanged", Some(*n)),
+            VacationStarted => ("VacationStarted", None),
+            VacationEnded => ("VacationEnded", None),
+            Jockeyed(n) => ("Jockeyed", Some(*n)),
+            SwitchOverStarted(n) => ("SwitchOverStarted", Some(*n)),
+            SwitchOverEnded(n) => ("SwitchOverEnded", Some(*n)),
+            PhaseCompleted(n) => ("PhaseCompleted", Some(*n)),
+            ServiceInterrupted => ("ServiceInterrupted", None),
+            PriorityServed(n) => ("PriorityServed", Some(*n)),
+            Reneged => ("Reneged", None),
+            Balked => ("Balked", None),
+            Dropped => ("Dropped", None),
+            Throttled => ("Throttled", None),
+            CallbackQueued => ("CallbackQueued", None),
+            Recalled => ("Recalled", None),
+            Evicted => ("Evicted", None),
+            Blocked => ("Blocked", None),
+            Unblocked => ("Unblocked", None),
+            FedBack(n) => ("FedBack", Some(*n)),
+            DeadlineMissed => ("DeadlineMissed", None),
+            Joined(n) => ("Joined", Some(*n)),
+            ResourceAcquired(n) => ("ResourceAcquired", Some(*n)),
+            ResourceReleased(n) => ("ResourceReleased", Some(*n)),
+            ServerActivated => ("ServerActivated", None),
+            ServerDeactivated => ("ServerDeactivated", None),
+            ServerBlocked => ("ServerBlocked", None),
+            ServerUnblocked => ("ServerUnblocked", None),
+            RetryScheduled => ("RetryScheduled", None),
+            RetrySucceeded => ("RetrySucceeded", None),
+            RetryAbandoned => ("RetryAbandoned", None),
+        }
+    }
 }
 
 /// The event log is essentially a wrapper around a vector of events. This is
@@ -192,6 +1330,11 @@ impl EventLog {
         self.size += 1;
         self
     }
+
+    /// The number of heap bytes allocated for events.
+    fn heap_bytes(&self) -> usize {
+        self.contents.capacity() * std::mem::size_of::<Event>()
+    }
 }
 
 /// Step the simulation forward by handling the next event message.
@@ -219,75 +1362,683 @@ fn step<'a>(
 }
 
 /// Handle the event message by updating the state and creating new followup
-/// event messages.
+/// event messages. Events involving an item are annotated with its metadata.
 fn handle_message(
     event_message: EventMessage,
     queue_state: &mut QueueState,
+) -> (&mut QueueState, Vec<EventMessage>, Vec<Event>) {
+    queue_state.met.clear();
+    let (queue_state, event_messages, mut events) = handle(event_message, queue_state);
+    for event in &mut events {
+        event.metadata = event.item.and_then(|id| queue_state.metadata(id));
+    }
+    queue_state.departed.clear();
+    (queue_state, event_messages, events)
+}
+
+/// Update the state for the event message, returning the followup event
+/// messages and the events.
+fn handle(
+    event_message: EventMessage,
+    queue_state: &mut QueueState,
 ) -> (&mut QueueState, Vec<EventMessage>, Vec<Event>) {
     match event_message.event_message_type {
-        EventMessageType::Arrive => {
-            if queue_state.can_buffer() {
-                // If an item can be added to the buffer, increment the buffer
-                // and create an event message to call for the next item to be
-                // served.
-                (
-                    queue_state.inc_buffer(),
-                    vec![EventMessage {
-                        event_message_type: EventMessageType::CallToServe,
-                        time: event_message.time,
-                    }],
-                    vec![Event {
-                        event_type: EventType::BufferIncremented,
-                        time: event_message.time,
-                    }],
-                )
+        EventMessageType::Arrive
+        | EventMessageType::ArriveFrom(_)
+        | EventMessageType::ArriveWith(_)
+        | EventMessageType::Retry(..) => {
+            let attributes = match event_message.event_message_type {
+                EventMessageType::ArriveFrom(id) => Attributes {
+                    source: Some(id),
+                    ..Attributes::default()
+                },
+                EventMessageType::ArriveWith(attributes)
+                | EventMessageType::Retry(attributes, _) => attributes,
+                _ => Attributes::default(),
+            };
+            let attempt = match event_message.event_message_type {
+                EventMessageType::Retry(_, attempt) => attempt,
+                _ => 1,
+            };
+            let source = attributes.source;
+            let event = |event_type, item| Event {
+                event_type,
+                time: event_message.time,
+                source,
+                server: None,
+                item,
+                metadata: None,
+            };
+            if attempt == 1 && queue_state.balks() {
+                // An item that balks doesn't join the queue, whether or not
+                // there's room. Items in orbit have already decided to join.
+                (queue_state, vec![], vec![event(EventType::Balked, None)])
+            } else if queue_state.throttles(event_message.time) {
+                // Admission control turns items away when they arrive too
+                // fast, whether or not there's room.
+                (queue_state, vec![], vec![event(EventType::Throttled, None)])
+            } else if queue_state.can_buffer() {
+                // If an item can be added to the buffer, add it.
+                let (messages, mut events) = admit(
+                    attributes,
+                    event_message.time,
+                    event_message.time,
+                    queue_state,
+                );
+                if attempt > 1 {
+                    events.insert(0, event(EventType::RetrySucceeded, None));
+                }
+                (queue_state, messages, events)
             } else {
-                // If the newly arrived item can't be added to the buffer, the
-                // state is unchanged and there are no new messages. Items that
-                // can't be buffered are effectively discarded.
-                (queue_state, vec![], vec![])
+                // Otherwise, the overflow policy decides what happens.
+                match queue_state.overflow {
+                    Overflow::DropOldest if queue_state.buffer_count > 0 => {
+                        let evicted = queue_state.evict_oldest();
+                        let (messages, mut events) = admit(
+                            attributes,
+                            event_message.time,
+                            event_message.time,
+                            queue_state,
+                        );
+                        events.insert(
+                            0,
+                            Event {
+                                source: evicted.source,
+                                ..event(EventType::Evicted, Some(evicted.id))
+                            },
+                        );
+                        (queue_state, messages, events)
+                    }
+                    Overflow::Callback => {
+                        let id = queue_state.new_item(attributes, event_message.time);
+                        queue_state.call_back(id);
+                        (
+                            queue_state,
+                            vec![],
+                            vec![event(EventType::CallbackQueued, Some(id))],
+                        )
+                    }
+                    Overflow::Block => {
+                        let from_source = matches!(
+                            event_message.event_message_type,
+                            EventMessageType::ArriveFrom(_)
+                        );
+                        queue_state.block(attributes, event_message.time, from_source);
+                        (queue_state, vec![], vec![event(EventType::Blocked, None)])
+                    }
+                    // An item that would be dropped goes into orbit instead,
+                    // unless it's out of attempts.
+                    _ => match queue_state.sample_retry_delay(attempt) {
+                        Some(delay) => {
+                            let retry = EventMessage {
+                                event_message_type: EventMessageType::Retry(
+                                    attributes,
+                                    attempt + 1,
+                                ),
+                                time: Time(event_message.time.0 + delay.0),
+                            };
+                            (
+                                queue_state,
+                                vec![retry],
+                                vec![event(EventType::RetryScheduled, None)],
+                            )
+                        }
+                        None => {
+                            queue_state.dropped += 1;
+                            let event_type = if attempt > 1 {
+                                EventType::RetryAbandoned
+                            } else {
+                                EventType::Dropped
+                            };
+                            (queue_state, vec![], vec![event(event_type, None)])
+                        }
+                    },
+                }
             }
         }
         EventMessageType::CallToServe => {
-            if queue_state.can_serve() {
-                // Getting this value here to avoid a borrow checker complaint
-                let server_duration = queue_state.server_duration;
-
-                // If an item can be served, decrement the buffer, increment
-                // the server, and create an exit event message.
-                (
-                    queue_state.dec_buffer().inc_server(),
-                    vec![EventMessage {
-                        event_message_type: EventMessageType::Exit,
-                        time: Time(event_message.time.0 + server_duration),
-                    }],
-                    vec![
-                        Event {
-                            event_type: EventType::BufferDecremented,
-                            time: event_message.time,
+            // A polling server that's done with its buffer moves on.
+            if let Some((next, end)) = queue_state.poll(event_message.time) {
+                let server = ServerId(0);
+                let message = EventMessage {
+                    event_message_type: EventMessageType::EndSwitchOver(server),
+                    time: end,
+                };
+                let event = Event {
+                    event_type: EventType::SwitchOverStarted(next),
+                    time: event_message.time,
+                    source: None,
+                    server: Some(server),
+                    item: None,
+                    metadata: None,
+                };
+                return (queue_state, vec![message], vec![event]);
+            }
+            // Under an N-policy, servers switch on once enough items are
+            // waiting.
+            let activated = queue_state.activate_servers();
+            let call = EventMessage {
+                event_message_type: EventMessageType::CallToServe,
+                time: event_message.time,
+            };
+            let recalls = vec![call; activated.len()];
+            let activated = activated
+                .into_iter()
+                .map(|server| Event {
+                    event_type: EventType::ServerActivated,
+                    time: event_message.time,
+                    source: None,
+                    server: Some(server),
+                    item: None,
+                    metadata: None,
+                })
+                .collect::<Vec<_>>();
+            let (mut messages, events) = if queue_state.can_serve() {
+                // If an item can be served, take it (or a batch of items) from
+                // the buffer, increment the server, and create an exit event
+                // message after a service time that depends on the class of
+                // the first item, unless it's known, plus a setup time if the
+                // server has been idle. The end of each phase of the service but
+                // the last gets its own message.
+                let items = queue_state.pop_batch();
+                let first = *queue_state.item(items[0]);
+                let source = first.source;
+                let server = queue_state.start_service(&items);
+                let setup_time = queue_state.sample_setup_time(server);
+                let mut phases = match first.remaining_work.or(first.work) {
+                    Some(work) => vec![queue_state.work_time(work, server)],
+                    None => queue_state.sample_service_phases(source, server),
+                };
+                let mut end = event_message.time.0 + setup_time.0;
+                // Each server that switched on calls for an item of its own.
+                let mut messages = recalls;
+                if let Some(sharing) = queue_state.processor_sharing.as_mut() {
+                    // Under processor sharing, the exit is scheduled by
+                    // `Simulation` along with everyone else's.
+                    let work = phases.iter().map(|t| t.0 as f64).sum();
+                    sharing.start(server, work, event_message.time);
+                    phases.clear();
+                }
+                for (phase, duration) in phases.iter().enumerate() {
+                    end += duration.0;
+                    messages.push(EventMessage {
+                        event_message_type: if phase + 1 < phases.len() {
+                            EventMessageType::CompletePhase(server, phase as u32)
+                        } else {
+                            EventMessageType::Exit(server)
                         },
-                        Event {
-                            event_type: EventType::ServerIncremented,
+                        time: Time(end),
+                    });
+                }
+                queue_state.server_pool.servers[server.0 as usize].busy_until = Time(end);
+                let mut events = activated;
+                for &id in &items {
+                    let item = queue_state.item(id);
+                    events.push(Event {
+                        event_type: EventType::BufferDecremented,
+                        time: event_message.time,
+                        source: item.source,
+                        server: None,
+                        item: Some(id),
+                        metadata: None,
+                    });
+                    if queue_state.discipline == Discipline::Priority {
+                        events.push(Event {
+                            event_type: EventType::PriorityServed(item.priority),
                             time: event_message.time,
-                        },
-                    ],
-                )
+                            source: item.source,
+                            server: None,
+                            item: Some(id),
+                            metadata: None,
+                        });
+                    }
+                }
+                events.push(Event {
+                    event_type: EventType::ServerIncremented,
+                    time: event_message.time,
+                    source,
+                    server: Some(server),
+                    item: Some(first.id),
+                    metadata: None,
+                });
+                events.extend(resource_events(
+                    EventType::ResourceAcquired,
+                    event_message.time,
+                    server,
+                    queue_state,
+                ));
+                // Taking items from the buffer may make room for blocked
+                // arrivals.
+                let (unblocked_messages, unblocked_events) =
+                    unblock(event_message.time, queue_state);
+                messages.extend(unblocked_messages);
+                events.extend(unblocked_events);
+                (messages, events)
             } else {
-                // If an item can't be served, the state is unchanged and there
-                // are no new messages.
-                (queue_state, vec![], vec![])
+                // If an item can't be served, there are no new messages.
+                (recalls, activated)
+            };
+            // An item in the virtual queue that's now at the head of the line
+            // is recalled.
+            if let Some(id) = queue_state.next_recall() {
+                messages.push(EventMessage {
+                    event_message_type: EventMessageType::Recall(id),
+                    time: event_message.time,
+                });
             }
+            (queue_state, messages, events)
         }
-        EventMessageType::Exit => (
-            queue_state.dec_server(),
-            vec![EventMessage {
+        EventMessageType::Exit(server) => {
+            let item = queue_state.serving(server).first().copied();
+            let event = Event {
+                event_type: EventType::ServerDecremented,
+                time: event_message.time,
+                source: item.and_then(|id| queue_state.item(id).source),
+                server: Some(server),
+                item,
+                metadata: None,
+            };
+            // Items that need another pass go back to the buffer before the
+            // server is released, and forked items whose last subtasks finish
+            // leave.
+            let fed_back = queue_state.set_time(event_message.time).feed_back(server);
+            let finished = queue_state.serving(server).to_vec();
+            let joined = queue_state.join(&finished);
+            queue_state.end_service(server);
+            let (queue_state, messages, mut events) =
+                vacation_or_serve(event_message.time, server, false, queue_state, event);
+            let mut exit_events = fed_back
+                .iter()
+                .map(|&id| {
+                    let item = queue_state.item(id);
+                    Event {
+                        event_type: EventType::FedBack(item.pass),
+                        time: event_message.time,
+                        source: item.source,
+                        server: Some(server),
+                        item: Some(id),
+                        metadata: None,
+                    }
+                })
+                .collect::<Vec<_>>();
+            exit_events.extend(resource_events(
+                EventType::ResourceReleased,
+                event_message.time,
+                server,
+                queue_state,
+            ));
+            exit_events.extend(joined.iter().map(|item| Event {
+                event_type: EventType::Joined(event_message.time.0 - item.arrived.0),
+                time: event_message.time,
+                source: item.source,
+                server: Some(server),
+                item: Some(item.id),
+                metadata: None,
+            }));
+            if !queue_state.server_pool.get(server).active {
+                exit_events.push(Event {
+                    event_type: EventType::ServerDeactivated,
+                    time: event_message.time,
+                    source: None,
+                    server: Some(server),
+                    item: None,
+                    metadata: None,
+                });
+            }
+            events.splice(1..1, exit_events);
+            (queue_state, messages, events)
+        }
+        EventMessageType::EndVacation(server) => {
+            let event = Event {
+                event_type: EventType::VacationEnded,
+                time: event_message.time,
+                source: None,
+                server: Some(server),
+                item: None,
+                metadata: None,
+            };
+            queue_state
+                .set_time(event_message.time)
+                .end_vacation(server);
+            vacation_or_serve(event_message.time, server, true, queue_state, event)
+        }
+        EventMessageType::EndSwitchOver(server) => {
+            queue_state.set_time(event_message.time);
+            let polling = queue_state.polling.as_mut().expect("polling system");
+            polling.end_switch_over();
+            let event = Event {
+                event_type: EventType::SwitchOverEnded(polling.current()),
+                time: event_message.time,
+                source: None,
+                server: Some(server),
+                item: None,
+                metadata: None,
+            };
+            let call = EventMessage {
                 event_message_type: EventMessageType::CallToServe,
                 time: event_message.time,
-            }],
-            vec![Event {
-                event_type: EventType::ServerDecremented,
+            };
+            (queue_state, vec![call], vec![event])
+        }
+        EventMessageType::CompletePhase(server, phase) => {
+            let item = queue_state.serving(server).first().copied();
+            let event = Event {
+                event_type: EventType::PhaseCompleted(phase),
                 time: event_message.time,
+                source: item.and_then(|id| queue_state.item(id).source),
+                server: Some(server),
+                item,
+                metadata: None,
+            };
+            (queue_state, vec![], vec![event])
+        }
+        EventMessageType::CheckDeadline(id) => {
+            if let Some(source) = queue_state.misses_deadline(id) {
+                let event = Event {
+                    event_type: EventType::DeadlineMissed,
+                    time: event_message.time,
+                    source,
+                    server: None,
+                    item: Some(id),
+                    metadata: None,
+                };
+                (queue_state, vec![], vec![event])
+            } else {
+                (queue_state, vec![], vec![])
+            }
+        }
+        EventMessageType::Renege(id) if queue_state.callbacks => {
+            // An impatient item takes a callback instead of reneging, which
+            // also makes room for a blocked arrival.
+            if queue_state.remove_buffered(id) {
+                queue_state.call_back(id);
+                let (messages, mut events) = unblock(event_message.time, queue_state);
+                events.insert(
+                    0,
+                    Event {
+                        event_type: EventType::CallbackQueued,
+                        time: event_message.time,
+                        source: queue_state.item(id).source,
+                        server: None,
+                        item: Some(id),
+                        metadata: None,
+                    },
+                );
+                (queue_state, messages, events)
+            } else {
+                (queue_state, vec![], vec![])
+            }
+        }
+        EventMessageType::Recall(id) => {
+            if queue_state.recall(id) {
+                let event = Event {
+                    event_type: EventType::Recalled,
+                    time: event_message.time,
+                    source: queue_state.item(id).source,
+                    server: None,
+                    item: Some(id),
+                    metadata: None,
+                };
+                let call = EventMessage {
+                    event_message_type: EventMessageType::CallToServe,
+                    time: event_message.time,
+                };
+                (queue_state, vec![call], vec![event])
+            } else {
+                (queue_state, vec![], vec![])
+            }
+        }
+        EventMessageType::Renege(id) => match queue_state.renege(id) {
+            // An item that reneges leaves the buffer without being served,
+            // which may make room for a blocked arrival.
+            Some(item) => {
+                let (messages, mut events) = unblock(event_message.time, queue_state);
+                events.insert(
+                    0,
+                    Event {
+                        event_type: EventType::Reneged,
+                        time: event_message.time,
+                        source: item.source,
+                        server: None,
+                        item: Some(id),
+                        metadata: None,
+                    },
+                );
+                (queue_state, messages, events)
+            }
+            None => (queue_state, vec![], vec![]),
+        },
+        EventMessageType::SetCapacity(server_capacity) => {
+            // Call one buffered item to be served for each server that's now
+            // available.
+            queue_state.set_server_capacity(server_capacity);
+            let available = server_capacity
+                .saturating_sub(queue_state.server_count)
+                .min(queue_state.buffer_count);
+            (
+                queue_state,
+                (0..available)
+                    .map(|_| EventMessage {
+                        event_message_type: EventMessageType::CallToServe,
+                        time: event_message.time,
+                    })
+                    .collect(),
+                vec![Event {
+                    event_type: EventType::CapacityChanged(server_capacity),
+                    time: event_message.time,
+                    source: None,
+                    server: None,
+                    item: None,
+                    metadata: None,
+                }],
+            )
+        }
+        // Processes are resumed by the process runtime in `Simulation`, and
+        // interrupts need the message queue to cancel exits, so they're
+        // handled by `interrupt` instead. Either way, the state is unchanged
+        // here.
+        EventMessageType::Resume(_) | EventMessageType::Interrupt(_) => {
+            (queue_state, vec![], vec![])
+        }
+    }
+}
+
+/// Add an item that arrived at `arrived` to the buffer at `time`, or its
+/// subtasks if items fork, and create an event message to call for the next
+/// item to be served. An item with a deadline gets a message for when it's
+/// due. A buffered item that might renege gets a message for when it runs
+/// out of patience, and under preemption, one that can't be served right
+/// away might interrupt a service.
+fn admit(
+    attributes: Attributes,
+    arrived: Time,
+    time: Time,
+    queue_state: &mut QueueState,
+) -> (Vec<EventMessage>, Vec<Event>) {
+    let items = match queue_state.fork {
+        Some(k) => queue_state.push_forked(attributes, arrived, k),
+        None => vec![queue_state.push_buffer(attributes, arrived)],
+    };
+    let first = queue_state.item(items[0]);
+    let item = first.parent.unwrap_or(first.id);
+    let mut messages = vec![];
+    if let Some(due) = queue_state.item(item).due {
+        messages.push(EventMessage {
+            event_message_type: EventMessageType::CheckDeadline(item),
+            time: due.max(time),
+        });
+    }
+    let mut events = vec![];
+    for item in items {
+        messages.push(EventMessage {
+            event_message_type: EventMessageType::CallToServe,
+            time,
+        });
+        if let Some(patience) = queue_state.sample_patience() {
+            messages.push(EventMessage {
+                event_message_type: EventMessageType::Renege(item),
+                time: Time(time.0 + patience.0),
+            });
+        }
+        if !queue_state.can_serve() {
+            if let Some(server) = queue_state.preemption_victim(item, time) {
+                messages.push(EventMessage {
+                    event_message_type: EventMessageType::Interrupt(server),
+                    time,
+                });
+            }
+        }
+        events.push(Event {
+            event_type: EventType::BufferIncremented,
+            time,
+            source: attributes.source,
+            server: None,
+            item: Some(item),
+            metadata: None,
+        });
+    }
+    (messages, events)
+}
+
+/// Admit blocked arrivals, in order, while there's room in the buffer. Each
+/// one keeps its original arrival time.
+fn unblock(time: Time, queue_state: &mut QueueState) -> (Vec<EventMessage>, Vec<Event>) {
+    let mut messages = vec![];
+    let mut events = vec![];
+    while queue_state.can_buffer() {
+        let Some((attributes, arrived, from_source)) = queue_state.blocked_arrivals.pop_front()
+        else {
+            break;
+        };
+        let (m, e) = admit(attributes, arrived, time, queue_state);
+        let item = e[0].item;
+        if let (true, Some(source)) = (from_source, attributes.source) {
+            queue_state.resumed_sources.push(source);
+        }
+        events.push(Event {
+            event_type: EventType::Unblocked,
+            time,
+            source: attributes.source,
+            server: None,
+            item,
+            metadata: None,
+        });
+        messages.extend(m);
+        events.extend(e);
+    }
+    (messages, events)
+}
+
+/// Interrupt the service on a server whose exit at `exit` was cancelled,
+/// putting its items back in the buffer and calling the next item to be
+/// served.
+fn interrupt(
+    event_message: EventMessage,
+    server: ServerId,
+    exit: Time,
+    queue_state: &mut QueueState,
+) -> (&mut QueueState, Vec<EventMessage>, Vec<Event>) {
+    let time = event_message.time;
+    let items = queue_state.set_time(time).interrupt_service(server, exit);
+    let source = |id| queue_state.item(id).source;
+    let events = items
+        .iter()
+        .map(|&id| Event {
+            event_type: EventType::ServiceInterrupted,
+            time,
+            source: source(id),
+            server: Some(server),
+            item: Some(id),
+            metadata: None,
+        })
+        .chain(items.iter().map(|&id| Event {
+            event_type: EventType::BufferIncremented,
+            time,
+            source: source(id),
+            server: None,
+            item: Some(id),
+            metadata: None,
+        }))
+        .chain([Event {
+            event_type: EventType::ServerDecremented,
+            time,
+            source: None,
+            server: Some(server),
+            item: None,
+            metadata: None,
+        }])
+        .chain(resource_events(
+            EventType::ResourceReleased,
+            time,
+            server,
+            queue_state,
+        ))
+        .collect();
+    (
+        queue_state,
+        vec![EventMessage {
+            event_message_type: EventMessageType::CallToServe,
+            time,
+        }],
+        events,
+    )
+}
+
+/// Events for a server acquiring or releasing its units of every resource
+/// pool.
+fn resource_events(
+    event_type: fn(u32) -> EventType,
+    time: Time,
+    server: ServerId,
+    queue_state: &QueueState,
+) -> Vec<Event> {
+    (0..queue_state.resources.len() as u32)
+        .map(|pool| Event {
+            event_type: event_type(pool),
+            time,
+            source: None,
+            server: Some(server),
+            item: None,
+            metadata: None,
+        })
+        .collect()
+}
+
+/// Send a server that just became free on vacation, or call the next item to
+/// be served if it stays.
+fn vacation_or_serve(
+    time: Time,
+    server: ServerId,
+    returning: bool,
+    queue_state: &mut QueueState,
+    event: Event,
+) -> (&mut QueueState, Vec<EventMessage>, Vec<Event>) {
+    match queue_state.start_vacation(server, returning) {
+        Some(end) => (
+            queue_state,
+            vec![EventMessage {
+                event_message_type: EventMessageType::EndVacation(server),
+                time: end,
             }],
+            vec![
+                event,
+                Event {
+                    event_type: EventType::VacationStarted,
+                    time,
+                    source: None,
+                    server: Some(server),
+                    item: None,
+                    metadata: None,
+                },
+            ],
+        ),
+        None => (
+            queue_state,
+            vec![EventMessage {
+                event_message_type: EventMessageType::CallToServe,
+                time,
+            }],
+            vec![event],
         ),
     }
 }
@@ -299,38 +2050,41 @@ fn main() {
     //
     // The position argument to `new` are the buffer capacity,
     // server capacity, and server duration.
-    let queue_state = &mut QueueState::new(5, 2, 10);
+    let sim = &mut Simulation::new(QueueState::new(5, 2, 10));
 
-    // Prime the event message queue with some arrival event messasges.
+    // Add a Poisson arrival source. Arrivals are scheduled one at a time as
+    // the simulation runs.
     //
     // CHANGE ME!
     //
-    // Change the number of arrivals.
-    let n_arrivals = 10;
-    let emq = &mut EventMessageQueue::new();
-    let emq = (0..n_arrivals)
-        .map(|t| EventMessage {
-            event_message_type: EventMessageType::Arrive,
-            time: Time(t),
-        })
-        .fold(emq, |acc, em| acc.push(em));
-
-    // Create an initially empty event log
-    let log = &mut EventLog::new();
-
-    // Call `step` in a loop until the message queue is empty
-    println!("\n\n");
-    println!("{0: >10} {1: >10} {2: >10}", "Time", "Buffer", "Server");
-    while let Some((_emq, state, _log)) = step(emq, queue_state, log) {
-        println!(
-            "{0: >10} {1: >10} {2: >10}",
-            state.time.0, state.buffer_count, state.server_count
-        );
+    // The position arguments to `new` are the arrival rate and the RNG seed.
+    sim.add_source(PoissonArrivals::new(0.2, 0));
+
+    // Arrivals never run out, so only simulate up to a horizon.
+    //
+    // CHANGE ME!
+    let horizon = Time(100);
+
+    // Call `step_until` in a loop until the horizon is reached, printing the
+    // state after each step
+    while let Some(state) = sim.step_until(horizon) {
+        println!("{state}");
     }
 
     // Print the contents of the event log
-    println!("\n\n");
-    log.contents.iter().for_each(|e| println!("{:?}", e));
+    println!();
+    sim.log.contents.iter().for_each(|e| println!("{e}"));
+
+    // Print a report of the run
+    println!();
+    print!("{}", report::report(&sim.state, &sim.summary()));
+
+    // With the `plot` feature, `--plot <dir>` draws charts of the run into a
+    // directory.
+    #[cfg(feature = "plot")]
+    if let Some(dir) = std::env::args().skip_while(|a| a != "--plot").nth(1) {
+        plot::write_charts(sim, &dir).expect("failed to write charts");
+    }
 }
 
 // Below are some rudimentary unit tests.
@@ -340,6 +2094,7 @@ mod tests {
     use super::*;
 
     #[test]
+    #[allow(clippy::useless_vec)]
     fn test_emq_mechanics() {
         // Prime the EMQ with a couple messages
         let emq = &mut EventMessageQueue::new();
@@ -391,6 +2146,10 @@ mod tests {
         let e = Event {
             time: Time(0),
             event_type: EventType::BufferIncremented,
+            source: None,
+            server: None,
+            item: None,
+            metadata: None,
         };
         let log = log.push(e);
         assert_eq!(1, log.size);
@@ -419,4 +2178,36 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_dropped_arrivals() {
+        // With room for one buffered item and no servers, the second of two
+        // arrivals is dropped, and the drop is logged and counted.
+        let emq = &mut EventMessageQueue::new();
+        let state = &mut QueueState::new(1, 0, 10);
+        let log = &mut EventLog::new();
+        for t in [0, 1] {
+            emq.push(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        while step(emq, state, log).is_some() {}
+
+        let dropped = log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::Dropped)
+            .map(|e| e.time)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Time(1)], dropped);
+        assert_eq!(1, state.dropped);
+        assert_eq!(0.5, state.loss_probability());
+    }
+
+    #[test]
+    fn test_event_type_parts() {
+        assert_eq!(("Reneged", None), EventType::Reneged.parts());
+        assert_eq!(("Jockeyed", Some(3)), EventType::Jockeyed(3).parts());
+    }
 }
@@ -0,0 +1,72 @@
+//! User-defined metrics.
+//!
+//! The built-in statistics cover the usual measures, but models often need
+//! something bespoke. A `Metric` registered with a `Simulation` sees every
+//! event as it's logged, along with the state after the step, and reports a
+//! value at the end, so there's no need to post-process the log.
+
+use std::fmt::Debug;
+
+use crate::{Event, QueueState, Time};
+
+/// The value a metric reports:
+/// - `Count`: A number of occurrences.
+/// - `Real`: A real number, such as an average.
+/// - `Series`: Values over time.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MetricValue {
+    Count(u64),
+    Real(f64),
+    Series(Vec<(Time, f64)>),
+}
+
+/// A statistic that's updated from each event of a run.
+pub trait Metric: Debug {
+    /// Update the statistic with an event and the state after the step that
+    /// produced it.
+    fn observe_event(&mut self, e: &Event, s: &QueueState);
+
+    /// The value of the statistic so far.
+    fn finalize(&self) -> MetricValue;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, EventType};
+
+    /// The most items in the buffer after an arrival.
+    #[derive(Debug, Default)]
+    struct LongestQueue(u32);
+
+    impl Metric for LongestQueue {
+        fn observe_event(&mut self, e: &Event, s: &QueueState) {
+            if e.event_type == EventType::BufferIncremented {
+                self.0 = self.0.max(s.buffer_count);
+            }
+        }
+
+        fn finalize(&self) -> MetricValue {
+            MetricValue::Count(self.0 as u64)
+        }
+    }
+
+    #[test]
+    fn test_custom_metric() {
+        // Items arrive at 0, 1, and 2 for services of 5, so the last two
+        // wait together.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        sim.add_metric("longest", LongestQueue::default());
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        assert_eq!(Some(MetricValue::Count(2)), sim.metric("longest"));
+        assert_eq!(None, sim.metric("missing"));
+        assert_eq!(vec![("longest", MetricValue::Count(2))], sim.metrics());
+    }
+}
@@ -0,0 +1,1401 @@
+//! Networks of queues, where items that leave one queue go on to another.
+//!
+//! Where an item goes when it exits a node is up to the node's routing policy
+//! (see the `routing` module), e.g., another node chosen with probabilities
+//! given by a routing matrix. By default, items leave the network. Items
+//! enter the network from arrival sources at any node, or from source nodes,
+//! which pass every item they generate straight on. Sink nodes take items
+//! out of the network, and record when and from where each one left.
+//!
+//! Each node of a network is a queue with its own `Simulation`, i.e., its own
+//! state, pending event messages, and arrival sources. The network keeps a
+//! shared calendar of when each node's earliest message is due, tagged with
+//! the node's ID, and always handles the earliest pending message across
+//! every node, with ties going to the node with the lowest ID. Finding it
+//! takes time logarithmic in the number of nodes, rather than a scan of
+//! every node's queue. When an item exits a node, the network
+//! passes it on to the next node as an arrival at the same time, keeping its
+//! class, i.e., the ID of the source it came from at the node where it
+//! entered the network, unless the routing policy switches it.
+//!
+//! A closed network has a fixed population of items that circulate forever,
+//! with no arrivals from outside. The items start out at a reference node,
+//! e.g., the terminals of an interactive system, where a think-time node
+//! (see `Network::add_think_node`) models users thinking between requests.
+//! Items routed out of a closed network go back to the reference node
+//! instead. The network measures the throughput at the reference node and
+//! the response time, i.e., the time from when an item leaves the reference
+//! node until it gets back to it.
+//!
+//! Items can also jockey between parallel queues: when one queue in a group
+//! gets long enough compared to the shortest, the item at the tail of its
+//! buffer jumps to the shortest one (see `Jockeying`).
+//!
+//! Under blocking after service, an item that finishes service at a node
+//! whose next node has a full buffer stays on its server, which can't take
+//! another item, until the next node has room for it. The item then leaves
+//! at the time room opens up. Without blocking, the item is turned away by
+//! the next node as any other arrival to a full buffer would be.
+//!
+//! Large networks can be composed of sub-networks, e.g., a "branch office"
+//! block added many times (see `Network::add_subnetwork`). A sub-network's
+//! nodes join the network under namespaced names, e.g., `"branch-3/teller"`,
+//! and their routing policies keep referring to nodes by their IDs within
+//! the sub-network. Items that leave a sub-network go wherever the block's
+//! exit leads.
+//!
+//! A network can be drawn as a Graphviz DOT graph (see `Network::to_dot`),
+//! e.g., to check a topology before a long run.
+//!
+//! Events logged by the nodes are collected in a network-wide log, tagged
+//! with the node that logged them. The network also keeps time averages of
+//! each node's queue length and busy servers, from which per-node statistics
+//! are worked out (see `Network::node_stats`), and records the journey of
+//! every item that leaves the network: its class, the nodes it visited, and
+//! its end-to-end sojourn time.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::arrival::{ArrivalProcess, Attributes, SourceId};
+use crate::config::{self, ConfigError};
+use crate::dist::Distribution;
+use crate::item::{Item, ItemId};
+use crate::routing::{Destination, Fixed, LoadBalancer, Probabilistic, Route, Router};
+use crate::server::ServerId;
+use crate::simulation::Simulation;
+use crate::{Event, EventMessage, EventMessageType, EventType, QueueState, Time};
+
+/// Identifies a node by the order in which it was added to the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u32);
+
+/// An event logged by a node.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeEvent {
+    pub node: NodeId,
+    pub event: Event,
+}
+
+/// An item that a sink took out of the network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exited {
+    /// The item's class when it left.
+    pub class: Option<SourceId>,
+    /// The node the item came from.
+    pub from: NodeId,
+    pub time: Time,
+}
+
+/// What a node does:
+/// - `Queue`: Buffers and serves items.
+/// - `Source`: Generates items from an arrival process and passes each one
+///   straight on, as a dispatcher does.
+/// - `Sink`: Takes the items routed to it out of the network, recording them
+///   in the order they left.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeKind {
+    Queue,
+    Source,
+    Sink(Vec<Exited>),
+}
+
+/// A node in a network.
+#[derive(Debug)]
+pub struct Node {
+    pub name: String,
+    pub kind: NodeKind,
+    pub sim: Simulation,
+}
+
+impl Node {
+    /// The items a sink took out of the network, or none if this isn't a
+    /// sink.
+    pub fn exited(&self) -> &[Exited] {
+        match &self.kind {
+            NodeKind::Sink(exited) => exited,
+            _ => &[],
+        }
+    }
+}
+
+/// A group of parallel queues between which items jockey. Whenever a queue
+/// holds at least `threshold` more items, buffered or in service, than the
+/// shortest queue in the group, the item at the tail of its buffer jumps to
+/// the shortest queue, with ties going to the lowest node ID. The jumping
+/// item arrives at the other queue at the same time, keeping its class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Jockeying {
+    pub nodes: Vec<NodeId>,
+    pub threshold: u32,
+}
+
+impl Jockeying {
+    /// Create a group of parallel queues with the given threshold, which is
+    /// at least 2, so that items don't jump back and forth.
+    pub fn new(nodes: Vec<NodeId>, threshold: u32) -> Self {
+        assert!(threshold >= 2, "jockeying threshold must be at least 2");
+        Self { nodes, threshold }
+    }
+}
+
+/// Statistics of a node, over the time from 0 until the last message handled
+/// by the network.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NodeStats {
+    /// The time-average number of busy servers.
+    pub mean_busy_servers: f64,
+    /// The time-average fraction of servers that are busy, or `None` if the
+    /// server pool is unbounded.
+    pub utilization: Option<f64>,
+    /// The time-average number of buffered items.
+    pub mean_queue_length: f64,
+    /// The mean time from joining the buffer to entering service, or `None`
+    /// if no item has entered service.
+    pub mean_wait: Option<f64>,
+}
+
+/// The path of an item through a network, from when it first arrived until
+/// it left.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Journey {
+    /// The item's class when it entered the network, i.e., the source it
+    /// came from at the node where it entered.
+    pub class: Option<SourceId>,
+    /// The nodes the item visited, in order.
+    pub path: Vec<NodeId>,
+    pub entered: Time,
+    pub left: Time,
+}
+
+impl Journey {
+    /// The end-to-end time the item spent in the network.
+    pub fn sojourn_time(&self) -> u32 {
+        self.left.0 - self.entered.0
+    }
+}
+
+/// Time-weighted sums of a node's queue length and busy servers.
+#[derive(Debug, Clone, Copy, Default)]
+struct Areas {
+    since: Time,
+    buffered: f64,
+    busy: f64,
+}
+
+/// A sub-network that was added to a network, as the range of node IDs its
+/// nodes got, where its items go when they leave it, and the sub-network it's
+/// nested in, if any. An exit of `Destination::Exit` leads out of the
+/// enclosing network.
+#[derive(Debug, Clone, Copy)]
+struct Block {
+    base: u32,
+    len: u32,
+    exit: Destination,
+    parent: Option<usize>,
+}
+
+/// The exit of a server that's blocked, along with where the items it's
+/// holding on to are going.
+#[derive(Debug)]
+struct Held {
+    node: NodeId,
+    exit: EventMessage,
+    routes: Vec<(ItemId, Route)>,
+}
+
+/// A network of queues, along with the routing policy of each node.
+#[derive(Debug)]
+pub struct Network {
+    nodes: Vec<Node>,
+    routers: Vec<Box<dyn Router>>,
+    pub log: Vec<NodeEvent>,
+    time: Time,
+    blocking: bool,
+    held: Vec<Held>,
+    routed: HashMap<(NodeId, ItemId), Route>,
+    reference: Option<NodeId>,
+    population: u32,
+    departures: HashMap<u64, Time>,
+    response_times: Vec<u32>,
+    areas: Vec<Areas>,
+    next_tag: u64,
+    in_transit: HashMap<u64, Journey>,
+    pub journeys: Vec<Journey>,
+    jockeying: Vec<Jockeying>,
+    blocks: Vec<Block>,
+    /// The innermost sub-network each node belongs to, if any.
+    block_of: Vec<Option<usize>>,
+    /// When each node's earliest pending message is due, earliest first.
+    /// Entries go stale as nodes handle and cancel messages, and are
+    /// refreshed as they come up.
+    calendar: BinaryHeap<Reverse<(Time, NodeId)>>,
+    /// When each node's live entry on the calendar is due, if it has one.
+    listed: Vec<Option<Time>>,
+    /// Nodes that may have been sent earlier messages than the calendar
+    /// knows of.
+    touched: Vec<NodeId>,
+}
+
+/// A read-only view of the network that routing policies can base their
+/// decisions on.
+#[derive(Debug, Clone, Copy)]
+pub struct NetworkState<'a> {
+    nodes: &'a [Node],
+    pub time: Time,
+}
+
+impl NetworkState<'_> {
+    /// The state of a node's queue.
+    pub fn state(&self, node: NodeId) -> &QueueState {
+        &self.nodes[node.0 as usize].sim.state
+    }
+
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check if the network has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Network {
+    /// Create an empty network.
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            routers: vec![],
+            log: vec![],
+            time: Time(0),
+            blocking: false,
+            held: vec![],
+            routed: HashMap::new(),
+            reference: None,
+            population: 0,
+            departures: HashMap::new(),
+            response_times: vec![],
+            areas: vec![],
+            next_tag: 0,
+            in_transit: HashMap::new(),
+            journeys: vec![],
+            jockeying: vec![],
+            blocks: vec![],
+            block_of: vec![],
+            calendar: BinaryHeap::new(),
+            listed: vec![],
+            touched: vec![],
+        }
+    }
+
+    /// Build a network from a JSON config (see the `config` module).
+    pub fn from_json(text: &str) -> Result<Self, ConfigError> {
+        config::network(text)
+    }
+
+    /// Make the network closed, with the given number of items starting out
+    /// at the reference node at time 0. The nodes should have room for the
+    /// whole population, since items that are turned away are lost.
+    pub fn with_population(mut self, reference: NodeId, population: u32) -> Self {
+        self.reference = Some(reference);
+        self.population = population;
+        for _ in 0..population {
+            let tag = self.next_tag;
+            self.next_tag += 1;
+            self.schedule(
+                reference,
+                EventMessage {
+                    event_message_type: EventMessageType::ArriveWith(Attributes {
+                        tag: Some(tag),
+                        ..Attributes::default()
+                    }),
+                    time: Time(0),
+                },
+            );
+        }
+        self
+    }
+
+    /// Block servers whose items can't move on because the next node's
+    /// buffer is full (blocking after service).
+    pub fn with_blocking(mut self) -> Self {
+        self.blocking = true;
+        self
+    }
+
+    /// Create a network of the given queues, where `matrix[i][j]` is the
+    /// probability that an item that exits node `i` goes to node `j`. Node
+    /// `i` samples its routes from an RNG seeded with `seed + i`. The nodes
+    /// are named by their IDs, e.g., `"node-0"`.
+    pub fn with_routing_matrix(states: Vec<QueueState>, matrix: &[Vec<f64>], seed: u64) -> Self {
+        let mut network = Self::new();
+        for state in states {
+            network.add_node(format!("node-{}", network.nodes.len()), state);
+        }
+        for (i, row) in matrix.iter().enumerate() {
+            let routes = row
+                .iter()
+                .enumerate()
+                .filter(|&(_, &p)| p > 0.0)
+                .map(|(j, &p)| (NodeId(j as u32), p))
+                .collect();
+            network.set_router(
+                NodeId(i as u32),
+                Probabilistic::new(routes, seed + i as u64),
+            );
+        }
+        network
+    }
+
+    /// Create a network of queues in series, where items that exit one queue
+    /// go on to the next, and leave after the last one. The nodes are named
+    /// by their IDs, e.g., `"node-0"`.
+    pub fn tandem(states: Vec<QueueState>) -> Self {
+        let mut network = Self::new();
+        let n = states.len() as u32;
+        for state in states {
+            let id = network.add_node(format!("node-{}", network.nodes.len()), state);
+            if id.0 + 1 < n {
+                network.connect(id, NodeId(id.0 + 1));
+            }
+        }
+        network
+    }
+
+    /// Add a queue whose items leave the network when they exit.
+    pub fn add_node(&mut self, name: impl Into<String>, state: QueueState) -> NodeId {
+        let id = NodeId(self.nodes.len() as u32);
+        self.nodes.push(Node {
+            name: name.into(),
+            kind: NodeKind::Queue,
+            sim: Simulation::new(state),
+        });
+        self.routers.push(Box::new(Fixed(Destination::Exit)));
+        self.areas.push(Areas::default());
+        self.block_of.push(None);
+        self.listed.push(None);
+        id
+    }
+
+    /// Add a dispatcher, a node that passes each item that arrives straight
+    /// on to one of the given nodes, as chosen by a load balancer. Items are
+    /// admitted and served in no time, so the dispatcher never holds on to
+    /// them.
+    pub fn add_dispatcher(&mut self, name: impl Into<String>, balancer: LoadBalancer) -> NodeId {
+        let state = QueueState::new(u32::MAX, 1, 0).with_infinite_servers();
+        let id = self.add_node(name, state);
+        self.set_router(id, balancer);
+        id
+    }
+
+    /// Add a sub-network as a block of nodes, returning the ID its first node
+    /// got, which is where items enter the block. The nodes are renamed
+    /// `"{name}/{node}"`. Items that leave the sub-network leave the network,
+    /// unless the block's exit is set (see `set_subnetwork_exit`).
+    ///
+    /// The sub-network's nodes, sources, routing policies, and jockeying
+    /// groups carry over, but it should be open and have no blocking of its
+    /// own, which is up to the network it's added to.
+    pub fn add_subnetwork(&mut self, name: &str, sub: Network) -> NodeId {
+        let base = self.nodes.len() as u32;
+        let offset = self.blocks.len();
+        let outer = offset + sub.blocks.len();
+        let shift = |destination| match destination {
+            Destination::Node(node) => Destination::Node(NodeId(node.0 + base)),
+            Destination::Exit => Destination::Exit,
+        };
+        for block in sub.blocks {
+            self.blocks.push(Block {
+                base: block.base + base,
+                exit: shift(block.exit),
+                parent: Some(block.parent.map_or(outer, |parent| parent + offset)),
+                ..block
+            });
+        }
+        self.blocks.push(Block {
+            base,
+            len: sub.nodes.len() as u32,
+            exit: Destination::Exit,
+            parent: None,
+        });
+        for jockeying in sub.jockeying {
+            self.jockeying.push(Jockeying {
+                nodes: jockeying
+                    .nodes
+                    .iter()
+                    .map(|node| NodeId(node.0 + base))
+                    .collect(),
+                ..jockeying
+            });
+        }
+        for (node, block) in sub.nodes.into_iter().zip(sub.block_of) {
+            self.touched.push(NodeId(self.nodes.len() as u32));
+            self.nodes.push(Node {
+                name: format!("{name}/{}", node.name),
+                ..node
+            });
+            self.block_of
+                .push(Some(block.map_or(outer, |block| block + offset)));
+            self.listed.push(None);
+        }
+        self.routers.extend(sub.routers);
+        self.areas.extend(sub.areas);
+        NodeId(base)
+    }
+
+    /// Set where items go when they leave the sub-network whose first node
+    /// has the given ID.
+    pub fn set_subnetwork_exit(&mut self, entry: NodeId, exit: Destination) -> &mut Self {
+        let block = self
+            .blocks
+            .iter_mut()
+            .rev()
+            .find(|block| block.base == entry.0)
+            .expect("no sub-network starts at the node");
+        block.exit = exit;
+        self
+    }
+
+    /// Add a source node, which generates items from the given arrival
+    /// process and passes them straight on, like a dispatcher. Items leave
+    /// the network unless the source is connected to another node.
+    pub fn add_source_node(
+        &mut self,
+        name: impl Into<String>,
+        process: impl ArrivalProcess + 'static,
+    ) -> NodeId {
+        let state = QueueState::new(u32::MAX, 1, 0).with_infinite_servers();
+        let id = self.add_node(name, state);
+        self.nodes[id.0 as usize].kind = NodeKind::Source;
+        self.add_source(id, process);
+        id
+    }
+
+    /// Add a sink node, which takes the items routed to it out of the
+    /// network.
+    pub fn add_sink(&mut self, name: impl Into<String>) -> NodeId {
+        let id = self.add_node(name, QueueState::new(0, 0, 0));
+        self.nodes[id.0 as usize].kind = NodeKind::Sink(vec![]);
+        id
+    }
+
+    /// Add a think-time node, where every item is delayed for a sampled
+    /// think time, independently of the others, before it moves on.
+    pub fn add_think_node(
+        &mut self,
+        name: impl Into<String>,
+        think_time: impl Distribution + 'static,
+        seed: u64,
+    ) -> NodeId {
+        let state = QueueState::new(u32::MAX, 1, 0)
+            .with_infinite_servers()
+            .with_service_time(think_time, seed);
+        self.add_node(name, state)
+    }
+
+    /// Set the routing policy of a node.
+    pub fn set_router(&mut self, node: NodeId, router: impl Router + 'static) -> &mut Self {
+        self.routers[node.0 as usize] = Box::new(router);
+        self
+    }
+
+    /// Let items jockey between a group of parallel queues.
+    pub fn add_jockeying(&mut self, jockeying: Jockeying) -> &mut Self {
+        self.jockeying.push(jockeying);
+        self
+    }
+
+    /// Send every item that exits one node on to another.
+    pub fn connect(&mut self, from: NodeId, to: NodeId) -> &mut Self {
+        self.set_router(from, Fixed(Destination::Node(to)))
+    }
+
+    /// Add an arrival source that feeds a node.
+    pub fn add_source(&mut self, node: NodeId, source: impl ArrivalProcess + 'static) -> SourceId {
+        self.touched.push(node);
+        self.nodes[node.0 as usize].sim.add_source(source)
+    }
+
+    /// Get a node.
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    /// Get a node to change it, e.g., to add an arrival source.
+    pub fn node_mut(&mut self, id: NodeId) -> &mut Node {
+        self.touched.push(id);
+        &mut self.nodes[id.0 as usize]
+    }
+
+    /// Look up a node by its name.
+    pub fn node_id(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name == name)
+            .map(|i| NodeId(i as u32))
+    }
+
+    /// The number of nodes.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Check if the network has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// The time of the last message handled.
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Schedule an event message at a node.
+    pub fn schedule(&mut self, node: NodeId, event_message: EventMessage) -> &mut Self {
+        self.touched.push(node);
+        self.nodes[node.0 as usize].sim.schedule(event_message);
+        self
+    }
+
+    /// Put a node on the calendar at the time its earliest message is due,
+    /// unless it's already there.
+    fn list(&mut self, id: NodeId) {
+        let due = self.nodes[id.0 as usize].sim.emq.peek_time();
+        if due != self.listed[id.0 as usize] {
+            if let Some(time) = due {
+                self.calendar.push(Reverse((time, id)));
+            }
+            self.listed[id.0 as usize] = due;
+        }
+    }
+
+    /// The node with the earliest pending message, from the calendar.
+    ///
+    /// A node's messages only ever get earlier by being scheduled, which
+    /// touches the node, so a live entry that doesn't match the node's
+    /// earliest message is for one it has since handled or cancelled, and is
+    /// replaced by one for the message that's now earliest.
+    fn next_node(&mut self) -> Option<NodeId> {
+        for id in std::mem::take(&mut self.touched) {
+            self.list(id);
+        }
+        loop {
+            let Reverse((time, id)) = *self.calendar.peek()?;
+            if self.listed[id.0 as usize] == Some(time) {
+                if self.nodes[id.0 as usize].sim.emq.peek_time() == Some(time) {
+                    return Some(id);
+                }
+                self.listed[id.0 as usize] = None;
+                self.calendar.pop();
+                self.list(id);
+            } else {
+                self.calendar.pop();
+            }
+        }
+    }
+
+    /// Handle the earliest pending message in the network, returning the node
+    /// that handled it, or `None` if there are no more messages.
+    pub fn step(&mut self) -> Option<NodeId> {
+        let id = self.next_node()?;
+        let node = &self.nodes[id.0 as usize];
+        // The items in service on a server that's about to exit may leave
+        // the node, so where they go is settled up front.
+        let exit = node.sim.emq.peek().copied();
+        let exiting = match exit.map(|em| em.event_message_type) {
+            Some(EventMessageType::Exit(server)) => node
+                .sim
+                .state
+                .serving(server)
+                .iter()
+                .map(|&item| *node.sim.state.item(item))
+                .collect::<Vec<_>>(),
+            _ => vec![],
+        };
+        let routes = exiting
+            .iter()
+            .map(|item| match self.routed.remove(&(id, item.id)) {
+                Some(route) => route,
+                None => self.next_route(id, item),
+            })
+            .collect::<Vec<_>>();
+        if let Some(
+            exit @ EventMessage {
+                event_message_type: EventMessageType::Exit(server),
+                ..
+            },
+        ) = exit
+        {
+            if self.blocking && !self.has_room(&routes) {
+                self.block(id, server, exit, &exiting, routes);
+                return Some(id);
+            }
+        }
+        let node = &mut self.nodes[id.0 as usize];
+        if let Some(time) = node.sim.emq.peek_time() {
+            let areas = &mut self.areas[id.0 as usize];
+            let elapsed = (time.0 - areas.since.0) as f64;
+            areas.buffered += elapsed * node.sim.state.buffer_count as f64;
+            areas.busy += elapsed * node.sim.state.server_count as f64;
+            areas.since = time;
+        }
+        let logged = node.sim.log.contents.len();
+        node.sim.step();
+        self.time = node.sim.state.time;
+        let node = &self.nodes[id.0 as usize];
+        self.log.extend(
+            node.sim.log.contents[logged..]
+                .iter()
+                .map(|&event| NodeEvent { node: id, event }),
+        );
+        let left = exiting
+            .into_iter()
+            .zip(routes)
+            .filter(|(item, _)| !node.sim.state.items.contains_key(&item.id))
+            .collect::<Vec<_>>();
+        for (item, route) in left {
+            self.route(id, &item, route);
+        }
+        self.unblock();
+        self.jockey();
+        Some(id)
+    }
+
+    /// Let at most one item per group of parallel queues jump to the
+    /// shortest queue. Any further jumps wait until the jumping item has
+    /// arrived.
+    fn jockey(&mut self) {
+        for i in 0..self.jockeying.len() {
+            let length = |node: &NodeId| {
+                let state = &self.nodes[node.0 as usize].sim.state;
+                state.buffer_count + state.server_count
+            };
+            let group = &self.jockeying[i];
+            let (Some(shortest), Some(longest)) = (
+                group.nodes.iter().min_by_key(|node| (length(node), node.0)),
+                group
+                    .nodes
+                    .iter()
+                    .max_by_key(|node| (length(node), u32::MAX - node.0)),
+            ) else {
+                continue;
+            };
+            if length(longest) < length(shortest) + group.threshold {
+                continue;
+            }
+            let (from, to) = (*longest, *shortest);
+            let sim = &mut self.nodes[from.0 as usize].sim;
+            let Some(item) = sim.state.take_last() else {
+                continue;
+            };
+            sim.emq.cancel(|em| {
+                matches!(
+                    em.event_message_type,
+                    EventMessageType::Renege(id) | EventMessageType::CheckDeadline(id)
+                        if id == item.id
+                )
+            });
+            self.log_item_event(from, EventType::Jockeyed(to.0), &item);
+            let route = Route {
+                destination: Destination::Node(to),
+                class: item.source,
+            };
+            self.route(from, &item, route);
+        }
+    }
+
+    /// Check if a node is a sink.
+    fn is_sink(&self, id: NodeId) -> bool {
+        matches!(self.nodes[id.0 as usize].kind, NodeKind::Sink(_))
+    }
+
+    /// Where an item that exits a node goes, and its class from then on,
+    /// according to the node's routing policy.
+    fn next_route(&mut self, from: NodeId, item: &Item) -> Route {
+        // Policies in a sub-network see the sub-network, and refer to nodes
+        // by their IDs within it.
+        let (base, len) = match self.block_of[from.0 as usize] {
+            Some(block) => (self.blocks[block].base, self.blocks[block].len),
+            None => (0, self.nodes.len() as u32),
+        };
+        let network_state = NetworkState {
+            nodes: &self.nodes[base as usize..(base + len) as usize],
+            time: self.time,
+        };
+        let local = NodeId(from.0 - base);
+        let route = self.routers[from.0 as usize].route_with_class(item, local, &network_state);
+        Route {
+            destination: self.resolve(from, route.destination),
+            ..route
+        }
+    }
+
+    /// Translate a destination chosen by a node's routing policy, which may
+    /// be in a sub-network, into a destination in the whole network.
+    fn resolve(&self, from: NodeId, destination: Destination) -> Destination {
+        let Some(mut block) = self.block_of[from.0 as usize] else {
+            return destination;
+        };
+        if let Destination::Node(node) = destination {
+            return Destination::Node(NodeId(node.0 + self.blocks[block].base));
+        }
+        loop {
+            match (self.blocks[block].exit, self.blocks[block].parent) {
+                (Destination::Exit, Some(parent)) => block = parent,
+                (exit, _) => return exit,
+            }
+        }
+    }
+
+    /// Check if every node that items are going to has room in its buffer
+    /// for them.
+    fn has_room(&self, routes: &[Route]) -> bool {
+        routes.iter().all(|route| match route.destination {
+            Destination::Node(to) => {
+                self.is_sink(to) || self.nodes[to.0 as usize].sim.state.can_buffer()
+            }
+            Destination::Exit => true,
+        })
+    }
+
+    /// Hold on to the exit of a server whose items have nowhere to go, and
+    /// log that the server is blocked.
+    fn block(
+        &mut self,
+        id: NodeId,
+        server: ServerId,
+        exit: EventMessage,
+        exiting: &[Item],
+        routes: Vec<Route>,
+    ) {
+        let sim = &mut self.nodes[id.0 as usize].sim;
+        sim.emq.pop();
+        sim.state.time = exit.time;
+        sim.state.server_pool.get_mut(server).blocked = true;
+        self.time = exit.time;
+        self.log_event(id, EventType::ServerBlocked, server, exiting);
+        self.held.push(Held {
+            node: id,
+            exit,
+            routes: exiting.iter().map(|item| item.id).zip(routes).collect(),
+        });
+    }
+
+    /// Let blocked servers whose items now have room to go on exit at the
+    /// current time, in the order they were blocked.
+    fn unblock(&mut self) {
+        let mut i = 0;
+        while i < self.held.len() {
+            let routes = self.held[i]
+                .routes
+                .iter()
+                .map(|&(_, route)| route)
+                .collect::<Vec<_>>();
+            if !self.has_room(&routes) {
+                i += 1;
+                continue;
+            }
+            let held = self.held.remove(i);
+            let EventMessageType::Exit(server) = held.exit.event_message_type else {
+                unreachable!("only exits are held");
+            };
+            let state = &mut self.nodes[held.node.0 as usize].sim.state;
+            state.server_pool.get_mut(server).blocked = false;
+            let items = state
+                .serving(server)
+                .iter()
+                .map(|&item| *state.item(item))
+                .collect::<Vec<_>>();
+            self.log_event(held.node, EventType::ServerUnblocked, server, &items);
+            for (item, route) in held.routes {
+                self.routed.insert((held.node, item), route);
+            }
+            let time = self.time;
+            self.schedule(
+                held.node,
+                EventMessage {
+                    event_message_type: EventMessageType::Exit(server),
+                    time,
+                },
+            );
+        }
+    }
+
+    /// Log an event about a server at a node, in the node's log and the
+    /// network's.
+    fn log_event(&mut self, id: NodeId, event_type: EventType, server: ServerId, items: &[Item]) {
+        self.push_event(
+            id,
+            Event {
+                time: self.time,
+                event_type,
+                source: items.first().and_then(|item| item.source),
+                server: Some(server),
+                item: items.first().map(|item| item.id),
+                metadata: items.first().map(Item::metadata),
+            },
+        );
+    }
+
+    /// Log an event about an item at a node.
+    fn log_item_event(&mut self, id: NodeId, event_type: EventType, item: &Item) {
+        self.push_event(
+            id,
+            Event {
+                time: self.time,
+                event_type,
+                source: item.source,
+                server: None,
+                item: Some(item.id),
+                metadata: Some(item.metadata()),
+            },
+        );
+    }
+
+    /// Add an event to a node's log and the network's.
+    fn push_event(&mut self, id: NodeId, event: Event) {
+        self.nodes[id.0 as usize].sim.log.push(event);
+        self.log.push(NodeEvent { node: id, event });
+    }
+
+    /// Add a node to the journey of an item that left it, starting a journey
+    /// for items that just entered the network, and finishing it for items
+    /// that leave. Returns the tag the item keeps for the rest of its
+    /// journey.
+    fn track(&mut self, from: NodeId, item: &Item, destination: Destination) -> u64 {
+        let tag = item.tag.unwrap_or_else(|| {
+            self.next_tag += 1;
+            self.next_tag - 1
+        });
+        let journey = self.in_transit.entry(tag).or_insert_with(|| Journey {
+            class: item.source,
+            path: vec![],
+            entered: item.arrived,
+            left: item.arrived,
+        });
+        journey.path.push(from);
+        if destination == Destination::Exit {
+            let mut journey = self.in_transit.remove(&tag).expect("journey in progress");
+            journey.left = self.time;
+            self.journeys.push(journey);
+        }
+        tag
+    }
+
+    /// Pass an item that left a node on to the next node, unless it leaves
+    /// the network.
+    fn route(&mut self, from: NodeId, item: &Item, route: Route) {
+        let destination = match (route.destination, self.reference) {
+            (Destination::Exit, Some(reference)) => Destination::Node(reference),
+            (Destination::Node(to), _) if self.is_sink(to) => {
+                if self.reference.is_none() {
+                    self.track(from, item, Destination::Exit);
+                }
+                if let NodeKind::Sink(exited) = &mut self.nodes[to.0 as usize].kind {
+                    exited.push(Exited {
+                        class: route.class,
+                        from,
+                        time: self.time,
+                    });
+                }
+                return;
+            }
+            _ => route.destination,
+        };
+        let tag = match self.reference {
+            Some(_) => item.tag,
+            None => Some(self.track(from, item, destination)),
+        };
+        if let (Some(reference), Some(tag)) = (self.reference, item.tag) {
+            if from == reference {
+                self.departures.insert(tag, self.time);
+            } else if destination == Destination::Node(reference) {
+                if let Some(left) = self.departures.remove(&tag) {
+                    self.response_times.push(self.time.0 - left.0);
+                }
+            }
+        }
+        if let Destination::Node(to) = destination {
+            let time = self.time;
+            self.schedule(
+                to,
+                EventMessage {
+                    event_message_type: EventMessageType::ArriveWith(Attributes {
+                        source: route.class,
+                        tag,
+                        ..Attributes::default()
+                    }),
+                    time,
+                },
+            );
+        }
+    }
+
+    /// Draw the network as a Graphviz DOT graph, with each node labeled by
+    /// its name, buffer capacity, and number of servers, and each route by
+    /// its probability, if it's fixed. Optionally, nodes are also labeled by
+    /// their utilization so far.
+    pub fn to_dot(&self, utilization: bool) -> String {
+        let count = |n: u32| match n {
+            u32::MAX => "inf".to_string(),
+            n => n.to_string(),
+        };
+        let mut dot = String::from("digraph network {\n");
+        let mut exits = false;
+        for (i, node) in self.nodes.iter().enumerate() {
+            let id = NodeId(i as u32);
+            let state = &node.sim.state;
+            let (shape, mut label) = match node.kind {
+                NodeKind::Queue => (
+                    "box",
+                    format!(
+                        "{}\\nbuffer {}, servers {}",
+                        node.name,
+                        count(state.buffer_capacity),
+                        count(state.server_capacity)
+                    ),
+                ),
+                NodeKind::Source => ("invhouse", node.name.clone()),
+                NodeKind::Sink(_) => ("doublecircle", node.name.clone()),
+            };
+            if utilization && node.kind == NodeKind::Queue {
+                if let Some(u) = self.node_stats(id).utilization {
+                    label += &format!("\\nutilization {u:.2}");
+                }
+            }
+            dot += &format!("    n{i} [shape={shape}, label=\"{label}\"];\n");
+            for (destination, p) in self.routers[i].targets() {
+                let to = match self.resolve(id, destination) {
+                    Destination::Node(to) => format!("n{}", to.0),
+                    Destination::Exit => {
+                        exits = true;
+                        "exit".to_string()
+                    }
+                };
+                match p {
+                    Some(p) => dot += &format!("    n{i} -> {to} [label=\"{p:.2}\"];\n"),
+                    None => dot += &format!("    n{i} -> {to};\n"),
+                }
+            }
+        }
+        if exits {
+            dot += "    exit [shape=point];\n";
+        }
+        dot += "}\n";
+        dot
+    }
+
+    /// The number of items in a closed network.
+    pub fn population(&self) -> u32 {
+        self.population
+    }
+
+    /// The number of items that got back to the reference node of a closed
+    /// network per unit of time, or 0 before any time has passed.
+    pub fn throughput(&self) -> f64 {
+        match self.time.0 {
+            0 => 0.0,
+            t => self.response_times.len() as f64 / t as f64,
+        }
+    }
+
+    /// The mean time from when an item left the reference node of a closed
+    /// network until it got back, or `None` if no item has got back yet.
+    pub fn mean_response_time(&self) -> Option<f64> {
+        if self.response_times.is_empty() {
+            return None;
+        }
+        let total = self.response_times.iter().map(|&t| t as f64).sum::<f64>();
+        Some(total / self.response_times.len() as f64)
+    }
+
+    /// Statistics of a node, worked out from its time averages and its
+    /// events in the log.
+    pub fn node_stats(&self, node: NodeId) -> NodeStats {
+        let state = &self.nodes[node.0 as usize].sim.state;
+        let areas = &self.areas[node.0 as usize];
+        let elapsed = (self.time.0 - areas.since.0) as f64;
+        let total = self.time.0.max(1) as f64;
+        let mean_busy_servers = (areas.busy + elapsed * state.server_count as f64) / total;
+        let mean_queue_length = (areas.buffered + elapsed * state.buffer_count as f64) / total;
+
+        let mut joined = HashMap::new();
+        let mut waits = vec![];
+        for e in self.log.iter().filter(|e| e.node == node) {
+            match (e.event.event_type, e.event.item) {
+                (EventType::BufferIncremented, Some(item)) => {
+                    joined.insert(item, e.event.time);
+                }
+                (EventType::BufferDecremented, Some(item)) => {
+                    if let Some(time) = joined.remove(&item) {
+                        waits.push((e.event.time.0 - time.0) as f64);
+                    }
+                }
+                _ => {}
+            }
+        }
+        NodeStats {
+            mean_busy_servers,
+            utilization: (!state.has_infinite_servers())
+                .then(|| mean_busy_servers / state.server_capacity as f64),
+            mean_queue_length,
+            mean_wait: (!waits.is_empty()).then(|| waits.iter().sum::<f64>() / waits.len() as f64),
+        }
+    }
+
+    /// The sojourn times of the items that left the network, broken down by
+    /// class and path.
+    pub fn sojourn_times(&self) -> HashMap<(Option<SourceId>, Vec<NodeId>), Vec<u32>> {
+        let mut times: HashMap<_, Vec<u32>> = HashMap::new();
+        for journey in &self.journeys {
+            times
+                .entry((journey.class, journey.path.clone()))
+                .or_default()
+                .push(journey.sojourn_time());
+        }
+        times
+    }
+
+    /// Like `step`, but only handle the next message if it's due at or before
+    /// the horizon.
+    pub fn step_until(&mut self, horizon: Time) -> Option<NodeId> {
+        let id = self.next_node()?;
+        match self.nodes[id.0 as usize].sim.emq.peek_time() {
+            Some(time) if time <= horizon => self.step(),
+            _ => None,
+        }
+    }
+
+    /// Step until there are no more messages.
+    pub fn run(&mut self) -> &mut Self {
+        while self.step().is_some() {}
+        self
+    }
+
+    /// Step until there are no more messages due at or before the horizon.
+    pub fn run_until(&mut self, horizon: Time) -> &mut Self {
+        while self.step_until(horizon).is_some() {}
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tandem() {
+        // Items arriving at 0 and 1 at a queue with services of 5 move on to
+        // a queue with services of 7 at 5 and 10, and leave it at 12 and 19.
+        let network =
+            &mut Network::tandem(vec![QueueState::new(10, 1, 5), QueueState::new(10, 1, 7)]);
+        for t in [0, 1] {
+            network.schedule(
+                NodeId(0),
+                EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                },
+            );
+        }
+        network.run();
+
+        let times = |node, event_type| {
+            network
+                .log
+                .iter()
+                .filter(|e| e.node == node && e.event.event_type == event_type)
+                .map(|e| e.event.time.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![5, 10], times(NodeId(1), EventType::BufferIncremented));
+        assert_eq!(vec![12, 19], times(NodeId(1), EventType::ServerDecremented));
+        assert_eq!(Time(19), network.time());
+        assert_eq!(Some(NodeId(1)), network.node_id("node-1"));
+    }
+
+    #[test]
+    fn test_calendar() {
+        // Messages are handled in time order across nodes, ties going to the
+        // lowest node, including ones scheduled directly on a node, and ones
+        // earlier than a node's pending messages.
+        let network = &mut Network::new();
+        for _ in 0..3 {
+            network.add_node("node", QueueState::new(10, 0, 100));
+        }
+        let arrive = |t| EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(t),
+        };
+        network.schedule(NodeId(2), arrive(1));
+        network.schedule(NodeId(1), arrive(3));
+        network.schedule(NodeId(0), arrive(3));
+        let earliest = |network: &Network| {
+            (0..3)
+                .filter_map(|i| {
+                    let due = network.nodes[i as usize].sim.emq.peek_time();
+                    due.map(|time| (time, NodeId(i)))
+                })
+                .min()
+                .map(|(_, id)| id)
+        };
+        assert_eq!(Some(NodeId(2)), network.step());
+        network.node_mut(NodeId(1)).sim.schedule(arrive(2));
+        let mut handled = vec![];
+        while let Some(expected) = earliest(network) {
+            assert_eq!(Some(expected), network.step());
+            handled.push((network.time().0, expected.0));
+        }
+        assert_eq!(None, network.step());
+        assert!(handled.is_sorted());
+        assert!(handled.contains(&(2, 1)));
+        assert_eq!(Some(&(3, 1)), handled.last());
+    }
+
+    #[test]
+    fn test_routing_matrix() {
+        // Items from a Poisson source at node 0 go to node 1 with probability
+        // 0.25 and to node 2 otherwise, and half of those at node 2 come back
+        // to node 0 for another visit.
+        use crate::arrival::PoissonArrivals;
+        let states = (0..3)
+            .map(|_| QueueState::new(1000, 1, 1).with_infinite_servers())
+            .collect();
+        let matrix = [
+            vec![0.0, 0.25, 0.75],
+            vec![0.0, 0.0, 0.0],
+            vec![0.5, 0.0, 0.0],
+        ];
+        let network = &mut Network::with_routing_matrix(states, &matrix, 1);
+        network.add_source(NodeId(0), PoissonArrivals::new(0.5, 2));
+        network.run_until(Time(20_000));
+
+        let visits = |node| {
+            network
+                .log
+                .iter()
+                .filter(|e| e.node == node && e.event.event_type == EventType::BufferIncremented)
+                .count() as f64
+        };
+        // Node 0 is visited 1.6 times per external arrival, node 1 0.4 times,
+        // and node 2 1.2 times.
+        let (v0, v1, v2) = (visits(NodeId(0)), visits(NodeId(1)), visits(NodeId(2)));
+        assert!((v1 / v0 - 0.25).abs() < 0.02);
+        assert!((v2 / v0 - 0.75).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_blocking_after_service() {
+        // Items arrive at 0, 1, and 2 at a queue with services of 2, which
+        // feeds a queue with room for one waiting item and services of 10.
+        // The third item finishes at 6 but has nowhere to go until the
+        // second item enters service at 12, so the first server is blocked
+        // in between.
+        let network = Network::tandem(vec![QueueState::new(10, 1, 2), QueueState::new(1, 1, 10)]);
+        let network = &mut network.with_blocking();
+        for t in [0, 1, 2] {
+            network.schedule(
+                NodeId(0),
+                EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                },
+            );
+        }
+        network.run();
+
+        let times = |node, event_type| {
+            network
+                .log
+                .iter()
+                .filter(|e| e.node == node && e.event.event_type == event_type)
+                .map(|e| e.event.time.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![6], times(NodeId(0), EventType::ServerBlocked));
+        assert_eq!(vec![12], times(NodeId(0), EventType::ServerUnblocked));
+        assert_eq!(
+            vec![2, 4, 12],
+            times(NodeId(1), EventType::BufferIncremented)
+        );
+        assert!(times(NodeId(1), EventType::Dropped).is_empty());
+        assert_eq!(Time(32), network.time());
+    }
+
+    #[test]
+    fn test_closed_network() {
+        // Two users think for 10 and then send a request to a CPU with
+        // services of 2. After the first round, where the second request
+        // waits for the first, every request takes 2.
+        use crate::dist::Deterministic;
+        let mut network = Network::new();
+        let terminals = network.add_think_node("terminals", Deterministic(10.0), 0);
+        let cpu = network.add_node("cpu", QueueState::new(10, 1, 2));
+        network.connect(terminals, cpu);
+        let network = &mut network.with_population(terminals, 2);
+        assert_eq!(0.0, network.throughput());
+        network.run_until(Time(120));
+
+        // One user gets back at 12, 24, ..., 120, and the other at 14, 26,
+        // ..., 110.
+        assert_eq!(2, network.population());
+        assert!((network.throughput() - 19.0 / 120.0).abs() < 1e-9);
+        assert!((network.mean_response_time().unwrap() - 40.0 / 19.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_node_stats_and_journeys() {
+        // As in the tandem test, items arrive at 0 and 1, and leave the first
+        // node at 5 and 10 and the second at 12 and 19. The second item waits
+        // for 4 at the first node and for 2 at the second.
+        let network =
+            &mut Network::tandem(vec![QueueState::new(10, 1, 5), QueueState::new(10, 1, 7)]);
+        for t in [0, 1] {
+            network.schedule(
+                NodeId(0),
+                EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                },
+            );
+        }
+        network.run();
+
+        let stats = network.node_stats(NodeId(0));
+        assert!((stats.utilization.unwrap() - 10.0 / 19.0).abs() < 1e-9);
+        assert!((stats.mean_queue_length - 4.0 / 19.0).abs() < 1e-9);
+        assert_eq!(Some(2.0), stats.mean_wait);
+        let stats = network.node_stats(NodeId(1));
+        assert!((stats.utilization.unwrap() - 14.0 / 19.0).abs() < 1e-9);
+        assert_eq!(Some(1.0), stats.mean_wait);
+
+        let sojourn_times = network.sojourn_times();
+        assert_eq!(
+            Some(&vec![12, 18]),
+            sojourn_times.get(&(None, vec![NodeId(0), NodeId(1)]))
+        );
+    }
+
+    #[test]
+    fn test_jockeying() {
+        // Items arrive at 0, 1, and 2 at the first of two parallel queues
+        // with services of 10. At 1, the first queue holds two more items than
+        // the second, so the second item jumps. At 2, the difference is only
+        // one.
+        let mut network = Network::new();
+        let first = network.add_node("first", QueueState::new(10, 1, 10));
+        let second = network.add_node("second", QueueState::new(10, 1, 10));
+        network.add_jockeying(Jockeying::new(vec![first, second], 2));
+        for t in [0, 1, 2] {
+            network.schedule(
+                first,
+                EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                },
+            );
+        }
+        network.run();
+
+        let jockeyed = network
+            .log
+            .iter()
+            .filter(|e| matches!(e.event.event_type, EventType::Jockeyed(_)))
+            .map(|e| (e.event.time.0, e.node, e.event.event_type))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(1, first, EventType::Jockeyed(1))], jockeyed);
+        assert_eq!(Time(20), network.time());
+        assert_eq!(0, network.node(first).sim.state.buffer_count);
+    }
+
+    #[test]
+    fn test_source_and_sink() {
+        // A source generates items at 10, 20, and 30, which are served for 5
+        // at a queue and then taken out of the network by a sink.
+        use crate::arrival::DeterministicArrivals;
+        let mut network = Network::new();
+        let source =
+            network.add_source_node("source", DeterministicArrivals::new(Time(10)).stop_after(3));
+        let queue = network.add_node("queue", QueueState::new(10, 1, 5));
+        let sink = network.add_sink("sink");
+        network.connect(source, queue).connect(queue, sink);
+        network.run();
+
+        let exited = network.node(sink).exited();
+        assert_eq!(
+            vec![15, 25, 35],
+            exited.iter().map(|e| e.time.0).collect::<Vec<_>>()
+        );
+        assert!(exited.iter().all(|e| e.from == queue));
+        assert_eq!(
+            Some(&vec![5, 5, 5]),
+            network
+                .sojourn_times()
+                .get(&(Some(SourceId(0)), vec![source, queue]))
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        // Items leave the first node for the second with probability 0.3,
+        // and leave the network otherwise.
+        let states = vec![QueueState::new(10, 2, 1), QueueState::new(u32::MAX, 1, 1)];
+        let matrix = [vec![0.0, 0.3], vec![0.0, 0.0]];
+        let network = Network::with_routing_matrix(states, &matrix, 0);
+        let expected = r#"digraph network {
+    n0 [shape=box, label="node-0\nbuffer 10, servers 2"];
+    n0 -> n1 [label="0.30"];
+    n0 -> exit [label="0.70"];
+    n1 [shape=box, label="node-1\nbuffer inf, servers 1"];
+    n1 -> exit [label="1.00"];
+    exit [shape=point];
+}
+"#;
+        assert_eq!(expected, network.to_dot(false));
+        assert!(network.to_dot(true).contains("utilization 0.00"));
+    }
+
+    #[test]
+    fn test_subnetworks() {
+        // Two branch blocks, each a front desk with services of 2 in front
+        // of a teller with services of 3, send their items on to the head
+        // office. An item arriving at the second branch at 0 reaches the
+        // teller at 2 and the head office at 5.
+        let branch = || Network::tandem(vec![QueueState::new(10, 1, 2), QueueState::new(10, 1, 3)]);
+        let mut network = Network::new();
+        let hq = network.add_node("hq", QueueState::new(10, 1, 1));
+        for name in ["branch-1", "branch-2"] {
+            let entry = network.add_subnetwork(name, branch());
+            network.set_subnetwork_exit(entry, Destination::Node(hq));
+        }
+        let entry = network.node_id("branch-2/node-0").unwrap();
+        network.schedule(
+            entry,
+            EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            },
+        );
+        network.run();
+
+        let arrivals = network
+            .log
+            .iter()
+            .filter(|e| e.event.event_type == EventType::BufferIncremented)
+            .map(|e| (e.event.time.0, network.node(e.node).name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(0, "branch-2/node-0"), (2, "branch-2/node-1"), (5, "hq")],
+            arrivals
+        );
+        assert_eq!(5, network.len());
+    }
+}
@@ -0,0 +1,709 @@
+//! Parquet files of events and items.
+//!
+//! With the `parquet` feature, `EventColumns::write_parquet` and
+//! `ItemColumns::write_parquet` write the columns of a run as Parquet files,
+//! which pandas, Polars, DuckDB, and Spark read directly, e.g., with
+//! `pandas.read_parquet`, and which are much smaller and faster to load than
+//! CSV for runs of millions of events.
+//!
+//! The crate doesn't depend on the `parquet` crate, so the file metadata is
+//! encoded by a small Thrift compact protocol writer of its own. Only what
+//! the columns need is supported: values are PLAIN encoded, nulls are given
+//! by RLE definition levels, and pages are uncompressed. Unsigned integers
+//! are stored as INT32 and INT64 annotated as UINT_32 and UINT_64.
+
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::columns::{EventColumns, ItemColumns};
+
+/// The most rows in a row group. Each column of a row group is written as
+/// a single page.
+pub const GROUP_ROWS: usize = 1024 * 1024;
+
+/// The start and end of a Parquet file.
+const MAGIC: &[u8] = b"PAR1";
+
+/// Physical types.
+const INT32: i32 = 1;
+const INT64: i32 = 2;
+const DOUBLE: i32 = 5;
+const BYTE_ARRAY: i32 = 6;
+
+/// Converted types, which annotate physical types.
+const UTF8: i32 = 0;
+const UINT_32: i32 = 13;
+const UINT_64: i32 = 14;
+
+/// Encodings.
+const PLAIN: i32 = 0;
+const RLE: i32 = 3;
+
+/// Writes Thrift structs in the compact protocol, in which each field is
+/// marked by its type and the difference of its ID from the last one's.
+#[derive(Debug)]
+struct Thrift {
+    bytes: Vec<u8>,
+    /// The ID of the last field of each struct being written, innermost last.
+    last: Vec<i16>,
+}
+
+/// Types of compact protocol fields.
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+impl Thrift {
+    fn new() -> Self {
+        Self {
+            bytes: vec![],
+            last: vec![0],
+        }
+    }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.bytes.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        self.bytes.push(n as u8);
+    }
+
+    /// A signed integer, zigzag encoded so small magnitudes are short.
+    fn zigzag(&mut self, n: i64) {
+        self.varint(((n << 1) ^ (n >> 63)) as u64);
+    }
+
+    fn header(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().unwrap();
+        let delta = id - *last;
+        *last = id;
+        match delta {
+            delta @ 1..=15 => self.bytes.push((delta as u8) << 4 | kind),
+            _ => {
+                self.bytes.push(kind);
+                self.zigzag(id as i64);
+            }
+        }
+    }
+
+    fn i32(&mut self, id: i16, n: i32) {
+        self.header(id, I32);
+        self.zigzag(n as i64);
+    }
+
+    fn i64(&mut self, id: i16, n: i64) {
+        self.header(id, I64);
+        self.zigzag(n);
+    }
+
+    fn binary(&mut self, id: i16, bytes: &[u8]) {
+        self.header(id, BINARY);
+        self.bytes_value(bytes);
+    }
+
+    fn bytes_value(&mut self, bytes: &[u8]) {
+        self.varint(bytes.len() as u64);
+        self.bytes.extend(bytes);
+    }
+
+    /// Start a list of `len` elements of a type, which are written next.
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.header(id, LIST);
+        match len {
+            0..=14 => self.bytes.push((len as u8) << 4 | kind),
+            _ => {
+                self.bytes.push(0xf0 | kind);
+                self.varint(len as u64);
+            }
+        }
+    }
+
+    /// Start a struct field.
+    fn begin(&mut self, id: i16) {
+        self.header(id, STRUCT);
+        self.begin_element();
+    }
+
+    /// Start a struct that's an element of a list.
+    fn begin_element(&mut self) {
+        self.last.push(0);
+    }
+
+    /// Finish a struct.
+    fn end(&mut self) {
+        self.bytes.push(0);
+        self.last.pop();
+    }
+
+    /// Finish the outermost struct.
+    fn finish(mut self) -> Vec<u8> {
+        self.end();
+        self.bytes
+    }
+}
+
+/// Values of a physical type, PLAIN encoded.
+trait Plain {
+    /// The physical type, and the converted type annotating it, if any.
+    const TYPES: (i32, Option<i32>);
+
+    fn encode(&self, bytes: &mut Vec<u8>);
+}
+
+impl Plain for u32 {
+    const TYPES: (i32, Option<i32>) = (INT32, Some(UINT_32));
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.to_le_bytes());
+    }
+}
+
+impl Plain for u64 {
+    const TYPES: (i32, Option<i32>) = (INT64, Some(UINT_64));
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.to_le_bytes());
+    }
+}
+
+impl Plain for f64 {
+    const TYPES: (i32, Option<i32>) = (DOUBLE, None);
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend(self.to_le_bytes());
+    }
+}
+
+impl Plain for String {
+    const TYPES: (i32, Option<i32>) = (BYTE_ARRAY, Some(UTF8));
+
+    fn encode(&self, bytes: &mut Vec<u8>) {
+        bytes.extend((self.len() as u32).to_le_bytes());
+        bytes.extend(self.as_bytes());
+    }
+}
+
+/// The values of a column, which are optional if it's nullable.
+trait Value {
+    type Plain: Plain;
+    const NULLABLE: bool;
+
+    fn value(&self) -> Option<&Self::Plain>;
+}
+
+macro_rules! value {
+    ($($t:ty),*) => {
+        $(
+            impl Value for $t {
+                type Plain = $t;
+                const NULLABLE: bool = false;
+
+                fn value(&self) -> Option<&$t> {
+                    Some(self)
+                }
+            }
+
+            impl Value for Option<$t> {
+                type Plain = $t;
+                const NULLABLE: bool = true;
+
+                fn value(&self) -> Option<&$t> {
+                    self.as_ref()
+                }
+            }
+        )*
+    };
+}
+
+value!(u32, u64, f64, String);
+
+/// Encode 0/1 definition levels with the RLE/bit-packing hybrid, as runs of
+/// equal levels, prefixed by its length as in version 1 data pages.
+fn levels(defined: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut runs: Vec<(u64, bool)> = vec![];
+    for defined in defined {
+        match runs.last_mut() {
+            Some((count, level)) if *level == defined => *count += 1,
+            _ => runs.push((1, defined)),
+        }
+    }
+    let mut thrift = Thrift::new();
+    for (count, level) in runs {
+        thrift.varint(count << 1);
+        thrift.bytes.push(level as u8);
+    }
+    let mut bytes = (thrift.bytes.len() as u32).to_le_bytes().to_vec();
+    bytes.extend(thrift.bytes);
+    bytes
+}
+
+/// The data of a page: the definition levels, if the values can be null,
+/// then the values that aren't.
+fn page<T: Value>(values: &[T]) -> Vec<u8> {
+    let mut data = vec![];
+    if T::NULLABLE {
+        data = levels(values.iter().map(|v| v.value().is_some()));
+    }
+    for value in values.iter().filter_map(Value::value) {
+        value.encode(&mut data);
+    }
+    data
+}
+
+/// A column to write: its schema, and the data of a page of a range of rows.
+struct Column<'a> {
+    name: &'static str,
+    types: (i32, Option<i32>),
+    nullable: bool,
+    page: Box<dyn Fn(Range<usize>) -> Vec<u8> + 'a>,
+}
+
+fn column<'a, T: Value>(name: &'static str, values: &'a [T]) -> Column<'a> {
+    Column {
+        name,
+        types: T::Plain::TYPES,
+        nullable: T::NULLABLE,
+        page: Box::new(move |rows| page(&values[rows])),
+    }
+}
+
+/// Where a column chunk was written, and how big it is.
+struct Chunk {
+    offset: usize,
+    size: usize,
+    values: usize,
+}
+
+/// The header of a version 1 data page.
+fn page_header(data: &[u8], values: usize) -> Vec<u8> {
+    let mut thrift = Thrift::new();
+    // A data page.
+    thrift.i32(1, 0);
+    thrift.i32(2, data.len() as i32);
+    thrift.i32(3, data.len() as i32);
+    thrift.begin(5);
+    thrift.i32(1, values as i32);
+    thrift.i32(2, PLAIN);
+    thrift.i32(3, RLE);
+    thrift.i32(4, RLE);
+    thrift.end();
+    thrift.finish()
+}
+
+/// The footer of a file: its schema and where its row groups are.
+fn file_metadata(columns: &[Column], rows: usize, groups: &[(Vec<Chunk>, usize)]) -> Vec<u8> {
+    let mut thrift = Thrift::new();
+    thrift.i32(1, 1);
+    thrift.list(2, STRUCT, columns.len() + 1);
+    thrift.begin_element();
+    thrift.binary(4, b"schema");
+    thrift.i32(5, columns.len() as i32);
+    thrift.end();
+    for column in columns {
+        thrift.begin_element();
+        thrift.i32(1, column.types.0);
+        // Optional or required.
+        thrift.i32(3, column.nullable as i32);
+        thrift.binary(4, column.name.as_bytes());
+        if let Some(converted) = column.types.1 {
+            thrift.i32(6, converted);
+        }
+        thrift.end();
+    }
+    thrift.i64(3, rows as i64);
+    thrift.list(4, STRUCT, groups.len());
+    for (chunks, rows) in groups {
+        thrift.begin_element();
+        thrift.list(1, STRUCT, chunks.len());
+        for (chunk, column) in chunks.iter().zip(columns) {
+            thrift.begin_element();
+            thrift.i64(2, chunk.offset as i64);
+            thrift.begin(3);
+            thrift.i32(1, column.types.0);
+            thrift.list(2, I32, 2);
+            thrift.zigzag(PLAIN as i64);
+            thrift.zigzag(RLE as i64);
+            thrift.list(3, BINARY, 1);
+            thrift.bytes_value(column.name.as_bytes());
+            // Uncompressed.
+            thrift.i32(4, 0);
+            thrift.i64(5, chunk.values as i64);
+            thrift.i64(6, chunk.size as i64);
+            thrift.i64(7, chunk.size as i64);
+            thrift.i64(9, chunk.offset as i64);
+            thrift.end();
+            thrift.end();
+        }
+        let size = chunks.iter().map(|c| c.size).sum::<usize>();
+        thrift.i64(2, size as i64);
+        thrift.i64(3, *rows as i64);
+        thrift.end();
+    }
+    thrift.binary(6, b"qute");
+    thrift.finish()
+}
+
+/// Write columns of `rows` rows as a Parquet file, in row groups of up to
+/// `GROUP_ROWS` rows.
+fn write_file(writer: &mut impl Write, columns: &[Column], rows: usize) -> io::Result<()> {
+    writer.write_all(MAGIC)?;
+    let mut offset = MAGIC.len();
+    let mut groups = vec![];
+    for start in (0..rows).step_by(GROUP_ROWS) {
+        let group = start..rows.min(start + GROUP_ROWS);
+        let mut chunks = vec![];
+        for column in columns {
+            let data = (column.page)(group.clone());
+            let header = page_header(&data, group.len());
+            writer.write_all(&header)?;
+            writer.write_all(&data)?;
+            let size = header.len() + data.len();
+            chunks.push(Chunk {
+                offset,
+                size,
+                values: group.len(),
+            });
+            offset += size;
+        }
+        groups.push((chunks, group.len()));
+    }
+    let metadata = file_metadata(columns, rows, &groups);
+    writer.write_all(&metadata)?;
+    writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+    writer.write_all(MAGIC)
+}
+
+impl EventColumns {
+    /// Write the events as a Parquet file.
+    pub fn write_parquet(&self, writer: &mut impl Write) -> io::Result<()> {
+        let columns = [
+            column("time", &self.time),
+            column("event_type", &self.event_type),
+            column("data", &self.data),
+            column("source", &self.source),
+            column("server", &self.server),
+            column("item", &self.item),
+            column("priority", &self.priority),
+            column("work", &self.work),
+            column("pass", &self.pass),
+            column("tag", &self.tag),
+        ];
+        write_file(writer, &columns, self.len())
+    }
+}
+
+impl ItemColumns {
+    /// Write the items as a Parquet file.
+    pub fn write_parquet(&self, writer: &mut impl Write) -> io::Result<()> {
+        let columns = [
+            column("item", &self.item),
+            column("departure", &self.departure),
+            column("wait", &self.wait),
+            column("service", &self.service),
+            column("sojourn", &self.sojourn),
+        ];
+        write_file(writer, &columns, self.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    /// A decoded Thrift value: an integer, bytes, a list, or a struct's
+    /// fields by ID.
+    #[derive(Debug, Clone, PartialEq)]
+    enum Decoded {
+        Int(i64),
+        Bytes(Vec<u8>),
+        List(Vec<Decoded>),
+        Struct(Vec<(i16, Decoded)>),
+    }
+
+    impl Decoded {
+        fn field(&self, id: i16) -> &Decoded {
+            match self {
+                Decoded::Struct(fields) => &fields.iter().find(|(i, _)| *i == id).unwrap().1,
+                _ => panic!("not a struct"),
+            }
+        }
+
+        fn int(&self, id: i16) -> i64 {
+            match self.field(id) {
+                Decoded::Int(n) => *n,
+                _ => panic!("not an integer"),
+            }
+        }
+
+        fn bytes(&self, id: i16) -> &[u8] {
+            match self.field(id) {
+                Decoded::Bytes(bytes) => bytes,
+                _ => panic!("not bytes"),
+            }
+        }
+
+        fn list(&self, id: i16) -> &[Decoded] {
+            match self.field(id) {
+                Decoded::List(values) => values,
+                _ => panic!("not a list"),
+            }
+        }
+
+        fn has(&self, id: i16) -> bool {
+            matches!(self, Decoded::Struct(fields) if fields.iter().any(|(i, _)| *i == id))
+        }
+    }
+
+    /// Reads the compact protocol, independently of `Thrift`.
+    struct Reader<'a> {
+        bytes: &'a [u8],
+        at: usize,
+    }
+
+    impl Reader<'_> {
+        fn byte(&mut self) -> u8 {
+            self.at += 1;
+            self.bytes[self.at - 1]
+        }
+
+        fn varint(&mut self) -> u64 {
+            let mut n = 0;
+            for shift in (0..).step_by(7) {
+                let byte = self.byte();
+                n |= ((byte & 0x7f) as u64) << shift;
+                if byte < 0x80 {
+                    break;
+                }
+            }
+            n
+        }
+
+        fn zigzag(&mut self) -> i64 {
+            let n = self.varint();
+            (n >> 1) as i64 ^ -((n & 1) as i64)
+        }
+
+        fn value(&mut self, kind: u8) -> Decoded {
+            match kind {
+                I32 | I64 => Decoded::Int(self.zigzag()),
+                BINARY => {
+                    let len = self.varint() as usize;
+                    self.at += len;
+                    Decoded::Bytes(self.bytes[self.at - len..self.at].to_vec())
+                }
+                LIST => {
+                    let header = self.byte();
+                    let len = match header >> 4 {
+                        15 => self.varint() as usize,
+                        len => len as usize,
+                    };
+                    Decoded::List((0..len).map(|_| self.value(header & 0xf)).collect())
+                }
+                STRUCT => {
+                    let mut fields = vec![];
+                    let mut id = 0;
+                    loop {
+                        let header = self.byte();
+                        if header == 0 {
+                            break Decoded::Struct(fields);
+                        }
+                        id = match header >> 4 {
+                            0 => self.zigzag() as i16,
+                            delta => id + delta as i16,
+                        };
+                        fields.push((id, self.value(header & 0xf)));
+                    }
+                }
+                _ => panic!("unexpected type {kind}"),
+            }
+        }
+    }
+
+    /// Decode a struct at a position, returning it and where it ends.
+    fn decode(bytes: &[u8], at: usize) -> (Decoded, usize) {
+        let mut reader = Reader { bytes, at };
+        (reader.value(STRUCT), reader.at)
+    }
+
+    /// Decode the metadata at the end of a file.
+    fn footer(file: &[u8]) -> Decoded {
+        let len = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap());
+        let (metadata, end) = decode(file, file.len() - 8 - len as usize);
+        assert_eq!(file.len() - 8, end);
+        metadata
+    }
+
+    /// The physical type, repetition, and converted type of a schema element.
+    fn types(element: &Decoded) -> (i32, i64, Option<i32>) {
+        let converted = element.has(6).then(|| element.int(6) as i32);
+        (element.int(1) as i32, element.int(3), converted)
+    }
+
+    /// Read a column of a file back, as the definition level and plain
+    /// bytes of each value, from the pages of each row group.
+    fn read_column(file: &[u8], metadata: &Decoded, index: usize) -> Vec<Option<Vec<u8>>> {
+        let mut values = vec![];
+        for group in metadata.list(4) {
+            let chunk = &group.list(1)[index];
+            let meta = chunk.field(3);
+            let (header, start) = decode(file, meta.int(9) as usize);
+            assert_eq!(0, meta.int(4));
+            assert_eq!(
+                meta.int(6) as usize,
+                start - meta.int(9) as usize + header.int(2) as usize
+            );
+            let count = header.field(5).int(1) as usize;
+            assert_eq!(meta.int(5) as usize, count);
+            let data = &file[start..start + header.int(2) as usize];
+            let (physical, nullable) =
+                (meta.int(1) as i32, metadata.list(2)[index + 1].int(3) == 1);
+
+            // Expand the runs of definition levels, if any.
+            let mut defined = vec![true; count];
+            let mut at = 0;
+            if nullable {
+                let len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+                let mut reader = Reader {
+                    bytes: &data[4..4 + len],
+                    at: 0,
+                };
+                defined.clear();
+                while reader.at < len {
+                    let run = reader.varint();
+                    assert_eq!(0, run & 1, "RLE runs only");
+                    let level = reader.byte() == 1;
+                    defined.extend(std::iter::repeat_n(level, (run >> 1) as usize));
+                }
+                at = 4 + len;
+            }
+            assert_eq!(count, defined.len());
+            for defined in defined {
+                if !defined {
+                    values.push(None);
+                    continue;
+                }
+                let width = match physical {
+                    INT32 => 4,
+                    INT64 | DOUBLE => 8,
+                    _ => 4 + u32::from_le_bytes(data[at..at + 4].try_into().unwrap()) as usize,
+                };
+                values.push(Some(data[at..at + width].to_vec()));
+                at += width;
+            }
+            assert_eq!(data.len(), at);
+        }
+        values
+    }
+
+    #[test]
+    fn test_parquet() {
+        // Items arriving at 0 and 1 for services of 5 wait 0 and 4.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+
+        // The file starts and ends with the magic number, before which are the
+        // metadata and its length.
+        let items = ItemColumns::from_log(&sim.log);
+        let file = &mut vec![];
+        items.write_parquet(file).unwrap();
+        assert_eq!(MAGIC, &file[..4]);
+        assert_eq!(MAGIC, &file[file.len() - 4..]);
+        let metadata = footer(file);
+
+        // The schema is a root with a required, unsigned element per column,
+        // and there's a row group of both rows.
+        assert_eq!(2, metadata.int(3));
+        let schema = metadata.list(2);
+        assert_eq!(b"schema", schema[0].bytes(4));
+        assert_eq!(5, schema[0].int(5));
+        let names = schema[1..].iter().map(|e| e.bytes(4)).collect::<Vec<_>>();
+        let expected: [&[u8]; 5] = [b"item", b"departure", b"wait", b"service", b"sojourn"];
+        assert_eq!(expected.to_vec(), names);
+        assert_eq!((INT64, 0, Some(UINT_64)), types(&schema[1]));
+        assert_eq!((INT32, 0, Some(UINT_32)), types(&schema[3]));
+        assert_eq!(1, metadata.list(4).len());
+        let wait = read_column(file, &metadata, 2);
+        let u32s = |values: &[u32]| {
+            values
+                .iter()
+                .map(|v| Some(v.to_le_bytes().to_vec()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(u32s(&items.wait), wait);
+        assert_eq!(u32s(&items.departure), read_column(file, &metadata, 1));
+
+        // Events have nullable columns, whose nulls are given by definition
+        // levels, and strings, which are prefixed by their lengths.
+        let events = EventColumns::from_log(&sim.log);
+        let file = &mut vec![];
+        events.write_parquet(file).unwrap();
+        let metadata = footer(file);
+        assert_eq!(events.len() as i64, metadata.int(3));
+        let schema = metadata.list(2);
+        assert_eq!((BYTE_ARRAY, 0, Some(UTF8)), types(&schema[2]));
+        assert_eq!((DOUBLE, 1, None), types(&schema[8]));
+        let event_type = read_column(file, &metadata, 1);
+        let strings = events
+            .event_type
+            .iter()
+            .map(|s| {
+                let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+                bytes.extend(s.as_bytes());
+                Some(bytes)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(strings, event_type);
+        let server = events
+            .server
+            .iter()
+            .map(|v| v.map(|v| v.to_le_bytes().to_vec()))
+            .collect::<Vec<_>>();
+        assert!(server.contains(&None) && server.iter().any(Option::is_some));
+        assert_eq!(server, read_column(file, &metadata, 4));
+        let work = read_column(file, &metadata, 7);
+        assert!(work.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn test_parquet_row_groups() {
+        // Rows are split into row groups, each with a page per column, and
+        // long runs of definition levels and long lists are encoded.
+        let columns = ItemColumns {
+            item: (0..GROUP_ROWS as u64 + 3).collect(),
+            ..ItemColumns::default()
+        };
+        let nulls = vec![None::<u32>; GROUP_ROWS + 3];
+        let file = &mut vec![];
+        let fields = [column("item", &columns.item), column("data", &nulls)];
+        write_file(file, &fields, columns.len()).unwrap();
+        let metadata = footer(file);
+        let groups = metadata.list(4);
+        assert_eq!(2, groups.len());
+        assert_eq!((GROUP_ROWS as i64, 3), (groups[0].int(3), groups[1].int(3)));
+        let item = read_column(file, &metadata, 0);
+        assert_eq!(GROUP_ROWS + 3, item.len());
+        let last = GROUP_ROWS as u64 + 2;
+        assert_eq!(Some(last.to_le_bytes().to_vec()), item[GROUP_ROWS + 2]);
+        assert_eq!(nulls.len(), read_column(file, &metadata, 1).len());
+
+        // A list of more than 14 elements has its length after the header.
+        let mut thrift = Thrift::new();
+        thrift.list(1, I32, 20);
+        (0..20).for_each(|n| thrift.zigzag(n));
+        let bytes = thrift.finish();
+        let (decoded, _) = decode(&bytes, 0);
+        assert_eq!(20, decoded.list(1).len());
+        assert_eq!(Decoded::Int(19), decoded.list(1)[19]);
+    }
+}
@@ -0,0 +1,244 @@
+//! Charts of a run as SVG images.
+//!
+//! With the `plot` feature, `write_charts` draws the queue length over
+//! time, a histogram of the waits, and the utilization of each server, as
+//! SVG files that open in any browser. The charts are drawn directly,
+//! without a plotting library, so they're plain: axes with a few ticks,
+//! and one series each.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::simulation::Simulation;
+use crate::stats::{ItemTimes, SamplePath, ServerLoad};
+use crate::Time;
+
+/// The size of a chart, and the margins around its plot area.
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 400.0;
+const LEFT: f64 = 60.0;
+const RIGHT: f64 = 20.0;
+const TOP: f64 = 40.0;
+const BOTTOM: f64 = 50.0;
+/// The number of intervals between ticks on a numeric axis.
+const TICKS: u32 = 5;
+/// The color of the series.
+const COLOR: &str = "steelblue";
+
+/// The axes of a chart, which map values to positions in the image.
+struct Axes {
+    x_max: f64,
+    y_max: f64,
+}
+
+impl Axes {
+    /// Axes from 0 up to the given maxima, which are at least 1 so that an
+    /// empty chart still has a scale.
+    fn new(x_max: f64, y_max: f64) -> Self {
+        Self {
+            x_max: x_max.max(1.0),
+            y_max: y_max.max(1.0),
+        }
+    }
+
+    /// The horizontal position of a value.
+    fn x(&self, value: f64) -> f64 {
+        LEFT + value / self.x_max * (WIDTH - LEFT - RIGHT)
+    }
+
+    /// The vertical position of a value.
+    fn y(&self, value: f64) -> f64 {
+        HEIGHT - BOTTOM - value / self.y_max * (HEIGHT - TOP - BOTTOM)
+    }
+
+    /// Evenly spaced ticks from 0 to a maximum, with their labels.
+    fn ticks(max: f64) -> Vec<(f64, String)> {
+        (0..=TICKS)
+            .map(|i| {
+                let value = max * i as f64 / TICKS as f64;
+                (value, format!("{}", (value * 100.0).round() / 100.0))
+            })
+            .collect()
+    }
+
+    /// The SVG of a chart with the given labels and ticks on the horizontal
+    /// axis, around the marks of its series.
+    fn render(&self, labels: [&str; 3], x_ticks: Vec<(f64, String)>, marks: &str) -> String {
+        let [title, x_label, y_label] = labels;
+        let (x0, y0) = (self.x(0.0), self.y(0.0));
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{WIDTH}\" height=\"{HEIGHT}\" \
+             font-family=\"sans-serif\" font-size=\"12\">\n\
+             <rect width=\"{WIDTH}\" height=\"{HEIGHT}\" fill=\"white\"/>\n\
+             <text x=\"{}\" y=\"24\" text-anchor=\"middle\" font-size=\"16\">{title}</text>\n",
+            WIDTH / 2.0
+        );
+        svg.push_str(marks);
+        svg.push_str(&format!(
+            "<path d=\"M{x0} {} V{y0} H{}\" fill=\"none\" stroke=\"black\"/>\n",
+            TOP,
+            WIDTH - RIGHT
+        ));
+        for (value, label) in x_ticks {
+            let x = self.x(value);
+            svg.push_str(&format!(
+                "<path d=\"M{x} {y0} v5\" stroke=\"black\"/>\
+                 <text x=\"{x}\" y=\"{}\" text-anchor=\"middle\">{label}</text>\n",
+                y0 + 18.0
+            ));
+        }
+        for (value, label) in Self::ticks(self.y_max) {
+            let y = self.y(value);
+            svg.push_str(&format!(
+                "<path d=\"M{x0} {y} h-5\" stroke=\"black\"/>\
+                 <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{label}</text>\n",
+                x0 - 8.0,
+                y + 4.0
+            ));
+        }
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{x_label}</text>\n\
+             <text transform=\"translate(16 {}) rotate(-90)\" text-anchor=\"middle\">{y_label}</text>\n\
+             </svg>\n",
+            (x0 + WIDTH - RIGHT) / 2.0,
+            HEIGHT - 10.0,
+            (TOP + y0) / 2.0
+        ));
+        svg
+    }
+
+    /// A bar over an interval of the horizontal axis.
+    fn bar(&self, start: f64, end: f64, height: f64) -> String {
+        format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{COLOR}\"/>\n",
+            self.x(start),
+            self.y(height),
+            self.x(end) - self.x(start),
+            self.y(0.0) - self.y(height)
+        )
+    }
+}
+
+/// A step chart of a count over time up to `end`, e.g., the queue length.
+pub fn count_over_time(path: &SamplePath, end: Time, title: &str) -> String {
+    let peak = path.peak().map_or(0, |(_, value)| value);
+    let axes = Axes::new(end.0 as f64, peak as f64);
+    let mut d = format!("M{} {}", axes.x(0.0), axes.y(0.0));
+    for &(time, value) in path.points().iter().filter(|(t, _)| *t <= end) {
+        d.push_str(&format!(
+            " H{} V{}",
+            axes.x(time.0 as f64),
+            axes.y(value as f64)
+        ));
+    }
+    d.push_str(&format!(" H{}", axes.x(end.0 as f64)));
+    let marks = format!("<path d=\"{d}\" fill=\"none\" stroke=\"{COLOR}\" stroke-width=\"2\"/>\n");
+    axes.render([title, "time", "count"], Axes::ticks(axes.x_max), &marks)
+}
+
+/// A histogram of times, e.g., waits, in the given number of equal bins
+/// from 0 to just past the longest time.
+pub fn histogram(times: &[u32], bins: u32, title: &str) -> String {
+    let bins = bins.max(1);
+    let longest = times.iter().copied().max().unwrap_or(0);
+    let width = longest / bins + 1;
+    let mut counts = vec![0; bins as usize];
+    for &time in times {
+        counts[(time / width) as usize] += 1;
+    }
+    let axes = Axes::new(
+        (width * bins) as f64,
+        counts.iter().copied().max().unwrap_or(0) as f64,
+    );
+    let marks = counts
+        .iter()
+        .enumerate()
+        .map(|(i, &count)| {
+            let start = (i as u32 * width) as f64;
+            axes.bar(start, start + width as f64, count as f64)
+        })
+        .collect::<String>();
+    axes.render([title, "time", "items"], Axes::ticks(axes.x_max), &marks)
+}
+
+/// A bar chart of the fraction of the run each server spent serving.
+pub fn utilization(loads: &[ServerLoad]) -> String {
+    let axes = Axes::new(loads.len() as f64, 1.0);
+    let marks = loads
+        .iter()
+        .enumerate()
+        .map(|(i, load)| axes.bar(i as f64 + 0.1, i as f64 + 0.9, load.busy_fraction()))
+        .collect::<String>();
+    let ticks = loads
+        .iter()
+        .enumerate()
+        .map(|(i, load)| (i as f64 + 0.5, load.server.0.to_string()))
+        .collect();
+    axes.render(["Utilization", "server", "busy fraction"], ticks, &marks)
+}
+
+/// The charts of a run so far, by name: the queue length, the waits, and
+/// the utilization.
+pub fn charts(sim: &Simulation) -> Vec<(&'static str, String)> {
+    let end = sim.state.time;
+    let waits = ItemTimes::from_log(&sim.log).waits;
+    vec![
+        (
+            "queue_length",
+            count_over_time(&sim.occupancy().buffered, end, "Queue length"),
+        ),
+        ("waits", histogram(&waits, 20, "Waits")),
+        ("utilization", utilization(&sim.server_loads())),
+    ]
+}
+
+/// Write the charts of a run so far to a directory, creating it if need
+/// be, as `queue_length.svg`, `waits.svg`, and `utilization.svg`.
+pub fn write_charts(sim: &Simulation, dir: impl AsRef<Path>) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    for (name, svg) in charts(sim) {
+        fs::write(dir.join(format!("{name}.svg")), svg)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventMessage, EventMessageType, QueueState};
+
+    #[test]
+    fn test_charts() {
+        // Three items arriving at 0 on one server for services of 5 make the
+        // queue 2, 1, and then 0 long.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for _ in 0..3 {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        }
+        sim.run();
+        let chart = count_over_time(&sim.occupancy().buffered, sim.state.time, "Queue");
+        assert!(chart.starts_with("<svg") && chart.ends_with("</svg>\n"));
+        // The step path rises to 2 at once, then falls twice.
+        let path = chart.lines().find(|l| l.contains(COLOR)).unwrap();
+        assert_eq!(3, path.matches(" V").count());
+
+        // Waits of 0, 5, and 10 fall in the first, middle, and last bins.
+        let chart = histogram(&[0, 5, 10], 3, "Waits");
+        assert_eq!(3, chart.matches(&format!("fill=\"{COLOR}\"")).count());
+
+        let chart = utilization(&sim.server_loads());
+        assert!(chart.contains(">0</text>"));
+
+        let dir = std::env::temp_dir().join(format!("qute-plot-{}", std::process::id()));
+        write_charts(sim, &dir).unwrap();
+        for name in ["queue_length.svg", "waits.svg", "utilization.svg"] {
+            assert!(fs::read_to_string(dir.join(name)).unwrap().contains("<svg"));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,460 @@
+//! A process-oriented layer on top of the event message engine.
+//!
+//! Instead of reasoning about individual event messages, the lifecycle of an
+//! item can be written down as a sequence of steps, e.g.,
+//!
+//! ```text
+//! Process::new().arrive().wait().serve().exit()
+//! ```
+//!
+//! Each process is driven by `EventMessageType::Resume` messages on the
+//! shared event message queue, and the effect of each step on the queue state
+//! is recorded with the same event types used by `handle_message`. Servers
+//! come from the queue's server pool, so server events carry the server, and
+//! service times are scaled by its speed.
+
+use std::collections::VecDeque;
+
+use crate::server::ServerId;
+use crate::{Event, EventMessage, EventMessageType, EventType, QueueState, Time};
+
+/// A single step in the lifecycle of an item:
+/// - `Arrive`: Enter the buffer, or be discarded if the buffer is full.
+/// - `Wait`: Wait in the buffer until a server is available.
+/// - `Hold`: Let the given amount of time pass.
+/// - `Serve`: Keep the server for a service time sampled from the queue's
+///   service time model, at the server's speed.
+/// - `Exit`: Leave the queue, releasing the server if one is held.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    Arrive,
+    Wait,
+    Hold(Time),
+    Serve,
+    Exit,
+}
+
+/// The lifecycle of an item, written as a sequence of steps.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Process {
+    steps: Vec<Step>,
+}
+
+impl Process {
+    /// Create a process with no steps.
+    pub fn new() -> Self {
+        Self { steps: vec![] }
+    }
+
+    /// Add an `Arrive` step.
+    pub fn arrive(mut self) -> Self {
+        self.steps.push(Step::Arrive);
+        self
+    }
+
+    /// Add a `Wait` step.
+    pub fn wait(mut self) -> Self {
+        self.steps.push(Step::Wait);
+        self
+    }
+
+    /// Add a `Hold` step with the given duration.
+    pub fn hold(mut self, duration: Time) -> Self {
+        self.steps.push(Step::Hold(duration));
+        self
+    }
+
+    /// Add a `Serve` step.
+    pub fn serve(mut self) -> Self {
+        self.steps.push(Step::Serve);
+        self
+    }
+
+    /// Add an `Exit` step.
+    pub fn exit(mut self) -> Self {
+        self.steps.push(Step::Exit);
+        self
+    }
+
+    /// Check that the steps are in a sensible order.
+    ///
+    /// An item has to arrive before it can wait, has to be in the queue to
+    /// exit, and can't do anything after exiting. `Hold` is allowed anywhere,
+    /// while `Serve` needs a server.
+    pub fn validate(&self) -> Result<(), ProcessError> {
+        let mut phase = Phase::Outside;
+        for (index, step) in self.steps.iter().enumerate() {
+            phase = match (step, phase) {
+                (Step::Hold(_), Phase::Outside | Phase::Buffered | Phase::Serving) => phase,
+                (Step::Arrive, Phase::Outside) => Phase::Buffered,
+                (Step::Wait, Phase::Buffered) => Phase::Serving,
+                (Step::Serve, Phase::Serving) => Phase::Serving,
+                (Step::Exit, Phase::Buffered | Phase::Serving) => Phase::Done,
+                _ => return Err(ProcessError::UnexpectedStep(index)),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Errors raised when a process can't be run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessError {
+    /// The step at the given index isn't allowed at that point of the
+    /// lifecycle.
+    UnexpectedStep(usize),
+    /// The simulation also has plain arrivals, which processes can't share
+    /// the queue with.
+    Mixed,
+}
+
+/// Identifies a spawned process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProcessId(pub u32);
+
+/// Where a process currently is relative to the queue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Outside,
+    Buffered,
+    Serving,
+    Done,
+}
+
+/// A spawned process, its progress through its steps, and the server it
+/// holds, if any.
+#[derive(Debug)]
+struct Running {
+    process: Process,
+    next: usize,
+    phase: Phase,
+    server: Option<ServerId>,
+}
+
+/// The runtime for all spawned processes.
+///
+/// Processes that are waiting for a server are kept in FIFO order. When a
+/// process releases a server, the server is handed directly to the process
+/// that has been waiting the longest.
+///
+/// Processes only change the buffer and server counts, without items of
+/// their own, so they drive a simulation on their own: a simulation with
+/// plain arrivals can't spawn processes, and one with processes can't take
+/// plain arrivals.
+#[derive(Debug, Default)]
+pub struct Processes {
+    running: Vec<Running>,
+    waiting: VecDeque<ProcessId>,
+    /// Heap bytes held by the steps of all spawned processes, kept up to date
+    /// on `spawn` so that `heap_bytes` doesn't have to walk every process.
+    step_bytes: usize,
+}
+
+impl Processes {
+    /// Create an empty runtime.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a process and return its ID. The process does nothing until
+    /// it's resumed.
+    pub fn spawn(&mut self, process: Process) -> Result<ProcessId, ProcessError> {
+        process.validate()?;
+        self.step_bytes += process.steps.capacity() * std::mem::size_of::<Step>();
+        let id = ProcessId(self.running.len() as u32);
+        self.running.push(Running {
+            process,
+            next: 0,
+            phase: Phase::Outside,
+            server: None,
+        });
+        Ok(id)
+    }
+
+    /// Whether no process has been spawned.
+    pub fn is_empty(&self) -> bool {
+        self.running.is_empty()
+    }
+
+    /// The number of heap bytes allocated for process bookkeeping.
+    pub fn heap_bytes(&self) -> usize {
+        self.running.capacity() * std::mem::size_of::<Running>()
+            + self.waiting.capacity() * std::mem::size_of::<ProcessId>()
+            + self.step_bytes
+    }
+
+    /// Run the process forward from its current step until it blocks or
+    /// finishes, returning the new event messages and events.
+    pub fn resume(
+        &mut self,
+        id: ProcessId,
+        now: Time,
+        queue_state: &mut QueueState,
+    ) -> (Vec<EventMessage>, Vec<Event>) {
+        let mut event_messages = vec![];
+        let mut events = vec![];
+        loop {
+            let running = &mut self.running[id.0 as usize];
+            let step = running.process.steps.get(running.next).copied();
+            running.next += 1;
+            match step {
+                Some(Step::Arrive) => {
+                    if queue_state.can_buffer() {
+                        queue_state.inc_buffer();
+                        running.phase = Phase::Buffered;
+                        events.push(event(EventType::BufferIncremented, now, None));
+                    } else {
+                        // Like plain arrivals, items that can't be buffered
+                        // are discarded.
+                        running.phase = Phase::Done;
+                        break;
+                    }
+                }
+                Some(Step::Wait) => {
+                    if !self.acquire(id, now, queue_state, &mut events) {
+                        self.waiting.push_back(id);
+                        break;
+                    }
+                }
+                Some(Step::Hold(duration)) => {
+                    event_messages.push(EventMessage {
+                        event_message_type: EventMessageType::Resume(id),
+                        time: Time(now.0 + duration.0),
+                    });
+                    break;
+                }
+                Some(Step::Serve) => {
+                    let server = running.server.expect("serving without a server");
+                    let duration = queue_state
+                        .sample_service_phases(None, server)
+                        .iter()
+                        .map(|phase| phase.0)
+                        .sum::<u32>();
+                    let end = Time(now.0 + duration);
+                    queue_state.server_pool.get_mut(server).busy_until = end;
+                    event_messages.push(EventMessage {
+                        event_message_type: EventMessageType::Resume(id),
+                        time: end,
+                    });
+                    break;
+                }
+                // Running out of steps is the same as exiting.
+                Some(Step::Exit) | None => {
+                    self.release(id, now, queue_state, &mut event_messages, &mut events);
+                    break;
+                }
+            }
+        }
+        (event_messages, events)
+    }
+
+    /// Move a buffered process into service on a server from the pool, or
+    /// return `false` if no server is available.
+    fn acquire(
+        &mut self,
+        id: ProcessId,
+        now: Time,
+        queue_state: &mut QueueState,
+        events: &mut Vec<Event>,
+    ) -> bool {
+        let Some(server) = queue_state.server_pool.acquire(&mut queue_state.rng) else {
+            return false;
+        };
+        queue_state.dec_buffer().inc_server();
+        let running = &mut self.running[id.0 as usize];
+        running.phase = Phase::Serving;
+        running.server = Some(server);
+        events.push(event(EventType::BufferDecremented, now, None));
+        events.push(event(EventType::ServerIncremented, now, Some(server)));
+        true
+    }
+
+    /// Remove a process from the queue and, if it was being served, hand the
+    /// server to the next waiting process.
+    fn release(
+        &mut self,
+        id: ProcessId,
+        now: Time,
+        queue_state: &mut QueueState,
+        event_messages: &mut Vec<EventMessage>,
+        events: &mut Vec<Event>,
+    ) {
+        let running = &mut self.running[id.0 as usize];
+        let phase = std::mem::replace(&mut running.phase, Phase::Done);
+        let server = running.server.take();
+        match phase {
+            Phase::Buffered => {
+                queue_state.dec_buffer();
+                events.push(event(EventType::BufferDecremented, now, None));
+            }
+            Phase::Serving => {
+                if let Some(server) = server {
+                    queue_state.server_pool.release(server);
+                }
+                queue_state.dec_server();
+                events.push(event(EventType::ServerDecremented, now, server));
+                if let Some(&next) = self.waiting.front() {
+                    if self.acquire(next, now, queue_state, events) {
+                        self.waiting.pop_front();
+                        event_messages.push(EventMessage {
+                            event_message_type: EventMessageType::Resume(next),
+                            time: now,
+                        });
+                    }
+                }
+            }
+            Phase::Outside | Phase::Done => {}
+        }
+    }
+}
+
+/// Shorthand for building an event, tagged with the server it involves.
+fn event(event_type: EventType, time: Time, server: Option<ServerId>) -> Event {
+    Event {
+        time,
+        event_type,
+        source: None,
+        server,
+        item: None,
+        metadata: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrival::Attributes;
+    use crate::simulation::{InjectError, Simulation};
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            Ok(()),
+            Process::new()
+                .arrive()
+                .wait()
+                .hold(Time(1))
+                .exit()
+                .validate()
+        );
+        assert_eq!(
+            Err(ProcessError::UnexpectedStep(0)),
+            Process::new().wait().validate()
+        );
+        assert_eq!(
+            Err(ProcessError::UnexpectedStep(2)),
+            Process::new().arrive().exit().hold(Time(1)).validate()
+        );
+        assert_eq!(
+            Err(ProcessError::UnexpectedStep(1)),
+            Process::new().arrive().serve().validate()
+        );
+    }
+
+    #[test]
+    fn test_processes_share_one_server() {
+        // Two items arrive at the same time, but only one can be served at a
+        // time, so the second one waits for the first to finish.
+        let sim = &mut Simulation::new(QueueState::new(5, 1, 10));
+        for _ in 0..2 {
+            let process = Process::new().arrive().wait().hold(Time(5)).exit();
+            sim.spawn(Time(0), process).unwrap();
+        }
+        sim.run();
+
+        assert_eq!(Time(10), sim.state.time);
+        assert_eq!(0, sim.state.buffer_count);
+        assert_eq!(0, sim.state.server_count);
+        let served = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerIncremented)
+            .map(|e| e.time)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Time(0), Time(5)], served);
+    }
+
+    #[test]
+    fn test_processes_serve_at_server_speed() {
+        // The fastest server takes the first item and gets through the 10
+        // units of work in 5. The third item waits for it, not for the slow
+        // server.
+        let state = QueueState::new(5, 2, 10).with_server_speeds(&[1.0, 2.0]);
+        let sim = &mut Simulation::new(state);
+        for _ in 0..3 {
+            sim.spawn(Time(0), Process::new().arrive().wait().serve().exit())
+                .unwrap();
+        }
+        sim.run();
+
+        let servers = |event_type| {
+            sim.log
+                .contents
+                .iter()
+                .filter(|e| e.event_type == event_type)
+                .map(|e| (e.time, e.server))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![
+                (Time(0), Some(ServerId(1))),
+                (Time(0), Some(ServerId(0))),
+                (Time(5), Some(ServerId(1))),
+            ],
+            servers(EventType::ServerIncremented)
+        );
+        assert_eq!(
+            vec![
+                (Time(5), Some(ServerId(1))),
+                (Time(10), Some(ServerId(1))),
+                (Time(10), Some(ServerId(0))),
+            ],
+            servers(EventType::ServerDecremented)
+        );
+        assert!(sim.state.server_pool.servers.iter().all(|s| !s.busy));
+    }
+
+    #[test]
+    fn test_processes_reject_plain_arrivals() {
+        // A pending plain arrival, or one that's already been handled, rules
+        // out processes.
+        let process = Process::new().arrive().wait().hold(Time(5)).exit();
+        let sim = &mut Simulation::new(QueueState::new(5, 1, 10));
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(0),
+        });
+        assert_eq!(
+            Err(ProcessError::Mixed),
+            sim.spawn(Time(0), process.clone())
+        );
+        sim.run();
+        assert_eq!(
+            Err(ProcessError::Mixed),
+            sim.spawn(Time(20), process.clone())
+        );
+
+        // A process in service and a plain arrival, with or without
+        // attributes, can't share the buffer.
+        let sim = &mut Simulation::new(QueueState::new(5, 1, 10));
+        sim.spawn(Time(0), process).unwrap();
+        sim.run_until(Time(1));
+        for event_message_type in [
+            EventMessageType::Arrive,
+            EventMessageType::ArriveWith(Attributes::default()),
+        ] {
+            let mixed = sim.try_schedule(EventMessage {
+                event_message_type,
+                time: Time(2),
+            });
+            assert_eq!(Some(ProcessError::Mixed), mixed.err());
+        }
+        assert_eq!(
+            Err(InjectError::Mixed),
+            sim.inject_arrival(Time(2), Attributes::default())
+        );
+        sim.run();
+        assert_eq!((0, 0), (sim.state.buffer_count, sim.state.server_count));
+    }
+}
@@ -0,0 +1,186 @@
+//! Opt-in instrumentation of the engine itself, as opposed to the system
+//! being simulated. This is meant for finding performance bottlenecks in big
+//! models: how fast messages are handled, how large the pending message queue
+//! gets, and how long individual handlers take.
+
+use std::time::{Duration, Instant};
+
+/// Performance measurements collected while a simulation runs.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    /// When profiling started.
+    started: Instant,
+    /// The wall-clock time between the start of profiling and the end of the
+    /// most recent step.
+    pub wall_time: Duration,
+    /// The number of event messages handled.
+    pub messages_handled: u64,
+    /// The number of events added to the log.
+    pub events_logged: u64,
+    /// The number of messages handled during each elapsed wall-clock second.
+    pub messages_per_second: Vec<u64>,
+    /// The largest number of pending event messages seen after a step.
+    pub peak_queue_size: u32,
+    /// Time spent in individual message handlers.
+    pub handler_latency: LatencyHistogram,
+}
+
+impl Profile {
+    /// Start profiling now.
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            wall_time: Duration::ZERO,
+            messages_handled: 0,
+            events_logged: 0,
+            messages_per_second: vec![],
+            peak_queue_size: 0,
+            handler_latency: LatencyHistogram::new(),
+        }
+    }
+
+    /// Record a single step: how long the handler took, how many events it
+    /// produced, and how many messages are pending afterwards.
+    pub fn record(&mut self, handler_time: Duration, events: usize, queue_size: u32) -> &mut Self {
+        self.wall_time = self.started.elapsed();
+        self.messages_handled += 1;
+        self.events_logged += events as u64;
+        self.peak_queue_size = self.peak_queue_size.max(queue_size);
+        self.handler_latency.record(handler_time);
+
+        let second = self.wall_time.as_secs() as usize;
+        if self.messages_per_second.len() <= second {
+            self.messages_per_second.resize(second + 1, 0);
+        }
+        self.messages_per_second[second] += 1;
+        self
+    }
+
+    /// The average number of messages handled per wall-clock second.
+    pub fn message_rate(&self) -> f64 {
+        let seconds = self.wall_time.as_secs_f64();
+        if seconds > 0.0 {
+            self.messages_handled as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A histogram of durations with power-of-two nanosecond buckets.
+///
+/// Bucket `i` counts durations `d` with `2^(i - 1) <= d < 2^i` nanoseconds,
+/// and bucket 0 counts durations under a nanosecond.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LatencyHistogram {
+    pub buckets: [u64; 64],
+    pub count: u64,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    /// Create an empty histogram.
+    pub fn new() -> Self {
+        Self {
+            buckets: [0; 64],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+
+    /// Add a duration to the histogram.
+    pub fn record(&mut self, duration: Duration) -> &mut Self {
+        let nanos = duration.as_nanos().min(u64::MAX as u128) as u64;
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        self.buckets[bucket.min(63)] += 1;
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+        self
+    }
+
+    /// The mean of the recorded durations.
+    pub fn mean(&self) -> Duration {
+        if self.count == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos((self.total.as_nanos() / self.count as u128) as u64)
+        }
+    }
+
+    /// An upper bound on the given quantile (between 0 and 1), taken as the
+    /// upper edge of the bucket that contains it.
+    pub fn quantile(&self, q: f64) -> Duration {
+        let target = (q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (i, &n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if n > 0 && seen >= target {
+                return Duration::from_nanos(1u64 << i.min(63));
+            }
+        }
+        self.max
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    #[test]
+    fn test_histogram_buckets() {
+        let hist = &mut LatencyHistogram::new();
+        hist.record(Duration::from_nanos(0))
+            .record(Duration::from_nanos(3))
+            .record(Duration::from_nanos(100));
+        assert_eq!(1, hist.buckets[0]);
+        assert_eq!(1, hist.buckets[2]);
+        assert_eq!(1, hist.buckets[7]);
+        assert_eq!(Duration::from_nanos(100), hist.max);
+        assert_eq!(Duration::from_nanos(128), hist.quantile(1.0));
+        assert_eq!(Duration::from_nanos(34), hist.mean());
+
+        // The mean stays right past 2^32 durations.
+        hist.count = 1 << 32;
+        hist.total = Duration::from_nanos(3 << 32);
+        assert_eq!(Duration::from_nanos(3), hist.mean());
+    }
+
+    #[test]
+    fn test_profiled_run() {
+        let sim = &mut Simulation::new(QueueState::new(5, 1, 2));
+        sim.enable_profiling();
+        (0..3).for_each(|t| {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        });
+        sim.run();
+
+        let profile = sim.profile().unwrap();
+        assert_eq!(sim.log.size as u64, profile.events_logged);
+        assert_eq!(profile.messages_handled, profile.handler_latency.count);
+        assert_eq!(
+            profile.messages_handled,
+            profile.messages_per_second.iter().sum::<u64>()
+        );
+        assert!(profile.peak_queue_size >= 2);
+    }
+}
@@ -0,0 +1,190 @@
+//! Prometheus metrics for long-lived simulations.
+//!
+//! With the `prometheus` feature, a simulation that runs as a service, e.g.,
+//! one paced to the wall clock or fed arrivals from outside, can expose its
+//! counters and gauges for Prometheus to scrape. `exposition` renders them
+//! in the Prometheus text format, and an `Endpoint` serves them over HTTP
+//! at `/metrics`. The endpoint doesn't need a thread of its own: the loop
+//! that steps the simulation polls it between steps, which answers any
+//! scrapes that are waiting.
+
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::simulation::Simulation;
+
+/// How long to wait on a slow scraper before giving up on it.
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A metric with its help text, type, and samples, each with its labels.
+fn metric(text: &mut String, name: &str, help: &str, kind: &str, samples: &[(&str, f64)]) {
+    let _ = writeln!(text, "# HELP {name} {help}\n# TYPE {name} {kind}");
+    for (labels, value) in samples {
+        let _ = writeln!(text, "{name}{labels} {value}");
+    }
+}
+
+/// The metrics of a simulation so far, in the Prometheus text format.
+pub fn exposition(sim: &Simulation) -> String {
+    let state = &sim.state;
+    let mut text = String::new();
+    let counters: [(&str, &str, f64); 3] = [
+        (
+            "qute_steps_total",
+            "Event messages handled.",
+            sim.steps() as f64,
+        ),
+        ("qute_events_total", "Events recorded.", sim.events() as f64),
+        (
+            "qute_arrivals_total",
+            "Items that arrived.",
+            state.arrivals() as f64,
+        ),
+    ];
+    for (name, help, value) in counters {
+        metric(&mut text, name, help, "counter", &[("", value)]);
+    }
+    let losses = [
+        ("{reason=\"dropped\"}", state.dropped as f64),
+        ("{reason=\"balked\"}", state.balked as f64),
+        ("{reason=\"throttled\"}", state.throttled as f64),
+        ("{reason=\"reneged\"}", state.reneged as f64),
+        ("{reason=\"evicted\"}", state.evicted as f64),
+    ];
+    let help = "Items lost, by reason.";
+    metric(&mut text, "qute_losses_total", help, "counter", &losses);
+    let gauges: [(&str, &str, f64); 4] = [
+        ("qute_sim_time", "The simulated time.", state.time.0 as f64),
+        (
+            "qute_queue_length",
+            "Items in the buffer.",
+            state.buffer_count as f64,
+        ),
+        (
+            "qute_busy_servers",
+            "Servers in use.",
+            state.server_count as f64,
+        ),
+        (
+            "qute_pending_messages",
+            "Event messages waiting to be handled.",
+            sim.emq.size as f64,
+        ),
+    ];
+    for (name, help, value) in gauges {
+        metric(&mut text, name, help, "gauge", &[("", value)]);
+    }
+    text
+}
+
+/// An HTTP endpoint serving the metrics of a simulation at `/metrics`.
+#[derive(Debug)]
+pub struct Endpoint {
+    listener: TcpListener,
+}
+
+impl Endpoint {
+    /// Listen on an address, e.g., `"0.0.0.0:9464"`, or port 0 for any free
+    /// port.
+    pub fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener })
+    }
+
+    /// The address the endpoint listens on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answer the requests that are waiting with the current metrics of a
+    /// simulation, without waiting for more, and return how many there
+    /// were. A request that fails doesn't stop the others.
+    pub fn poll(&self, sim: &Simulation) -> io::Result<usize> {
+        let mut answered = 0;
+        loop {
+            match self.listener.accept() {
+                Ok((mut stream, _)) => {
+                    let _ = answer(&mut stream, sim);
+                    answered += 1;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(answered),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Read a request from a connection and respond to it.
+fn answer(stream: &mut TcpStream, sim: &Simulation) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    // Only the request line matters, which is in the first read in practice.
+    let mut request = [0; 1024];
+    let n = stream.read(&mut request)?;
+    let request = String::from_utf8_lossy(&request[..n]);
+    let target = request.split_whitespace().take(2).collect::<Vec<_>>();
+    let (status, body) = match target[..] {
+        ["GET", "/metrics"] => ("200 OK", exposition(sim)),
+        ["GET", _] => ("404 Not Found", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    #[test]
+    fn test_metrics() {
+        // Three items arrive at once to a queue with room for one and a
+        // single server, so one is dropped.
+        let sim = &mut Simulation::new(QueueState::new(1, 1, 5));
+        for _ in 0..3 {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        }
+        sim.run_until(Time(1));
+        let text = exposition(sim);
+        assert!(text.contains("# TYPE qute_arrivals_total counter\nqute_arrivals_total 3\n"));
+        assert!(text.contains("qute_losses_total{reason=\"dropped\"} 1\n"));
+        assert!(text.contains(&format!("qute_steps_total {}\n", sim.steps())));
+        assert!(text.contains(&format!("qute_events_total {}\n", sim.log.contents.len())));
+        assert!(text.contains("qute_busy_servers 1\n"));
+
+        // Waiting scrapes are answered by a poll, and there are none left
+        // after.
+        let endpoint = Endpoint::bind("127.0.0.1:0").unwrap();
+        let address = endpoint.local_addr().unwrap();
+        let scrape = |target: &str| {
+            let mut stream = TcpStream::connect(address).unwrap();
+            write!(stream, "GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            stream
+        };
+        let mut streams = [scrape("/metrics"), scrape("/other")];
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(2, endpoint.poll(sim).unwrap());
+        assert_eq!(0, endpoint.poll(sim).unwrap());
+        let mut response = String::new();
+        streams[0].read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&text));
+        response.clear();
+        streams[1].read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Replaying runs to reproduce them exactly.
+//!
+//! A `Simulation` can keep a trace of every message it handled. Feeding
+//! that trace back through the message handlers from the same initial
+//! state, seeds included, reproduces the run's events and state trajectory
+//! exactly, without the message queue or arrival sources, so a trace saved
+//! before a change to the handlers shows exactly where the change makes a
+//! run diverge. Traces and logs can be saved and loaded as JSON Lines (see
+//! the `json` and `sink` modules).
+//!
+//! Processes and interrupts are run by `Simulation` itself rather than the
+//! handlers, so traces with `Resume` or `Interrupt` messages can't be
+//! replayed, and the events that arrival sources report, e.g., `SourceOn`,
+//! aren't reproduced.
+
+use std::io::BufRead;
+
+use crate::json::{self, FromJson};
+use crate::{
+    handle_message, Event, EventLog, EventMessage, EventMessageType, EventType, QueueState,
+};
+
+/// Errors raised when a trace can't be replayed:
+/// - `Unsupported`: The message at the given index isn't handled by the
+///   handlers.
+/// - `OutOfOrder`: The message at the given index is earlier than the one
+///   before it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayError {
+    Unsupported(usize),
+    OutOfOrder(usize),
+}
+
+/// Handle the messages of a trace in order, starting from the given state,
+/// and return the events they produced.
+pub fn replay(state: &mut QueueState, messages: &[EventMessage]) -> Result<EventLog, ReplayError> {
+    let mut log = EventLog::new();
+    for (i, &message) in messages.iter().enumerate() {
+        if let EventMessageType::Resume(_) | EventMessageType::Interrupt(_) =
+            message.event_message_type
+        {
+            return Err(ReplayError::Unsupported(i));
+        }
+        if message.time < state.time {
+            return Err(ReplayError::OutOfOrder(i));
+        }
+        let (state, _, events) = handle_message(message, state);
+        state.set_time(message.time);
+        events.into_iter().for_each(|e| {
+            log.push(e);
+        });
+    }
+    Ok(log)
+}
+
+/// Where two logs first differ: the index of the event, and the event in
+/// each log there, if it has one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Divergence {
+    pub index: usize,
+    pub expected: Option<Event>,
+    pub actual: Option<Event>,
+}
+
+/// Check that a replayed log matches the original one, leaving out the
+/// events reported by arrival sources, which replays don't reproduce.
+pub fn verify(expected: &EventLog, actual: &EventLog) -> Result<(), Box<Divergence>> {
+    let expected = expected
+        .contents
+        .iter()
+        .filter(|e| {
+            !matches!(
+                e.event_type,
+                EventType::RegimeChanged(_) | EventType::SourceOn | EventType::SourceOff
+            )
+        })
+        .collect::<Vec<_>>();
+    for index in 0..expected.len().max(actual.contents.len()) {
+        let (e, a) = (expected.get(index).copied(), actual.contents.get(index));
+        if e != a {
+            return Err(Box::new(Divergence {
+                index,
+                expected: e.copied(),
+                actual: a.copied(),
+            }));
+        }
+    }
+    Ok(())
+}
+
+/// Read values written one JSON object per line, e.g., by a `JsonLines`
+/// sink, skipping blank lines. A problem names the line it's on.
+pub fn read_lines<T: FromJson>(reader: impl BufRead) -> Result<Vec<T>, String> {
+    let mut values = vec![];
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("line {}: {e}", i + 1))?;
+        if !line.trim().is_empty() {
+            values.push(json::parse(&line).map_err(|e| format!("line {}: {e}", i + 1))?);
+        }
+    }
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrival::PoissonArrivals;
+    use crate::dist::Exponential;
+    use crate::json::ToJson;
+    use crate::simulation::Simulation;
+    use crate::Time;
+
+    fn state() -> QueueState {
+        QueueState::new(3, 2, 0).with_service_time(Exponential(0.2), 1)
+    }
+
+    #[test]
+    fn test_replay() {
+        // A run with random arrivals and services replays exactly from its
+        // trace, saved and loaded as JSON Lines, and a changed log is caught
+        // where it diverges.
+        let sim = &mut Simulation::new(state());
+        sim.enable_message_trace();
+        sim.add_source(PoissonArrivals::new(0.5, 2));
+        sim.run_until(Time(200));
+        let text = sim
+            .message_trace()
+            .unwrap()
+            .iter()
+            .map(|m| m.to_json().to_string() + "\n")
+            .collect::<String>();
+        let messages = read_lines::<EventMessage>(text.as_bytes()).unwrap();
+        let replayed_state = &mut state();
+        let replayed = replay(replayed_state, &messages).unwrap();
+        assert_eq!(Ok(()), verify(&sim.log, &replayed));
+        assert_eq!(
+            (
+                sim.state.time,
+                sim.state.buffer_count,
+                sim.state.server_count
+            ),
+            (
+                replayed_state.time,
+                replayed_state.buffer_count,
+                replayed_state.server_count
+            )
+        );
+
+        let mut changed = EventLog::new();
+        for e in &replayed.contents[..10] {
+            changed.push(*e);
+        }
+        let divergence = verify(&sim.log, &changed).unwrap_err();
+        assert_eq!((10, None), (divergence.index, divergence.actual));
+
+        let later = messages.iter().find(|m| m.time > messages[0].time).unwrap();
+        let backwards = [*later, messages[0]];
+        assert_eq!(
+            Err(ReplayError::OutOfOrder(1)),
+            replay(&mut state(), &backwards).map(|_| ())
+        );
+        assert!(read_lines::<EventMessage>("{}\n".as_bytes()).is_err());
+    }
+}
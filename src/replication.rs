@@ -0,0 +1,240 @@
+//! Independent replications of a simulation.
+//!
+//! A single run is one sample of a random process, so estimates from it come
+//! without a sense of their precision. Running the same model several times
+//! with different seeds gives a sample of each summary metric, from which a
+//! confidence interval for its mean follows.
+//!
+//! Runs can also come in antithetic pairs, the second run of each driven by
+//! the mirror image of the first's random draws. The two runs of a pair are
+//! negatively correlated, so the average of each pair is a less noisy
+//! sample than either run on its own.
+
+use crate::crn::Streams;
+use crate::stats::{Interval, Precision, SummaryStats};
+
+/// Picks a metric out of a summary, if it's defined.
+type Metric = fn(&SummaryStats) -> Option<f64>;
+
+/// The summaries of independent runs of the same model. If `antithetic`,
+/// the runs come in antithetic pairs, one after the other.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Replications {
+    pub runs: Vec<SummaryStats>,
+    pub antithetic: bool,
+}
+
+impl Replications {
+    /// Make `n` runs, each given its index, which can serve as a seed, and
+    /// returning the summary of the run.
+    pub fn run(n: u64, mut replicate: impl FnMut(u64) -> SummaryStats) -> Self {
+        Self {
+            runs: (0..n).map(&mut replicate).collect(),
+            antithetic: false,
+        }
+    }
+
+    /// Make runs, each given its index, until the confidence interval for
+    /// the mean of a metric reaches a target precision, but no fewer than
+    /// `min` runs and no more than `max`.
+    pub fn run_until_precise(
+        min: u64,
+        max: u64,
+        mut replicate: impl FnMut(u64) -> SummaryStats,
+        metric: impl Fn(&SummaryStats) -> Option<f64>,
+        precision: Precision,
+    ) -> Self {
+        let mut replications = Self::default();
+        for i in 0..max {
+            replications.runs.push(replicate(i));
+            let interval = replications.interval(&metric, precision.level);
+            if i + 1 >= min && interval.is_some_and(|i| precision.is_met(&i)) {
+                break;
+            }
+        }
+        replications
+    }
+
+    /// Make `n` antithetic pairs of runs, each given the streams of its
+    /// replication, or their antithetic counterparts, and returning the
+    /// summary of the run.
+    pub fn run_antithetic(n: u64, mut replicate: impl FnMut(Streams) -> SummaryStats) -> Self {
+        Self {
+            runs: (0..n)
+                .flat_map(|i| [Streams::new(i), Streams::new(i).antithetic()])
+                .map(&mut replicate)
+                .collect(),
+            antithetic: true,
+        }
+    }
+
+    /// The confidence interval for the mean of a metric across runs, at the
+    /// given confidence level. Runs where the metric is undefined are left
+    /// out, along with their antithetic partners, and the sample is the pair
+    /// averages.
+    pub fn interval(
+        &self,
+        metric: impl Fn(&SummaryStats) -> Option<f64>,
+        level: f64,
+    ) -> Option<Interval> {
+        let sample = if self.antithetic {
+            self.runs
+                .chunks_exact(2)
+                .filter_map(|pair| Some((metric(&pair[0])? + metric(&pair[1])?) / 2.0))
+                .collect::<Vec<_>>()
+        } else {
+            self.runs.iter().filter_map(metric).collect::<Vec<_>>()
+        };
+        Interval::new(&sample, level)
+    }
+
+    /// The confidence intervals for the means of every summary metric, by
+    /// name, at the given confidence level.
+    pub fn intervals(&self, level: f64) -> Vec<(&'static str, Option<Interval>)> {
+        let metrics: [(&'static str, Metric); 11] = [
+            ("mean_busy_servers", |s| Some(s.mean_busy_servers)),
+            ("mean_capacity", |s| s.mean_capacity),
+            ("utilization", |s| s.utilization),
+            ("mean_queue_length", |s| Some(s.mean_queue_length)),
+            ("mean_number_in_system", |s| Some(s.mean_number_in_system)),
+            ("mean_wait", |s| s.wait.map(|w| w.mean)),
+            ("mean_service", |s| s.service.map(|w| w.mean)),
+            ("mean_sojourn", |s| s.sojourn.map(|w| w.mean)),
+            ("throughput", |s| Some(s.flow.throughput())),
+            ("loss_probability", |s| Some(s.flow.loss_probability())),
+            ("abandonment_probability", |s| {
+                Some(s.flow.abandonment_probability())
+            }),
+        ];
+        metrics
+            .into_iter()
+            .map(|(name, metric)| (name, self.interval(metric, level)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrival::{DeterministicArrivals, PoissonArrivals};
+    use crate::crn::Stream;
+    use crate::dist::{Exponential, LogNormal};
+    use crate::simulation::Simulation;
+    use crate::stats::t_quantile;
+    use crate::{QueueState, Time};
+
+    #[test]
+    fn test_t_intervals() {
+        // Known t quantiles, and the interval for 1 through 5, whose
+        // standard deviation is sqrt(2.5).
+        assert!((t_quantile(0.975, 1.0) - 12.706).abs() < 1e-3);
+        assert!((t_quantile(0.975, 4.0) - 2.776).abs() < 1e-3);
+        assert!((t_quantile(0.95, 30.0) - 1.697).abs() < 1e-3);
+        let interval = Interval::new(&[1.0, 2.0, 3.0, 4.0, 5.0], 0.95).unwrap();
+        assert!((interval.std_dev - 2.5f64.sqrt()).abs() < 1e-9);
+        assert!((interval.half_width - 1.963).abs() < 1e-3);
+        assert_eq!(None, Interval::new(&[1.0], 0.95));
+    }
+
+    #[test]
+    fn test_replications() {
+        // Runs of an M/D/1 queue with different seeds vary in utilization,
+        // but not in capacity, which is always 1.
+        let replications = Replications::run(10, |seed| {
+            let sim = &mut Simulation::new(QueueState::new(100, 1, 5));
+            sim.add_source(PoissonArrivals::new(0.1, seed));
+            sim.run_until(Time(1000));
+            sim.summary()
+        });
+        let intervals = replications.intervals(0.9);
+        assert_eq!(11, intervals.len());
+        let capacity = intervals[1].1.unwrap();
+        assert_eq!(
+            (10, 1.0, 0.0),
+            (capacity.n, capacity.mean, capacity.half_width)
+        );
+        let utilization = intervals[2].1.unwrap();
+        assert!(utilization.half_width > 0.0);
+        let mean = replications
+            .runs
+            .iter()
+            .filter_map(|r| r.utilization)
+            .sum::<f64>()
+            / 10.0;
+        assert!((utilization.mean - mean).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_replicate_until_precise() {
+        // Utilization varies little from run to run, so a loose target is
+        // met after the minimum number of runs, and an impossibly tight one
+        // only stops at the maximum.
+        let run = |seed| {
+            let sim = &mut Simulation::new(QueueState::new(100, 1, 5));
+            sim.add_source(PoissonArrivals::new(0.1, seed));
+            sim.run_until(Time(1000));
+            sim.summary()
+        };
+        let utilization = |s: &SummaryStats| s.utilization;
+        let loose =
+            Replications::run_until_precise(3, 50, run, utilization, Precision::new(0.5, 0.95));
+        assert_eq!(3, loose.runs.len());
+        let tight =
+            Replications::run_until_precise(3, 8, run, utilization, Precision::new(1e-6, 0.95));
+        assert_eq!(8, tight.runs.len());
+    }
+
+    #[test]
+    fn test_antithetic_replications() {
+        // Pairs of M/M/1 runs on antithetic streams estimate utilization
+        // more tightly than as many independent runs: the pair averages vary
+        // less than averages of two independent runs would.
+        let run = |streams: Streams| {
+            let state = QueueState::new(1000, 1, 0)
+                .with_service_time(Exponential(0.01), streams.seed(Stream::Other))
+                .with_service_stream(streams.seed(Stream::Service));
+            let sim = &mut Simulation::new(state);
+            sim.add_source(PoissonArrivals::new(
+                0.005,
+                streams.seed(Stream::Arrivals(0)),
+            ));
+            sim.run_until(Time(20_000));
+            sim.summary()
+        };
+        let antithetic = Replications::run_antithetic(10, run);
+        let independent = Replications::run(20, |i| run(Streams::new(i)));
+        assert_eq!(20, antithetic.runs.len());
+        let metric = |s: &SummaryStats| s.utilization;
+        let a = antithetic.interval(metric, 0.95).unwrap();
+        let b = independent.interval(metric, 0.95).unwrap();
+        assert_eq!(10, a.n);
+        assert!(a.std_dev < b.std_dev / 2f64.sqrt());
+        assert!(a.half_width < b.half_width);
+    }
+
+    #[test]
+    fn test_antithetic_normal_services() {
+        // Lognormal services are driven by normal samples, which the
+        // antithetic streams negate, so pairs of runs with regular arrivals
+        // cancel most of the noise in the utilization.
+        let run = |streams: Streams| {
+            let service = LogNormal {
+                mu: 100f64.ln() - 0.125,
+                sigma: 0.5,
+            };
+            let state = QueueState::new(1000, 1, 0)
+                .with_service_time(service, streams.seed(Stream::Other))
+                .with_service_stream(streams.seed(Stream::Service));
+            let sim = &mut Simulation::new(state);
+            sim.add_source(DeterministicArrivals::new(Time(200)));
+            sim.run_until(Time(20_000));
+            sim.summary()
+        };
+        let antithetic = Replications::run_antithetic(10, run);
+        let independent = Replications::run(20, |i| run(Streams::new(i)));
+        let metric = |s: &SummaryStats| s.utilization;
+        let a = antithetic.interval(metric, 0.95).unwrap();
+        let b = independent.interval(metric, 0.95).unwrap();
+        assert!(a.std_dev < b.std_dev / 2f64.sqrt());
+    }
+}
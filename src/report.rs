@@ -0,0 +1,372 @@
+//! Human-readable output.
+//!
+//! Queue states, events, and summaries print as short text, and `report`
+//! renders a whole run as a Markdown document: the configuration of the
+//! queue, its key metrics, and the percentiles of its per-item times.
+//! `html_report` renders the same as a self-contained HTML page, with
+//! charts of the run under the `plot` feature, and `html_replications`
+//! renders the confidence intervals of a set of replications.
+
+use std::fmt;
+
+use crate::replication::Replications;
+use crate::simulation::Simulation;
+use crate::stats::{SummaryStats, TimeStats};
+use crate::{Event, QueueState};
+
+/// A capacity, which may be unlimited.
+fn capacity(n: u32) -> String {
+    match n {
+        u32::MAX => "unlimited".to_string(),
+        n => n.to_string(),
+    }
+}
+
+/// Prints the clock and the buffer and server counts against their
+/// capacities, e.g., `time 12: 3/5 buffered, 2/2 servers busy`.
+impl fmt::Display for QueueState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "time {}: {}/{} buffered, {}/{} servers busy",
+            self.time.0,
+            self.buffer_count,
+            capacity(self.buffer_capacity),
+            self.server_count,
+            capacity(self.server_capacity)
+        )
+    }
+}
+
+/// Prints the time and type of the event, followed by what it's tagged
+/// with, e.g., `12 ServerIncremented source=1 server=0 item=4`.
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:?}", self.time.0, self.event_type)?;
+        if let Some(source) = self.source {
+            write!(f, " source={}", source.0)?;
+        }
+        if let Some(server) = self.server {
+            write!(f, " server={}", server.0)?;
+        }
+        if let Some(item) = self.item {
+            write!(f, " item={}", item.0)?;
+        }
+        Ok(())
+    }
+}
+
+/// Prints the key metrics one per line.
+impl fmt::Display for SummaryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in metrics(self) {
+            writeln!(f, "{name}: {value}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The key metrics of a summary, by name, formatted.
+fn metrics(summary: &SummaryStats) -> Vec<(&'static str, String)> {
+    let flow = &summary.flow;
+    let mut metrics = vec![
+        ("end", summary.end.0.to_string()),
+        (
+            "utilization",
+            summary
+                .utilization
+                .map_or("-".to_string(), |u| format!("{u:.4}")),
+        ),
+        (
+            "mean busy servers",
+            format!("{:.4}", summary.mean_busy_servers),
+        ),
+        (
+            "mean queue length",
+            format!("{:.4}", summary.mean_queue_length),
+        ),
+        (
+            "mean number in system",
+            format!("{:.4}", summary.mean_number_in_system),
+        ),
+        ("arrivals", flow.arrivals.to_string()),
+        ("departures", flow.departures.to_string()),
+        ("throughput", format!("{:.4}", flow.throughput())),
+        (
+            "loss probability",
+            format!("{:.4}", flow.loss_probability()),
+        ),
+    ];
+    if let Some(level) = summary.service_level {
+        metrics.push((
+            "service level",
+            format!("{:.4} within {}", level.attained, level.target),
+        ));
+    }
+    metrics
+}
+
+/// A table of text cells, which renders as Markdown or HTML.
+struct Table {
+    header: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// The table in Markdown.
+    fn markdown(&self) -> String {
+        let mut table = format!("| {} |\n", self.header.join(" | "));
+        table.push_str(&format!("|{}\n", " --- |".repeat(self.header.len())));
+        for row in &self.rows {
+            table.push_str(&format!("| {} |\n", row.join(" | ")));
+        }
+        table
+    }
+
+    /// The table in HTML.
+    fn html(&self) -> String {
+        let cells = |tag: &str, row: &[&str]| {
+            let cells = row
+                .iter()
+                .map(|cell| format!("<{tag}>{}</{tag}>", escape(cell)))
+                .collect::<String>();
+            format!("<tr>{cells}</tr>\n")
+        };
+        let mut table = format!("<table>\n{}", cells("th", &self.header));
+        for row in &self.rows {
+            table.push_str(&cells(
+                "td",
+                &row.iter().map(String::as_str).collect::<Vec<_>>(),
+            ));
+        }
+        table.push_str("</table>\n");
+        table
+    }
+}
+
+/// Escape text for HTML.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The configuration of a queue.
+fn configuration(state: &QueueState) -> Table {
+    let servers = if state.has_infinite_servers() {
+        "infinite".to_string()
+    } else {
+        capacity(state.server_capacity)
+    };
+    let rows = [
+        ("buffer capacity", capacity(state.buffer_capacity)),
+        ("servers", servers),
+        ("discipline", format!("{:?}", state.discipline)),
+    ];
+    Table {
+        header: vec!["Setting", "Value"],
+        rows: rows
+            .into_iter()
+            .map(|(name, value)| vec![name.to_string(), value])
+            .collect(),
+    }
+}
+
+/// The key metrics of a summary.
+fn metrics_table(summary: &SummaryStats) -> Table {
+    Table {
+        header: vec!["Metric", "Value"],
+        rows: metrics(summary)
+            .into_iter()
+            .map(|(name, value)| vec![name.to_string(), value])
+            .collect(),
+    }
+}
+
+/// The percentiles of the per-item times of a summary.
+fn times(summary: &SummaryStats) -> Table {
+    let times: [(&str, Option<TimeStats>); 4] = [
+        ("wait", summary.wait),
+        ("service", summary.service),
+        ("sojourn", summary.sojourn),
+        ("interdeparture", summary.interdeparture),
+    ];
+    let rows = times.into_iter().map(|(name, stats)| match stats {
+        Some(s) => vec![
+            name.to_string(),
+            s.count.to_string(),
+            format!("{:.2}", s.mean),
+            s.p50.to_string(),
+            s.p90.to_string(),
+            s.p99.to_string(),
+            s.max.to_string(),
+        ],
+        None => [name, "0", "-", "-", "-", "-", "-"]
+            .map(String::from)
+            .to_vec(),
+    });
+    Table {
+        header: vec!["Time", "Count", "Mean", "p50", "p90", "p99", "Max"],
+        rows: rows.collect(),
+    }
+}
+
+/// Render a run as a Markdown document, given its final state and summary.
+pub fn report(state: &QueueState, summary: &SummaryStats) -> String {
+    format!(
+        "# Run summary\n\n## Configuration\n\n{}\n## Metrics\n\n{}\n## Times\n\n{}",
+        configuration(state).markdown(),
+        metrics_table(summary).markdown(),
+        times(summary).markdown()
+    )
+}
+
+/// The style of HTML reports.
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+th { background: #f4f4f4; }
+svg { display: block; margin-bottom: 1em; }
+";
+
+/// A self-contained HTML page.
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n\
+         <h1>{}</h1>\n{body}</body>\n</html>\n",
+        escape(title),
+        escape(title)
+    )
+}
+
+/// Render a run so far as a self-contained HTML page, to share with people
+/// who won't run the model themselves: the configuration, the key metrics,
+/// the per-item times, and, with the `plot` feature, charts of the run.
+pub fn html_report(sim: &Simulation) -> String {
+    let summary = sim.summary();
+    #[cfg(feature = "plot")]
+    let charts = crate::plot::charts(sim).into_iter().map(|(_, svg)| svg);
+    #[cfg(feature = "plot")]
+    let charts = format!("<h2>Charts</h2>\n{}", charts.collect::<String>());
+    #[cfg(not(feature = "plot"))]
+    let charts = "";
+    let body = format!(
+        "<h2>Configuration</h2>\n{}<h2>Metrics</h2>\n{}<h2>Times</h2>\n{}{charts}",
+        configuration(&sim.state).html(),
+        metrics_table(&summary).html(),
+        times(&summary).html()
+    );
+    page("Run summary", &body)
+}
+
+/// Render a set of replications as a self-contained HTML page, with a
+/// confidence interval at the given level for each metric.
+pub fn html_replications(replications: &Replications, level: f64) -> String {
+    let rows = replications
+        .intervals(level)
+        .into_iter()
+        .map(|(name, interval)| {
+            let mut row = vec![name.to_string()];
+            match interval {
+                Some(i) => row.extend(
+                    [i.mean, i.half_width, i.lower(), i.upper()].map(|x| format!("{x:.4}")),
+                ),
+                None => row.extend(["-"; 4].map(String::from)),
+            }
+            row
+        });
+    let table = Table {
+        header: vec!["Metric", "Mean", "Half-width", "Lower", "Upper"],
+        rows: rows.collect(),
+    };
+    let pairs = if replications.antithetic {
+        ", in antithetic pairs"
+    } else {
+        ""
+    };
+    let body = format!(
+        "<p>{} runs{pairs}, with {}% confidence intervals.</p>\n{}",
+        replications.runs.len(),
+        level * 100.0,
+        table.html()
+    );
+    page("Replications", &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, Time};
+
+    #[test]
+    fn test_display_and_report() {
+        // Items arrive at 0 and 1 for services of 5, and the second waits 4.
+        let sim = &mut Simulation::new(QueueState::new(5, 1, 5));
+        for t in [0, 1] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.step();
+        assert_eq!(
+            "time 0: 1/5 buffered, 0/1 servers busy",
+            sim.state.to_string()
+        );
+        let summary = sim.run();
+        assert_eq!(
+            "0 BufferIncremented item=0",
+            sim.log.contents[0].to_string()
+        );
+        assert_eq!(
+            "0 ServerIncremented server=0 item=0",
+            sim.log.contents[2].to_string()
+        );
+        assert!(summary.to_string().contains("departures: 2\n"));
+        let report = report(&sim.state, &summary);
+        assert!(report.starts_with("# Run summary\n"));
+        assert!(report.contains("| buffer capacity | 5 |\n"));
+        assert!(report.contains("| wait | 2 | 2.00 | 0 | 4 | 4 | 4 |\n"));
+        assert!(report.contains("| interdeparture | 1 | 5.00 |"));
+    }
+
+    #[test]
+    fn test_html() {
+        // The HTML report has the same tables as the Markdown one.
+        let sim = &mut Simulation::new(QueueState::new(5, 1, 5));
+        for t in [0, 1] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        let summary = sim.run();
+        let html = html_report(sim);
+        assert!(html.starts_with("<!DOCTYPE html>\n"));
+        assert!(html.contains("<h1>Run summary</h1>"));
+        assert!(html.contains("<tr><td>buffer capacity</td><td>5</td></tr>"));
+        assert!(html.contains("<tr><td>wait</td><td>2</td><td>2.00</td>"));
+        assert_eq!(cfg!(feature = "plot"), html.contains("<svg"));
+        assert_eq!("a &lt;b&gt; &amp; &quot;c&quot;", escape("a <b> & \"c\""));
+
+        // Replications get an interval per metric, or a dash without enough
+        // runs to work one out.
+        let replications = Replications {
+            runs: vec![summary, summary],
+            antithetic: false,
+        };
+        let html = html_replications(&replications, 0.95);
+        assert!(html.contains("<p>2 runs, with 95% confidence intervals.</p>"));
+        assert!(html.contains("<tr><td>utilization</td><td>1.0000</td><td>0.0000</td>"));
+        let one = Replications {
+            runs: replications.runs[..1].to_vec(),
+            antithetic: false,
+        };
+        assert!(html_replications(&one, 0.95).contains("<td>utilization</td><td>-</td>"));
+    }
+}
@@ -0,0 +1,99 @@
+//! Secondary resources that items need alongside a server, e.g., a bed and a
+//! nurse for a patient, where the doctor is the server.
+//!
+//! An item only enters service once a server and enough units of every
+//! resource are free, and the units are held until the service ends.
+
+/// A pool of identical units of a finite resource, of which every service
+/// holds `needed` units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourcePool {
+    pub capacity: u32,
+    pub needed: u32,
+    in_use: u32,
+}
+
+impl ResourcePool {
+    /// Create a pool with the given number of units, of which each service
+    /// needs `needed`.
+    pub fn new(capacity: u32, needed: u32) -> Self {
+        assert!(
+            needed <= capacity,
+            "a service can't need more units than the pool has"
+        );
+        Self {
+            capacity,
+            needed,
+            in_use: 0,
+        }
+    }
+
+    /// The number of units held by services.
+    pub fn in_use(&self) -> u32 {
+        self.in_use
+    }
+
+    /// Check if there are enough free units for another service.
+    pub fn has_available(&self) -> bool {
+        self.in_use + self.needed <= self.capacity
+    }
+
+    /// Take the units for a service.
+    pub fn acquire(&mut self) {
+        assert!(self.has_available(), "not enough free units");
+        self.in_use += self.needed;
+    }
+
+    /// Give back the units of a service.
+    pub fn release(&mut self) {
+        self.in_use = self.in_use.saturating_sub(self.needed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, EventType, QueueState, Time};
+
+    #[test]
+    fn test_resource_pool() {
+        // With 2 servers but a single bed, two items that arrive together are
+        // served one after the other, and the bed is released and acquired
+        // again at 10.
+        let state = QueueState::new(10, 2, 10).with_resource_pool(ResourcePool::new(1, 1));
+        let sim = &mut Simulation::new(state);
+        for _ in 0..2 {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        }
+        sim.run_until(Time(5));
+        assert_eq!((1, 1), (sim.state.server_count, sim.state.buffer_count));
+        sim.run();
+
+        let bed = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| {
+                matches!(
+                    e.event_type,
+                    EventType::ResourceAcquired(0) | EventType::ResourceReleased(0)
+                )
+            })
+            .map(|e| (e.time.0, e.event_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (0, EventType::ResourceAcquired(0)),
+                (10, EventType::ResourceReleased(0)),
+                (10, EventType::ResourceAcquired(0)),
+                (20, EventType::ResourceReleased(0)),
+            ],
+            bed
+        );
+        assert_eq!(0, sim.state.resources[0].in_use());
+    }
+}
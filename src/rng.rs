@@ -0,0 +1,137 @@
+//! A small, seedable pseudo-random number generator.
+//!
+//! This is the xoshiro256** generator, seeded by expanding a single `u64`
+//! with SplitMix64, as recommended by its authors. It's fast, has good
+//! statistical quality, and makes runs exactly reproducible from a seed,
+//! which is all a simulation needs. It is not cryptographically secure.
+//!
+//! A seed with its top bit set gives the antithetic stream of the same seed
+//! without it: every uniform sample `u` becomes `1 - u`, so a run that drew
+//! long service times draws short ones instead. Averaging such a pair of
+//! runs cancels part of their noise.
+
+/// The seed bit that selects the antithetic stream.
+const ANTITHETIC: u64 = 1 << 63;
+
+/// A seeded pseudo-random number generator.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rng {
+    s: [u64; 4],
+    antithetic: bool,
+}
+
+impl Rng {
+    /// Create a generator from a seed. The same seed always produces the same
+    /// sequence of numbers.
+    pub fn new(seed: u64) -> Self {
+        let mut x = seed & !ANTITHETIC;
+        let mut s = [0; 4];
+        for word in s.iter_mut() {
+            x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = x;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            *word = z ^ (z >> 31);
+        }
+        Self {
+            s,
+            antithetic: seed & ANTITHETIC != 0,
+        }
+    }
+
+    /// The seed of the antithetic stream of a seed, and vice versa.
+    pub fn antithetic_seed(seed: u64) -> u64 {
+        seed ^ ANTITHETIC
+    }
+
+    /// Create the generator for one of several independent streams derived
+    /// from a seed, e.g., one for arrivals and one for services.
+    pub fn stream(seed: u64, stream: u64) -> Self {
+        Self::new(Self::new(stream).next_u64() ^ seed)
+    }
+
+    /// The next 64 random bits.
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.s[1] << 17;
+        self.s[2] ^= self.s[0];
+        self.s[3] ^= self.s[1];
+        self.s[1] ^= self.s[2];
+        self.s[0] ^= self.s[3];
+        self.s[2] ^= t;
+        self.s[3] = self.s[3].rotate_left(45);
+        // Complementing the bits maps the uniform sample `u` from the top 53
+        // bits to `1 - u - 2^-53`, which stays in `[0, 1)`.
+        if self.antithetic {
+            !result
+        } else {
+            result
+        }
+    }
+
+    /// A uniform sample from `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        // Use the top 53 bits, which is all the precision an `f64` mantissa
+        // can hold.
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// An exponentially distributed sample with the given rate.
+    pub fn exponential(&mut self, rate: f64) -> f64 {
+        // `1 - u` is in `(0, 1]`, so the log is always finite.
+        -(1.0 - self.uniform()).ln() / rate
+    }
+
+    /// A standard normal sample, generated with the Box-Muller transform.
+    ///
+    /// Mirroring the uniforms doesn't mirror the sample, since the cosine is
+    /// symmetric about one half, so the antithetic stream negates the sample
+    /// of the plain stream instead.
+    pub fn normal(&mut self) -> f64 {
+        let antithetic = std::mem::replace(&mut self.antithetic, false);
+        let u = 1.0 - self.uniform();
+        let v = self.uniform();
+        self.antithetic = antithetic;
+        let z = (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos();
+        if antithetic {
+            -z
+        } else {
+            z
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reproducible() {
+        let a = &mut Rng::new(7);
+        let b = &mut Rng::new(7);
+        let c = &mut Rng::new(8);
+        let xs = (0..5).map(|_| a.next_u64()).collect::<Vec<_>>();
+        assert_eq!(xs, (0..5).map(|_| b.next_u64()).collect::<Vec<_>>());
+        assert_ne!(xs, (0..5).map(|_| c.next_u64()).collect::<Vec<_>>());
+        // The antithetic stream mirrors every uniform sample.
+        let a = &mut Rng::new(7);
+        let d = &mut Rng::new(Rng::antithetic_seed(7));
+        for _ in 0..5 {
+            let (u, v) = (a.uniform(), d.uniform());
+            assert!((0.0..1.0).contains(&v));
+            assert!((u + v - 1.0).abs() < 1e-15);
+            // And negates every normal sample.
+            assert_eq!(a.normal(), -d.normal());
+        }
+    }
+
+    #[test]
+    fn test_sample_means() {
+        let rng = &mut Rng::new(1);
+        let n = 100_000;
+        let uniform = (0..n).map(|_| rng.uniform()).sum::<f64>() / n as f64;
+        let exponential = (0..n).map(|_| rng.exponential(0.5)).sum::<f64>() / n as f64;
+        assert!((uniform - 0.5).abs() < 0.01);
+        assert!((exponential - 2.0).abs() < 0.05);
+    }
+}
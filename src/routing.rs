@@ -0,0 +1,462 @@
+//! Routing policies, i.e., rules for where an item goes when it exits a node
+//! of a network.
+//!
+//! A policy can be fixed, random, based on the item's class, or based on the
+//! state of the network, e.g., to avoid a congested node. Load balancers
+//! spread items over a set of nodes, e.g., by joining the shortest queue.
+//! Custom policies implement `Router`.
+//!
+//! Policies can also switch the class of an item as it moves on, e.g., when
+//! triage reclassifies patients, which affects how later nodes route and
+//! serve it (see `ClassSwitch`).
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::arrival::SourceId;
+use crate::item::Item;
+use crate::network::{NetworkState, NodeId};
+use crate::rng::Rng;
+
+/// Where an item goes when it exits a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Destination {
+    Node(NodeId),
+    Exit,
+}
+
+/// Where an item goes when it exits a node, along with its class from then
+/// on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Route {
+    pub destination: Destination,
+    pub class: Option<SourceId>,
+}
+
+/// A routing policy.
+pub trait Router: Debug {
+    /// Choose where an item that exited the node `from` goes next.
+    fn route(&mut self, item: &Item, from: NodeId, network_state: &NetworkState) -> Destination;
+
+    /// Choose where an item that exited the node `from` goes next, and its
+    /// class from then on. By default, the item keeps its class.
+    fn route_with_class(
+        &mut self,
+        item: &Item,
+        from: NodeId,
+        network_state: &NetworkState,
+    ) -> Route {
+        Route {
+            destination: self.route(item, from, network_state),
+            class: item.source,
+        }
+    }
+
+    /// The places the policy can send items, along with the probability of
+    /// each, if it's fixed. This is used to draw the network, and policies
+    /// that don't say send items nowhere as far as the drawing goes.
+    fn targets(&self) -> Vec<(Destination, Option<f64>)> {
+        vec![]
+    }
+}
+
+/// Every item goes to the same place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixed(pub Destination);
+
+impl Router for Fixed {
+    fn route(&mut self, _: &Item, _: NodeId, _: &NetworkState) -> Destination {
+        self.0
+    }
+
+    fn targets(&self) -> Vec<(Destination, Option<f64>)> {
+        vec![(self.0, Some(1.0))]
+    }
+}
+
+/// Items go to each node with the given probability, and leave the network
+/// with whatever probability is left over.
+#[derive(Debug, Clone)]
+pub struct Probabilistic {
+    routes: Vec<(NodeId, f64)>,
+    rng: Rng,
+}
+
+impl Probabilistic {
+    /// Create a policy with the given `(node, probability)` routes, sampling
+    /// from an RNG with the given seed.
+    pub fn new(routes: Vec<(NodeId, f64)>, seed: u64) -> Self {
+        assert!(
+            routes.iter().map(|&(_, p)| p).sum::<f64>() <= 1.0 + 1e-9,
+            "routing probabilities can't add up to more than 1"
+        );
+        Self {
+            routes,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Router for Probabilistic {
+    fn route(&mut self, _: &Item, _: NodeId, _: &NetworkState) -> Destination {
+        let u = self.rng.uniform();
+        let mut cumulative = 0.0;
+        self.routes
+            .iter()
+            .find(|&&(_, p)| {
+                cumulative += p;
+                u < cumulative
+            })
+            .map_or(Destination::Exit, |&(node, _)| Destination::Node(node))
+    }
+
+    fn targets(&self) -> Vec<(Destination, Option<f64>)> {
+        let mut targets = self
+            .routes
+            .iter()
+            .map(|&(node, p)| (Destination::Node(node), Some(p)))
+            .collect::<Vec<_>>();
+        let left = 1.0 - self.routes.iter().map(|&(_, p)| p).sum::<f64>();
+        if left > 1e-9 {
+            targets.push((Destination::Exit, Some(left)));
+        }
+        targets
+    }
+}
+
+/// Items go to a place that depends on their class, i.e., their source, and
+/// items of other classes go to a default place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ByClass {
+    pub routes: HashMap<SourceId, Destination>,
+    pub default: Destination,
+}
+
+impl Router for ByClass {
+    fn route(&mut self, item: &Item, _: NodeId, _: &NetworkState) -> Destination {
+        item.source
+            .and_then(|source| self.routes.get(&source).copied())
+            .unwrap_or(self.default)
+    }
+
+    fn targets(&self) -> Vec<(Destination, Option<f64>)> {
+        let mut destinations = self.routes.values().copied().collect::<Vec<_>>();
+        destinations.push(self.default);
+        destinations.sort_by_key(|destination| match destination {
+            Destination::Node(node) => (0, node.0),
+            Destination::Exit => (1, 0),
+        });
+        destinations.dedup();
+        destinations.into_iter().map(|d| (d, None)).collect()
+    }
+}
+
+/// Items go to a node unless it's congested, i.e., at least `threshold`
+/// items are waiting there, in which case they go elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Congestion {
+    pub node: NodeId,
+    pub threshold: u32,
+    pub otherwise: Destination,
+}
+
+impl Router for Congestion {
+    fn route(&mut self, _: &Item, _: NodeId, network_state: &NetworkState) -> Destination {
+        if network_state.state(self.node).buffer_count < self.threshold {
+            Destination::Node(self.node)
+        } else {
+            self.otherwise
+        }
+    }
+
+    fn targets(&self) -> Vec<(Destination, Option<f64>)> {
+        vec![(Destination::Node(self.node), None), (self.otherwise, None)]
+    }
+}
+
+/// How a load balancer picks one of its nodes:
+/// - `ShortestQueue`: The node with the fewest items, waiting or in service
+///   (JSQ), breaking ties by the lowest ID.
+/// - `PowerOfTwo`: The node with fewer items of two different ones picked at
+///   random (JSQ(2)).
+/// - `RoundRobin`: Each node in turn.
+/// - `Random`: A node picked uniformly at random.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balancing {
+    ShortestQueue,
+    PowerOfTwo,
+    RoundRobin,
+    Random,
+}
+
+/// A load balancer that spreads items over a set of nodes.
+#[derive(Debug, Clone)]
+pub struct LoadBalancer {
+    pub nodes: Vec<NodeId>,
+    pub balancing: Balancing,
+    next: usize,
+    rng: Rng,
+}
+
+impl LoadBalancer {
+    /// Create a load balancer over the given nodes, sampling from an RNG with
+    /// the given seed.
+    pub fn new(nodes: Vec<NodeId>, balancing: Balancing, seed: u64) -> Self {
+        assert!(!nodes.is_empty(), "a load balancer needs at least one node");
+        Self {
+            nodes,
+            balancing,
+            next: 0,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// An index below `n` picked uniformly at random, or 0 if `n` is 0.
+    fn pick_index(&mut self, n: usize) -> usize {
+        ((self.rng.uniform() * n as f64) as usize).min(n.saturating_sub(1))
+    }
+}
+
+impl Router for LoadBalancer {
+    fn route(&mut self, _: &Item, _: NodeId, network_state: &NetworkState) -> Destination {
+        let items = |node: NodeId| {
+            let state = network_state.state(node);
+            state.buffer_count + state.server_count
+        };
+        let node = match self.balancing {
+            Balancing::ShortestQueue => *self
+                .nodes
+                .iter()
+                .min_by_key(|&&node| (items(node), node))
+                .unwrap(),
+            Balancing::PowerOfTwo => {
+                let n = self.nodes.len();
+                let i = self.pick_index(n);
+                let j = (i + 1 + self.pick_index(n - 1)) % n;
+                let (a, b) = (self.nodes[i], self.nodes[j]);
+                if (items(b), b) < (items(a), a) {
+                    b
+                } else {
+                    a
+                }
+            }
+            Balancing::RoundRobin => {
+                let node = self.nodes[self.next % self.nodes.len()];
+                self.next += 1;
+                node
+            }
+            Balancing::Random => {
+                let i = self.pick_index(self.nodes.len());
+                self.nodes[i]
+            }
+        };
+        Destination::Node(node)
+    }
+
+    fn targets(&self) -> Vec<(Destination, Option<f64>)> {
+        self.nodes
+            .iter()
+            .map(|&node| (Destination::Node(node), None))
+            .collect()
+    }
+}
+
+/// Items switch class before they're routed by another policy, which sees
+/// the new class. An item of class `c` switches to each class in
+/// `switches[c]` with the given probability, and keeps its class with
+/// whatever probability is left over.
+#[derive(Debug)]
+pub struct ClassSwitch {
+    pub router: Box<dyn Router>,
+    pub switches: HashMap<SourceId, Vec<(SourceId, f64)>>,
+    rng: Rng,
+}
+
+impl ClassSwitch {
+    /// Create a policy that switches classes with the given `(class,
+    /// probability)` switches by class, sampling from an RNG with the given
+    /// seed.
+    pub fn new(
+        router: impl Router + 'static,
+        switches: HashMap<SourceId, Vec<(SourceId, f64)>>,
+        seed: u64,
+    ) -> Self {
+        assert!(
+            switches
+                .values()
+                .all(|to| to.iter().map(|&(_, p)| p).sum::<f64>() <= 1.0 + 1e-9),
+            "class switching probabilities can't add up to more than 1"
+        );
+        Self {
+            router: Box::new(router),
+            switches,
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Router for ClassSwitch {
+    fn route(&mut self, item: &Item, from: NodeId, network_state: &NetworkState) -> Destination {
+        self.route_with_class(item, from, network_state).destination
+    }
+
+    fn route_with_class(
+        &mut self,
+        item: &Item,
+        from: NodeId,
+        network_state: &NetworkState,
+    ) -> Route {
+        let switches = item.source.and_then(|source| self.switches.get(&source));
+        let class = match switches {
+            Some(switches) => {
+                let u = self.rng.uniform();
+                let mut cumulative = 0.0;
+                switches
+                    .iter()
+                    .find(|&&(_, p)| {
+                        cumulative += p;
+                        u < cumulative
+                    })
+                    .map_or(item.source, |&(class, _)| Some(class))
+            }
+            None => item.source,
+        };
+        let switched = Item {
+            source: class,
+            ..*item
+        };
+        self.router.route_with_class(&switched, from, network_state)
+    }
+
+    fn targets(&self) -> Vec<(Destination, Option<f64>)> {
+        self.router.targets()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arrival::Attributes;
+    use crate::network::Network;
+    use crate::{EventMessage, EventMessageType, EventType, QueueState, Time};
+
+    /// Send items of the given classes through a front node with services of
+    /// 1 to a router, one per time unit, and count the arrivals at each node.
+    fn arrivals(router: impl Router + 'static, classes: &[Option<SourceId>]) -> Vec<usize> {
+        let network = &mut Network::new();
+        let front = network.add_node("front", QueueState::new(10, 10, 1));
+        network.add_node("primary", QueueState::new(10, 1, 100));
+        network.add_node("backup", QueueState::new(10, 1, 100));
+        network.set_router(front, router);
+        for (t, &source) in classes.iter().enumerate() {
+            network.schedule(
+                front,
+                EventMessage {
+                    event_message_type: EventMessageType::ArriveWith(Attributes {
+                        source,
+                        ..Attributes::default()
+                    }),
+                    time: Time(t as u32),
+                },
+            );
+        }
+        network.run_until(Time(50));
+        (0..3)
+            .map(|i| {
+                network
+                    .log
+                    .iter()
+                    .filter(|e| {
+                        e.node == NodeId(i) && e.event.event_type == EventType::BufferIncremented
+                    })
+                    .count()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_congestion_routing() {
+        // The primary node serves the first item and lets two wait, and then
+        // it's congested, so the last two items go to the backup node.
+        let router = Congestion {
+            node: NodeId(1),
+            threshold: 2,
+            otherwise: Destination::Node(NodeId(2)),
+        };
+        assert_eq!(vec![5, 3, 2], arrivals(router, &[None; 5]));
+    }
+
+    #[test]
+    fn test_class_routing() {
+        // Items of class 0 go to the primary node, and the others leave.
+        let router = ByClass {
+            routes: HashMap::from([(SourceId(0), Destination::Node(NodeId(1)))]),
+            default: Destination::Exit,
+        };
+        let classes = [
+            Some(SourceId(0)),
+            None,
+            Some(SourceId(1)),
+            Some(SourceId(0)),
+        ];
+        assert_eq!(vec![4, 2, 0], arrivals(router, &classes));
+    }
+
+    #[test]
+    fn test_load_balancing() {
+        // Items arrive every 2 units at a dispatcher in front of a fast and a
+        // slow queue. Joining the shortest queue, or the shorter of two picked
+        // at random, sends every item to the fast queue, which is always
+        // empty again by the next arrival, while round-robin alternates.
+        let arrivals = |balancing| {
+            let network = &mut Network::new();
+            let fast = network.add_node("fast", QueueState::new(10, 1, 1));
+            let slow = network.add_node("slow", QueueState::new(10, 1, 100));
+            let balancer = LoadBalancer::new(vec![fast, slow], balancing, 0);
+            let dispatcher = network.add_dispatcher("dispatcher", balancer);
+            for t in 0..5 {
+                network.schedule(
+                    dispatcher,
+                    EventMessage {
+                        event_message_type: EventMessageType::Arrive,
+                        time: Time(2 * t),
+                    },
+                );
+            }
+            network.run_until(Time(50));
+            [fast, slow].map(|node| {
+                network
+                    .log
+                    .iter()
+                    .filter(|e| {
+                        e.node == node && e.event.event_type == EventType::BufferIncremented
+                    })
+                    .count()
+            })
+        };
+        assert_eq!([5, 0], arrivals(Balancing::ShortestQueue));
+        assert_eq!([5, 0], arrivals(Balancing::PowerOfTwo));
+        assert_eq!([3, 2], arrivals(Balancing::RoundRobin));
+    }
+
+    #[test]
+    fn test_class_switching() {
+        // Items of class 1 are reclassified as class 0 before they're routed,
+        // so they go to the primary node along with the items of class 0.
+        let router = ClassSwitch::new(
+            ByClass {
+                routes: HashMap::from([(SourceId(0), Destination::Node(NodeId(1)))]),
+                default: Destination::Exit,
+            },
+            HashMap::from([(SourceId(1), vec![(SourceId(0), 1.0)])]),
+            0,
+        );
+        let classes = [
+            Some(SourceId(0)),
+            None,
+            Some(SourceId(1)),
+            Some(SourceId(1)),
+        ];
+        assert_eq!(vec![4, 3, 0], arrivals(router, &classes));
+    }
+}
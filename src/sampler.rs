@@ -0,0 +1,101 @@
+//! Snapshots of the queue at fixed intervals of simulated time.
+//!
+//! The event log has everything needed to rebuild the queue-length
+//! trajectory, but it's large and awkward to plot from. A `Sampler` instead
+//! keeps one small record per interval, which can be written out as CSV.
+
+use std::io::{self, Write};
+
+use crate::{QueueState, Time};
+
+/// The number of buffered items and busy servers at a sampled time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sample {
+    pub time: Time,
+    pub buffer_count: u32,
+    pub server_count: u32,
+}
+
+/// Records a `Sample` every `interval` units of simulated time, starting
+/// from 0.
+///
+/// A sample at a time reflects every event up to and including it, so it's
+/// only taken once the clock has moved past it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sampler {
+    pub interval: u32,
+    next: Time,
+    pub samples: Vec<Sample>,
+}
+
+impl Sampler {
+    /// Create a sampler with the given interval.
+    pub fn new(interval: u32) -> Self {
+        assert!(interval > 0, "the sampling interval must be positive");
+        Self {
+            interval,
+            next: Time(0),
+            samples: vec![],
+        }
+    }
+
+    /// Take the samples due before the given time from a state that hasn't
+    /// changed since the last of them.
+    pub fn observe(&mut self, state: &QueueState, before: Time) -> &mut Self {
+        while self.next < before {
+            self.samples.push(Sample {
+                time: self.next,
+                buffer_count: state.buffer_count,
+                server_count: state.server_count,
+            });
+            self.next = Time(self.next.0 + self.interval);
+        }
+        self
+    }
+
+    /// Write the samples as CSV, with a header row.
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        writeln!(writer, "time,buffer_count,server_count")?;
+        for s in &self.samples {
+            writeln!(writer, "{},{},{}", s.time.0, s.buffer_count, s.server_count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType};
+
+    #[test]
+    fn test_sampling() {
+        // Items arrive at 0, 1, and 2 for services of 5, sampled every 4
+        // until the last exit at 15.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        sim.enable_sampling(4);
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let sampler = sim.sampler().unwrap();
+        assert_eq!(
+            vec![(0, 0, 1), (4, 2, 1), (8, 1, 1), (12, 0, 1)],
+            sampler
+                .samples
+                .iter()
+                .map(|s| (s.time.0, s.buffer_count, s.server_count))
+                .collect::<Vec<_>>()
+        );
+        let mut csv = vec![];
+        sampler.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            "time,buffer_count,server_count\n0,0,1\n4,2,1\n8,1,1\n12,0,1\n",
+            String::from_utf8(csv).unwrap()
+        );
+    }
+}
@@ -0,0 +1,750 @@
+//! Individual servers and the policy for choosing which idle server takes the
+//! next item.
+//!
+//! Servers can work at different speeds, e.g., senior and junior agents. A
+//! server with speed 2 finishes a service in half the sampled service time.
+//!
+//! The number of servers on shift can also change over time according to a
+//! `ShiftSchedule`, and servers can take vacations when they run out of work
+//! and need setup time before serving again. Under an N-policy, idle servers
+//! wait until enough items are buffered before they start serving. In a
+//! polling system, a single server cycles over several buffers.
+//!
+//! Servers can also take items in batches, in which case the server count
+//! of the queue is still the number of busy servers rather than the number
+//! of items in service. With infinite servers, the pool's capacity is
+//! `u32::MAX` and every buffered item enters service immediately.
+
+use crate::dist::Distribution;
+use crate::item::Item;
+use crate::rng::Rng;
+use crate::Time;
+
+/// Identifies a server by its position in the server pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ServerId(pub u32);
+
+/// A single server.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Server {
+    /// How many units of work the server gets through per unit of time.
+    pub speed: f64,
+    /// Whether the server is serving an item.
+    pub busy: bool,
+    /// When the server's current service is due to end.
+    pub busy_until: Time,
+    /// Whether the server is on shift. Servers that go off shift while busy
+    /// finish their current item before leaving.
+    pub on_shift: bool,
+    /// Whether the server is away on vacation.
+    pub on_vacation: bool,
+    /// Whether the server has been idle since its last service, so that the
+    /// next service is preceded by a setup time. Servers start out idle.
+    pub needs_setup: bool,
+    /// Whether the server is switched on under an N-policy. Servers without
+    /// one are always on.
+    pub active: bool,
+    /// Whether the server is done with its service but is holding on to the
+    /// item, because the next queue in a network has no room for it.
+    pub blocked: bool,
+}
+
+impl Server {
+    /// Create an idle server with the given speed.
+    pub fn new(speed: f64) -> Self {
+        Self {
+            speed,
+            busy: false,
+            busy_until: Time(0),
+            on_shift: true,
+            on_vacation: false,
+            needs_setup: true,
+            active: true,
+            blocked: false,
+        }
+    }
+
+    /// Check if the server can take an item.
+    pub fn is_available(&self) -> bool {
+        !self.busy && self.on_shift && !self.on_vacation && self.active
+    }
+}
+
+/// How many buffered items a server takes per service start: as many as are
+/// waiting, up to `max`, and servers wait until at least `min` are buffered.
+/// The items in a batch are served together, in a single service time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Batch {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Batch {
+    /// Take up to `max` items at once, as soon as any are waiting.
+    pub fn new(max: u32) -> Self {
+        assert!(max > 0, "a batch must hold at least one item");
+        Self { min: 1, max }
+    }
+
+    /// Wait until at least `min` items are buffered.
+    pub fn with_min(self, min: u32) -> Self {
+        assert!(
+            (1..=self.max).contains(&min),
+            "the minimum batch size must be between 1 and the maximum"
+        );
+        Self { min, ..self }
+    }
+}
+
+impl Default for Batch {
+    /// One item at a time.
+    fn default() -> Self {
+        Self::new(1)
+    }
+}
+
+/// What a server does when it finishes a service and finds the buffer empty:
+/// - `Single`: Takes one vacation of the sampled length, then waits idle.
+/// - `Multiple`: Takes vacations of sampled lengths until it returns to find
+///   an item waiting.
+#[derive(Debug)]
+pub enum Vacation {
+    Single(Box<dyn Distribution>),
+    Multiple(Box<dyn Distribution>),
+}
+
+impl Vacation {
+    /// The distribution of vacation lengths.
+    pub fn length(&self) -> &dyn Distribution {
+        match self {
+            Vacation::Single(length) | Vacation::Multiple(length) => length.as_ref(),
+        }
+    }
+}
+
+/// How much of a buffer a polling server serves per visit:
+/// - `Exhaustive`: Every item, including those that arrive during the
+///   visit, until the buffer is empty.
+/// - `Gated`: Only the items that were waiting when the visit started.
+/// - `Limited`: At most the given number of items.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Exhaustive,
+    Gated,
+    Limited(u32),
+}
+
+/// A polling system, where a single server visits several buffers in a
+/// cycle, serving each one in order of arrival, and takes a sampled
+/// switch-over time to move on to the next. Items wait in the buffer of
+/// their class, i.e., their source ID modulo the number of buffers, and
+/// items without a source wait in buffer 0.
+///
+/// The server starts at buffer 0. When its visit is over and any buffer
+/// holds an item, it moves on, and otherwise waits where it is.
+#[derive(Debug)]
+pub struct Polling {
+    pub buffers: u32,
+    pub visit: Visit,
+    pub switch_over: Box<dyn Distribution>,
+    current: u32,
+    /// Whether the server has started serving the current buffer.
+    open: bool,
+    /// How many more items the server may serve on this visit, if limited.
+    left: Option<u32>,
+    switching: bool,
+}
+
+impl Polling {
+    /// Create a polling system with the given number of buffers.
+    pub fn new(buffers: u32, visit: Visit, switch_over: impl Distribution + 'static) -> Self {
+        assert!(buffers > 0, "a polling system needs at least one buffer");
+        Self {
+            buffers,
+            visit,
+            switch_over: Box::new(switch_over),
+            current: 0,
+            open: false,
+            left: None,
+            switching: false,
+        }
+    }
+
+    /// The buffer the server is at, or is switching over to.
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Check if the server is switching over to the next buffer.
+    pub fn is_switching(&self) -> bool {
+        self.switching
+    }
+
+    /// The buffer an item waits in.
+    pub fn buffer_of(&self, item: &Item) -> u32 {
+        item.source.map_or(0, |source| source.0 % self.buffers)
+    }
+
+    /// Start serving the current buffer, which holds the given number of
+    /// items.
+    pub fn open(&mut self, waiting: u32) {
+        self.open = true;
+        self.left = match self.visit {
+            Visit::Exhaustive => None,
+            Visit::Gated => Some(waiting),
+            Visit::Limited(k) => Some(k),
+        };
+    }
+
+    /// Check if the server has started serving the current buffer.
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Check if the server may serve another item on this visit, if the
+    /// current buffer holds one.
+    pub fn may_serve(&self) -> bool {
+        self.open && !self.switching && self.left != Some(0)
+    }
+
+    /// Count an item taken from the current buffer.
+    pub fn serve(&mut self) {
+        if let Some(left) = self.left.as_mut() {
+            *left = left.saturating_sub(1);
+        }
+    }
+
+    /// Leave the current buffer to switch over to the next one, returning
+    /// the next buffer.
+    pub fn start_switch_over(&mut self) -> u32 {
+        self.switching = true;
+        self.open = false;
+        self.current = (self.current + 1) % self.buffers;
+        self.current
+    }
+
+    /// Arrive at the next buffer.
+    pub fn end_switch_over(&mut self) {
+        self.switching = false;
+    }
+}
+
+/// An N-policy, where a switched-off server only switches on once the buffer
+/// holds at least its threshold number of items, e.g., to spread a setup cost
+/// over more items. Servers start out switched off, and by default switch off
+/// again whenever they finish a service and find the buffer empty. Servers
+/// without a threshold of their own have a threshold of 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NPolicy {
+    pub thresholds: Vec<u32>,
+    pub stops_when_empty: bool,
+}
+
+impl NPolicy {
+    /// Create a policy with the given thresholds, by server ID.
+    pub fn new(thresholds: &[u32]) -> Self {
+        Self {
+            thresholds: thresholds.to_vec(),
+            stops_when_empty: true,
+        }
+    }
+
+    /// Keep servers on once they've switched on.
+    pub fn staying_on(self) -> Self {
+        Self {
+            stops_when_empty: false,
+            ..self
+        }
+    }
+
+    /// The threshold of the given server.
+    pub fn threshold(&self, server: ServerId) -> u32 {
+        self.thresholds.get(server.0 as usize).copied().unwrap_or(1)
+    }
+}
+
+/// How to choose among idle servers:
+/// - `FastestIdle`: The idle server with the highest speed, breaking ties by
+///   the lowest ID.
+/// - `Random`: An idle server chosen uniformly at random.
+/// - `RoundRobin`: The first idle server after the one chosen last.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ServerSelection {
+    #[default]
+    FastestIdle,
+    Random,
+    RoundRobin,
+}
+
+/// A pool of servers together with its selection policy.
+///
+/// An unbounded pool has as many servers as there are items to serve, and
+/// adds a new server with speed 1 whenever every existing one is busy.
+#[derive(Debug, Clone)]
+pub struct ServerPool {
+    pub servers: Vec<Server>,
+    pub selection: ServerSelection,
+    pub unbounded: bool,
+    /// The server chosen last, for round-robin selection.
+    last: Option<usize>,
+}
+
+impl ServerPool {
+    /// Create a pool of idle servers with the given speeds.
+    pub fn new(speeds: &[f64]) -> Self {
+        Self {
+            servers: speeds.iter().map(|&speed| Server::new(speed)).collect(),
+            selection: ServerSelection::default(),
+            unbounded: false,
+            last: None,
+        }
+    }
+
+    /// Get a server.
+    pub fn get(&self, id: ServerId) -> &Server {
+        &self.servers[id.0 as usize]
+    }
+
+    /// Get a server to change it.
+    pub fn get_mut(&mut self, id: ServerId) -> &mut Server {
+        &mut self.servers[id.0 as usize]
+    }
+
+    /// Choose an idle server according to the selection policy and mark it
+    /// busy, or return `None` if every server is busy. An unbounded pool adds
+    /// a server instead.
+    pub fn acquire(&mut self, rng: &mut Rng) -> Option<ServerId> {
+        if self.unbounded && !self.has_available() {
+            self.servers.push(Server::new(1.0));
+        }
+        let idle = self
+            .servers
+            .iter()
+            .enumerate()
+            .filter(|(_, s)| s.is_available())
+            .map(|(i, _)| i);
+        let chosen = match self.selection {
+            ServerSelection::FastestIdle => idle
+                .rev()
+                .max_by(|&a, &b| self.servers[a].speed.total_cmp(&self.servers[b].speed)),
+            ServerSelection::Random => {
+                let idle = idle.collect::<Vec<_>>();
+                let k = (rng.uniform() * idle.len() as f64) as usize;
+                idle.get(k).copied()
+            }
+            ServerSelection::RoundRobin => {
+                let start = self.last.map_or(0, |i| i + 1);
+                let n = self.servers.len();
+                (0..n)
+                    .map(|k| (start + k) % n)
+                    .find(|&i| self.servers[i].is_available())
+            }
+        }?;
+        self.servers[chosen].busy = true;
+        self.last = Some(chosen);
+        Some(ServerId(chosen as u32))
+    }
+
+    /// Mark a server idle.
+    pub fn release(&mut self, id: ServerId) {
+        self.servers[id.0 as usize].busy = false;
+    }
+
+    /// Check if any existing server can take an item.
+    pub fn has_available(&self) -> bool {
+        self.servers.iter().any(Server::is_available)
+    }
+
+    /// The number of servers on shift.
+    pub fn on_shift(&self) -> u32 {
+        self.servers.iter().filter(|s| s.on_shift).count() as u32
+    }
+
+    /// Change the number of servers on shift.
+    ///
+    /// Servers coming on shift are the off-shift servers with the lowest IDs,
+    /// and new servers with speed 1 are added if there aren't enough. Servers
+    /// going off shift are idle ones where possible, highest IDs first. This
+    /// makes an unbounded pool bounded.
+    pub fn set_capacity(&mut self, capacity: u32) {
+        self.unbounded = false;
+        let mut on_shift = self.on_shift();
+        for server in self.servers.iter_mut().filter(|s| !s.on_shift) {
+            if on_shift >= capacity {
+                break;
+            }
+            server.on_shift = true;
+            on_shift += 1;
+        }
+        while on_shift < capacity {
+            self.servers.push(Server::new(1.0));
+            on_shift += 1;
+        }
+
+        for busy in [false, true] {
+            for server in self.servers.iter_mut().rev() {
+                if on_shift <= capacity {
+                    return;
+                }
+                if server.on_shift && server.busy == busy {
+                    server.on_shift = false;
+                    on_shift -= 1;
+                }
+            }
+        }
+    }
+}
+
+/// A schedule of changes to the number of servers on shift, e.g., 2 servers
+/// from 9 to 17 and 1 otherwise.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ShiftSchedule {
+    /// `(time, capacity)` pairs sorted by time. For repeating schedules, the
+    /// times are offsets into the period.
+    changes: Vec<(Time, u32)>,
+    period: Option<Time>,
+}
+
+impl ShiftSchedule {
+    /// Create a schedule that applies each `(time, capacity)` change once.
+    pub fn new(mut changes: Vec<(Time, u32)>) -> Self {
+        changes.sort_by_key(|&(time, _)| time);
+        Self {
+            changes,
+            period: None,
+        }
+    }
+
+    /// Create a schedule that repeats every `period`, with change times given
+    /// as offsets into the period, e.g., a day of 24 hours with changes at
+    /// hours 9 and 17.
+    pub fn repeating(period: Time, changes: Vec<(Time, u32)>) -> Self {
+        assert!(period.0 > 0, "the period of a schedule must be positive");
+        Self {
+            period: Some(period),
+            ..Self::new(changes)
+        }
+    }
+
+    /// The first change at or after the given time.
+    pub fn next_change(&self, from: Time) -> Option<(Time, u32)> {
+        match self.period {
+            None => self.changes.iter().find(|&&(t, _)| t >= from).copied(),
+            Some(period) => {
+                let start = from.0 - from.0 % period.0;
+                [start, start + period.0].iter().find_map(|&offset| {
+                    self.changes
+                        .iter()
+                        .map(|&(t, capacity)| (Time(offset + t.0 % period.0), capacity))
+                        .filter(|&(t, _)| t >= from)
+                        .min_by_key(|&(t, _)| t)
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_selection_policies() {
+        let rng = &mut Rng::new(0);
+
+        // The fastest idle server is chosen first, then the next fastest.
+        let pool = &mut ServerPool::new(&[1.0, 3.0, 2.0, 3.0]);
+        let chosen = (0..4)
+            .map(|_| pool.acquire(rng).unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 3, 2, 0], chosen);
+        assert_eq!(None, pool.acquire(rng));
+
+        // Round-robin skips busy servers and wraps around.
+        let pool = &mut ServerPool::new(&[1.0, 1.0, 1.0]);
+        pool.selection = ServerSelection::RoundRobin;
+        assert_eq!(Some(ServerId(0)), pool.acquire(rng));
+        assert_eq!(Some(ServerId(1)), pool.acquire(rng));
+        pool.release(ServerId(0));
+        assert_eq!(Some(ServerId(2)), pool.acquire(rng));
+        assert_eq!(Some(ServerId(0)), pool.acquire(rng));
+
+        // Random selection only ever picks idle servers.
+        let pool = &mut ServerPool::new(&[1.0, 1.0, 1.0]);
+        pool.selection = ServerSelection::Random;
+        pool.servers[1].busy = true;
+        for _ in 0..20 {
+            let id = pool.acquire(rng).unwrap();
+            assert_ne!(ServerId(1), id);
+            pool.release(id);
+        }
+    }
+
+    #[test]
+    fn test_fast_server_finishes_sooner() {
+        use crate::simulation::Simulation;
+        use crate::{EventMessage, EventMessageType, EventType, QueueState, Time};
+
+        // Two items arrive together. The fast server takes the first and
+        // finishes in half the time, and the log records who served whom.
+        let state = QueueState::new(10, 2, 10).with_server_speeds(&[1.0, 2.0]);
+        let sim = &mut Simulation::new(state);
+        (0..2).for_each(|_| {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        });
+        sim.run();
+
+        let exits = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerDecremented)
+            .map(|e| (e.time, e.server.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(Time(5), ServerId(1)), (Time(10), ServerId(0))], exits);
+    }
+
+    #[test]
+    fn test_repeating_schedule() {
+        let schedule = ShiftSchedule::repeating(Time(24), vec![(Time(9), 2), (Time(17), 1)]);
+        assert_eq!(Some((Time(9), 2)), schedule.next_change(Time(0)));
+        assert_eq!(Some((Time(9), 2)), schedule.next_change(Time(9)));
+        assert_eq!(Some((Time(17), 1)), schedule.next_change(Time(10)));
+        assert_eq!(Some((Time(33), 2)), schedule.next_change(Time(18)));
+
+        let once = ShiftSchedule::new(vec![(Time(5), 3)]);
+        assert_eq!(None, once.next_change(Time(6)));
+    }
+
+    #[test]
+    fn test_busy_servers_finish_before_leaving() {
+        use crate::simulation::Simulation;
+        use crate::{EventMessage, EventMessageType, EventType, QueueState};
+
+        // Two servers are busy until 10 when the capacity drops to one at 5.
+        // Both finish their items, and the third item waits for the one
+        // server that's left.
+        let sim = &mut Simulation::new(QueueState::new(10, 2, 10));
+        sim.set_shift_schedule(ShiftSchedule::new(vec![(Time(5), 1)]));
+        (0..3).for_each(|_| {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        });
+        sim.run();
+
+        let log = &sim.log.contents;
+        assert!(log
+            .iter()
+            .any(|e| e.event_type == EventType::CapacityChanged(1) && e.time == Time(5)));
+        let exits = log
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerDecremented)
+            .map(|e| e.time)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![Time(10), Time(10), Time(20)], exits);
+        assert_eq!(1, sim.state.server_pool.on_shift());
+    }
+
+    #[test]
+    fn test_vacations_and_setup() {
+        use crate::dist::Deterministic;
+        use crate::simulation::Simulation;
+        use crate::{EventMessage, EventMessageType, EventType, QueueState};
+
+        // Items arrive at 0 and 23 to a single server with a setup time of 2
+        // and vacations of 5. With multiple vacations, the server is away
+        // from 22 to 27, so the second item waits. With a single vacation,
+        // the server is back at 17 and takes the second item right away.
+        let run = |vacation: Vacation| {
+            let state = QueueState::new(10, 1, 10)
+                .with_vacation(vacation)
+                .with_setup_time(Deterministic(2.0));
+            let sim = &mut Simulation::new(state);
+            for t in [0, 23] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            sim.run_until(Time(40));
+            let times = |event_type| {
+                sim.log
+                    .contents
+                    .iter()
+                    .filter(|e| e.event_type == event_type)
+                    .map(|e| e.time.0)
+                    .collect::<Vec<_>>()
+            };
+            (
+                times(EventType::ServerDecremented),
+                times(EventType::VacationStarted),
+            )
+        };
+
+        let (exits, vacations) = run(Vacation::Multiple(Box::new(Deterministic(5.0))));
+        assert_eq!(vec![12, 39], exits);
+        assert_eq!(vec![12, 17, 22, 39], vacations);
+
+        let (exits, vacations) = run(Vacation::Single(Box::new(Deterministic(5.0))));
+        assert_eq!(vec![12, 35], exits);
+        assert_eq!(vec![12, 35], vacations);
+    }
+
+    #[test]
+    fn test_batch_service() {
+        use crate::simulation::Simulation;
+        use crate::{EventMessage, EventMessageType, EventType, QueueState};
+
+        // An oven bakes 2 to 3 items at once. The first item waits for a
+        // second at 4, the next three arrivals are baked together at 14, and
+        // the last one waits for a partner that never comes.
+        let state = QueueState::new(10, 1, 10).with_batch(Batch::new(3).with_min(2));
+        let sim = &mut Simulation::new(state);
+        for t in [0, 4, 6, 7, 8, 20] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+
+        let times = |event_type| {
+            sim.log
+                .contents
+                .iter()
+                .filter(|e| e.event_type == event_type)
+                .map(|e| e.time.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![4, 4, 14, 14, 14], times(EventType::BufferDecremented));
+        assert_eq!(vec![4, 14], times(EventType::ServerIncremented));
+        assert_eq!(1, sim.state.buffer_count);
+    }
+
+    #[test]
+    fn test_infinite_servers() {
+        use crate::simulation::Simulation;
+        use crate::{EventMessage, EventMessageType, EventType, QueueState};
+
+        // Every item enters service on arrival, however many are in service.
+        let state = QueueState::new(100, 0, 10).with_infinite_servers();
+        let sim = &mut Simulation::new(state);
+        for t in 0..50 {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t / 10),
+            });
+        }
+        sim.run_until(Time(5));
+        assert_eq!(50, sim.state.server_count);
+        assert_eq!(0, sim.state.buffer_count);
+
+        sim.run();
+        assert!(sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerDecremented)
+            .all(|e| e.time.0 >= 10 && e.time.0 < 15));
+        assert_eq!(50, sim.state.server_pool.servers.len());
+    }
+
+    #[test]
+    fn test_n_policy() {
+        use crate::simulation::Simulation;
+        use crate::{EventMessage, EventMessageType, EventType, QueueState, Time};
+
+        // The server switches on when the third item arrives at 2, serves
+        // all three, and switches off at 32, so an item arriving at 40 waits
+        // unless the server stays on.
+        let run = |n_policy| {
+            let state = QueueState::new(10, 1, 10).with_n_policy(n_policy);
+            let sim = &mut Simulation::new(state);
+            for t in [0, 1, 2, 40] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            let switches = sim
+                .log
+                .contents
+                .iter()
+                .filter(|e| {
+                    matches!(
+                        e.event_type,
+                        EventType::ServerActivated | EventType::ServerDeactivated
+                    )
+                })
+                .map(|e| (e.time.0, e.event_type))
+                .collect::<Vec<_>>();
+            (switches, sim.state.buffer_count)
+        };
+
+        let n_policy = NPolicy::new(&[3]);
+        assert_eq!(
+            (
+                vec![
+                    (2, EventType::ServerActivated),
+                    (32, EventType::ServerDeactivated)
+                ],
+                1
+            ),
+            run(n_policy.clone())
+        );
+        assert_eq!(
+            (vec![(2, EventType::ServerActivated)], 0),
+            run(n_policy.staying_on())
+        );
+    }
+
+    #[test]
+    fn test_polling() {
+        // Items of class 0 arrive at 0 and 1, and of class 1 at 2 and 4, for
+        // services of 2 with switch-overs of 1.
+        use crate::arrival::{Attributes, SourceId};
+        use crate::dist::Deterministic;
+        use crate::simulation::Simulation;
+        use crate::{EventMessage, EventMessageType, EventType, QueueState};
+        let exits = |visit| {
+            let state =
+                QueueState::new(10, 1, 2).with_polling(Polling::new(2, visit, Deterministic(1.0)));
+            let sim = &mut Simulation::new(state);
+            for (t, class) in [(0, 0), (1, 0), (2, 1), (4, 1)] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::ArriveWith(Attributes {
+                        source: Some(SourceId(class)),
+                        ..Attributes::default()
+                    }),
+                    time: Time(t),
+                });
+            }
+            sim.run();
+            sim.log
+                .contents
+                .iter()
+                .filter(|e| e.event_type == EventType::ServerDecremented && e.item.is_some())
+                .map(|e| (e.time.0, e.item.unwrap().0))
+                .collect::<Vec<_>>()
+        };
+        // Exhaustively, the server empties buffer 0 before switching over.
+        assert_eq!(
+            vec![(2, 0), (4, 1), (7, 2), (9, 3)],
+            exits(Visit::Exhaustive)
+        );
+        // One item per visit, the server alternates between the buffers.
+        assert_eq!(
+            vec![(2, 0), (5, 2), (8, 1), (11, 3)],
+            exits(Visit::Limited(1))
+        );
+        // The second item isn't waiting yet when the first visit starts, and
+        // the fourth isn't when the second starts.
+        assert_eq!(vec![(2, 0), (5, 2), (8, 1), (11, 3)], exits(Visit::Gated));
+    }
+}
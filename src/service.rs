@@ -0,0 +1,453 @@
+//! Models of how long it takes to serve an item.
+//!
+//! A service time is sampled each time an item enters service, and the item's
+//! `Exit` is scheduled that far in the future. Any `Distribution` can be used
+//! as a service time model, and `ClassServiceTimes` picks a different model
+//! for each class of item. `PhaseType` services go through sequential phases,
+//! and the end of each phase but the last is logged.
+//!
+//! With preemptive priorities, an arriving item that finds every server
+//! busy interrupts the service of a lower-priority item, which goes back to
+//! the front of the buffer. Under processor sharing, the items in service
+//! share a single processor instead, and their exits are rescheduled
+//! whenever an item starts or finishes. With feedback, items that finish
+//! service may go back to the buffer for another pass.
+
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::arrival::SourceId;
+use crate::dist::Distribution;
+use crate::rng::Rng;
+use crate::server::ServerId;
+use crate::Time;
+
+/// What happens to an item whose service is interrupted, e.g., by a
+/// breakdown or a higher-priority item:
+/// - `Resume`: The item continues later with the work it had left.
+/// - `Restart`: The item starts over later with a freshly sampled service
+///   time.
+///
+/// A resumed multi-phase service continues as a single phase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Preemption {
+    #[default]
+    Resume,
+    Restart,
+}
+
+/// Feedback, where an item that finishes service goes back to the buffer for
+/// another pass with the given probability, e.g., rework after a failed
+/// inspection. With a maximum number of passes, an item always leaves after
+/// its last one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Feedback {
+    pub probability: f64,
+    pub max_passes: Option<u32>,
+}
+
+impl Feedback {
+    /// Create feedback with no limit on the number of passes.
+    pub fn new(probability: f64) -> Self {
+        Self {
+            probability,
+            max_passes: None,
+        }
+    }
+
+    /// Limit the number of passes, counting the first.
+    pub fn with_max_passes(mut self, max_passes: u32) -> Self {
+        self.max_passes = Some(max_passes);
+        self
+    }
+
+    /// Decide whether an item that finished its pass with the given number
+    /// goes back for another one.
+    pub fn returns(&self, pass: u32, rng: &mut Rng) -> bool {
+        self.max_passes.is_none_or(|max| pass < max) && rng.uniform() < self.probability
+    }
+}
+
+/// A model of service durations.
+pub trait ServiceTime: Debug {
+    /// Sample the duration of a service that's starting now for an item of
+    /// the given class, i.e., from the given arrival source.
+    fn sample(&mut self, class: Option<SourceId>, rng: &mut Rng) -> Time;
+
+    /// Sample the durations of the sequential phases of a service. By
+    /// default, a service has a single phase.
+    fn sample_phases(&mut self, class: Option<SourceId>, rng: &mut Rng) -> Vec<Time> {
+        vec![self.sample(class, rng)]
+    }
+}
+
+impl<D: Distribution> ServiceTime for D {
+    fn sample(&mut self, _class: Option<SourceId>, rng: &mut Rng) -> Time {
+        Time::from_f64(Distribution::sample(self, rng))
+    }
+}
+
+/// Service times that depend on the class of the item being served, e.g., so
+/// that high-complexity jobs take longer. Items of classes without their own
+/// model, and items without a class, use the default model.
+#[derive(Debug)]
+pub struct ClassServiceTimes {
+    classes: HashMap<SourceId, Box<dyn ServiceTime>>,
+    default: Box<dyn ServiceTime>,
+}
+
+impl ClassServiceTimes {
+    /// Create class-based service times with the given default model.
+    pub fn new(default: impl ServiceTime + 'static) -> Self {
+        Self {
+            classes: HashMap::new(),
+            default: Box::new(default),
+        }
+    }
+
+    /// Use the given model for items of a class.
+    pub fn with_class(mut self, class: SourceId, service_time: impl ServiceTime + 'static) -> Self {
+        self.classes.insert(class, Box::new(service_time));
+        self
+    }
+}
+
+impl ClassServiceTimes {
+    /// The model for items of a class.
+    fn model(&mut self, class: Option<SourceId>) -> &mut dyn ServiceTime {
+        match class.and_then(|c| self.classes.get_mut(&c)) {
+            Some(service_time) => service_time.as_mut(),
+            None => self.default.as_mut(),
+        }
+    }
+}
+
+impl ServiceTime for ClassServiceTimes {
+    fn sample(&mut self, class: Option<SourceId>, rng: &mut Rng) -> Time {
+        self.model(class).sample(class, rng)
+    }
+
+    fn sample_phases(&mut self, class: Option<SourceId>, rng: &mut Rng) -> Vec<Time> {
+        self.model(class).sample_phases(class, rng)
+    }
+}
+
+/// Service in sequential phases, each with its own duration distribution.
+///
+/// After each phase, the service moves on to the next phase with that phase's
+/// continuation probability, and ends otherwise. With all continuation
+/// probabilities equal to 1, this is a generalized Erlang distribution, and
+/// otherwise it's a Coxian distribution.
+#[derive(Debug, Default)]
+pub struct PhaseType {
+    phases: Vec<(Box<dyn Distribution>, f64)>,
+}
+
+impl PhaseType {
+    /// Create a service with no phases yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The Erlang distribution as `k` exponential phases with the given rate.
+    pub fn erlang(k: u32, rate: f64) -> Self {
+        (0..k).fold(Self::new(), |service, _| {
+            service.phase(crate::dist::Exponential(rate), 1.0)
+        })
+    }
+
+    /// Add a phase, after which the service continues with the given
+    /// probability. The continuation probability of the last phase is
+    /// ignored.
+    pub fn phase(mut self, duration: impl Distribution + 'static, proceed: f64) -> Self {
+        self.phases.push((Box::new(duration), proceed));
+        self
+    }
+}
+
+impl ServiceTime for PhaseType {
+    fn sample(&mut self, class: Option<SourceId>, rng: &mut Rng) -> Time {
+        Time(self.sample_phases(class, rng).iter().map(|t| t.0).sum())
+    }
+
+    fn sample_phases(&mut self, _class: Option<SourceId>, rng: &mut Rng) -> Vec<Time> {
+        assert!(
+            !self.phases.is_empty(),
+            "a phase-type service needs at least one phase"
+        );
+        let mut durations = vec![];
+        for (duration, proceed) in &self.phases {
+            durations.push(Time::from_f64(duration.sample(rng)));
+            if rng.uniform() >= *proceed {
+                break;
+            }
+        }
+        durations
+    }
+}
+
+/// Processor-sharing service, where the items in service share a single
+/// processor of speed 1 equally, e.g., CPU time slicing. With `n` items in
+/// service, each one gets through `1 / n` units of work per unit of time, so
+/// every exit time changes whenever an item starts or finishes.
+///
+/// Each item in service occupies a server from the pool, which only serves
+/// as a slot, so the number of servers limits how many items share the
+/// processor at once. Phases of multi-phase services aren't logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessorSharing {
+    /// The remaining work of the item in each occupied slot.
+    work: HashMap<ServerId, f64>,
+    /// The time up to which the remaining work has been updated.
+    since: Time,
+}
+
+impl ProcessorSharing {
+    /// Create an idle processor.
+    pub fn new() -> Self {
+        Self {
+            work: HashMap::new(),
+            since: Time(0),
+        }
+    }
+
+    /// Bring the remaining work of every item in service up to date.
+    fn advance(&mut self, now: Time) {
+        if !self.work.is_empty() {
+            let done = now.0.saturating_sub(self.since.0) as f64 / self.work.len() as f64;
+            self.work
+                .values_mut()
+                .for_each(|work| *work = (*work - done).max(0.0));
+        }
+        self.since = now;
+    }
+
+    /// Start serving an item with the given amount of work in a slot.
+    pub fn start(&mut self, slot: ServerId, work: f64, now: Time) {
+        self.advance(now);
+        self.work.insert(slot, work);
+    }
+
+    /// Stop serving the item in a slot, returning the work it had left.
+    pub fn finish(&mut self, slot: ServerId, now: Time) -> Option<f64> {
+        self.advance(now);
+        self.work.remove(&slot)
+    }
+
+    /// The time each item in service will finish if nothing else starts or
+    /// finishes first.
+    pub fn exits(&self) -> Vec<(ServerId, Time)> {
+        let n = self.work.len() as f64;
+        let mut exits = self
+            .work
+            .iter()
+            .map(|(&slot, &work)| (slot, Time::from_f64(self.since.0 as f64 + work * n)))
+            .collect::<Vec<_>>();
+        exits.sort();
+        exits
+    }
+}
+
+impl Default for ProcessorSharing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dist::Exponential;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, EventType, QueueState};
+
+    #[test]
+    fn test_exponential_service_times() {
+        // With plenty of servers, every item is served as soon as it arrives,
+        // so the time between its arrival and exit is its service time.
+        let state = QueueState::new(1000, 1000, 0).with_service_time(Exponential(0.1), 2);
+        let sim = &mut Simulation::new(state);
+        (0..1000).for_each(|_| {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+        });
+        sim.run();
+
+        let exits = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerDecremented)
+            .map(|e| e.time.0 as f64)
+            .collect::<Vec<_>>();
+        let mean = exits.iter().sum::<f64>() / exits.len() as f64;
+        assert_eq!(1000, exits.len());
+        assert!((mean - 10.0).abs() < 1.0, "mean = {}", mean);
+    }
+
+    #[test]
+    fn test_class_service_times() {
+        // Items from the "complex" stream take five times as long, and the
+        // service start events are tagged with the class being served.
+        use crate::arrival::{ArrivalProcess, DeterministicArrivals, SourceId};
+        use crate::dist::Deterministic;
+        let complex = SourceId(1);
+        let service_time =
+            ClassServiceTimes::new(Deterministic(2.0)).with_class(complex, Deterministic(10.0));
+        let state = QueueState::new(100, 1, 0).with_service_time(service_time, 0);
+        let sim = &mut Simulation::new(state);
+        let simple =
+            sim.add_labeled_source("simple", DeterministicArrivals::new(Time(20)).stop_after(2));
+        sim.add_labeled_source(
+            "complex",
+            DeterministicArrivals::new(Time(30)).stop_after(1),
+        );
+        sim.run();
+
+        let services = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerIncremented)
+            .map(|e| (e.time, e.source.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![(Time(20), simple), (Time(30), complex), (Time(40), simple)],
+            services
+        );
+    }
+
+    #[test]
+    fn test_phase_type_service() {
+        // The service always ends after the second of three phases, and the
+        // end of the first phase is logged along the way.
+        use crate::dist::Deterministic;
+        let service_time = PhaseType::new()
+            .phase(Deterministic(3.0), 1.0)
+            .phase(Deterministic(4.0), 0.0)
+            .phase(Deterministic(100.0), 1.0);
+        let state = QueueState::new(10, 1, 0).with_service_time(service_time, 0);
+        let sim = &mut Simulation::new(state);
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(1),
+        });
+        sim.run();
+
+        let events = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.server.is_some())
+            .map(|e| (e.time.0, e.event_type))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (1, EventType::ServerIncremented),
+                (4, EventType::PhaseCompleted(0)),
+                (8, EventType::ServerDecremented),
+            ],
+            events
+        );
+
+        // Erlang phases add up to the Erlang mean.
+        let erlang = &mut PhaseType::erlang(4, 0.1);
+        let rng = &mut Rng::new(5);
+        let mean = (0..10_000).map(|_| erlang.sample(None, rng).0).sum::<u32>() as f64 / 10_000.0;
+        assert!((mean - 40.0).abs() < 1.0, "mean = {}", mean);
+    }
+
+    #[test]
+    fn test_interrupted_service() {
+        // A 10-unit service is interrupted at 4. Resuming finishes the
+        // remaining 6 units by the original exit time, restarting takes 10
+        // more, and the original exit is cancelled either way.
+        use crate::server::ServerId;
+        let exits = |preemption| {
+            let state = QueueState::new(10, 1, 10).with_preemption(preemption);
+            let sim = &mut Simulation::new(state);
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(0),
+            });
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Interrupt(ServerId(0)),
+                time: Time(4),
+            });
+            sim.run();
+            assert!(sim
+                .log
+                .contents
+                .iter()
+                .any(|e| e.event_type == EventType::ServiceInterrupted && e.time == Time(4)));
+            sim.log
+                .contents
+                .iter()
+                .filter(|e| e.event_type == EventType::ServerDecremented)
+                .map(|e| e.time.0)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![4, 10], exits(Preemption::Resume));
+        assert_eq!(vec![4, 14], exits(Preemption::Restart));
+    }
+
+    #[test]
+    fn test_processor_sharing() {
+        // Items with 10 units of work arrive at 0 and 4. Alone, the first
+        // item gets through 4 units by 4, and shares the processor after
+        // that, so it finishes at 16. The second item got through 6 units by
+        // then, and finishes its last 4 alone at 20.
+        let state = QueueState::new(10, 10, 10).with_processor_sharing();
+        let sim = &mut Simulation::new(state);
+        for t in [0, 4] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+
+        let exits = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == EventType::ServerDecremented)
+            .map(|e| e.time.0)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![16, 20], exits);
+        assert_eq!(0, sim.emq.size);
+    }
+
+    #[test]
+    fn test_feedback() {
+        // An item that always needs rework is served three times when
+        // capped at three passes, and then leaves.
+        let state = QueueState::new(10, 1, 10).with_feedback(Feedback::new(1.0).with_max_passes(3));
+        let sim = &mut Simulation::new(state);
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(0),
+        });
+        sim.run();
+        let fed_back = sim
+            .log
+            .contents
+            .iter()
+            .filter_map(|e| match e.event_type {
+                EventType::FedBack(pass) => Some((e.time.0, pass)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![(10, 2), (20, 3)], fed_back);
+        assert_eq!(Time(30), sim.state.time);
+        assert!(sim.state.items.is_empty());
+
+        // Otherwise, about half of all passes are followed by another.
+        let feedback = Feedback::new(0.5);
+        let rng = &mut Rng::new(0);
+        let returned = (0..10_000).filter(|_| feedback.returns(1, rng)).count();
+        assert!((returned as f64 / 10_000.0 - 0.5).abs() < 0.02);
+    }
+}
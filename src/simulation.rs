@@ -0,0 +1,957 @@
+//! A `Simulation` bundles the event message queue, queue state, and event log
+//! together with anything else that's needed to drive them, so that callers
+//! don't have to thread each piece through `step` by hand.
+
+use std::io;
+use std::time::Instant;
+
+use crate::arrival::{ArrivalProcess, Attributes, SourceId};
+use crate::checkpoint::{Checkpoints, Every, Observer};
+use crate::metric::{Metric, MetricValue};
+use crate::process::{Process, ProcessError, ProcessId, Processes};
+use crate::profile::Profile;
+use crate::sampler::Sampler;
+use crate::server::{ServerId, ShiftSchedule};
+use crate::sink::EventSink;
+use crate::stats::{
+    BatchMeans, BatchMetric, Histogram, Interval, ItemTracker, Occupancy, OnlineStats, Periods,
+    Precision, Regeneration, SamplePath, ServerLoad, SummaryStats, TimeSketches,
+};
+use crate::trace::Tracer;
+use crate::{
+    handle_message, interrupt, Event, EventLog, EventMessage, EventMessageQueue, EventMessageType,
+    EventType, QueueState, Time,
+};
+
+/// The full simulation: pending event messages, the queue state, the log of
+/// what has happened so far, the arrival sources and spawned processes that
+/// generate new messages, the sample paths of the queue length, busy servers,
+/// and server capacity, and, optionally, a performance profile of the engine.
+#[derive(Debug)]
+pub struct Simulation {
+    pub emq: EventMessageQueue,
+    pub state: QueueState,
+    pub log: EventLog,
+    sources: Vec<Stream>,
+    /// Events reported by arrival sources that haven't been logged yet,
+    /// sorted by time. Sources can report events ahead of the clock, so these
+    /// are held back until the simulation catches up to them.
+    source_events: Vec<Event>,
+    processes: Processes,
+    shifts: Option<ShiftSchedule>,
+    profile: Option<Profile>,
+    peak_memory: usize,
+    occupancy: Occupancy,
+    /// The server capacity over time, unless the server pool is unbounded.
+    capacity: Option<SamplePath>,
+    sketches: Option<(ItemTracker, TimeSketches)>,
+    sampler: Option<Sampler>,
+    online: Option<OnlineStats>,
+    /// Whether events are kept in the log.
+    keep_log: bool,
+    /// The target wait for the service level in summaries.
+    service_target: Option<u32>,
+    metrics: Vec<(String, Box<dyn Metric>)>,
+    checkpoints: Option<Checkpoints>,
+    sink: Option<Box<dyn EventSink>>,
+    /// The first error the sink ran into, after which it's left alone.
+    sink_error: Option<io::Error>,
+    tracer: Option<Box<dyn Tracer>>,
+    /// Every message handled so far, if tracing messages is enabled.
+    message_trace: Option<Vec<EventMessage>>,
+    /// The number of messages handled and events recorded so far, whether
+    /// or not the events are kept in the log.
+    steps: u64,
+    events: u64,
+}
+
+/// An arrival source registered with a simulation, along with the label of
+/// the items it generates.
+#[derive(Debug)]
+struct Stream {
+    label: String,
+    process: Box<dyn ArrivalProcess>,
+}
+
+/// An estimate of the heap memory used by a simulation, in bytes.
+///
+/// This counts allocated capacity rather than occupied length, since that's
+/// what the process actually holds on to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MemoryFootprint {
+    /// Pending event messages.
+    pub message_queue: usize,
+    /// Events in the log.
+    pub event_log: usize,
+    /// Per-item tracking, i.e., items in the queue and spawned processes.
+    pub items: usize,
+    /// The largest total seen after any step.
+    pub peak: usize,
+}
+
+/// Errors raised when an arrival can't be injected into a simulation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InjectError {
+    /// The arrival time is earlier than the simulation clock.
+    InPast { time: Time, now: Time },
+    /// The arrival is tagged with a source that isn't registered.
+    UnknownSource(SourceId),
+    /// Processes have been spawned, which plain arrivals can't be mixed
+    /// with.
+    Mixed,
+}
+
+impl MemoryFootprint {
+    /// The current total across all structures.
+    pub fn total(&self) -> usize {
+        self.message_queue + self.event_log + self.items
+    }
+}
+
+impl Simulation {
+    /// Create a simulation with the given initial state, no pending messages,
+    /// and an empty log.
+    pub fn new(state: QueueState) -> Self {
+        let capacity = (!state.has_infinite_servers()).then(|| {
+            let mut capacity = SamplePath::new();
+            capacity.record(state.time, state.server_capacity);
+            capacity
+        });
+        Self {
+            emq: EventMessageQueue::new(),
+            state,
+            log: EventLog::new(),
+            sources: vec![],
+            source_events: vec![],
+            processes: Processes::new(),
+            shifts: None,
+            profile: None,
+            peak_memory: 0,
+            occupancy: Occupancy::new(),
+            capacity,
+            sketches: None,
+            sampler: None,
+            online: None,
+            keep_log: true,
+            service_target: None,
+            metrics: vec![],
+            checkpoints: None,
+            sink: None,
+            sink_error: None,
+            tracer: None,
+            message_trace: None,
+            steps: 0,
+            events: 0,
+        }
+    }
+
+    /// Estimate the memory currently used by the simulation, along with the
+    /// high-water mark.
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        let footprint = MemoryFootprint {
+            message_queue: self.emq.heap_bytes(),
+            event_log: self.log.heap_bytes(),
+            items: self.state.heap_bytes() + self.processes.heap_bytes(),
+            peak: 0,
+        };
+        MemoryFootprint {
+            peak: self.peak_memory.max(footprint.total()),
+            ..footprint
+        }
+    }
+
+    /// Start collecting a performance profile of the engine. Profiling is off
+    /// by default because timing every handler isn't free.
+    pub fn enable_profiling(&mut self) -> &mut Self {
+        self.profile = Some(Profile::new());
+        self
+    }
+
+    /// Start sketching the waiting and sojourn times of items as they leave,
+    /// with histograms binned like the given one and t-digests with the
+    /// given compression.
+    pub fn enable_time_sketches(&mut self, histogram: Histogram, compression: f64) -> &mut Self {
+        self.sketches = Some((
+            ItemTracker::new(),
+            TimeSketches::new(histogram, compression),
+        ));
+        self
+    }
+
+    /// The sketches of waiting and sojourn times, if sketching is enabled.
+    pub fn time_sketches(&self) -> Option<&TimeSketches> {
+        self.sketches.as_ref().map(|(_, sketches)| sketches)
+    }
+
+    /// Stop keeping events in the log, which saves memory on long runs but
+    /// leaves the statistics worked out from the log empty. Online
+    /// statistics don't depend on the log.
+    pub fn discard_log(&mut self) -> &mut Self {
+        self.keep_log = false;
+        self
+    }
+
+    /// Send every event to a sink as it's logged, whether or not the log
+    /// keeps it.
+    pub fn set_sink(&mut self, sink: Box<dyn EventSink>) -> &mut Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Tell a tracer about every step as it happens.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) -> &mut Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Start keeping every message handled, in order, so the run can be
+    /// replayed (see the `replay` module).
+    pub fn enable_message_trace(&mut self) -> &mut Self {
+        self.message_trace = Some(vec![]);
+        self
+    }
+
+    /// The messages handled so far, if tracing messages is enabled.
+    pub fn message_trace(&self) -> Option<&[EventMessage]> {
+        self.message_trace.as_deref()
+    }
+
+    /// Flush the sink, returning the first error it ran into, if any.
+    pub fn flush_sink(&mut self) -> io::Result<()> {
+        if let Some(error) = self.sink_error.take() {
+            return Err(error);
+        }
+        self.sink.as_mut().map_or(Ok(()), |sink| sink.flush())
+    }
+
+    /// Log an event, and send it to the sink.
+    fn record(&mut self, e: Event) {
+        self.events += 1;
+        if let (Some(sink), None) = (self.sink.as_mut(), &self.sink_error) {
+            self.sink_error = sink.write_event(&e).err();
+        }
+        if self.keep_log {
+            self.log.push(e);
+        }
+    }
+
+    /// The number of messages handled so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// The number of events recorded so far, including any that weren't kept
+    /// in the log.
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    /// Start updating statistics as the run goes.
+    pub fn enable_online_stats(&mut self) -> &mut Self {
+        let mut online = OnlineStats::new();
+        online.end_step(&self.state);
+        self.online = Some(online);
+        self
+    }
+
+    /// The statistics updated as the run goes, if they're enabled.
+    pub fn online_stats(&self) -> Option<&OnlineStats> {
+        self.online.as_ref()
+    }
+
+    /// Register a user-defined metric under a name, to be updated from every
+    /// event from now on.
+    pub fn add_metric(
+        &mut self,
+        name: impl Into<String>,
+        metric: impl Metric + 'static,
+    ) -> &mut Self {
+        self.metrics.push((name.into(), Box::new(metric)));
+        self
+    }
+
+    /// The value of the user-defined metric with the given name, if there's
+    /// one.
+    pub fn metric(&self, name: &str) -> Option<MetricValue> {
+        self.metrics
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, metric)| metric.finalize())
+    }
+
+    /// The values of every user-defined metric, in order of registration.
+    pub fn metrics(&self) -> Vec<(&str, MetricValue)> {
+        self.metrics
+            .iter()
+            .map(|(name, metric)| (name.as_str(), metric.finalize()))
+            .collect()
+    }
+
+    /// Start sampling the buffer and server counts every `interval` units of
+    /// simulated time.
+    pub fn enable_sampling(&mut self, interval: u32) -> &mut Self {
+        self.sampler = Some(Sampler::new(interval));
+        self
+    }
+
+    /// Hand a summary of the run so far to an observer at regular intervals,
+    /// and stop the run if it says so.
+    pub fn set_checkpoints(&mut self, every: Every, observer: Observer) -> &mut Self {
+        self.checkpoints = Some(Checkpoints::new(every, observer));
+        self
+    }
+
+    /// Whether a checkpoint observer stopped the run.
+    pub fn stopped(&self) -> bool {
+        self.checkpoints.as_ref().is_some_and(|c| c.stopped())
+    }
+
+    /// The sampled buffer and server counts, if sampling is enabled.
+    pub fn sampler(&self) -> Option<&Sampler> {
+        self.sampler.as_ref()
+    }
+
+    /// The performance profile, if profiling is enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// The sample paths of the queue length and busy servers so far.
+    pub fn occupancy(&self) -> &Occupancy {
+        &self.occupancy
+    }
+
+    /// Summarize the run so far, up to the current time.
+    pub fn summary(&self) -> SummaryStats {
+        SummaryStats::new(
+            &self.occupancy,
+            self.capacity.as_ref(),
+            &self.log,
+            self.state.time,
+            self.service_target,
+        )
+    }
+
+    /// Report the fraction of items that waited no longer than the given
+    /// target in summaries.
+    pub fn set_service_target(&mut self, target: u32) -> &mut Self {
+        self.service_target = Some(target);
+        self
+    }
+
+    /// Summarize the run so far for each class of items, i.e., each source,
+    /// whose labels can be looked up with `source_label`.
+    pub fn summary_by_class(&self) -> Vec<(Option<SourceId>, SummaryStats)> {
+        SummaryStats::by_class(
+            self.capacity.as_ref(),
+            &self.log,
+            self.state.time,
+            self.service_target,
+        )
+    }
+
+    /// The busy periods of the system and idle periods of its servers so
+    /// far.
+    pub fn periods(&self) -> Periods {
+        Periods::new(&self.occupancy, &self.log)
+    }
+
+    /// The workload of each server in the run so far.
+    pub fn server_loads(&self) -> Vec<ServerLoad> {
+        let servers = self.state.server_pool.servers.len();
+        ServerLoad::from_log(&self.log, servers, self.state.time)
+    }
+
+    /// The regeneration points of the run so far.
+    pub fn regeneration(&self) -> Regeneration {
+        Regeneration::new(&self.occupancy)
+    }
+
+    /// The end of the warm-up period of the run so far, as found by MSER-5.
+    pub fn warm_up(&self) -> Time {
+        self.occupancy.warm_up(self.state.time)
+    }
+
+    /// The batch-means confidence interval for a metric over the run so far,
+    /// after a warm-up period, e.g., the one from `warm_up`.
+    pub fn batch_means(
+        &self,
+        warm_up: Time,
+        batches: u32,
+        metric: BatchMetric,
+        level: f64,
+    ) -> Option<Interval> {
+        BatchMeans::new(warm_up, self.state.time, batches).interval(
+            &self.occupancy,
+            &self.log,
+            metric,
+            level,
+        )
+    }
+
+    /// Run until the batch-means interval for a metric, after the warm-up
+    /// found by MSER-5, reaches a target precision, checking every `every`
+    /// units of simulated time, or until the horizon or the messages run
+    /// out. Returns the last interval found.
+    pub fn run_until_precise(
+        &mut self,
+        metric: BatchMetric,
+        batches: u32,
+        precision: Precision,
+        every: u32,
+        horizon: Time,
+    ) -> Option<Interval> {
+        assert!(every > 0, "the checking interval must be positive");
+        let mut until = self.state.time.0;
+        loop {
+            until = until.saturating_add(every).min(horizon.0);
+            self.run_until(Time(until));
+            let interval = self.batch_means(self.warm_up(), batches, metric, precision.level);
+            let done = interval.is_some_and(|i| precision.is_met(&i));
+            if done || until >= horizon.0 || self.emq.peek_time().is_none() || self.stopped() {
+                return interval;
+            }
+        }
+    }
+
+    /// Schedule an event message.
+    ///
+    /// # Panics
+    ///
+    /// If it's a plain arrival and processes have been spawned. Use
+    /// `try_schedule` to get an error instead.
+    pub fn schedule(&mut self, event_message: EventMessage) -> &mut Self {
+        self.try_schedule(event_message)
+            .expect("plain arrivals can't be mixed with processes")
+    }
+
+    /// Schedule an event message, unless it's a plain arrival and processes
+    /// have been spawned.
+    pub fn try_schedule(&mut self, event_message: EventMessage) -> Result<&mut Self, ProcessError> {
+        if !self.processes.is_empty() && admits_items(&event_message) {
+            return Err(ProcessError::Mixed);
+        }
+        self.emq.push(event_message);
+        Ok(self)
+    }
+
+    /// Add an arrival source and schedule its first arrival. The source is
+    /// labeled by its ID, e.g., `"source-0"`.
+    pub fn add_source(&mut self, source: impl ArrivalProcess + 'static) -> SourceId {
+        let label = format!("source-{}", self.sources.len());
+        self.add_labeled_source(label, source)
+    }
+
+    /// Add an arrival source whose items carry the given label, and schedule
+    /// its first arrival.
+    ///
+    /// Any number of sources can feed the same queue. Their arrivals are
+    /// interleaved by time, and events caused by their items are tagged with
+    /// the source ID.
+    pub fn add_labeled_source(
+        &mut self,
+        label: impl Into<String>,
+        source: impl ArrivalProcess + 'static,
+    ) -> SourceId {
+        assert!(
+            self.processes.is_empty(),
+            "arrival sources can't be mixed with processes"
+        );
+        let id = SourceId(self.sources.len() as u32);
+        self.sources.push(Stream {
+            label: label.into(),
+            process: Box::new(source),
+        });
+        self.schedule_arrival(id, self.state.time);
+        id
+    }
+
+    /// The label of a source.
+    pub fn source_label(&self, id: SourceId) -> &str {
+        &self.sources[id.0 as usize].label
+    }
+
+    /// Look up a source by its label.
+    pub fn source_id(&self, label: &str) -> Option<SourceId> {
+        self.sources
+            .iter()
+            .position(|s| s.label == label)
+            .map(|i| SourceId(i as u32))
+    }
+
+    /// Schedule the next arrival from a source, if it has one.
+    fn schedule_arrival(&mut self, id: SourceId, now: Time) {
+        let source = &mut self.sources[id.0 as usize].process;
+        let interarrival = source.next_interarrival(now, &self.state);
+        self.source_events
+            .extend(source.take_events().into_iter().map(|e| Event {
+                source: Some(id),
+                ..e
+            }));
+        self.source_events.sort_by_key(|e| e.time);
+        if let Some(interarrival) = interarrival {
+            self.schedule(EventMessage {
+                event_message_type: EventMessageType::ArriveFrom(id),
+                time: Time(now.0 + interarrival.0),
+            });
+        }
+    }
+
+    /// Tell the source of a departing item about its departure, or every
+    /// source if the item has no source, and schedule any arrivals that
+    /// result from it.
+    fn notify_departure(&mut self, now: Time, source: Option<SourceId>) {
+        for i in 0..self.sources.len() {
+            if source.is_some_and(|id| id.0 as usize != i) {
+                continue;
+            }
+            if let Some(delay) = self.sources[i].process.on_departure(now, &self.state) {
+                self.schedule(EventMessage {
+                    event_message_type: EventMessageType::ArriveFrom(SourceId(i as u32)),
+                    time: Time(now.0 + delay.0),
+                });
+            }
+        }
+    }
+
+    /// Log the source events that are due at or before the given time.
+    fn flush_source_events(&mut self, time: Time) {
+        let due = self
+            .source_events
+            .iter()
+            .take_while(|e| e.time <= time)
+            .count();
+        for e in self.source_events.drain(..due).collect::<Vec<_>>() {
+            self.record(e);
+        }
+    }
+
+    /// Push an ad-hoc arrival into the simulation, e.g., from an embedding
+    /// program or an interactive session. The arrival can't be earlier than
+    /// the current clock, and if it's tagged with a source, the source has to
+    /// be registered.
+    pub fn inject_arrival(
+        &mut self,
+        time: Time,
+        attributes: Attributes,
+    ) -> Result<(), InjectError> {
+        if time < self.state.time {
+            return Err(InjectError::InPast {
+                time,
+                now: self.state.time,
+            });
+        }
+        if let Some(source) = attributes.source {
+            if source.0 as usize >= self.sources.len() {
+                return Err(InjectError::UnknownSource(source));
+            }
+        }
+        self.try_schedule(EventMessage {
+            event_message_type: EventMessageType::ArriveWith(attributes),
+            time,
+        })
+        .map_err(|_| InjectError::Mixed)?;
+        Ok(())
+    }
+
+    /// Change the number of servers on shift over time according to a
+    /// schedule. Changes are scheduled one at a time, starting with the first
+    /// one at or after the current time.
+    pub fn set_shift_schedule(&mut self, schedule: ShiftSchedule) -> &mut Self {
+        self.shifts = Some(schedule);
+        self.schedule_shift_change(self.state.time);
+        self
+    }
+
+    /// Schedule the next shift change at or after the given time.
+    fn schedule_shift_change(&mut self, from: Time) {
+        if let Some((time, capacity)) = self.shifts.as_ref().and_then(|s| s.next_change(from)) {
+            self.schedule(EventMessage {
+                event_message_type: EventMessageType::SetCapacity(capacity),
+                time,
+            });
+        }
+    }
+
+    /// Spawn a process that starts at the given time, unless the simulation
+    /// has, or has had, plain arrivals.
+    pub fn spawn(&mut self, time: Time, process: Process) -> Result<ProcessId, ProcessError> {
+        if self.state.next_item > 0
+            || !self.sources.is_empty()
+            || self.emq.messages.iter().any(admits_items)
+        {
+            return Err(ProcessError::Mixed);
+        }
+        let id = self.processes.spawn(process)?;
+        self.schedule(EventMessage {
+            event_message_type: EventMessageType::Resume(id),
+            time,
+        });
+        Ok(id)
+    }
+
+    /// Cancel the exit (and any phase completions) of the service on a server
+    /// and interrupt it. Interrupting an idle server does nothing.
+    fn interrupt(
+        &mut self,
+        event_message: EventMessage,
+        server: ServerId,
+    ) -> (Vec<EventMessage>, Vec<Event>) {
+        self.state.preempting.remove(&server);
+        let cancelled = self.emq.cancel(|em| match em.event_message_type {
+            EventMessageType::Exit(s) | EventMessageType::CompletePhase(s, _) => s == server,
+            _ => false,
+        });
+        match cancelled
+            .iter()
+            .find(|em| em.event_message_type == EventMessageType::Exit(server))
+        {
+            Some(exit) => {
+                let (_, event_messages, events) =
+                    interrupt(event_message, server, exit.time, &mut self.state);
+                (event_messages, events)
+            }
+            None => (vec![], vec![]),
+        }
+    }
+
+    /// Take a checkpoint if one is due before the next message, returning
+    /// whether the run should go on.
+    fn checkpoint(&mut self, before: Time) -> bool {
+        let Some(mut checkpoints) = self.checkpoints.take() else {
+            return true;
+        };
+        let go_on = if checkpoints.stopped() {
+            false
+        } else if checkpoints.is_due(before) {
+            checkpoints.observe(&self.summary(), before)
+        } else {
+            true
+        };
+        self.checkpoints = Some(checkpoints);
+        go_on
+    }
+
+    /// Handle the next event message, returning the updated state, or `None`
+    /// if there are no more messages or a checkpoint observer stopped the
+    /// run.
+    pub fn step(&mut self) -> Option<&QueueState> {
+        if !self.checkpoint(self.emq.peek_time()?) {
+            return None;
+        }
+        let (event_message, _) = self.emq.pop()?;
+        self.steps += 1;
+        self.flush_source_events(event_message.time);
+        if let Some(sampler) = self.sampler.as_mut() {
+            sampler.observe(&self.state, event_message.time);
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.enter_step(&event_message);
+        }
+        if let Some(trace) = self.message_trace.as_mut() {
+            trace.push(event_message);
+        }
+        let started = self.profile.as_ref().map(|_| Instant::now());
+        let (event_messages, events) = match event_message.event_message_type {
+            EventMessageType::Resume(id) => {
+                self.processes
+                    .resume(id, event_message.time, &mut self.state)
+            }
+            EventMessageType::Interrupt(server) => self.interrupt(event_message, server),
+            _ => {
+                let (_, event_messages, events) = handle_message(event_message, &mut self.state);
+                (event_messages, events)
+            }
+        };
+        self.state.set_time(event_message.time);
+        match event_message.event_message_type {
+            // A blocked source's next arrival waits until the blocked one is
+            // admitted.
+            EventMessageType::ArriveFrom(id)
+                if !events.iter().any(|e| e.event_type == EventType::Blocked) =>
+            {
+                self.schedule_arrival(id, event_message.time)
+            }
+            EventMessageType::Exit(_) => {
+                let source = events
+                    .iter()
+                    .find(|e| e.event_type == EventType::ServerDecremented)
+                    .and_then(|e| e.source);
+                self.notify_departure(event_message.time, source)
+            }
+            EventMessageType::SetCapacity(_) => {
+                self.schedule_shift_change(Time(event_message.time.0 + 1))
+            }
+            _ => {}
+        }
+        for id in self.state.take_resumed_sources() {
+            self.schedule_arrival(id, event_message.time);
+        }
+        if self.state.patience.is_some() {
+            // Items that entered service won't renege.
+            for id in events
+                .iter()
+                .filter(|e| e.event_type == EventType::BufferDecremented)
+                .filter_map(|e| e.item)
+            {
+                self.emq
+                    .cancel(|em| em.event_message_type == EventMessageType::Renege(id));
+            }
+        }
+        if !self.state.met.is_empty() {
+            // Items that met their deadlines don't need checking.
+            let met = &self.state.met;
+            self.emq.cancel(|em| {
+                matches!(em.event_message_type, EventMessageType::CheckDeadline(id) if met.contains(&id))
+            });
+        }
+        if let Some(sharing) = &self.state.processor_sharing {
+            // An item may have started or finished sharing the processor, so
+            // replace every pending exit.
+            self.emq
+                .cancel(|em| matches!(em.event_message_type, EventMessageType::Exit(_)));
+            for (server, time) in sharing.exits() {
+                self.emq.push(EventMessage {
+                    event_message_type: EventMessageType::Exit(server),
+                    time,
+                });
+            }
+        }
+        let n_events = events.len();
+        event_messages.into_iter().for_each(|em| {
+            self.emq.push(em);
+        });
+        if let Some((tracker, sketches)) = self.sketches.as_mut() {
+            events.iter().for_each(|e| {
+                tracker.observe(e);
+            });
+            for departure in tracker.flush() {
+                sketches.record(&departure);
+            }
+        }
+        if let Some(online) = self.online.as_mut() {
+            events.iter().for_each(|e| {
+                online.observe(e);
+            });
+            online.end_step(&self.state);
+        }
+        for (_, metric) in self.metrics.iter_mut() {
+            events.iter().for_each(|e| {
+                metric.observe_event(e, &self.state);
+            });
+        }
+        if let Some(checkpoints) = self.checkpoints.as_mut() {
+            checkpoints.count(n_events);
+        }
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.exit_step(&event_message, &events);
+        }
+        events.into_iter().for_each(|e| self.record(e));
+        self.occupancy.record_state(&self.state);
+        if let Some(capacity) = self.capacity.as_mut() {
+            capacity.record(self.state.time, self.state.server_capacity);
+        }
+        if let (Some(profile), Some(started)) = (self.profile.as_mut(), started) {
+            profile.record(started.elapsed(), n_events, self.emq.size);
+        }
+        self.peak_memory = self.memory_footprint().peak;
+        Some(&self.state)
+    }
+
+    /// Like `step`, but only handle the next message if it's due at or before
+    /// the horizon.
+    pub fn step_until(&mut self, horizon: Time) -> Option<&QueueState> {
+        match self.emq.peek_time() {
+            Some(time) if time <= horizon => self.step(),
+            _ => None,
+        }
+    }
+
+    /// Step until there are no more messages.
+    ///
+    /// Note: Many arrival sources never run out of arrivals, so use
+    /// `run_until` when any are registered.
+    pub fn run(&mut self) -> SummaryStats {
+        while self.step().is_some() {}
+        self.summary()
+    }
+
+    /// Step until there are no more messages due at or before the horizon.
+    pub fn run_until(&mut self, horizon: Time) -> &mut Self {
+        while self.step_until(horizon).is_some() {}
+        self
+    }
+}
+
+/// Whether a message brings a plain item into the queue.
+fn admits_items(event_message: &EventMessage) -> bool {
+    matches!(
+        event_message.event_message_type,
+        EventMessageType::Arrive
+            | EventMessageType::ArriveFrom(_)
+            | EventMessageType::ArriveWith(_)
+            | EventMessageType::Retry(..)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_matches_step() {
+        // Running a simulation should leave the state and log exactly as
+        // calling the free `step` function in a loop would.
+        let messages = (0..4).map(|t| EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(t),
+        });
+
+        let sim = &mut Simulation::new(QueueState::new(2, 1, 3));
+        messages.clone().for_each(|em| {
+            sim.schedule(em);
+        });
+        sim.run();
+
+        let emq = &mut EventMessageQueue::new();
+        let state = &mut QueueState::new(2, 1, 3);
+        let log = &mut EventLog::new();
+        messages.for_each(|em| {
+            emq.push(em);
+        });
+        while crate::step(emq, state, log).is_some() {}
+
+        assert_eq!(state.time, sim.state.time);
+        assert_eq!(log.contents, sim.log.contents);
+    }
+
+    #[test]
+    fn test_lazy_arrivals() {
+        // Only one arrival from a source is pending at any time, and the
+        // horizon is respected.
+        let sim = &mut Simulation::new(QueueState::new(100, 100, 1));
+        sim.add_source(crate::arrival::PoissonArrivals::new(1.0, 3));
+        assert_eq!(1, sim.emq.size);
+        sim.run_until(Time(50));
+        assert!(sim.state.time <= Time(50));
+        assert!(sim.emq.peek_time().unwrap() > Time(50));
+        let arrivals = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == crate::EventType::BufferIncremented)
+            .count();
+        assert!(arrivals > 25 && arrivals < 75, "arrivals = {}", arrivals);
+    }
+
+    #[test]
+    fn test_run_until_precise() {
+        // An M/D/1 queue at half load pins down its utilization to within 5%
+        // long before the horizon, and stops there.
+        let sim = &mut Simulation::new(QueueState::new(1000, 1, 5));
+        sim.add_source(crate::arrival::PoissonArrivals::new(0.1, 7));
+        let precision = Precision::new(0.05, 0.95);
+        let horizon = Time(1_000_000);
+        let interval = sim
+            .run_until_precise(BatchMetric::BusyServers, 20, precision, 1000, horizon)
+            .unwrap();
+        assert!(precision.is_met(&interval));
+        assert!((interval.mean - 0.5).abs() < 0.05);
+        assert!(sim.state.time < Time(100_000));
+    }
+
+    #[test]
+    fn test_memory_footprint() {
+        let sim = &mut Simulation::new(QueueState::new(2, 1, 3));
+        assert_eq!(0, sim.memory_footprint().total());
+
+        (0..4).for_each(|t| {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        });
+        let queued = sim.memory_footprint();
+        assert!(queued.message_queue >= 4 * std::mem::size_of::<EventMessage>());
+        assert_eq!(0, queued.event_log);
+
+        // The peak never drops below the current total.
+        sim.run();
+        let done = sim.memory_footprint();
+        assert!(done.event_log > 0);
+        assert!(done.peak >= done.total());
+        assert!(done.peak >= queued.total());
+    }
+
+    #[test]
+    fn test_labeled_sources() {
+        // Two streams feed one queue, and arrivals are interleaved by time and
+        // tagged with their stream.
+        use crate::arrival::DeterministicArrivals;
+        let sim = &mut Simulation::new(QueueState::new(100, 100, 1));
+        let fast = sim.add_labeled_source("fast", DeterministicArrivals::new(Time(3)));
+        let slow = sim.add_labeled_source("slow", DeterministicArrivals::new(Time(5)));
+        assert_eq!("slow", sim.source_label(slow));
+        assert_eq!(Some(fast), sim.source_id("fast"));
+        sim.run_until(Time(14));
+
+        let arrivals = sim
+            .log
+            .contents
+            .iter()
+            .filter(|e| e.event_type == crate::EventType::BufferIncremented)
+            .map(|e| (e.time.0, e.source.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (3, fast),
+                (5, slow),
+                (6, fast),
+                (9, fast),
+                (10, slow),
+                (12, fast)
+            ],
+            arrivals
+        );
+    }
+
+    #[test]
+    fn test_inject_arrival() {
+        use crate::arrival::DeterministicArrivals;
+        let sim = &mut Simulation::new(QueueState::new(100, 100, 1));
+        let source = sim.add_source(DeterministicArrivals::new(Time(5)));
+        sim.run_until(Time(10));
+
+        assert_eq!(
+            Err(InjectError::InPast {
+                time: Time(7),
+                now: Time(10)
+            }),
+            sim.inject_arrival(Time(7), Attributes::default())
+        );
+        assert_eq!(
+            Err(InjectError::UnknownSource(SourceId(1))),
+            sim.inject_arrival(
+                Time(12),
+                Attributes {
+                    source: Some(SourceId(1)),
+                    ..Attributes::default()
+                }
+            )
+        );
+
+        let attributes = Attributes {
+            source: Some(source),
+            ..Attributes::default()
+        };
+        assert_eq!(Ok(()), sim.inject_arrival(Time(12), attributes));
+        sim.run_until(Time(12));
+        let injected = sim
+            .log
+            .contents
+            .iter()
+            .find(|e| e.time == Time(12) && e.event_type == crate::EventType::BufferIncremented)
+            .unwrap();
+        assert_eq!(Some(source), injected.source);
+    }
+}
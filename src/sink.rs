@@ -0,0 +1,120 @@
+//! Streaming events out of a run as it goes.
+//!
+//! The event log only holds events in memory, so nothing outside the process
+//! sees them until the run is over. An `EventSink` is handed each event as
+//! it's logged instead, e.g., to write it to a file that another tool tails.
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+
+use crate::json::ToJson;
+use crate::Event;
+
+/// Somewhere to send events as they happen.
+pub trait EventSink: Debug {
+    /// Take the next event.
+    fn write_event(&mut self, e: &Event) -> io::Result<()>;
+
+    /// Push out any events that are held back.
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+/// Writes each event as a JSON object on its own line (see the `json`
+/// module), to a file or any other writer. Lines are buffered and written,
+/// and the writer flushed, every `batch` events, so a reader sees whole
+/// lines at a reasonable cost.
+#[derive(Debug)]
+pub struct JsonLines<W: Write + Debug> {
+    writer: W,
+    batch: usize,
+    buffer: String,
+    pending: usize,
+}
+
+impl<W: Write + Debug> JsonLines<W> {
+    /// Create a sink that writes to a writer in batches of the given size.
+    pub fn new(writer: W, batch: usize) -> Self {
+        assert!(batch > 0, "the batch size must be positive");
+        Self {
+            writer,
+            batch,
+            buffer: String::new(),
+            pending: 0,
+        }
+    }
+
+    /// Flush the sink and return its writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        EventSink::flush(&mut self)?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Debug> EventSink for JsonLines<W> {
+    fn write_event(&mut self, e: &Event) -> io::Result<()> {
+        self.buffer.push_str(&e.to_json().to_string());
+        self.buffer.push('\n');
+        self.pending += 1;
+        if self.pending >= self.batch {
+            EventSink::flush(self)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.write_all(self.buffer.as_bytes())?;
+        self.buffer.clear();
+        self.pending = 0;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::json::parse;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    /// A writer whose output can be read while a sink holds on to it.
+    #[derive(Debug, Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines() {
+        // Two items make eight events, which come out in batches of 3 as the
+        // run goes on, and the last two once the sink is flushed.
+        let output = Shared::default();
+        let lines = || String::from_utf8(output.0.borrow().clone()).unwrap();
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        sim.set_sink(Box::new(JsonLines::new(output.clone(), 3)));
+        for t in [0, 1] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        assert_eq!(6, lines().lines().count());
+        sim.flush_sink().unwrap();
+        let events = lines()
+            .lines()
+            .map(|line| parse::<Event>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(sim.log.contents, events);
+    }
+}
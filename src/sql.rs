@@ -0,0 +1,221 @@
+//! Runs as SQL scripts for SQLite.
+//!
+//! `SqlScript` writes the events of a run as it goes, along with its items
+//! and summary once it's over, as SQL statements that load into a SQLite
+//! database, e.g., with `sqlite3 runs.db < run.sql`. Every row is keyed by a
+//! run ID, so many runs can share one database and be compared with SQL.
+//!
+//! The schema, created by `SCHEMA`, is:
+//! - `events`: `run_id`, `time`, `event_type`, `data`, `source`, `server`,
+//!   `item`, `priority`, `work`, `pass`, and `tag`, as in `EventColumns`.
+//! - `items`: `run_id`, `item`, `departure`, `wait`, `service`, and
+//!   `sojourn`, as in `ItemColumns`.
+//! - `summaries`: `run_id` and the main metrics of `SummaryStats`, with
+//!   `NULL` for the times when no item finished service.
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+
+use crate::columns::ItemColumns;
+use crate::sink::EventSink;
+use crate::stats::{SummaryStats, TimeStats};
+use crate::{Event, EventLog};
+
+/// The statements that create the tables, if they don't exist yet.
+pub const SCHEMA: &str = "\
+CREATE TABLE IF NOT EXISTS events (
+    run_id INTEGER NOT NULL,
+    time INTEGER NOT NULL,
+    event_type TEXT NOT NULL,
+    data INTEGER,
+    source INTEGER,
+    server INTEGER,
+    item INTEGER,
+    priority INTEGER,
+    work REAL,
+    pass INTEGER,
+    tag INTEGER
+);
+CREATE TABLE IF NOT EXISTS items (
+    run_id INTEGER NOT NULL,
+    item INTEGER NOT NULL,
+    departure INTEGER NOT NULL,
+    wait INTEGER NOT NULL,
+    service INTEGER NOT NULL,
+    sojourn INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS summaries (
+    run_id INTEGER PRIMARY KEY,
+    end_time INTEGER NOT NULL,
+    utilization REAL,
+    mean_queue_length REAL NOT NULL,
+    mean_number_in_system REAL NOT NULL,
+    mean_wait REAL,
+    mean_service REAL,
+    mean_sojourn REAL,
+    arrivals INTEGER NOT NULL,
+    departures INTEGER NOT NULL,
+    throughput REAL NOT NULL,
+    loss_probability REAL NOT NULL
+);
+";
+
+/// Writes a run as SQL statements, with the events in transactions of
+/// `batch` events each, which SQLite loads much faster than one at a time.
+#[derive(Debug)]
+pub struct SqlScript<W: Write + Debug> {
+    writer: W,
+    run: u64,
+    batch: usize,
+    buffer: String,
+    pending: usize,
+}
+
+impl<W: Write + Debug> SqlScript<W> {
+    /// Create a script for the run with the given ID.
+    pub fn new(writer: W, run: u64, batch: usize) -> Self {
+        assert!(batch > 0, "the batch size must be positive");
+        Self {
+            writer,
+            run,
+            batch,
+            buffer: String::new(),
+            pending: 0,
+        }
+    }
+
+    /// Write the statements that create the tables.
+    pub fn write_schema(&mut self) -> io::Result<()> {
+        self.writer.write_all(SCHEMA.as_bytes())
+    }
+
+    /// Write the items that finished service in a log, in one transaction.
+    pub fn write_items(&mut self, log: &EventLog) -> io::Result<()> {
+        let items = ItemColumns::from_log(log);
+        writeln!(self.writer, "BEGIN;")?;
+        for i in 0..items.len() {
+            writeln!(
+                self.writer,
+                "INSERT INTO items VALUES ({}, {}, {}, {}, {}, {});",
+                self.run,
+                items.item[i],
+                items.departure[i],
+                items.wait[i],
+                items.service[i],
+                items.sojourn[i]
+            )?;
+        }
+        writeln!(self.writer, "COMMIT;")
+    }
+
+    /// Write the summary of the run.
+    pub fn write_summary(&mut self, summary: &SummaryStats) -> io::Result<()> {
+        let mean = |stats: Option<TimeStats>| stats.map_or("NULL".to_string(), |s| real(s.mean));
+        writeln!(
+            self.writer,
+            "INSERT INTO summaries VALUES ({}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {}, {});",
+            self.run,
+            summary.end.0,
+            nullable(summary.utilization.map(real)),
+            real(summary.mean_queue_length),
+            real(summary.mean_number_in_system),
+            mean(summary.wait),
+            mean(summary.service),
+            mean(summary.sojourn),
+            summary.flow.arrivals,
+            summary.flow.departures,
+            real(summary.flow.throughput()),
+            real(summary.flow.loss_probability())
+        )
+    }
+
+    /// Flush the script and return its writer.
+    pub fn into_inner(mut self) -> io::Result<W> {
+        EventSink::flush(&mut self)?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write + Debug> EventSink for SqlScript<W> {
+    fn write_event(&mut self, e: &Event) -> io::Result<()> {
+        let (name, data) = e.event_type.parts();
+        self.buffer.push_str(&format!(
+            "INSERT INTO events VALUES ({}, {}, '{}', {}, {}, {}, {}, {}, {}, {}, {});\n",
+            self.run,
+            e.time.0,
+            name,
+            nullable(data),
+            nullable(e.source.map(|id| id.0)),
+            nullable(e.server.map(|id| id.0)),
+            nullable(e.item.map(|id| id.0)),
+            nullable(e.metadata.map(|m| m.priority)),
+            nullable(e.metadata.and_then(|m| m.work).map(real)),
+            nullable(e.metadata.map(|m| m.pass)),
+            nullable(e.metadata.and_then(|m| m.tag)),
+        ));
+        self.pending += 1;
+        if self.pending >= self.batch {
+            EventSink::flush(self)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.pending > 0 {
+            write!(self.writer, "BEGIN;\n{}COMMIT;\n", self.buffer)?;
+            self.buffer.clear();
+            self.pending = 0;
+        }
+        self.writer.flush()
+    }
+}
+
+/// A real number as an SQL literal, with numbers that aren't finite as
+/// `NULL`, which SQLite has no literal for, and without the sign of a
+/// negative zero.
+fn real(x: f64) -> String {
+    if x.is_finite() {
+        (x + 0.0).to_string()
+    } else {
+        "NULL".to_string()
+    }
+}
+
+/// A nullable value as an SQL literal.
+fn nullable(value: Option<impl ToString>) -> String {
+    value.map_or("NULL".to_string(), |v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EventMessage, EventMessageType, QueueState, Time};
+
+    #[test]
+    fn test_sql_script() {
+        // A single item arriving at 0 for a service of 5 makes four events,
+        // written in two transactions, one item, and a summary.
+        let mut script = SqlScript::new(vec![], 7, 2);
+        script.write_schema().unwrap();
+        let mut sim = crate::simulation::Simulation::new(QueueState::new(10, 1, 5));
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(0),
+        });
+        for e in sim.run_until(Time(10)).log.contents.clone() {
+            script.write_event(&e).unwrap();
+        }
+        script.write_items(&sim.log).unwrap();
+        script.write_summary(&sim.summary()).unwrap();
+        let sql = String::from_utf8(script.into_inner().unwrap()).unwrap();
+        assert!(sql.starts_with(SCHEMA));
+        assert_eq!(3, sql.matches("BEGIN;").count());
+        assert!(sql.contains(
+            "INSERT INTO events VALUES (7, 0, 'BufferIncremented', NULL, NULL, NULL, 0, 0, NULL, 1, NULL);"
+        ));
+        assert!(sql.contains("INSERT INTO items VALUES (7, 0, 5, 0, 5, 5);"));
+        assert!(
+            sql.contains("INSERT INTO summaries VALUES (7, 5, 1, 0, 1, 0, 5, 5, 1, 1, 0.2, 0);")
+        );
+    }
+}
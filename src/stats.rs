@@ -0,0 +1,2274 @@
+//! Statistics of a run.
+//!
+//! Time averages integrate a count over time, i.e., take the area under its
+//! sample path, and divide by the length of the run. The sample paths of the
+//! queue length and the number of busy servers can be recorded live by a
+//! `Simulation`, or worked out after the fact from the event log. Waiting
+//! and sojourn times are worked out per item from the event log, as are the
+//! counts of what became of arrivals, over the run or over windows of it.
+//!
+//! There are three ways to put a confidence interval on a long-run mean
+//! from a single run: batch means, which split the run into long batches;
+//! regenerative analysis, which splits it into the cycles between the
+//! instants the system empties; and, across runs, independent replications.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::arrival::SourceId;
+use crate::item::ItemId;
+use crate::server::ServerId;
+use crate::{Event, EventLog, EventType, QueueState, Time};
+
+/// A count that changes over time, as the times it changed along with its
+/// value from then on. The count is 0 until the first change.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SamplePath {
+    points: Vec<(Time, u32)>,
+}
+
+impl SamplePath {
+    /// Create a path that's 0 throughout.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the value of the count at a time no earlier than the last one
+    /// recorded. A value recorded at the same time as the last one replaces
+    /// it.
+    pub fn record(&mut self, time: Time, value: u32) -> &mut Self {
+        if self.points.last().is_some_and(|&(t, _)| t == time) {
+            self.points.pop();
+        }
+        if self.points.last().map_or(0, |&(_, v)| v) != value {
+            self.points.push((time, value));
+        }
+        self
+    }
+
+    /// The times the count changed, along with its value from then on.
+    pub fn points(&self) -> &[(Time, u32)] {
+        &self.points
+    }
+
+    /// The value of the count at a time.
+    pub fn value_at(&self, time: Time) -> u32 {
+        let i = self.points.partition_point(|&(t, _)| t <= time);
+        i.checked_sub(1).map_or(0, |i| self.points[i].1)
+    }
+
+    /// The area under the path from 0 until the given time.
+    pub fn area(&self, until: Time) -> f64 {
+        self.points
+            .iter()
+            .enumerate()
+            .take_while(|(_, &(t, _))| t < until)
+            .map(|(i, &(t, value))| {
+                let end = self
+                    .points
+                    .get(i + 1)
+                    .map_or(until, |&(next, _)| next.min(until));
+                (end.0 - t.0) as f64 * value as f64
+            })
+            .sum()
+    }
+
+    /// The area under the path between two times.
+    pub fn area_between(&self, start: Time, end: Time) -> f64 {
+        self.area(end) - self.area(start)
+    }
+
+    /// The time average of the count from 0 until the given time, or 0 if
+    /// no time has passed.
+    pub fn time_average(&self, until: Time) -> f64 {
+        match until.0 {
+            0 => 0.0,
+            t => self.area(until) / t as f64,
+        }
+    }
+
+    /// The highest value of the count, along with when it was first reached,
+    /// or `None` if the count never rose above 0.
+    pub fn peak(&self) -> Option<(Time, u32)> {
+        self.points
+            .iter()
+            .copied()
+            .filter(|&(_, value)| value > 0)
+            .reduce(|peak, point| if point.1 > peak.1 { point } else { peak })
+    }
+
+    /// The total time from 0 until the given time that the count was at or
+    /// above a threshold.
+    pub fn time_at_or_above(&self, threshold: u32, until: Time) -> u32 {
+        self.points
+            .iter()
+            .enumerate()
+            .take_while(|(_, &(t, _))| t < until)
+            .filter(|(_, &(_, value))| value >= threshold)
+            .map(|(i, &(t, _))| {
+                let end = self
+                    .points
+                    .get(i + 1)
+                    .map_or(until, |&(next, _)| next.min(until));
+                end.0 - t.0
+            })
+            .sum()
+    }
+
+    /// The time averages of the count over consecutive windows of the given
+    /// width from 0, up to the last whole window before the end time.
+    pub fn averages(&self, width: u32, end: Time) -> Vec<f64> {
+        assert!(width > 0, "windows must have a positive width");
+        let area = self.cumulative_area();
+        (0..end.0 / width)
+            .map(|i| (area(Time((i + 1) * width)) - area(Time(i * width))) / width as f64)
+            .collect()
+    }
+
+    /// The area from 0 until any time, for taking many areas of a long path.
+    fn cumulative_area(&self) -> impl Fn(Time) -> f64 + '_ {
+        // The area up to each change, so that the area up to any time is a
+        // binary search away.
+        let mut cumulative = Vec::with_capacity(self.points.len());
+        let mut total = 0.0;
+        for (i, &(t, value)) in self.points.iter().enumerate() {
+            cumulative.push(total);
+            if let Some(&(next, _)) = self.points.get(i + 1) {
+                total += (next.0 - t.0) as f64 * value as f64;
+            }
+        }
+        move |time: Time| match self.points.partition_point(|&(t, _)| t <= time) {
+            0 => 0.0,
+            i => {
+                let (t, value) = self.points[i - 1];
+                cumulative[i - 1] + (time.0 - t.0) as f64 * value as f64
+            }
+        }
+    }
+}
+
+/// The sample autocorrelations of a series at lags 1 through `max_lag`, or
+/// fewer if the series is too short.
+///
+/// Queue lengths and waits of successive items are strongly correlated, so
+/// these show how far apart observations have to be to be roughly
+/// independent, e.g., whether batch means, or the observations from
+/// `SamplePath::averages` or `ItemTimes`, are spaced widely enough. A series
+/// that doesn't vary has no correlation to speak of, and gets all zeros.
+pub fn autocorrelation(series: &[f64], max_lag: usize) -> Vec<f64> {
+    let n = series.len();
+    let mean = series.iter().sum::<f64>() / n as f64;
+    let deviations = series.iter().map(|x| x - mean).collect::<Vec<_>>();
+    let variance = deviations.iter().map(|d| d * d).sum::<f64>();
+    (1..=max_lag.min(n.saturating_sub(1)))
+        .map(|lag| match variance {
+            0.0 => 0.0,
+            v => {
+                deviations
+                    .iter()
+                    .zip(&deviations[lag..])
+                    .map(|(a, b)| a * b)
+                    .sum::<f64>()
+                    / v
+            }
+        })
+        .collect()
+}
+
+/// The MSER-5 truncation point of a series of observations, i.e., the
+/// number of observations at the start to drop as the initial transient.
+///
+/// The observations are averaged in batches of 5, and the truncation is the
+/// number of batches, d, that minimizes the marginal standard error rule,
+/// the sum of squared deviations of the remaining batch averages over the
+/// square of their count. Only truncating up to half of the batches is
+/// considered, since a minimum past that is a sign the run is too short.
+pub fn mser5(observations: &[f64]) -> usize {
+    let batches = observations
+        .chunks_exact(5)
+        .map(|batch| batch.iter().sum::<f64>() / 5.0)
+        .collect::<Vec<_>>();
+    // Work back from the end, keeping running sums of the batch averages and
+    // their squares, to get each statistic in constant time.
+    let n = batches.len();
+    let (mut sum, mut sum_squares) = (0.0, 0.0);
+    let mut best = (f64::INFINITY, 0);
+    for d in (0..n).rev() {
+        sum += batches[d];
+        sum_squares += batches[d] * batches[d];
+        let m = (n - d) as f64;
+        let mser = (sum_squares - sum * sum / m).max(0.0) / (m * m);
+        if d < n.div_ceil(2) && mser <= best.0 {
+            best = (mser, d);
+        }
+    }
+    5 * best.1
+}
+
+/// A summary of a run from 0 until its end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SummaryStats {
+    /// When the run ended.
+    pub end: Time,
+    /// The time-average number of busy servers.
+    pub mean_busy_servers: f64,
+    /// The time-average server capacity, which can vary over a run with
+    /// shifts or capacity changes, or `None` if the server pool is unbounded.
+    pub mean_capacity: Option<f64>,
+    /// The fraction of capacity-time that servers were busy, or 0 if there
+    /// was no capacity, or `None` if the server pool is unbounded, since
+    /// there's no capacity to be a fraction of.
+    pub utilization: Option<f64>,
+    /// The time-average number of buffered items, Lq.
+    pub mean_queue_length: f64,
+    /// The time-average number in the system, L.
+    pub mean_number_in_system: f64,
+    /// The most items ever buffered, and when that was first reached.
+    pub peak_queue_length: Option<(Time, u32)>,
+    /// The most items ever in the system, and when that was first reached.
+    pub peak_number_in_system: Option<(Time, u32)>,
+    /// The waiting times of items that finished service, if any did.
+    pub wait: Option<TimeStats>,
+    /// The rest of the sojourn times of items that finished service, i.e.,
+    /// the time they spent in service, if any did.
+    pub service: Option<TimeStats>,
+    /// The sojourn times of items that finished service, if any did.
+    pub sojourn: Option<TimeStats>,
+    /// The times between successive departures, if at least two items
+    /// finished service.
+    pub interdeparture: Option<TimeStats>,
+    /// What became of arrivals over the whole run.
+    pub flow: Flow,
+    /// The fraction of items that finished service within the target wait,
+    /// if a target is set and any item finished service.
+    pub service_level: Option<ServiceLevel>,
+}
+
+impl SummaryStats {
+    /// Summarize the sample paths of a run, along with the path of its
+    /// server capacity, unless its pool is unbounded, and its event log, from
+    /// 0 until the given time, with the service level against a target wait,
+    /// if any.
+    pub fn new(
+        occupancy: &Occupancy,
+        capacity: Option<&SamplePath>,
+        log: &EventLog,
+        end: Time,
+        service_target: Option<u32>,
+    ) -> Self {
+        let times = ItemTimes::from_log(log);
+        Self {
+            end,
+            mean_busy_servers: occupancy.busy.time_average(end),
+            mean_capacity: capacity.map(|capacity| capacity.time_average(end)),
+            utilization: capacity.map(|capacity| match capacity.area(end) {
+                0.0 => 0.0,
+                c => occupancy.busy.area(end) / c,
+            }),
+            mean_queue_length: occupancy.mean_queue_length(end),
+            mean_number_in_system: occupancy.mean_number_in_system(end),
+            peak_queue_length: occupancy.buffered.peak(),
+            peak_number_in_system: occupancy.in_system.peak(),
+            wait: TimeStats::new(&times.waits),
+            service: TimeStats::new(&times.services()),
+            sojourn: TimeStats::new(&times.sojourns),
+            interdeparture: TimeStats::new(
+                &times
+                    .departures
+                    .windows(2)
+                    .map(|pair| pair[1].0 - pair[0].0)
+                    .collect::<Vec<_>>(),
+            ),
+            flow: Flow::over(log, Time(0), end),
+            service_level: service_target
+                .and_then(|target| ServiceLevel::new(target, &times.waits)),
+        }
+    }
+
+    /// Summarize each class of items, i.e., the items from each source, with
+    /// those from no source under `None`, in order of class. Each summary
+    /// only counts the events of its class, so, e.g., its utilization is the
+    /// class's share of the capacity-time, while the capacity is shared.
+    pub fn by_class(
+        capacity: Option<&SamplePath>,
+        log: &EventLog,
+        end: Time,
+        service_target: Option<u32>,
+    ) -> Vec<(Option<SourceId>, Self)> {
+        let mut classes = log
+            .contents
+            .iter()
+            .filter(|e| {
+                e.item.is_some()
+                    || matches!(
+                        e.event_type,
+                        EventType::Dropped
+                            | EventType::RetryAbandoned
+                            | EventType::Balked
+                            | EventType::Throttled
+                    )
+            })
+            .map(|e| e.source)
+            .collect::<Vec<_>>();
+        classes.sort_by_key(|class| class.map(|id| id.0));
+        classes.dedup();
+        classes
+            .into_iter()
+            .map(|class| {
+                let mut class_log = EventLog::new();
+                for e in log.contents.iter().filter(|e| e.source == class) {
+                    class_log.push(*e);
+                }
+                let occupancy = Occupancy::from_log(&class_log);
+                let summary = Self::new(&occupancy, capacity, &class_log, end, service_target);
+                (class, summary)
+            })
+            .collect()
+    }
+
+    /// The three sides of Little's law for the run, or `None` if no item
+    /// finished service.
+    pub fn littles_law(&self) -> Option<LittlesLaw> {
+        Some(LittlesLaw {
+            l: self.mean_number_in_system,
+            lambda: self.flow.rate(self.flow.admitted()),
+            w: self.sojourn?.mean,
+        })
+    }
+}
+
+/// The time-average number in the system, L, the effective arrival rate, λ,
+/// and the mean sojourn time, W, of a run, which should satisfy L = λW.
+///
+/// The law holds exactly for a run that starts and ends empty. Items still
+/// in the system at the end, or that reneged, which don't count toward W,
+/// open up a gap, so a large discrepancy on a drained run points to a
+/// bookkeeping bug.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LittlesLaw {
+    pub l: f64,
+    pub lambda: f64,
+    pub w: f64,
+}
+
+impl LittlesLaw {
+    /// The relative discrepancy |L - λW| / L, or 0 if both sides are 0.
+    pub fn discrepancy(&self) -> f64 {
+        let gap = (self.l - self.lambda * self.w).abs();
+        if gap == 0.0 {
+            0.0
+        } else {
+            gap / self.l
+        }
+    }
+}
+
+/// The fraction of items that finished service whose wait was at most a
+/// target, e.g., 80% within 20.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServiceLevel {
+    pub target: u32,
+    pub attained: f64,
+}
+
+impl ServiceLevel {
+    /// Work out the service level from the waits of items that finished
+    /// service, or return `None` if there are none.
+    pub fn new(target: u32, waits: &[u32]) -> Option<Self> {
+        let within = waits.iter().filter(|&&wait| wait <= target).count();
+        (!waits.is_empty()).then(|| Self {
+            target,
+            attained: within as f64 / waits.len() as f64,
+        })
+    }
+}
+
+/// Summary statistics of a sample of times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeStats {
+    pub count: usize,
+    pub mean: f64,
+    /// The sample variance, or 0 for a single time.
+    pub variance: f64,
+    pub min: u32,
+    pub max: u32,
+    pub p50: u32,
+    pub p90: u32,
+    pub p99: u32,
+}
+
+impl TimeStats {
+    /// Summarize a sample of times, or return `None` if it's empty.
+    pub fn new(times: &[u32]) -> Option<Self> {
+        let mut sorted = times.to_vec();
+        sorted.sort_unstable();
+        let (&min, &max) = (sorted.first()?, sorted.last()?);
+        let n = sorted.len();
+        let mean = sorted.iter().map(|&t| t as f64).sum::<f64>() / n as f64;
+        let variance = match n {
+            1 => 0.0,
+            _ => {
+                sorted
+                    .iter()
+                    .map(|&t| (t as f64 - mean).powi(2))
+                    .sum::<f64>()
+                    / (n - 1) as f64
+            }
+        };
+        // Percentiles go by the nearest rank.
+        let percentile = |q: f64| sorted[((q * n as f64).ceil() as usize).clamp(1, n) - 1];
+        Some(Self {
+            count: n,
+            mean,
+            variance,
+            min,
+            max,
+            p50: percentile(0.5),
+            p90: percentile(0.9),
+            p99: percentile(0.99),
+        })
+    }
+
+    /// The squared coefficient of variation, i.e., the variance over the
+    /// square of the mean, or 0 if the mean is 0. It's 1 for exponential
+    /// times and 0 for constant ones.
+    pub fn scv(&self) -> f64 {
+        match self.mean {
+            0.0 => 0.0,
+            mean => self.variance / (mean * mean),
+        }
+    }
+}
+
+/// The waiting and sojourn times of items that finished service, in order
+/// of departure.
+///
+/// An item's wait is the total time it spent in the buffer, over every pass
+/// if it was fed back, and its sojourn is the time from when it first joined
+/// the buffer until it left for good. Items that reneged, were evicted, or
+/// jockeyed away aren't counted.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ItemTimes {
+    pub items: Vec<ItemId>,
+    pub departures: Vec<Time>,
+    pub waits: Vec<u32>,
+    pub sojourns: Vec<u32>,
+}
+
+impl ItemTimes {
+    /// Work out the times from the item events in a log.
+    pub fn from_log(log: &EventLog) -> Self {
+        let mut tracker = ItemTracker::new();
+        let mut times = Self::default();
+        let mut record = |departures: Vec<Departure>| {
+            for d in departures {
+                times.items.push(d.item);
+                times.departures.push(d.time);
+                times.waits.push(d.wait);
+                times.sojourns.push(d.sojourn);
+            }
+        };
+        for (i, e) in log.contents.iter().enumerate() {
+            // Items only leave for good once every event of the step that
+            // served them has been seen.
+            if i > 0 && log.contents[i - 1].time < e.time {
+                record(tracker.flush());
+            }
+            tracker.observe(e);
+        }
+        record(tracker.flush());
+        times
+    }
+
+    /// The time each item spent outside the buffer, i.e., its sojourn less
+    /// its wait, which is its service time over every pass.
+    pub fn services(&self) -> Vec<u32> {
+        self.sojourns
+            .iter()
+            .zip(&self.waits)
+            .map(|(sojourn, wait)| sojourn - wait)
+            .collect()
+    }
+}
+
+/// An item that finished service and left, with its wait and sojourn time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Departure {
+    pub item: ItemId,
+    pub time: Time,
+    pub wait: u32,
+    pub sojourn: u32,
+}
+
+/// Follows items through the events of a run, one event at a time, to work
+/// out their wait and sojourn times as they leave. Items are forgotten once
+/// they leave, so the memory it holds scales with the number in the system
+/// rather than the length of the run.
+#[derive(Debug, Clone, Default)]
+pub struct ItemTracker {
+    joined: HashMap<ItemId, Time>,
+    buffered_since: HashMap<ItemId, Time>,
+    waited: HashMap<ItemId, u32>,
+    /// Items entering service together are logged before the server event,
+    /// which is tagged with the first of them.
+    starting: Vec<ItemId>,
+    batches: HashMap<ItemId, Vec<ItemId>>,
+    /// Items that finished service in the current step, which may yet be fed
+    /// back.
+    leaving: Vec<(ItemId, Time)>,
+}
+
+impl ItemTracker {
+    /// Create a tracker that hasn't seen any items.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Follow an event.
+    pub fn observe(&mut self, e: &Event) -> &mut Self {
+        let Some(id) = e.item else { return self };
+        match e.event_type {
+            EventType::BufferIncremented | EventType::Recalled | EventType::FedBack(_) => {
+                self.joined.entry(id).or_insert(e.time);
+                self.buffered_since.insert(id, e.time);
+                self.leaving.retain(|&(item, _)| item != id);
+            }
+            EventType::BufferDecremented | EventType::CallbackQueued => {
+                if let Some(since) = self.buffered_since.remove(&id) {
+                    *self.waited.entry(id).or_default() += e.time.0 - since.0;
+                }
+                if e.event_type == EventType::BufferDecremented {
+                    self.starting.push(id);
+                }
+            }
+            EventType::ServerIncremented if !self.starting.is_empty() => {
+                self.batches.insert(id, std::mem::take(&mut self.starting));
+            }
+            EventType::ServerDecremented => {
+                for item in self.batches.remove(&id).unwrap_or_else(|| vec![id]) {
+                    self.leaving.push((item, e.time));
+                }
+            }
+            EventType::Reneged | EventType::Evicted | EventType::Jockeyed(_) => {
+                self.joined.remove(&id);
+                self.buffered_since.remove(&id);
+                self.waited.remove(&id);
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Whether an item has joined the buffer and not left yet.
+    pub fn is_tracking(&self, item: ItemId) -> bool {
+        self.joined.contains_key(&item)
+    }
+
+    /// The items that left in the step whose events were just observed, in
+    /// order of ID.
+    pub fn flush(&mut self) -> Vec<Departure> {
+        let mut leaving = std::mem::take(&mut self.leaving);
+        leaving.sort_unstable();
+        leaving
+            .into_iter()
+            .filter_map(|(item, time)| {
+                let joined = self.joined.remove(&item)?;
+                Some(Departure {
+                    item,
+                    time,
+                    wait: self.waited.remove(&item).unwrap_or(0),
+                    sojourn: time.0 - joined.0,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The completed busy periods of a run, when there was anything in the
+/// system, and idle periods of its servers, between the end of one service
+/// and the start of the next. A period still going on at the end of the run
+/// isn't counted, and neither is a server's wait for its first item.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Periods {
+    pub busy: Vec<(Time, Time)>,
+    pub idle: Vec<(ServerId, Time, Time)>,
+}
+
+impl Periods {
+    /// Find the busy periods in the sample paths of a run, and the idle
+    /// periods in its log.
+    pub fn new(occupancy: &Occupancy, log: &EventLog) -> Self {
+        let mut busy = vec![];
+        let mut started = None;
+        for &(time, value) in occupancy.in_system.points() {
+            match (started, value) {
+                (None, 1..) => started = Some(time),
+                (Some(start), 0) => {
+                    busy.push((start, time));
+                    started = None;
+                }
+                _ => {}
+            }
+        }
+        let mut idle = vec![];
+        let mut freed = HashMap::new();
+        for e in &log.contents {
+            match (e.event_type, e.server) {
+                (EventType::ServerDecremented, Some(server)) => {
+                    freed.insert(server, e.time);
+                }
+                (EventType::ServerIncremented, Some(server)) => {
+                    if let Some(end) = freed.remove(&server).filter(|&end| end < e.time) {
+                        idle.push((server, end, e.time));
+                    }
+                }
+                _ => {}
+            }
+        }
+        Self { busy, idle }
+    }
+
+    /// Summary statistics of the lengths of the busy periods.
+    pub fn busy_stats(&self) -> Option<TimeStats> {
+        let lengths = self.busy.iter().map(|(start, end)| end.0 - start.0);
+        TimeStats::new(&lengths.collect::<Vec<_>>())
+    }
+
+    /// Summary statistics of the lengths of the idle periods, across
+    /// servers.
+    pub fn idle_stats(&self) -> Option<TimeStats> {
+        let lengths = self.idle.iter().map(|(_, start, end)| end.0 - start.0);
+        TimeStats::new(&lengths.collect::<Vec<_>>())
+    }
+}
+
+/// The workload of one server over a run: how long it spent serving, and
+/// how many items it finished. Services cut short by an interrupt count
+/// toward the busy time, but their items don't count as served.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ServerLoad {
+    pub server: ServerId,
+    pub busy_time: u64,
+    pub served: u64,
+    pub end: Time,
+}
+
+impl ServerLoad {
+    /// The workloads of the given number of servers in a log of a run up to
+    /// `end`, by server ID. A service still going on at the end counts up
+    /// to the end.
+    pub fn from_log(log: &EventLog, servers: usize, end: Time) -> Vec<Self> {
+        let mut loads = (0..servers)
+            .map(|i| Self {
+                server: ServerId(i as u32),
+                busy_time: 0,
+                served: 0,
+                end,
+            })
+            .collect::<Vec<_>>();
+        // The items taken from the buffer for the next service, and the start
+        // and batch size of each server's current service.
+        let mut taken = (Time(0), 0);
+        let mut serving = HashMap::new();
+        for e in &log.contents {
+            match (e.event_type, e.server) {
+                (EventType::BufferDecremented, _) => {
+                    taken = (e.time, if taken.0 == e.time { taken.1 + 1 } else { 1 });
+                }
+                (EventType::ServerIncremented, Some(server)) => {
+                    let batch = if taken.0 == e.time { taken.1.max(1) } else { 1 };
+                    serving.insert(server, (e.time, batch));
+                    taken.1 = 0;
+                }
+                (EventType::ServerDecremented, Some(server)) => {
+                    let Some((start, batch)) = serving.remove(&server) else {
+                        continue;
+                    };
+                    if let Some(load) = loads.get_mut(server.0 as usize) {
+                        load.busy_time += (e.time.0 - start.0) as u64;
+                        if e.item.is_some() {
+                            load.served += batch;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (server, (start, _)) in serving {
+            if let Some(load) = loads.get_mut(server.0 as usize) {
+                load.busy_time += end.0.saturating_sub(start.0) as u64;
+            }
+        }
+        loads
+    }
+
+    /// The fraction of the run the server spent serving.
+    pub fn busy_fraction(&self) -> f64 {
+        if self.end.0 == 0 {
+            0.0
+        } else {
+            self.busy_time as f64 / self.end.0 as f64
+        }
+    }
+}
+
+/// A streaming histogram with equal-width bins, plus counts of values below
+/// and above its range.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    pub start: f64,
+    pub width: f64,
+    pub counts: Vec<u64>,
+    pub below: u64,
+    pub above: u64,
+    pub count: u64,
+}
+
+impl Histogram {
+    /// Create an empty histogram with the given number of bins of the given
+    /// width, starting from the given value.
+    pub fn new(start: f64, width: f64, bins: usize) -> Self {
+        assert!(width > 0.0 && bins > 0, "bins must have a positive width");
+        Self {
+            start,
+            width,
+            counts: vec![0; bins],
+            below: 0,
+            above: 0,
+            count: 0,
+        }
+    }
+
+    /// The end of the histogram's range.
+    pub fn end(&self) -> f64 {
+        self.start + self.width * self.counts.len() as f64
+    }
+
+    /// Add a value to the histogram.
+    pub fn record(&mut self, value: f64) -> &mut Self {
+        self.count += 1;
+        if value < self.start {
+            self.below += 1;
+        } else if value >= self.end() {
+            self.above += 1;
+        } else {
+            let last = self.counts.len() - 1;
+            let bin = ((value - self.start) / self.width) as usize;
+            self.counts[bin.min(last)] += 1;
+        }
+        self
+    }
+
+    /// An estimate of the given quantile (between 0 and 1), interpolating
+    /// within the bin that contains it, or `None` if the histogram is empty.
+    /// Quantiles outside the range are clamped to its ends.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let target = q.clamp(0.0, 1.0) * self.count as f64;
+        let mut seen = self.below as f64;
+        if target <= seen {
+            return Some(self.start);
+        }
+        for (i, &n) in self.counts.iter().enumerate() {
+            if n > 0 && target <= seen + n as f64 {
+                let fraction = (target - seen) / n as f64;
+                return Some(self.start + (i as f64 + fraction) * self.width);
+            }
+            seen += n as f64;
+        }
+        Some(self.end())
+    }
+}
+
+/// A t-digest, which sketches a distribution with a bounded number of
+/// weighted centroids, small near the tails and large in the middle, so that
+/// tail quantiles stay accurate without keeping every value.
+///
+/// Values are buffered and merged into the centroids in bulk. The
+/// compression bounds the number of centroids, at around twice its value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TDigest {
+    compression: f64,
+    /// Centroid means and weights, in order of mean.
+    centroids: Vec<(f64, u64)>,
+    buffer: Vec<f64>,
+    pub count: u64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl TDigest {
+    /// Create an empty digest with the given compression, e.g., 100.
+    pub fn new(compression: f64) -> Self {
+        assert!(compression >= 1.0, "the compression must be at least 1");
+        Self {
+            compression,
+            centroids: vec![],
+            buffer: vec![],
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a value to the digest.
+    pub fn record(&mut self, value: f64) -> &mut Self {
+        self.buffer.push(value);
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        if self.buffer.len() as f64 >= 5.0 * self.compression {
+            self.compress();
+        }
+        self
+    }
+
+    /// The number of centroids after merging any buffered values.
+    pub fn size(&self) -> usize {
+        let mut digest = self.clone();
+        digest.compress();
+        digest.centroids.len()
+    }
+
+    /// Merge the buffered values into the centroids.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.extend(self.buffer.drain(..).map(|value| (value, 1)));
+        all.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+        // The k1 scale function, under which a centroid can span at most a
+        // unit of k.
+        let n = self.count as f64;
+        let k = |q: f64| self.compression / (2.0 * std::f64::consts::PI) * (2.0 * q - 1.0).asin();
+        let mut merged: Vec<(f64, u64)> = Vec::with_capacity(all.len());
+        let mut left = 0.0;
+        let mut k_left = k(0.0);
+        for (mean, weight) in all {
+            match merged.last_mut() {
+                Some(last) if k((left + (last.1 + weight) as f64) / n) - k_left <= 1.0 => {
+                    let total = last.1 + weight;
+                    last.0 += (mean - last.0) * weight as f64 / total as f64;
+                    last.1 = total;
+                }
+                _ => {
+                    if let Some(last) = merged.last() {
+                        left += last.1 as f64;
+                        k_left = k(left / n);
+                    }
+                    merged.push((mean, weight));
+                }
+            }
+        }
+        self.centroids = merged;
+    }
+
+    /// An estimate of the given quantile (between 0 and 1), interpolating
+    /// between centroid means, or `None` if the digest is empty.
+    pub fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let mut digest = self.clone();
+        digest.compress();
+        let centroids = &digest.centroids;
+        let target = q.clamp(0.0, 1.0) * self.count as f64;
+        // Each centroid's weight is centered on its mean, and the extremes
+        // anchor the ends.
+        let mut previous = (0.0, self.min);
+        let mut seen = 0.0;
+        for &(mean, weight) in centroids {
+            let center = seen + weight as f64 / 2.0;
+            if target < center {
+                let fraction = (target - previous.0) / (center - previous.0);
+                return Some(previous.1 + fraction * (mean - previous.1));
+            }
+            previous = (center, mean);
+            seen += weight as f64;
+        }
+        let fraction = (target - previous.0) / (seen - previous.0).max(f64::MIN_POSITIVE);
+        Some(previous.1 + fraction.min(1.0) * (self.max - previous.1))
+    }
+}
+
+/// Streaming sketches of the waiting and sojourn times of items as they
+/// leave, for runs too long to keep every time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeSketches {
+    pub wait_histogram: Histogram,
+    pub sojourn_histogram: Histogram,
+    pub wait_digest: TDigest,
+    pub sojourn_digest: TDigest,
+}
+
+impl TimeSketches {
+    /// Create empty sketches, with histograms binned like the given one and
+    /// digests with the given compression.
+    pub fn new(histogram: Histogram, compression: f64) -> Self {
+        Self {
+            wait_histogram: histogram.clone(),
+            sojourn_histogram: histogram,
+            wait_digest: TDigest::new(compression),
+            sojourn_digest: TDigest::new(compression),
+        }
+    }
+
+    /// Add the times of an item that left.
+    pub fn record(&mut self, departure: &Departure) -> &mut Self {
+        self.wait_histogram.record(departure.wait as f64);
+        self.sojourn_histogram.record(departure.sojourn as f64);
+        self.wait_digest.record(departure.wait as f64);
+        self.sojourn_digest.record(departure.sojourn as f64);
+        self
+    }
+}
+
+/// A metric to average over the batches of a run:
+/// - `QueueLength`: The time-average number of buffered items.
+/// - `NumberInSystem`: The time-average number in the system.
+/// - `BusyServers`: The time-average number of busy servers.
+/// - `Throughput`: Departures per unit time.
+/// - `Wait`: The mean wait of the items that left in the batch.
+/// - `Sojourn`: The mean sojourn time of the items that left in the batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BatchMetric {
+    QueueLength,
+    NumberInSystem,
+    BusyServers,
+    Throughput,
+    Wait,
+    Sojourn,
+}
+
+/// The method of batch means for a single long run: after a warm-up
+/// period, the rest of the run is split into batches of equal length, and
+/// the batch averages of a metric are treated as a sample of roughly
+/// independent observations. Batches have to be long compared to how long
+/// the queue remembers its state for that to hold.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchMeans {
+    pub warm_up: Time,
+    pub end: Time,
+    pub batches: u32,
+}
+
+impl BatchMeans {
+    /// Split the run from the end of the warm-up until the end time into the
+    /// given number of batches. Any time left over from rounding the batch
+    /// length down is dropped from the end.
+    pub fn new(warm_up: Time, end: Time, batches: u32) -> Self {
+        assert!(
+            batches > 0 && end.0.saturating_sub(warm_up.0) >= batches,
+            "every batch must have a positive length"
+        );
+        Self {
+            warm_up,
+            end,
+            batches,
+        }
+    }
+
+    /// Like `new`, but start the batches at the end of the warm-up period
+    /// that MSER-5 finds in the sample paths.
+    pub fn after_warm_up(occupancy: &Occupancy, end: Time, batches: u32) -> Self {
+        Self::new(occupancy.warm_up(end), end, batches)
+    }
+
+    /// The start and end of each batch.
+    pub fn bounds(&self) -> Vec<(Time, Time)> {
+        let length = (self.end.0 - self.warm_up.0) / self.batches;
+        (0..self.batches)
+            .map(|i| {
+                let start = self.warm_up.0 + i * length;
+                (Time(start), Time(start + length))
+            })
+            .collect()
+    }
+
+    /// The average of a metric over each batch. Batches in which no item
+    /// left are left out of waits and sojourn times.
+    pub fn means(&self, occupancy: &Occupancy, log: &EventLog, metric: BatchMetric) -> Vec<f64> {
+        let times = ItemTimes::from_log(log);
+        self.bounds()
+            .into_iter()
+            .filter_map(|(start, end)| {
+                let length = (end.0 - start.0) as f64;
+                let average = |path: &SamplePath| path.area_between(start, end) / length;
+                let left = || {
+                    times
+                        .departures
+                        .iter()
+                        .enumerate()
+                        .filter(move |(_, &t)| start <= t && t < end)
+                        .map(|(i, _)| i)
+                };
+                let mean = |values: &[u32]| {
+                    let (sum, n) =
+                        left().fold((0.0, 0), |(sum, n), i| (sum + values[i] as f64, n + 1));
+                    (n > 0).then(|| sum / n as f64)
+                };
+                match metric {
+                    BatchMetric::QueueLength => Some(average(&occupancy.buffered)),
+                    BatchMetric::NumberInSystem => Some(average(&occupancy.in_system)),
+                    BatchMetric::BusyServers => Some(average(&occupancy.busy)),
+                    BatchMetric::Throughput => Some(left().count() as f64 / length),
+                    BatchMetric::Wait => mean(&times.waits),
+                    BatchMetric::Sojourn => mean(&times.sojourns),
+                }
+            })
+            .collect()
+    }
+
+    /// The lag-1 autocorrelation of the batch averages of a metric, or
+    /// `None` with fewer than two batches. Values well above 0 mean the
+    /// batches are too short to treat as independent.
+    pub fn lag1(&self, occupancy: &Occupancy, log: &EventLog, metric: BatchMetric) -> Option<f64> {
+        autocorrelation(&self.means(occupancy, log, metric), 1)
+            .first()
+            .copied()
+    }
+
+    /// The confidence interval for the mean of a metric from its batch
+    /// averages, at the given confidence level.
+    pub fn interval(
+        &self,
+        occupancy: &Occupancy,
+        log: &EventLog,
+        metric: BatchMetric,
+        level: f64,
+    ) -> Option<Interval> {
+        Interval::new(&self.means(occupancy, log, metric), level)
+    }
+}
+
+/// A t-based confidence interval for the mean of a sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    /// The number of observations.
+    pub n: usize,
+    pub mean: f64,
+    /// The sample standard deviation.
+    pub std_dev: f64,
+    /// The half-width of the interval around the mean.
+    pub half_width: f64,
+    /// The confidence level, e.g., 0.95.
+    pub level: f64,
+}
+
+impl Interval {
+    /// Work out the interval for the mean of a sample at a confidence level
+    /// between 0 and 1, or return `None` if there are fewer than two
+    /// observations.
+    pub fn new(sample: &[f64], level: f64) -> Option<Self> {
+        assert!(
+            0.0 < level && level < 1.0,
+            "the confidence level must be between 0 and 1"
+        );
+        let n = sample.len();
+        if n < 2 {
+            return None;
+        }
+        let mean = sample.iter().sum::<f64>() / n as f64;
+        let variance = sample.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+        let std_dev = variance.sqrt();
+        let t = t_quantile((1.0 + level) / 2.0, (n - 1) as f64);
+        Some(Self {
+            n,
+            mean,
+            std_dev,
+            half_width: t * std_dev / (n as f64).sqrt(),
+            level,
+        })
+    }
+
+    /// The lower end of the interval.
+    pub fn lower(&self) -> f64 {
+        self.mean - self.half_width
+    }
+
+    /// The upper end of the interval.
+    pub fn upper(&self) -> f64 {
+        self.mean + self.half_width
+    }
+
+    /// The half-width relative to the size of the mean, or infinity if the
+    /// mean is 0.
+    pub fn relative_half_width(&self) -> f64 {
+        match self.mean.abs() {
+            0.0 => f64::INFINITY,
+            size => self.half_width / size,
+        }
+    }
+
+    /// Work out the interval for a ratio of means, e.g., of the area under a
+    /// path over regeneration cycles to their lengths, from pairs of
+    /// numerators and denominators, or return `None` if there are fewer than
+    /// two pairs or the denominators are all 0.
+    ///
+    /// The mean is the ratio estimate `r = sum(y) / sum(x)`, and the standard
+    /// deviation is that of the residuals `y - r * x` over the mean of `x`.
+    pub fn ratio(pairs: &[(f64, f64)], level: f64) -> Option<Self> {
+        assert!(
+            0.0 < level && level < 1.0,
+            "the confidence level must be between 0 and 1"
+        );
+        let n = pairs.len();
+        let (y, x) = pairs
+            .iter()
+            .fold((0.0, 0.0), |(y, x), &(dy, dx)| (y + dy, x + dx));
+        if n < 2 || x == 0.0 {
+            return None;
+        }
+        let mean = y / x;
+        let variance = pairs
+            .iter()
+            .map(|&(y, x)| (y - mean * x).powi(2))
+            .sum::<f64>()
+            / (n - 1) as f64;
+        let std_dev = variance.sqrt() / (x / n as f64);
+        let t = t_quantile((1.0 + level) / 2.0, (n - 1) as f64);
+        Some(Self {
+            n,
+            mean,
+            std_dev,
+            half_width: t * std_dev / (n as f64).sqrt(),
+            level,
+        })
+    }
+}
+
+/// A target precision for an estimate: a confidence interval at the given
+/// level whose half-width is at most `target` relative to its mean, e.g.,
+/// 0.05 for within 5%.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Precision {
+    pub target: f64,
+    pub level: f64,
+}
+
+impl Precision {
+    /// Create a target precision.
+    pub fn new(target: f64, level: f64) -> Self {
+        assert!(target > 0.0, "the target precision must be positive");
+        Self { target, level }
+    }
+
+    /// Whether an interval is precise enough.
+    pub fn is_met(&self, interval: &Interval) -> bool {
+        interval.relative_half_width() <= self.target
+    }
+}
+
+/// The regeneration points of a run, the instants the system emptied, from
+/// which on it starts afresh.
+///
+/// The cycles between consecutive points are independent and identically
+/// distributed, at least when arrivals are Poisson, so a long-run mean is a
+/// ratio of means over cycles, e.g., the mean queue length is the mean area
+/// under the queue length per cycle over the mean cycle length. Unlike
+/// batch means, there's no batch size to choose and no warm-up to discard,
+/// but a heavily loaded system may empty too rarely to give many cycles.
+/// The partial cycles before the first point and after the last aren't
+/// counted.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Regeneration {
+    pub points: Vec<Time>,
+}
+
+impl Regeneration {
+    /// Find the instants the number in system dropped to 0.
+    pub fn new(occupancy: &Occupancy) -> Self {
+        let points = occupancy.in_system.points();
+        let points = points
+            .windows(2)
+            .filter(|w| w[0].1 > 0 && w[1].1 == 0)
+            .map(|w| w[1].0)
+            .collect();
+        Self { points }
+    }
+
+    /// The number of complete cycles.
+    pub fn cycles(&self) -> usize {
+        self.points.len().saturating_sub(1)
+    }
+
+    /// The confidence interval for the time average of a path, e.g., one of
+    /// an `Occupancy`, over the complete cycles.
+    pub fn time_average(&self, path: &SamplePath, level: f64) -> Option<Interval> {
+        let area = path.cumulative_area();
+        let pairs = self
+            .points
+            .windows(2)
+            .map(|w| (area(w[1]) - area(w[0]), (w[1].0 - w[0].0) as f64))
+            .collect::<Vec<_>>();
+        Interval::ratio(&pairs, level)
+    }
+
+    /// The confidence interval for the mean of a per-item time, e.g., the
+    /// waits of `ItemTimes`, over the items that left during the complete
+    /// cycles. An item leaving at a point belongs to the cycle it ends.
+    pub fn item_average(&self, times: &ItemTimes, values: &[u32], level: f64) -> Option<Interval> {
+        let mut pairs = vec![(0.0, 0.0); self.cycles()];
+        for (&time, &value) in times.departures.iter().zip(values) {
+            let i = self.points.partition_point(|&t| t < time);
+            if let Some(pair) = i.checked_sub(1).and_then(|i| pairs.get_mut(i)) {
+                pair.0 += value as f64;
+                pair.1 += 1.0;
+            }
+        }
+        Interval::ratio(&pairs, level)
+    }
+}
+
+/// The quantile of Student's t distribution with the given degrees of
+/// freedom at a probability of at least 1/2, found by bisecting the CDF.
+pub fn t_quantile(p: f64, df: f64) -> f64 {
+    let cdf = |t: f64| 1.0 - 0.5 * incomplete_beta(df / (df + t * t), df / 2.0, 0.5);
+    let (mut lo, mut hi) = (0.0, 1.0);
+    while cdf(hi) < p {
+        hi *= 2.0;
+    }
+    for _ in 0..100 {
+        let mid = (lo + hi) / 2.0;
+        if cdf(mid) < p {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    (lo + hi) / 2.0
+}
+
+/// The regularized incomplete beta function, I_x(a, b), by its continued
+/// fraction.
+fn incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 || x >= 1.0 {
+        return x.clamp(0.0, 1.0);
+    }
+    // The continued fraction converges fast below the mean, so use the
+    // symmetry I_x(a, b) = 1 - I_(1 - x)(b, a) above it.
+    if x > (a + 1.0) / (a + b + 2.0) {
+        return 1.0 - incomplete_beta(1.0 - x, b, a);
+    }
+    let front =
+        (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp() / a;
+    // Lentz's method.
+    let tiny = 1e-300;
+    let (mut c, mut d) = (1.0, 1.0 - (a + b) * x / (a + 1.0));
+    d = 1.0 / if d.abs() < tiny { tiny } else { d };
+    let mut f = d;
+    for m in 1..300 {
+        let m = m as f64;
+        for numerator in [
+            m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m)),
+            -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0)),
+        ] {
+            d = 1.0 + numerator * d;
+            d = 1.0 / if d.abs() < tiny { tiny } else { d };
+            c = 1.0 + numerator / c;
+            c = if c.abs() < tiny { tiny } else { c };
+            f *= c * d;
+        }
+        if (c * d - 1.0).abs() < 1e-15 {
+            break;
+        }
+    }
+    front * f
+}
+
+/// The log of the gamma function, by the Lanczos approximation.
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000000000190015, |sum, (i, c)| {
+            sum + c / (x + 1.0 + i as f64)
+        });
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+/// Counts of arrivals and what became of them over a window of time, from
+/// which rates per unit time and loss and abandonment probabilities follow.
+///
+/// An arrival is counted when its fate is known: when it's admitted to the
+/// buffer, or dropped, balks, or is throttled. An item that gave up retrying
+/// counts as dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Flow {
+    pub start: Time,
+    pub end: Time,
+    pub arrivals: u64,
+    pub departures: u64,
+    pub dropped: u64,
+    pub balked: u64,
+    pub throttled: u64,
+    pub reneged: u64,
+}
+
+/// What an event counts toward in a `Flow`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tally {
+    Admitted,
+    Departed,
+    Dropped,
+    Balked,
+    Throttled,
+    Reneged,
+}
+
+impl Flow {
+    /// Create a window with nothing counted.
+    pub fn new(start: Time, end: Time) -> Self {
+        Self {
+            start,
+            end,
+            arrivals: 0,
+            departures: 0,
+            dropped: 0,
+            balked: 0,
+            throttled: 0,
+            reneged: 0,
+        }
+    }
+
+    /// Count what happened in a log from the start time through the end
+    /// time.
+    pub fn over(log: &EventLog, start: Time, end: Time) -> Self {
+        tallies(log)
+            .into_iter()
+            .filter(|&(time, _)| start <= time && time <= end)
+            .fold(Self::new(start, end), |mut flow, (_, tally)| {
+                flow.add(tally);
+                flow
+            })
+    }
+
+    /// Split the run from 0 through the given end time into consecutive
+    /// windows of the given width, and count what happened in each. Each
+    /// window includes its start but not its end, except the last, which
+    /// ends with the run and may be shorter.
+    pub fn windows(log: &EventLog, width: u32, end: Time) -> Vec<Self> {
+        assert!(width > 0, "windows must have a positive width");
+        let n = end.0 / width + 1;
+        let mut windows = (0..n)
+            .map(|i| Self::new(Time(i * width), Time(((i + 1) * width).min(end.0))))
+            .collect::<Vec<_>>();
+        for (time, tally) in tallies(log) {
+            if time <= end {
+                windows[(time.0 / width) as usize].add(tally);
+            }
+        }
+        // An end on a window boundary leaves an empty last window.
+        if windows.len() > 1 && windows[windows.len() - 1].start == end {
+            let last = windows.pop().unwrap();
+            let previous = windows.last_mut().unwrap();
+            previous.merge(&last);
+        }
+        windows
+    }
+
+    fn add(&mut self, tally: Tally) {
+        match tally {
+            Tally::Admitted => {}
+            Tally::Departed => self.departures += 1,
+            Tally::Dropped => self.dropped += 1,
+            Tally::Balked => self.balked += 1,
+            Tally::Throttled => self.throttled += 1,
+            Tally::Reneged => self.reneged += 1,
+        }
+        if !matches!(tally, Tally::Departed | Tally::Reneged) {
+            self.arrivals += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.arrivals += other.arrivals;
+        self.departures += other.departures;
+        self.dropped += other.dropped;
+        self.balked += other.balked;
+        self.throttled += other.throttled;
+        self.reneged += other.reneged;
+    }
+
+    /// The length of the window.
+    pub fn duration(&self) -> u32 {
+        self.end.0 - self.start.0
+    }
+
+    /// Divide a count by the duration, or return 0 if the window is empty.
+    fn rate(&self, count: u64) -> f64 {
+        match self.duration() {
+            0 => 0.0,
+            d => count as f64 / d as f64,
+        }
+    }
+
+    /// Arrivals per unit time.
+    pub fn arrival_rate(&self) -> f64 {
+        self.rate(self.arrivals)
+    }
+
+    /// Departures per unit time, i.e., the effective throughput.
+    pub fn throughput(&self) -> f64 {
+        self.rate(self.departures)
+    }
+
+    /// Drops per unit time.
+    pub fn drop_rate(&self) -> f64 {
+        self.rate(self.dropped)
+    }
+
+    /// Balks per unit time.
+    pub fn balk_rate(&self) -> f64 {
+        self.rate(self.balked)
+    }
+
+    /// Reneges per unit time.
+    pub fn renege_rate(&self) -> f64 {
+        self.rate(self.reneged)
+    }
+
+    /// The number of arrivals that were admitted to the buffer.
+    pub fn admitted(&self) -> u64 {
+        self.arrivals - self.dropped - self.balked - self.throttled
+    }
+
+    /// The fraction of arrivals that were dropped, or 0 if there were none.
+    pub fn loss_probability(&self) -> f64 {
+        match self.arrivals {
+            0 => 0.0,
+            arrivals => self.dropped as f64 / arrivals as f64,
+        }
+    }
+
+    /// The fraction of admitted arrivals that reneged, or 0 if there were
+    /// none.
+    pub fn abandonment_probability(&self) -> f64 {
+        match self.admitted() {
+            0 => 0.0,
+            admitted => self.reneged as f64 / admitted as f64,
+        }
+    }
+}
+
+/// The times of the events in a log that count toward a `Flow`.
+fn tallies(log: &EventLog) -> Vec<(Time, Tally)> {
+    let mut admitted = HashSet::new();
+    let mut tallies = log
+        .contents
+        .iter()
+        .filter_map(|e| Some((e.time, tally(e, |id| admitted.insert(id))?)))
+        .collect::<Vec<_>>();
+    tallies.extend(
+        ItemTimes::from_log(log)
+            .departures
+            .into_iter()
+            .map(|time| (time, Tally::Departed)),
+    );
+    tallies
+}
+
+/// What an event counts toward in a `Flow`, other than a departure, given
+/// whether an item is new, i.e., hasn't been admitted before.
+fn tally(e: &Event, mut is_new: impl FnMut(ItemId) -> bool) -> Option<Tally> {
+    match (e.event_type, e.item) {
+        (EventType::BufferIncremented | EventType::Recalled, Some(id)) if is_new(id) => {
+            Some(Tally::Admitted)
+        }
+        (EventType::Dropped | EventType::RetryAbandoned, _) => Some(Tally::Dropped),
+        (EventType::Balked, _) => Some(Tally::Balked),
+        (EventType::Throttled, _) => Some(Tally::Throttled),
+        (EventType::Reneged, _) => Some(Tally::Reneged),
+        _ => None,
+    }
+}
+
+/// Welford's running mean and variance of a stream of values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Welford {
+    pub count: u64,
+    pub mean: f64,
+    /// The sum of squared deviations from the mean.
+    m2: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Welford {
+    /// Create an accumulator that hasn't seen any values.
+    pub fn new() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a value.
+    pub fn record(&mut self, value: f64) -> &mut Self {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self
+    }
+
+    /// The sample variance, or 0 for fewer than two values.
+    pub fn variance(&self) -> f64 {
+        match self.count {
+            0 | 1 => 0.0,
+            n => self.m2 / (n - 1) as f64,
+        }
+    }
+}
+
+impl Default for Welford {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The running integral of a count over time, for time averages without
+/// keeping the sample path.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TimeIntegral {
+    last: Time,
+    value: u32,
+    area: f64,
+}
+
+impl TimeIntegral {
+    /// Create an integral of a count that's 0 from time 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the value of the count at a time no earlier than the last one
+    /// recorded.
+    pub fn record(&mut self, time: Time, value: u32) -> &mut Self {
+        self.area = self.area(time);
+        self.last = time;
+        self.value = value;
+        self
+    }
+
+    /// The area under the count from 0 until a time no earlier than the
+    /// last one recorded.
+    pub fn area(&self, until: Time) -> f64 {
+        self.area + (until.0 - self.last.0) as f64 * self.value as f64
+    }
+
+    /// The time average of the count from 0 until the given time, or 0 if
+    /// no time has passed.
+    pub fn time_average(&self, until: Time) -> f64 {
+        match until.0 {
+            0 => 0.0,
+            t => self.area(until) / t as f64,
+        }
+    }
+}
+
+/// Statistics that are updated as a run goes, from each event and the state
+/// after each step, so they don't depend on the event log being kept. The
+/// memory they hold scales with the number in the system, not the length of
+/// the run.
+#[derive(Debug, Clone)]
+pub struct OnlineStats {
+    pub wait: Welford,
+    pub sojourn: Welford,
+    /// What became of arrivals, from 0 until the end of the last step.
+    pub flow: Flow,
+    pub buffered: TimeIntegral,
+    pub busy: TimeIntegral,
+    pub in_system: TimeIntegral,
+    /// The server capacity, or `None` if the server pool is unbounded.
+    pub capacity: Option<TimeIntegral>,
+    tracker: ItemTracker,
+}
+
+impl OnlineStats {
+    /// Create statistics of a run that hasn't started.
+    pub fn new() -> Self {
+        Self {
+            wait: Welford::new(),
+            sojourn: Welford::new(),
+            flow: Flow::new(Time(0), Time(0)),
+            buffered: TimeIntegral::new(),
+            busy: TimeIntegral::new(),
+            in_system: TimeIntegral::new(),
+            capacity: Some(TimeIntegral::new()),
+            tracker: ItemTracker::new(),
+        }
+    }
+
+    /// Count an event.
+    pub fn observe(&mut self, e: &Event) -> &mut Self {
+        let tracker = &self.tracker;
+        if let Some(tally) = tally(e, |id| !tracker.is_tracking(id)) {
+            self.flow.add(tally);
+        }
+        self.tracker.observe(e);
+        self
+    }
+
+    /// Wrap up a step, once all of its events have been observed, with the
+    /// state after it.
+    pub fn end_step(&mut self, state: &QueueState) -> &mut Self {
+        for departure in self.tracker.flush() {
+            self.flow.add(Tally::Departed);
+            self.wait.record(departure.wait as f64);
+            self.sojourn.record(departure.sojourn as f64);
+        }
+        let time = state.time;
+        self.flow.end = time;
+        self.buffered.record(time, state.buffer_count);
+        self.busy.record(time, state.server_count);
+        self.in_system
+            .record(time, state.buffer_count + state.server_count);
+        if state.has_infinite_servers() {
+            self.capacity = None;
+        }
+        if let Some(capacity) = self.capacity.as_mut() {
+            capacity.record(time, state.server_capacity);
+        }
+        self
+    }
+
+    /// The time-average number of buffered items, Lq, so far.
+    pub fn mean_queue_length(&self) -> f64 {
+        self.buffered.time_average(self.flow.end)
+    }
+
+    /// The time-average number in the system, L, so far.
+    pub fn mean_number_in_system(&self) -> f64 {
+        self.in_system.time_average(self.flow.end)
+    }
+
+    /// The fraction of capacity-time that servers were busy so far, or 0 if
+    /// there was no capacity, or `None` if the server pool is unbounded.
+    pub fn utilization(&self) -> Option<f64> {
+        let capacity = self.capacity.as_ref()?;
+        Some(match capacity.area(self.flow.end) {
+            0.0 => 0.0,
+            c => self.busy.area(self.flow.end) / c,
+        })
+    }
+}
+
+impl Default for OnlineStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The number of events of each type in consecutive time buckets, for a
+/// quick look at, e.g., whether departures keep up with arrivals. Types with
+/// a value, like `FedBack(2)`, are counted by variant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventCounts {
+    pub width: u32,
+    /// The event types, in order of first appearance.
+    pub types: Vec<&'static str>,
+    /// The start of each bucket, with the count of each type in it.
+    pub rows: Vec<(Time, Vec<u64>)>,
+}
+
+impl EventCounts {
+    /// Count the events in a log in buckets of the given width, from 0
+    /// through the last event.
+    pub fn from_log(log: &EventLog, width: u32) -> Self {
+        assert!(width > 0, "buckets must have a positive width");
+        let mut counts = Self {
+            width,
+            types: vec![],
+            rows: vec![],
+        };
+        for e in &log.contents {
+            let (name, _) = e.event_type.parts();
+            let column = match counts.types.iter().position(|&t| t == name) {
+                Some(column) => column,
+                None => {
+                    counts.types.push(name);
+                    counts.rows.iter_mut().for_each(|(_, row)| row.push(0));
+                    counts.types.len() - 1
+                }
+            };
+            let bucket = (e.time.0 / width) as usize;
+            while counts.rows.len() <= bucket {
+                let start = Time(counts.rows.len() as u32 * width);
+                counts.rows.push((start, vec![0; counts.types.len()]));
+            }
+            counts.rows[bucket].1[column] += 1;
+        }
+        counts
+    }
+
+    /// Format the counts as a table with a row per bucket and a column per
+    /// event type.
+    pub fn to_table(&self) -> String {
+        let widths = self
+            .types
+            .iter()
+            .map(|t| t.len().max(5))
+            .collect::<Vec<_>>();
+        let mut table = format!("{: >10}", "Time");
+        for (t, w) in self.types.iter().zip(&widths) {
+            table.push_str(&format!(" {: >w$}", t));
+        }
+        table.push('\n');
+        for (start, row) in &self.rows {
+            table.push_str(&format!("{: >10}", start.0));
+            for (n, w) in row.iter().zip(&widths) {
+                table.push_str(&format!(" {: >w$}", n));
+            }
+            table.push('\n');
+        }
+        table
+    }
+}
+
+/// The sample paths of the number of buffered items, the number of busy
+/// servers, and their sum, the number in the system.
+///
+/// The number in service is counted in busy servers, so with batch service,
+/// a batch counts once.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Occupancy {
+    pub buffered: SamplePath,
+    pub busy: SamplePath,
+    pub in_system: SamplePath,
+}
+
+impl Occupancy {
+    /// Create empty sample paths.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the counts at a time.
+    pub fn record(&mut self, time: Time, buffered: u32, busy: u32) -> &mut Self {
+        self.buffered.record(time, buffered);
+        self.busy.record(time, busy);
+        self.in_system.record(time, buffered + busy);
+        self
+    }
+
+    /// Record the counts of a queue at its current time.
+    pub fn record_state(&mut self, state: &QueueState) -> &mut Self {
+        self.record(state.time, state.buffer_count, state.server_count)
+    }
+
+    /// Replay the buffer and server events in a log to recover the sample
+    /// paths.
+    pub fn from_log(log: &EventLog) -> Self {
+        let mut occupancy = Self::new();
+        let mut buffered = HashSet::new();
+        let mut busy = 0u32;
+        for e in &log.contents {
+            match (e.event_type, e.item) {
+                (
+                    EventType::BufferIncremented | EventType::Recalled | EventType::FedBack(_),
+                    Some(item),
+                ) => {
+                    buffered.insert(item);
+                }
+                // An item that found the buffer full can also be queued for
+                // a callback, without ever having been buffered.
+                (
+                    EventType::BufferDecremented
+                    | EventType::Reneged
+                    | EventType::Evicted
+                    | EventType::Jockeyed(_)
+                    | EventType::CallbackQueued,
+                    Some(item),
+                ) => {
+                    buffered.remove(&item);
+                }
+                (EventType::ServerIncremented, _) => busy += 1,
+                (EventType::ServerDecremented, _) => busy = busy.saturating_sub(1),
+                _ => continue,
+            }
+            occupancy.record(e.time, buffered.len() as u32, busy);
+        }
+        occupancy
+    }
+
+    /// The time-average number of buffered items, Lq, from 0 until the given
+    /// time.
+    pub fn mean_queue_length(&self, until: Time) -> f64 {
+        self.buffered.time_average(until)
+    }
+
+    /// The time-average number in the system, L, from 0 until the given
+    /// time.
+    pub fn mean_number_in_system(&self, until: Time) -> f64 {
+        self.in_system.time_average(until)
+    }
+
+    /// The end of the warm-up period of a run that ended at the given time,
+    /// found by MSER-5 on the number in the system averaged over each unit
+    /// of time.
+    pub fn warm_up(&self, end: Time) -> Time {
+        Time(mser5(&self.in_system.averages(1, end)) as u32)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType};
+
+    #[test]
+    fn test_time_averages() {
+        // Items arrive at 0, 1, and 2 for services of 5, so one item waits
+        // from 1 to 2, two from 2 to 5, and one from 5 to 10, and the server
+        // is busy until 15.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+
+        let live = sim.occupancy();
+        assert_eq!(live, &Occupancy::from_log(&sim.log));
+        assert_eq!(
+            &[(Time(1), 1), (Time(2), 2), (Time(5), 1), (Time(10), 0)],
+            live.buffered.points()
+        );
+        assert!((live.mean_queue_length(Time(15)) - 12.0 / 15.0).abs() < 1e-9);
+        assert!((live.mean_number_in_system(Time(15)) - 27.0 / 15.0).abs() < 1e-9);
+        assert_eq!(1, live.in_system.value_at(Time(12)));
+
+        // Two items wait from 2 to 5, and three are in the system until 5.
+        let summary = sim.summary();
+        assert_eq!(Some((Time(2), 2)), summary.peak_queue_length);
+        assert_eq!(Some((Time(2), 3)), summary.peak_number_in_system);
+        assert_eq!(3, live.buffered.time_at_or_above(2, Time(15)));
+        assert_eq!(9, live.buffered.time_at_or_above(1, Time(15)));
+    }
+
+    #[test]
+    fn test_utilization() {
+        // Two servers are busy 0-10, 1-11, and 10-20 for 30 busy-server-time
+        // in all. Capacity drops to 1 at 12, for 2 * 12 + 8 = 32 of
+        // capacity-time.
+        let sim = &mut Simulation::new(QueueState::new(10, 2, 10));
+        for t in [0, 1, 10] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::SetCapacity(1),
+            time: Time(12),
+        });
+        let summary = sim.run();
+        assert_eq!(Time(20), summary.end);
+        assert!((summary.mean_busy_servers - 1.5).abs() < 1e-9);
+        assert!((summary.mean_capacity.unwrap() - 1.6).abs() < 1e-9);
+        assert!((summary.utilization.unwrap() - 30.0 / 32.0).abs() < 1e-9);
+
+        // An unbounded pool has no capacity to be busy a fraction of. Items
+        // served 0-10, 5-15, and 5-15 keep 2 servers busy on average.
+        let state = QueueState::new(10, 0, 10).with_infinite_servers();
+        let sim = &mut Simulation::new(state);
+        sim.enable_online_stats();
+        for t in [0, 5, 5] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        let summary = sim.run();
+        assert!((summary.mean_busy_servers - 2.0).abs() < 1e-9);
+        assert_eq!((None, None), (summary.mean_capacity, summary.utilization));
+        assert_eq!(None, sim.online_stats().unwrap().utilization());
+    }
+
+    #[test]
+    fn test_wait_and_sojourn_times() {
+        // Items arrive at 0, 1, and 2 for services of 5, so they wait 0, 4,
+        // and 8, and stay 5, 9, and 13.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.enable_time_sketches(Histogram::new(0.0, 5.0, 4), 100.0);
+        let summary = sim.run();
+        let times = ItemTimes::from_log(&sim.log);
+        let sketches = sim.time_sketches().unwrap();
+        assert_eq!(vec![2, 1, 0, 0], sketches.wait_histogram.counts);
+        assert_eq!(vec![0, 2, 1, 0], sketches.sojourn_histogram.counts);
+        assert_eq!(Some(13.0), sketches.sojourn_digest.quantile(1.0));
+        assert_eq!(vec![0, 4, 8], times.waits);
+        assert_eq!(vec![5, 9, 13], times.sojourns);
+        assert_eq!(vec![5, 5, 5], times.services());
+
+        let wait = summary.wait.unwrap();
+        assert_eq!((3, 4.0, 16.0), (wait.count, wait.mean, wait.variance));
+        assert_eq!(
+            (0, 8, 4, 8, 8),
+            (wait.min, wait.max, wait.p50, wait.p90, wait.p99)
+        );
+        assert_eq!(Some(9.0), summary.sojourn.map(|s| s.mean));
+        assert_eq!(
+            Some((5.0, 0.0, 5)),
+            summary.service.map(|s| (s.mean, s.variance, s.p99))
+        );
+        // Items leave at 5, 10, and 15, like clockwork.
+        let interdeparture = summary.interdeparture.unwrap();
+        assert_eq!(
+            (2, 5.0, 0.0),
+            (
+                interdeparture.count,
+                interdeparture.mean,
+                interdeparture.scv()
+            )
+        );
+        assert_eq!(None, TimeStats::new(&[]));
+
+        // The queue starts and ends empty, so L = λW exactly.
+        let law = summary.littles_law().unwrap();
+        assert!((law.lambda - 0.2).abs() < 1e-9);
+        assert!(law.discrepancy() < 1e-9);
+    }
+
+    #[test]
+    fn test_flow() {
+        // With a buffer of 1 and services of 10, of the items arriving at 0,
+        // 1, 2, and 12, the one at 2 is dropped. Items leave at 10, 20, and
+        // 30.
+        let sim = &mut Simulation::new(QueueState::new(1, 1, 10));
+        for t in [0, 1, 2, 12] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        let flow = sim.run().flow;
+        assert_eq!((4, 3, 1), (flow.arrivals, flow.departures, flow.dropped));
+        assert!((flow.throughput() - 0.1).abs() < 1e-9);
+        assert!((flow.loss_probability() - 0.25).abs() < 1e-9);
+
+        // The last window ends with the run, so it counts the exit at 30.
+        let windows = Flow::windows(&sim.log, 10, Time(30));
+        assert_eq!(
+            vec![(0, 10, 3, 0), (10, 20, 1, 1), (20, 30, 0, 2)],
+            windows
+                .iter()
+                .map(|w| (w.start.0, w.end.0, w.arrivals, w.departures))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_batch_means() {
+        // With an arrival every 10 for a service of 5, the server is busy
+        // half of every batch, and every item leaves 5 after it arrives.
+        use crate::arrival::DeterministicArrivals;
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        sim.add_source(DeterministicArrivals::new(Time(10)));
+        sim.run_until(Time(1000));
+        let batches = BatchMeans::new(Time(100), Time(1000), 9);
+        assert_eq!((Time(100), Time(200)), batches.bounds()[0]);
+        let busy = sim
+            .batch_means(Time(100), 9, BatchMetric::BusyServers, 0.95)
+            .unwrap();
+        assert_eq!((9, 0.5, 0.0), (busy.n, busy.mean, busy.half_width));
+        assert_eq!(
+            vec![5.0; 9],
+            batches.means(sim.occupancy(), &sim.log, BatchMetric::Sojourn)
+        );
+        assert_eq!(
+            vec![0.1; 9],
+            batches.means(sim.occupancy(), &sim.log, BatchMetric::Throughput)
+        );
+        assert_eq!(
+            Some(0.0),
+            batches.lag1(sim.occupancy(), &sim.log, BatchMetric::BusyServers)
+        );
+    }
+
+    #[test]
+    fn test_mser5() {
+        // Twenty high observations are followed by a steady alternation, so
+        // MSER-5 drops the first four batches.
+        let observations = (0..100)
+            .map(|i| match i {
+                0..20 => 10.0,
+                _ => (1 + i % 2) as f64,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(20, mser5(&observations));
+
+        // The number in the system is 10 until 50 and 1 after.
+        let occupancy = &mut Occupancy::new();
+        occupancy.record(Time(0), 9, 1).record(Time(50), 0, 1);
+        assert_eq!(Time(50), occupancy.warm_up(Time(500)));
+        let batches = BatchMeans::after_warm_up(occupancy, Time(500), 5);
+        assert_eq!((Time(50), Time(140)), batches.bounds()[0]);
+    }
+
+    #[test]
+    fn test_histogram_and_digest() {
+        // Uniform values from 0 to 99,999.
+        let hist = &mut Histogram::new(0.0, 1000.0, 50);
+        let digest = &mut TDigest::new(100.0);
+        for i in 0..100_000 {
+            hist.record(i as f64);
+            digest.record(i as f64);
+        }
+        assert_eq!((0, 50_000, 1000), (hist.below, hist.above, hist.counts[0]));
+        assert_eq!(Some(25_000.0), hist.quantile(0.25));
+        assert_eq!(Some(50_000.0), hist.quantile(0.9));
+
+        assert!(digest.size() <= 200);
+        for (q, expected) in [(0.5, 50_000.0), (0.9, 90_000.0), (0.99, 99_000.0)] {
+            let estimate = digest.quantile(q).unwrap();
+            assert!((estimate - expected).abs() < 0.001 * 100_000.0);
+        }
+        assert!((digest.quantile(0.999).unwrap() - 99_900.0).abs() < 10.0);
+    }
+
+    #[test]
+    fn test_by_class() {
+        // Two items from source 0 arrive at 0 and 1, and one from source 1
+        // at 2, for services of 5, so source 0 has the server for 10 of the
+        // 15 units and source 1 for the other 5.
+        use crate::arrival::Attributes;
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for (t, source) in [(0, 0), (1, 0), (2, 1)] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::ArriveWith(Attributes {
+                    source: Some(SourceId(source)),
+                    ..Attributes::default()
+                }),
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let classes = sim.summary_by_class();
+        assert_eq!(
+            vec![Some(SourceId(0)), Some(SourceId(1))],
+            classes.iter().map(|(class, _)| *class).collect::<Vec<_>>()
+        );
+        let (first, second) = (&classes[0].1, &classes[1].1);
+        assert!((first.utilization.unwrap() - 10.0 / 15.0).abs() < 1e-9);
+        assert!((second.utilization.unwrap() - 5.0 / 15.0).abs() < 1e-9);
+        assert_eq!(Some(2.0), first.wait.map(|w| w.mean));
+        assert_eq!(Some(13.0), second.sojourn.map(|s| s.mean));
+        assert_eq!(Some(8), second.wait.map(|w| w.p50));
+        assert_eq!(Some(5), second.service.map(|s| s.p50));
+        assert_eq!((2, 1), (first.flow.arrivals, second.flow.arrivals));
+
+        // Source 0's items wait 0 and 4, and source 1's waits 8.
+        let attained = |(_, summary): &(_, SummaryStats)| summary.service_level.unwrap().attained;
+        sim.set_service_target(4);
+        assert_eq!(
+            Some(2.0 / 3.0),
+            sim.summary().service_level.map(|s| s.attained)
+        );
+        let classes = sim.summary_by_class();
+        assert_eq!((1.0, 0.0), (attained(&classes[0]), attained(&classes[1])));
+    }
+
+    #[test]
+    fn test_busy_and_idle_periods() {
+        // Items arrive at 0, 1, 2, and 20 for services of 5, so the system
+        // is busy from 0 to 15 and from 20 to 25, and the server is idle in
+        // between.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1, 2, 20] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let periods = sim.periods();
+        assert_eq!(
+            vec![(Time(0), Time(15)), (Time(20), Time(25))],
+            periods.busy
+        );
+        assert_eq!(vec![(ServerId(0), Time(15), Time(20))], periods.idle);
+        assert_eq!(
+            Some((10.0, 15)),
+            periods.busy_stats().map(|s| (s.mean, s.max))
+        );
+        assert_eq!(Some(5.0), periods.idle_stats().map(|s| s.mean));
+    }
+
+    #[test]
+    fn test_regeneration() {
+        // Items arrive at 0, 1, 2, 20, 30, and 31 for services of 5, so the
+        // system empties at 15, 25, and 40. The cycle from 15 to 25 has an
+        // area of 5 and one item that didn't wait, and the one from 25 to 40
+        // an area of 14 and two items that waited 0 and 4.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1, 2, 20, 30, 31] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let regeneration = sim.regeneration();
+        assert_eq!(vec![Time(15), Time(25), Time(40)], regeneration.points);
+        assert_eq!(2, regeneration.cycles());
+        let in_system = regeneration
+            .time_average(&sim.occupancy().in_system, 0.95)
+            .unwrap();
+        // The residuals of the areas are -2.6 and 2.6, over a mean cycle
+        // length of 12.5.
+        assert!((in_system.mean - 19.0 / 25.0).abs() < 1e-9);
+        assert!((in_system.std_dev - (2.0 * 2.6f64.powi(2)).sqrt() / 12.5).abs() < 1e-9);
+        let times = ItemTimes::from_log(&sim.log);
+        let wait = regeneration.item_average(&times, &times.waits, 0.95);
+        assert!((wait.unwrap().mean - 4.0 / 3.0).abs() < 1e-9);
+        assert_eq!(
+            None,
+            Regeneration::default().time_average(&SamplePath::new(), 0.95)
+        );
+    }
+
+    #[test]
+    fn test_server_loads() {
+        // With three servers and services of 5, the first server takes the
+        // items at 0, 6, and 20, the second the ones at 1 and 22, and the
+        // items at 20 and 22 are still in service when the run ends at 22.
+        // The third server is never used.
+        let sim = &mut Simulation::new(QueueState::new(10, 3, 5));
+        for t in [0, 1, 6, 20, 22] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run_until(Time(22));
+        let loads = sim.server_loads();
+        assert_eq!(
+            vec![(12, 2), (5, 1), (0, 0)],
+            loads
+                .iter()
+                .map(|l| (l.busy_time, l.served))
+                .collect::<Vec<_>>()
+        );
+        assert!((loads[0].busy_fraction() - 12.0 / 22.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_online_stats() {
+        // Online statistics of a run without a log match the summary of the
+        // same run with one.
+        let run = |keep_log: bool| {
+            let mut sim = Simulation::new(QueueState::new(1, 1, 5));
+            sim.enable_online_stats();
+            if !keep_log {
+                sim.discard_log();
+            }
+            for t in [0, 1, 2, 3, 20] {
+                sim.schedule(EventMessage {
+                    event_message_type: EventMessageType::Arrive,
+                    time: Time(t),
+                });
+            }
+            let summary = sim.run();
+            (
+                summary,
+                sim.online_stats().unwrap().clone(),
+                sim.log.contents.len(),
+            )
+        };
+        let (summary, _, logged) = run(true);
+        let (_, online, unlogged) = run(false);
+        assert!(logged > 0);
+        assert_eq!(0, unlogged);
+        assert_eq!(summary.flow, online.flow);
+        let wait = summary.wait.unwrap();
+        assert!((wait.mean - online.wait.mean).abs() < 1e-9);
+        assert!((wait.variance - online.wait.variance()).abs() < 1e-9);
+        assert!((summary.mean_number_in_system - online.mean_number_in_system()).abs() < 1e-9);
+        assert!((summary.utilization.unwrap() - online.utilization().unwrap()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_autocorrelation() {
+        // An alternating series is perfectly anticorrelated at odd lags, and
+        // the waits of items queued behind each other move together.
+        let alternating = (0..1000).map(|i| (i % 2) as f64).collect::<Vec<_>>();
+        let r = autocorrelation(&alternating, 2);
+        assert!((r[0] + 1.0).abs() < 0.01 && (r[1] - 1.0).abs() < 0.01);
+        assert_eq!(vec![0.0; 3], autocorrelation(&[2.0; 10], 3));
+        assert_eq!(1, autocorrelation(&[1.0, 2.0], 5).len());
+
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1, 2, 3, 4, 5] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let waits = ItemTimes::from_log(&sim.log)
+            .waits
+            .iter()
+            .map(|&w| w as f64)
+            .collect::<Vec<_>>();
+        assert!(autocorrelation(&waits, 1)[0] > 0.0);
+    }
+
+    #[test]
+    fn test_event_counts() {
+        // Items arrive at 0, 1, and 2 for services of 5, and leave at 5, 10,
+        // and 15.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1, 2] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let counts = EventCounts::from_log(&sim.log, 10);
+        assert_eq!(
+            vec![
+                "BufferIncremented",
+                "BufferDecremented",
+                "ServerIncremented",
+                "ServerDecremented"
+            ],
+            counts.types
+        );
+        assert_eq!(
+            vec![(Time(0), vec![3, 2, 2, 1]), (Time(10), vec![0, 1, 1, 2])],
+            counts.rows
+        );
+        assert_eq!(
+            Some("      Time BufferIncremented BufferDecremented ServerIncremented ServerDecremented"),
+            counts.to_table().lines().next()
+        );
+    }
+}
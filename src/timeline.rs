@@ -0,0 +1,363 @@
+//! Timelines of a run for trace viewers.
+//!
+//! `chrome_trace` lays out what each server was doing, and when each item
+//! waited and was served, in the Chrome trace-event format, which loads into
+//! `about://tracing` or Perfetto for a zoomable view of the run. Servers are
+//! threads of one process and items threads of another, and one unit of
+//! simulated time shows as one microsecond.
+//!
+//! `Lifecycles` tabulates what became of each arrival, for drawing a Gantt
+//! chart of the items or reading into another tool as CSV.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::item::ItemId;
+use crate::json::Json;
+use crate::server::ServerId;
+use crate::{EventLog, EventType, Time};
+
+/// A stretch of time a server spent on a service, tagged with the item it
+/// served, or the first item of a batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusyInterval {
+    pub server: ServerId,
+    pub start: Time,
+    pub end: Time,
+    pub item: Option<ItemId>,
+}
+
+impl BusyInterval {
+    /// Find the busy intervals in a log of a run up to `end`, in order of
+    /// start. A service still going on at the end runs up to the end.
+    pub fn from_log(log: &EventLog, end: Time) -> Vec<Self> {
+        let mut intervals = vec![];
+        let mut started = HashMap::new();
+        for e in &log.contents {
+            match (e.event_type, e.server) {
+                (EventType::ServerIncremented, Some(server)) => {
+                    started.insert(server, (e.time, e.item));
+                }
+                (EventType::ServerDecremented, Some(server)) => {
+                    if let Some((start, item)) = started.remove(&server) {
+                        intervals.push(Self {
+                            server,
+                            start,
+                            end: e.time,
+                            item,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        intervals.extend(started.into_iter().map(|(server, (start, item))| Self {
+            server,
+            start,
+            end: end.max(start),
+            item,
+        }));
+        intervals.sort_by_key(|i| (i.start, i.server.0));
+        intervals
+    }
+}
+
+/// What became of an arrival:
+/// - `Served`: It finished service and left.
+/// - `Dropped`: It found the buffer full, or gave up retrying.
+/// - `Balked`: It decided not to join.
+/// - `Throttled`: Admission control turned it away.
+/// - `Reneged`: It ran out of patience in the buffer.
+/// - `Evicted`: It was dropped from the buffer to make room.
+/// - `Jockeyed`: It moved to a parallel queue.
+/// - `InSystem`: It was still in the queue at the end of the run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Served,
+    Dropped,
+    Balked,
+    Throttled,
+    Reneged,
+    Evicted,
+    Jockeyed,
+    InSystem,
+}
+
+/// The life of one arrival: when it arrived, when its service started and
+/// ended on which server, and how it ended up. Arrivals turned away at the
+/// door never became items, so they have no item ID. An item served more
+/// than once, e.g., with feedback, has the start of its first service and
+/// the end and server of its last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lifecycle {
+    pub item: Option<ItemId>,
+    pub arrival: Time,
+    pub service_start: Option<Time>,
+    pub service_end: Option<Time>,
+    pub server: Option<ServerId>,
+    pub outcome: Outcome,
+}
+
+/// The lifecycles of the arrivals in a run, in order of arrival.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Lifecycles {
+    pub lifecycles: Vec<Lifecycle>,
+}
+
+impl Lifecycles {
+    /// Work out the lifecycles from a log.
+    pub fn from_log(log: &EventLog) -> Self {
+        let mut lifecycles = vec![];
+        let mut index = HashMap::new();
+        // The items taken from the buffer in the current step, which the next
+        // server to start serves, and the items on each server.
+        let mut taken = vec![];
+        let mut serving: HashMap<ServerId, Vec<ItemId>> = HashMap::new();
+        let arrival = |time, outcome| Lifecycle {
+            item: None,
+            arrival: time,
+            service_start: None,
+            service_end: None,
+            server: None,
+            outcome,
+        };
+        for e in &log.contents {
+            let outcome = match e.event_type {
+                EventType::Dropped | EventType::RetryAbandoned => Some(Outcome::Dropped),
+                EventType::Balked => Some(Outcome::Balked),
+                EventType::Throttled => Some(Outcome::Throttled),
+                EventType::Reneged => Some(Outcome::Reneged),
+                EventType::Evicted => Some(Outcome::Evicted),
+                EventType::Jockeyed(_) => Some(Outcome::Jockeyed),
+                _ => None,
+            };
+            let Some(item) = e.item else {
+                if let Some(outcome) = outcome {
+                    lifecycles.push(arrival(e.time, outcome));
+                }
+                // An interrupted service goes back to the buffer.
+                if let (EventType::ServerDecremented, Some(server)) = (e.event_type, e.server) {
+                    serving.remove(&server);
+                }
+                continue;
+            };
+            let i = *index.entry(item).or_insert_with(|| {
+                lifecycles.push(Lifecycle {
+                    item: Some(item),
+                    ..arrival(e.time, Outcome::InSystem)
+                });
+                lifecycles.len() - 1
+            });
+            let lifecycle = &mut lifecycles[i];
+            if let Some(outcome) = outcome {
+                lifecycle.outcome = outcome;
+            }
+            match (e.event_type, e.server) {
+                (EventType::BufferDecremented, _) => taken.push(item),
+                (EventType::ServerIncremented, Some(server)) => {
+                    if taken.is_empty() {
+                        taken.push(item);
+                    }
+                    for &id in &taken {
+                        let lifecycle = &mut lifecycles[index[&id]];
+                        lifecycle.service_start.get_or_insert(e.time);
+                        lifecycle.server = Some(server);
+                    }
+                    serving.insert(server, std::mem::take(&mut taken));
+                }
+                (EventType::ServerDecremented, Some(server)) => {
+                    for id in serving.remove(&server).unwrap_or_default() {
+                        let lifecycle = &mut lifecycles[index[&id]];
+                        lifecycle.service_end = Some(e.time);
+                        lifecycle.outcome = Outcome::Served;
+                    }
+                }
+                (EventType::FedBack(_), _) => lifecycle.outcome = Outcome::InSystem,
+                _ => {}
+            }
+        }
+        Self { lifecycles }
+    }
+
+    /// Write the lifecycles as CSV, with a header row and missing values
+    /// left empty.
+    pub fn write_csv(&self, writer: &mut impl Write) -> io::Result<()> {
+        let field = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+        writeln!(
+            writer,
+            "item,arrival,service_start,service_end,server,outcome"
+        )?;
+        for l in &self.lifecycles {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{:?}",
+                field(l.item.map(|id| id.0)),
+                l.arrival.0,
+                field(l.service_start.map(|t| t.0 as u64)),
+                field(l.service_end.map(|t| t.0 as u64)),
+                field(l.server.map(|id| id.0 as u64)),
+                l.outcome
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The stretches of time items spent in the buffer, in order of start, from
+/// when they joined it, or came back to it, until they left it.
+fn waits(log: &EventLog, end: Time) -> Vec<(ItemId, Time, Time)> {
+    let mut waits = vec![];
+    let mut joined = HashMap::new();
+    for e in &log.contents {
+        let Some(item) = e.item else {
+            continue;
+        };
+        match e.event_type {
+            EventType::BufferIncremented | EventType::FedBack(_) | EventType::Recalled => {
+                joined.insert(item, e.time);
+            }
+            EventType::BufferDecremented
+            | EventType::Reneged
+            | EventType::Evicted
+            | EventType::Jockeyed(_) => {
+                if let Some(start) = joined.remove(&item) {
+                    waits.push((item, start, e.time));
+                }
+            }
+            _ => {}
+        }
+    }
+    waits.extend(
+        joined
+            .into_iter()
+            .map(|(item, start)| (item, start, end.max(start))),
+    );
+    waits.sort_by_key(|&(item, start, _)| (start, item.0));
+    waits
+}
+
+/// A complete event spanning a stretch of time on a thread.
+fn span(name: String, pid: u32, tid: u64, start: Time, end: Time) -> Json {
+    Json::Object(vec![
+        ("name".to_string(), Json::String(name)),
+        ("ph".to_string(), Json::String("X".to_string())),
+        ("ts".to_string(), Json::Number(start.0 as f64)),
+        ("dur".to_string(), Json::Number((end.0 - start.0) as f64)),
+        ("pid".to_string(), Json::Number(pid as f64)),
+        ("tid".to_string(), Json::Number(tid as f64)),
+    ])
+}
+
+/// A metadata event naming a process.
+fn process_name(pid: u32, name: &str) -> Json {
+    Json::Object(vec![
+        ("name".to_string(), Json::String("process_name".to_string())),
+        ("ph".to_string(), Json::String("M".to_string())),
+        ("pid".to_string(), Json::Number(pid as f64)),
+        (
+            "args".to_string(),
+            Json::Object(vec![("name".to_string(), Json::String(name.to_string()))]),
+        ),
+    ])
+}
+
+/// The process of the servers, with a thread per server.
+const SERVERS: u32 = 1;
+/// The process of the items, with a thread per item.
+const ITEMS: u32 = 2;
+
+/// Lay out a log of a run up to `end` as a Chrome trace: a span per service
+/// on each server, and a span per wait and per service for each item.
+pub fn chrome_trace(log: &EventLog, end: Time) -> Json {
+    let mut events = vec![
+        process_name(SERVERS, "servers"),
+        process_name(ITEMS, "items"),
+    ];
+    for interval in BusyInterval::from_log(log, end) {
+        let name = match interval.item {
+            Some(item) => format!("item {}", item.0),
+            None => "service".to_string(),
+        };
+        let server = interval.server.0 as u64;
+        events.push(span(name, SERVERS, server, interval.start, interval.end));
+        if let Some(item) = interval.item {
+            let name = format!("service on server {server}");
+            events.push(span(name, ITEMS, item.0, interval.start, interval.end));
+        }
+    }
+    for (item, start, end) in waits(log, end) {
+        events.push(span("wait".to_string(), ITEMS, item.0, start, end));
+    }
+    Json::Object(vec![("traceEvents".to_string(), Json::Array(events))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType, QueueState};
+
+    #[test]
+    fn test_lifecycles() {
+        // With a buffer of 1 and two servers serving for 5, items arriving at
+        // 0 and 1 are served right away, the one at 2 waits until 5, the one
+        // at 3 is dropped, and the ones at 2 and 7 are still in service at
+        // the end.
+        let sim = &mut Simulation::new(QueueState::new(1, 2, 5));
+        for t in [0, 1, 2, 3, 7] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run_until(Time(8));
+        let lifecycles = Lifecycles::from_log(&sim.log);
+        let csv = &mut vec![];
+        lifecycles.write_csv(csv).unwrap();
+        assert_eq!(
+            "item,arrival,service_start,service_end,server,outcome\n\
+             0,0,0,5,0,Served\n\
+             1,1,1,6,1,Served\n\
+             2,2,5,,0,InSystem\n\
+             ,3,,,,Dropped\n\
+             3,7,7,,1,InSystem\n",
+            String::from_utf8(csv.clone()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_chrome_trace() {
+        // Items arrive at 0 and 1 for services of 5 on one server, so the
+        // second waits from 1 to 5, and it's still in service at 8.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        for t in [0, 1] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run_until(Time(8));
+        let intervals = BusyInterval::from_log(&sim.log, Time(8));
+        assert_eq!(
+            vec![(Time(0), Time(5)), (Time(5), Time(8))],
+            intervals
+                .iter()
+                .map(|i| (i.start, i.end))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(Some(ItemId(1)), intervals[1].item);
+        assert_eq!(
+            vec![(ItemId(0), Time(0), Time(0)), (ItemId(1), Time(1), Time(5))],
+            waits(&sim.log, Time(8))
+        );
+        let trace = chrome_trace(&sim.log, Time(8));
+        let events = trace.get("traceEvents").and_then(Json::as_array).unwrap();
+        // Two process names, two services on the server and for the items,
+        // and two waits.
+        assert_eq!(8, events.len());
+        assert_eq!(
+            r#"{"name":"wait","ph":"X","ts":1,"dur":4,"pid":2,"tid":1}"#,
+            events[7].to_string()
+        );
+    }
+}
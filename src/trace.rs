@@ -0,0 +1,154 @@
+//! Hooks for diagnosing a run from inside a larger application.
+//!
+//! A `Tracer` is told when each step of a simulation starts, with the
+//! message it handles and the simulated time, and when it ends, with the
+//! events it produced, which is where an application hooks in its own
+//! observability. `Logfmt` is a ready-made tracer that writes one
+//! structured `key=value` line per step.
+//!
+//! The crate doesn't depend on `tracing`, but an application that does can
+//! bridge to it with a tracer that enters a span for each step, with the
+//! simulated time as a field, and leaves it when the step is done:
+//!
+//! ```text
+//! #[derive(Debug, Default)]
+//! struct Spans(Option<tracing::span::EnteredSpan>);
+//!
+//! impl Tracer for Spans {
+//!     fn enter_step(&mut self, message: &EventMessage) {
+//!         let span = tracing::info_span!("step", sim_time = message.time.0);
+//!         self.0 = Some(span.entered());
+//!     }
+//!
+//!     fn exit_step(&mut self, _message: &EventMessage, events: &[Event]) {
+//!         tracing::debug!(events = events.len());
+//!         self.0 = None;
+//!     }
+//! }
+//!
+//! sim.set_tracer(Box::new(Spans::default()));
+//! ```
+
+use std::fmt::Debug;
+use std::io::Write;
+
+use crate::{Event, EventMessage};
+
+/// Told about each step of a simulation as it happens.
+pub trait Tracer: Debug {
+    /// A step is about to handle a message, due at the simulated time of the
+    /// message.
+    fn enter_step(&mut self, message: &EventMessage);
+
+    /// The step that handled a message is done, having produced the given
+    /// events.
+    fn exit_step(&mut self, message: &EventMessage, events: &[Event]);
+}
+
+/// Writes a line per step in logfmt, e.g., `sim_time=5
+/// message=Exit(ServerId(0)) events=1 event_types=ServerDecremented`, with
+/// the message quoted if it has spaces, as messages with attributes do.
+/// Errors from the writer are ignored, since tracing shouldn't stop a run.
+#[derive(Debug)]
+pub struct Logfmt<W: Write + Debug> {
+    pub writer: W,
+}
+
+impl<W: Write + Debug> Tracer for Logfmt<W> {
+    fn enter_step(&mut self, _message: &EventMessage) {}
+
+    fn exit_step(&mut self, message: &EventMessage, events: &[Event]) {
+        let types = events
+            .iter()
+            .map(|e| e.event_type.parts().0)
+            .collect::<Vec<_>>()
+            .join(",");
+        let _ = writeln!(
+            self.writer,
+            "sim_time={} message={} events={} event_types={}",
+            message.time.0,
+            value(&format!("{:?}", message.event_message_type)),
+            events.len(),
+            types
+        );
+    }
+}
+
+/// A logfmt value, quoted, with quotes and backslashes escaped, if it's
+/// empty or has spaces, quotes, or equals signs.
+fn value(text: &str) -> String {
+    if !text.is_empty() && !text.contains([' ', '"', '=', '\\']) {
+        return text.to_string();
+    }
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::simulation::Simulation;
+    use crate::{EventMessageType, QueueState, Time};
+
+    /// Records what it's told.
+    #[derive(Debug, Default)]
+    struct Recorder(Rc<RefCell<Vec<String>>>);
+
+    impl Tracer for Recorder {
+        fn enter_step(&mut self, message: &EventMessage) {
+            self.0
+                .borrow_mut()
+                .push(format!("enter {}", message.time.0));
+        }
+
+        fn exit_step(&mut self, message: &EventMessage, events: &[Event]) {
+            let entry = format!("exit {} {}", message.time.0, events.len());
+            self.0.borrow_mut().push(entry);
+        }
+    }
+
+    #[test]
+    fn test_tracer() {
+        // An item arriving at 0 for a service of 5 takes four steps: the
+        // arrival, the call to serve, the exit, and another call to serve,
+        // which finds nothing to do.
+        let trace = Rc::new(RefCell::new(vec![]));
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        sim.set_tracer(Box::new(Recorder(Rc::clone(&trace))));
+        sim.schedule(EventMessage {
+            event_message_type: EventMessageType::Arrive,
+            time: Time(0),
+        });
+        sim.run();
+        assert_eq!(
+            vec![
+                "enter 0", "exit 0 1", "enter 0", "exit 0 2", "enter 5", "exit 5 1", "enter 5",
+                "exit 5 0"
+            ],
+            *trace.borrow()
+        );
+
+        let tracer = &mut Logfmt { writer: vec![] };
+        tracer.exit_step(
+            &EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(3),
+            },
+            &sim.log.contents[..1],
+        );
+        assert_eq!(
+            "sim_time=3 message=Arrive events=1 event_types=BufferIncremented\n",
+            String::from_utf8(tracer.writer.clone()).unwrap()
+        );
+
+        // Messages with spaces are quoted, and quotes in them escaped.
+        assert_eq!("Exit(ServerId(0))", value("Exit(ServerId(0))"));
+        assert_eq!(
+            r#""ArriveWith(Attributes { tag: \"a=b\" })""#,
+            value(r#"ArriveWith(Attributes { tag: "a=b" })"#)
+        );
+        assert_eq!("\"\"", value(""));
+    }
+}
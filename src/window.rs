@@ -0,0 +1,195 @@
+//! Metrics over a sliding window of simulated time.
+//!
+//! Averages over a whole run hide transient overloads. A `SlidingWindow`
+//! tracks a measure over only the most recent stretch of time, e.g., the
+//! 95th percentile wait of items that left in the last 60 units, and keeps
+//! the trajectory of that measure as the run goes.
+
+use std::collections::VecDeque;
+
+use crate::metric::{Metric, MetricValue};
+use crate::stats::{Departure, ItemTracker};
+use crate::{Event, QueueState, Time};
+
+/// What a sliding window measures, over the items that left within it:
+/// - `MeanWait`: The mean wait.
+/// - `WaitQuantile`: The given quantile (between 0 and 1) of the waits, by
+///   the nearest rank.
+/// - `Throughput`: Departures per unit time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WindowMeasure {
+    MeanWait,
+    WaitQuantile(f64),
+    Throughput,
+}
+
+/// A measure over the window of the last `width` units of time, i.e., from
+/// just after `now - width` through `now`. Register it with a `Simulation`
+/// as a `Metric`, which reports a series of the measure at each time it
+/// changed, including when an item's departure drops out of the window.
+/// The wait of an empty window is NaN in the series.
+#[derive(Debug, Clone)]
+pub struct SlidingWindow {
+    pub width: u32,
+    pub measure: WindowMeasure,
+    tracker: ItemTracker,
+    now: Time,
+    recent: VecDeque<Departure>,
+    series: Vec<(Time, f64)>,
+}
+
+impl SlidingWindow {
+    /// Create a window of the given width.
+    pub fn new(width: u32, measure: WindowMeasure) -> Self {
+        assert!(width > 0, "the window must have a positive width");
+        Self {
+            width,
+            measure,
+            tracker: ItemTracker::new(),
+            now: Time(0),
+            recent: VecDeque::new(),
+            series: vec![],
+        }
+    }
+
+    /// The measure over the window as of the last time the clock moved, or
+    /// `None` for a wait if no item left within it.
+    pub fn current(&self) -> Option<f64> {
+        let n = self.recent.len();
+        if n == 0 && self.measure != WindowMeasure::Throughput {
+            return None;
+        }
+        let waits = || self.recent.iter().map(|d| d.wait);
+        Some(match self.measure {
+            WindowMeasure::MeanWait => waits().map(|w| w as f64).sum::<f64>() / n as f64,
+            WindowMeasure::WaitQuantile(q) => {
+                let mut sorted = waits().collect::<Vec<_>>();
+                sorted.sort_unstable();
+                sorted[((q * n as f64).ceil() as usize).clamp(1, n) - 1] as f64
+            }
+            WindowMeasure::Throughput => n as f64 / self.width as f64,
+        })
+    }
+
+    /// Close out the current time: slide the window past the departures
+    /// that drop out of it before then, one time at a time, take in the
+    /// items that left, and extend the series wherever the measure changed.
+    fn close(&mut self) {
+        let now = self.now;
+        while let Some(expiry) = self.recent.front().map(|d| Time(d.time.0 + self.width)) {
+            if expiry >= now {
+                break;
+            }
+            self.slide(expiry);
+        }
+        self.recent.extend(self.tracker.flush());
+        self.slide(now);
+    }
+
+    /// Drop the departures that are out of the window ending at the given
+    /// time, and extend the series if the measure changed.
+    fn slide(&mut self, time: Time) {
+        let width = self.width;
+        while self
+            .recent
+            .front()
+            .is_some_and(|d| d.time.0 + width <= time.0)
+        {
+            self.recent.pop_front();
+        }
+        let value = self.current().unwrap_or(f64::NAN);
+        let changed = match self.series.last() {
+            None => !value.is_nan(),
+            Some(&(_, last)) => last != value && !(last.is_nan() && value.is_nan()),
+        };
+        if changed {
+            self.series.push((time, value));
+        }
+    }
+}
+
+impl Metric for SlidingWindow {
+    fn observe_event(&mut self, e: &Event, _s: &QueueState) {
+        if e.time > self.now {
+            self.close();
+            self.now = e.time;
+        }
+        self.tracker.observe(e);
+    }
+
+    fn finalize(&self) -> MetricValue {
+        let mut window = self.clone();
+        window.close();
+        MetricValue::Series(window.series)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::Simulation;
+    use crate::{EventMessage, EventMessageType};
+
+    #[test]
+    fn test_sliding_window() {
+        // Items arrive at 0, 1, 2, and 30 for services of 5, so they leave at
+        // 5, 10, 15, and 35 after waits of 0, 4, 8, and 0. Over windows of
+        // 10, the mean wait rises to 6 at 15 and 8 at 20, when the second
+        // departure drops out, there's no wait from 25 to 35, and it's 0 at
+        // 35. The throughput is 0 until the first departure and between 25
+        // and 35.
+        let sim = &mut Simulation::new(QueueState::new(10, 1, 5));
+        sim.add_metric("wait", SlidingWindow::new(10, WindowMeasure::MeanWait));
+        sim.add_metric(
+            "p90",
+            SlidingWindow::new(10, WindowMeasure::WaitQuantile(0.9)),
+        );
+        sim.add_metric("rate", SlidingWindow::new(10, WindowMeasure::Throughput));
+        for t in [0, 1, 2, 30] {
+            sim.schedule(EventMessage {
+                event_message_type: EventMessageType::Arrive,
+                time: Time(t),
+            });
+        }
+        sim.run();
+        let series = |name| match sim.metric(name) {
+            Some(MetricValue::Series(series)) => series
+                .into_iter()
+                .map(|(t, value)| (t.0, (!value.is_nan()).then_some(value)))
+                .collect::<Vec<_>>(),
+            _ => panic!("expected a series"),
+        };
+        assert_eq!(
+            vec![
+                (5, Some(0.0)),
+                (10, Some(2.0)),
+                (15, Some(6.0)),
+                (20, Some(8.0)),
+                (25, None),
+                (35, Some(0.0))
+            ],
+            series("wait")
+        );
+        assert_eq!(
+            vec![
+                (5, Some(0.0)),
+                (10, Some(4.0)),
+                (15, Some(8.0)),
+                (25, None),
+                (35, Some(0.0))
+            ],
+            series("p90")
+        );
+        assert_eq!(
+            vec![
+                (0, Some(0.0)),
+                (5, Some(0.1)),
+                (10, Some(0.2)),
+                (20, Some(0.1)),
+                (25, Some(0.0)),
+                (35, Some(0.1))
+            ],
+            series("rate")
+        );
+    }
+}